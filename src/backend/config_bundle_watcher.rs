@@ -0,0 +1,279 @@
+use glob::Pattern;
+use notify::event::{AccessKind, AccessMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    io::{self, Read},
+    path::Path,
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
+
+use super::{
+    command_channel, remove_document, update_document, wait_for_start, AsyncWatcherHandler,
+    DocumentEvent, EventSender, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::WatcherError;
+
+/// Archive formats supported by [`run_bundle_watcher`], determined by the file extension.
+#[derive(Clone, Copy, Debug)]
+enum BundleFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl BundleFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A changing archive is read at the latest this many debounce intervals after the first change.
+pub const BUNDLE_DEBOUNCE_MAX_FACTOR: u32 = 10;
+
+/// Watches a `.tar`, `.tar.gz`/`.tgz` or `.zip` archive and emits each file in it as a document.
+///
+/// Files whose path inside the archive matches `inner_pattern` are extracted in memory and
+/// emitted with that path (without a leading `./`) as document id. Files that are not valid
+/// UTF-8 are skipped. When the archive changes, it is read again once no further change was
+/// reported for `debounce` (adjustable with [`WatcherHandle::set_debounce`]), but at the latest
+/// [`BUNDLE_DEBOUNCE_MAX_FACTOR`] times `debounce` after the first change, and the differences to
+/// the previous extraction are emitted.
+///
+/// If the archive cannot be read, for example because it is still being written, the error is
+/// logged and the previous documents are kept; replace the archive atomically (write a temporary
+/// file and rename it) to avoid this. If the archive is deleted, all its documents are removed.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_bundle_watcher(
+    bundle_path: impl AsRef<Path>,
+    inner_pattern: &str,
+    mut debounce: Duration,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let bundle_path = bundle_path.as_ref().to_path_buf();
+    let (Some(format), Some(file_name)) = (
+        BundleFormat::from_path(&bundle_path),
+        bundle_path.file_name().map(OsString::from),
+    ) else {
+        return Err(WatcherError::UnsupportedBundle(bundle_path));
+    };
+    let inner_pattern = Pattern::new(inner_pattern)?;
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        // The archive is usually replaced by a rename, so its directory is watched. notify reports
+        // absolute paths, so the directory is canonicalized to compare them.
+        let directory = match bundle_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let directory = tokio::fs::canonicalize(directory)
+            .await
+            .map_err(|e| WatcherError::FileReadError(directory.to_path_buf(), e))?;
+        let bundle_path = directory.join(file_name);
+
+        let (wh, mut rx) = AsyncWatcherHandler::new();
+        let mut watcher = notify::recommended_watcher(wh)?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        let mut hashes: HashMap<String, u64> = HashMap::new();
+        sync_bundle(
+            &bundle_path,
+            format,
+            &inner_pattern,
+            &mut hashes,
+            &event_sender,
+        )
+        .await;
+        event_sender
+            .send(DocumentEvent::InitialScanComplete)
+            .await
+            .ok();
+
+        let mut reload_at: Option<Instant> = None;
+        let mut changed_since: Option<Instant> = None;
+        let reload_deadline = |since: Instant, debounce: Duration| {
+            (Instant::now() + debounce).min(since + debounce * BUNDLE_DEBOUNCE_MAX_FACTOR)
+        };
+        loop {
+            tokio::select! {
+                Some(event) = rx.recv() => {
+                    if event.paths.contains(&bundle_path) && modifies_bundle(&event.kind) {
+                        let since = *changed_since.get_or_insert_with(Instant::now);
+                        reload_at = Some(reload_deadline(since, debounce));
+                    }
+                }
+                _ = tokio::time::sleep_until(reload_at.unwrap_or_else(Instant::now)), if reload_at.is_some() => {
+                    reload_at = None;
+                    changed_since = None;
+                    sync_bundle(&bundle_path, format, &inner_pattern, &mut hashes, &event_sender).await;
+                }
+                // Check for control commands
+                Some(command) = command_receiver.recv() => match command {
+                    WatcherCommand::Stop => {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                    WatcherCommand::SetDebounce(new_debounce) => {
+                        debounce = new_debounce;
+                        if let Some(since) = changed_since {
+                            reload_at = Some(reload_deadline(since, debounce));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        log::debug!("Exiting bundle config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Bundle,
+        },
+        event_receiver,
+    ))
+}
+
+/// Returns false for events caused by reading the archive, including the watcher's own reads.
+fn modifies_bundle(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) => false,
+        _ => true,
+    }
+}
+
+/// Extracts the archive and emits the differences to the known documents.
+async fn sync_bundle(
+    bundle_path: &Path,
+    format: BundleFormat,
+    inner_pattern: &Pattern,
+    hashes: &mut HashMap<String, u64>,
+    event_sender: &EventSender,
+) {
+    let path = bundle_path.to_path_buf();
+    let pattern = inner_pattern.clone();
+    let entries =
+        match tokio::task::spawn_blocking(move || read_bundle(&path, format, &pattern)).await {
+            Ok(Ok(entries)) => entries,
+            Ok(Err(err)) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Ok(Err(err)) => {
+                log::error!("Cannot read bundle {:?}: {}", bundle_path, err);
+                return;
+            }
+            Err(err) => {
+                log::error!("Error extracting bundle {:?}: {}", bundle_path, err);
+                return;
+            }
+        };
+
+    let removed: Vec<String> = hashes
+        .keys()
+        .filter(|id| !entries.contains_key(*id))
+        .cloned()
+        .collect();
+    for id in removed {
+        remove_document(id, hashes, event_sender).await;
+    }
+    for (id, content) in entries {
+        update_document(id, content, hashes, event_sender).await;
+    }
+}
+
+/// Reads all files in the archive at `path` whose path matches `pattern`.
+fn read_bundle(
+    path: &Path,
+    format: BundleFormat,
+    pattern: &Pattern,
+) -> io::Result<BTreeMap<String, String>> {
+    let file = std::fs::File::open(path)?;
+    let mut entries = BTreeMap::new();
+
+    match format {
+        BundleFormat::Tar => read_tar(file, pattern, &mut entries)?,
+        BundleFormat::TarGz => read_tar(flate2::read::GzDecoder::new(file), pattern, &mut entries)?,
+        BundleFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+            for index in 0..archive.len() {
+                let mut entry = archive.by_index(index).map_err(io::Error::other)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry_id(entry.name());
+                if pattern.matches(&name) {
+                    let mut bytes = Vec::new();
+                    entry.read_to_end(&mut bytes)?;
+                    insert_entry(name, bytes, &mut entries);
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn read_tar(
+    reader: impl Read,
+    pattern: &Pattern,
+    entries: &mut BTreeMap<String, String>,
+) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(name) = entry.path()?.to_str().map(entry_id) else {
+            continue;
+        };
+        if pattern.matches(&name) {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            insert_entry(name, bytes, entries);
+        }
+    }
+    Ok(())
+}
+
+/// Archives created from a directory (`tar -C dir .`) prefix all paths with `./`.
+fn entry_id(name: &str) -> String {
+    name.strip_prefix("./").unwrap_or(name).to_string()
+}
+
+fn insert_entry(name: String, bytes: Vec<u8>, entries: &mut BTreeMap<String, String>) {
+    match String::from_utf8(bytes) {
+        Ok(content) => {
+            entries.insert(name, content);
+        }
+        Err(err) => {
+            log::warn!(
+                "Skipping bundle entry [{}] that is not valid UTF-8: {}",
+                name,
+                err
+            );
+        }
+    }
+}
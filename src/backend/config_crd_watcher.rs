@@ -0,0 +1,284 @@
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::NamespaceResourceScope;
+use kube::{api::Api, runtime::watcher, Client, Resource, ResourceExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+use super::{
+    command_channel, remove_document, sync_documents, update_document, wait_for_start,
+    DocumentEvent, EventMask, RetryDelay, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::{
+    clock::{default_clock, SharedClock},
+    WatcherError,
+};
+
+/// Selects the custom resources watched by [`run_crd_watcher`].
+#[derive(Clone, Debug)]
+pub enum ResourceSelector {
+    /// The resource with this name.
+    Name(String),
+    /// All resources matching this label selector (e.g. `app=my-service,tier=backend`).
+    Labels(String),
+}
+
+/// Optional settings for [`run_crd_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct CrdWatcherOptions {
+    /// Exit the watcher task when the Kubernetes watch stream ends instead of restarting it.
+    pub terminate_on_source_end: bool,
+    /// Delay before restarting the watch stream or retrying after an error (default 3 seconds
+    /// ± 20%).
+    pub retry_delay: RetryDelay,
+    /// Source of the time for the retry timer (default [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+impl Default for CrdWatcherOptions {
+    fn default() -> Self {
+        Self {
+            terminate_on_source_end: false,
+            retry_delay: RetryDelay::new(Duration::from_secs(3), 0.2),
+            clock: default_clock(),
+        }
+    }
+}
+
+/// Starts watching instances of the custom resource `K` in the given namespace.
+///
+/// Every selected resource is a document with the resource name as id and its `spec`,
+/// serialized as JSON, as content (resources without a `spec` are serialized completely).
+/// Updates that keep the `resourceVersion` are skipped, and updates that only touch other fields,
+/// such as the status, produce no events since the content is unchanged.
+///
+/// Like the ConfigMap watcher, the resources are re-listed whenever the Kubernetes watch is
+/// (re-)started and compared with the known documents, so resources deleted while the watch was
+/// interrupted are emitted as removed documents.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_crd_watcher<K>(
+    namespace: String,
+    selector: ResourceSelector,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + DeserializeOwned
+        + Serialize
+        + Clone
+        + Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    run_crd_watcher_with_options::<K>(namespace, selector, CrdWatcherOptions::default())
+}
+
+/// Same as [`run_crd_watcher`] but with additional [`CrdWatcherOptions`].
+pub fn run_crd_watcher_with_options<K>(
+    namespace: String,
+    selector: ResourceSelector,
+    options: CrdWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + DeserializeOwned
+        + Serialize
+        + Clone
+        + Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    spawn_crd_watcher::<K>(None, namespace, selector, options)
+}
+
+/// Same as [`run_crd_watcher_with_options`], but talks to the cluster through `client` instead of
+/// the default client, e.g. to use a custom configuration or a mocked API in tests.
+pub fn run_crd_watcher_with_client<K>(
+    client: Client,
+    namespace: String,
+    selector: ResourceSelector,
+    options: CrdWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + DeserializeOwned
+        + Serialize
+        + Clone
+        + Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    spawn_crd_watcher::<K>(Some(client), namespace, selector, options)
+}
+
+/// Spawns the watcher task, which creates the default client on start if `client` is `None`.
+fn spawn_crd_watcher<K>(
+    client: Option<Client>,
+    namespace: String,
+    selector: ResourceSelector,
+    options: CrdWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + DeserializeOwned
+        + Serialize
+        + Clone
+        + Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+        let client = match client {
+            Some(client) => client,
+            None => match Client::try_default().await {
+                Ok(client) => client,
+                Err(_) => {
+                    log::error!("Cannot create kubernetes client. CRD watcher will exit!");
+                    return Ok(());
+                }
+            },
+        };
+        let api: Api<K> = Api::namespaced(client, &namespace);
+        let config = match &selector {
+            ResourceSelector::Name(name) => {
+                watcher::Config::default().fields(format!("metadata.name={}", name).as_str())
+            }
+            ResourceSelector::Labels(labels) => watcher::Config::default().labels(labels),
+        };
+        let mut hashes: HashMap<String, u64> = HashMap::new();
+        // resourceVersion of every tracked resource
+        let mut versions: HashMap<String, String> = HashMap::new();
+        // Resources listed between `Init` and `InitDone` of a (re-)started watch
+        let mut init_buffer: Option<BTreeMap<String, String>> = None;
+        let mut initial_scan_done = false;
+
+        let mut stream = watcher(api.clone(), config.clone()).boxed();
+        loop {
+            tokio::select! {
+                event = stream.try_next() => {
+                    match event {
+                        Ok(Some(watcher::Event::Init)) => {
+                            init_buffer = Some(BTreeMap::new());
+                            versions.clear();
+                        }
+                        Ok(Some(watcher::Event::InitApply(resource))) => {
+                            let name = resource.name_any();
+                            if let Some(version) = resource.resource_version() {
+                                versions.insert(name.clone(), version);
+                            }
+                            if let Some(content) = resource_content(&resource) {
+                                match init_buffer.as_mut() {
+                                    Some(buffered) => {
+                                        buffered.insert(name, content);
+                                    }
+                                    None => update_document(name, content, &mut hashes, &event_sender).await,
+                                }
+                            }
+                        }
+                        Ok(Some(watcher::Event::InitDone)) => {
+                            // Diff the complete (re-)listed state against the known documents
+                            // so that resources deleted while the watch was down are reported too
+                            if let Some(buffered) = init_buffer.take() {
+                                let new_data = buffered
+                                    .iter()
+                                    .map(|(name, content)| (name.clone(), Cow::Borrowed(content.as_str())))
+                                    .collect();
+                                sync_documents(new_data, &mut hashes, &event_sender, EventMask::ALL).await;
+                            }
+                            if !initial_scan_done {
+                                initial_scan_done = true;
+                                event_sender
+                                    .send(DocumentEvent::InitialScanComplete)
+                                    .await
+                                    .ok();
+                            }
+                        }
+                        Ok(Some(watcher::Event::Apply(resource))) => {
+                            let name = resource.name_any();
+                            let version = resource.resource_version();
+                            if version.is_some() && versions.get(&name) == version.as_ref() {
+                                continue;
+                            }
+                            if let Some(version) = version {
+                                versions.insert(name.clone(), version);
+                            }
+                            if let Some(content) = resource_content(&resource) {
+                                update_document(name, content, &mut hashes, &event_sender).await;
+                            }
+                        }
+                        Ok(Some(watcher::Event::Delete(resource))) => {
+                            let name = resource.name_any();
+                            versions.remove(&name);
+                            remove_document(name, &mut hashes, &event_sender).await;
+                        }
+                        Ok(None) if options.terminate_on_source_end => {
+                            log::warn!("==> Kubernetes CRD Watcher stream has ended. There will not be any more config updates.");
+                            break;
+                        }
+                        Ok(None) => {
+                            log::warn!("==> Kubernetes CRD Watcher stream has ended. Restarting watch.");
+                            options.retry_delay.sleep_on(&*options.clock).await;
+                            stream = watcher(api.clone(), config.clone()).boxed();
+                        }
+                        Err(err) => {
+                            log::error!("==> Error in Kubernetes CRD Watcher: {}", err);
+                            options.retry_delay.sleep_on(&*options.clock).await;
+                        }
+                    }
+                },
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::CustomResource,
+        },
+        event_receiver,
+    ))
+}
+
+/// Serializes the `spec` of the resource, or the whole resource if it has none.
+fn resource_content<K: Resource + Serialize>(resource: &K) -> Option<String> {
+    let value = match serde_json::to_value(resource) {
+        Ok(serde_json::Value::Object(mut object)) => match object.remove("spec") {
+            Some(spec) => spec,
+            None => serde_json::Value::Object(object),
+        },
+        Ok(value) => value,
+        Err(err) => {
+            log::error!("Cannot serialize custom resource: {}", err);
+            return None;
+        }
+    };
+    serde_json::to_string(&value)
+        .inspect_err(|err| log::error!("Cannot serialize custom resource: {}", err))
+        .ok()
+}
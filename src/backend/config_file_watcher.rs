@@ -1,22 +1,338 @@
-use glob::Pattern;
+use futures::{Stream, StreamExt};
+use glob::{MatchOptions, Pattern};
 use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RemoveKind, RenameMode};
 use notify::EventKind;
 use notify::{RecursiveMode, Watcher};
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, BufReader};
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
 use tokio::task::{self};
+use tokio::time::{Duration, Instant};
 use walkdir::WalkDir;
 
-use super::{DocumentEvent, WatcherHandle};
+use super::{
+    command_channel, send_masked, wait_for_start, CommandReceiver, DebounceMetrics, DocumentEvent,
+    EventMask, EventSender, RetryDelay, SourceKind, WatcherHandle,
+};
 use crate::backend::WatcherCommand;
-use crate::{hash_str, WatcherError};
+use crate::{
+    clock::{default_clock, SharedClock},
+    hash_str, WatcherError,
+};
+
+/// Predicate over a file system event kind. See [`FileEventPolicy::Custom`].
+pub type FileEventPredicate = Arc<dyn Fn(&EventKind) -> bool + Send + Sync>;
+
+/// Selects which file system events cause a matching file to be read.
+///
+/// Removals and renames are always handled; the policy only decides which of the remaining
+/// events count as a (possible) content change.
+#[derive(Clone, Default)]
+pub enum FileEventPolicy {
+    /// Only `Access(Close(Write))`, i.e. a file that was opened for writing has been closed.
+    /// Avoids reading half-written files and duplicate reads, but this event is only reported
+    /// on Linux (inotify).
+    CloseWrite,
+    /// `Create(File)`, `Modify(Data)` and `Access(Close(Write))`, plus `Create(Any)` and
+    /// `Modify(Any)` as reported on Windows. A single write can trigger several reads; unchanged
+    /// content does not produce duplicate document events.
+    #[default]
+    Standard,
+    /// Read the file whenever the predicate returns `true`.
+    Custom(FileEventPredicate),
+}
+
+impl FileEventPolicy {
+    fn triggers_read(&self, kind: &EventKind) -> bool {
+        match self {
+            FileEventPolicy::CloseWrite => {
+                matches!(
+                    kind,
+                    EventKind::Access(AccessKind::Close(AccessMode::Write))
+                )
+            }
+            FileEventPolicy::Standard => matches!(
+                kind,
+                EventKind::Create(CreateKind::File | CreateKind::Any)
+                    | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+                    | EventKind::Access(AccessKind::Close(AccessMode::Write))
+            ),
+            FileEventPolicy::Custom(predicate) => predicate(kind),
+        }
+    }
+}
+
+impl std::fmt::Debug for FileEventPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileEventPolicy::CloseWrite => write!(f, "CloseWrite"),
+            FileEventPolicy::Standard => write!(f, "Standard"),
+            FileEventPolicy::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// Function returning the files included by a document, given its content and path.
+pub type IncludeFn = Arc<dyn Fn(&str, &Path) -> Vec<PathBuf> + Send + Sync>;
+
+/// Returns the files included by a document. See
+/// [`ConfigFileWatcherOptions::include_resolver`].
+#[derive(Clone)]
+pub struct IncludeResolver(pub IncludeFn);
+
+impl IncludeResolver {
+    pub fn new(resolve: impl Fn(&str, &Path) -> Vec<PathBuf> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(resolve))
+    }
+}
+
+impl std::fmt::Debug for IncludeResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IncludeResolver(..)")
+    }
+}
+
+/// Kind of content a file is required to have, see
+/// [`ConfigFileWatcherOptions::content_type_filter`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContentType {
+    /// YAML, including JSON, which is valid YAML.
+    Yaml,
+    /// JSON objects or arrays.
+    Json,
+}
+
+impl ContentType {
+    /// Classifies `text`, the beginning of a file, heuristically: a first character `{` or `[`
+    /// means JSON; a first line that is not empty or a comment and that starts a document (`---`,
+    /// `%YAML`), a sequence item (`- `) or a mapping entry (`key:`) means YAML. Returns `None`
+    /// otherwise, e.g. for plain text or files containing only comments.
+    pub fn detect(text: &str) -> Option<ContentType> {
+        let text = text.trim_start();
+        if text.starts_with(['{', '[']) {
+            return Some(ContentType::Json);
+        }
+        let line = text
+            .lines()
+            .map(str::trim_end)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+        let yaml = line.starts_with("---")
+            || line.starts_with("%YAML")
+            || line == "-"
+            || line.starts_with("- ")
+            || is_mapping_entry(line);
+        yaml.then_some(ContentType::Yaml)
+    }
+
+    /// Returns true if content of type `detected` satisfies this content type.
+    fn accepts(self, detected: ContentType) -> bool {
+        self == detected || (self == ContentType::Yaml && detected == ContentType::Json)
+    }
+}
+
+/// Returns true if `line` starts with a plain or quoted key followed by `:`.
+fn is_mapping_entry(line: &str) -> bool {
+    let key = match line.split_once(": ") {
+        Some((key, _)) => key,
+        None => match line.strip_suffix(':') {
+            Some(key) => key,
+            None => return false,
+        },
+    };
+    let quoted = key.len() >= 2
+        && ((key.starts_with('"') && key.ends_with('"'))
+            || (key.starts_with('\'') && key.ends_with('\'')));
+    quoted
+        || (!key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/')))
+}
+
+/// Order in which the files found by the initial scan are reported.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InitialOrder {
+    /// The order in which the directory walk returns the files, which depends on the file system.
+    #[default]
+    Unspecified,
+    /// Ascending by path.
+    PathAsc,
+    /// Descending by path.
+    PathDesc,
+    /// Oldest modification time first, ties ordered by path. Files whose modification time
+    /// cannot be read come first.
+    Mtime,
+}
+
+/// Optional settings for [`run_config_file_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct ConfigFileWatcherOptions {
+    /// Read at most this many bytes of each file. Only this prefix is hashed and emitted, so
+    /// changes after it do not produce events. Tokenizers only see the prefix as well; a document
+    /// cut off at the limit is passed to the deserializer incomplete.
+    pub read_limit: Option<usize>,
+    /// Capacity of the channel between the notify thread and the watcher task (default 100).
+    /// When it is full, the notify thread blocks until the watcher task catches up.
+    pub internal_channel_capacity: usize,
+    /// Capacity of the returned `DocumentEvent` channel (default 100). When it is full, the
+    /// watcher task waits for the consumer. Every buffered event holds a complete file content,
+    /// so large capacities can use considerable memory for big files.
+    pub event_channel_capacity: usize,
+    /// Which file system events cause a matching file to be read (default
+    /// [`FileEventPolicy::Standard`]).
+    pub event_policy: FileEventPolicy,
+    /// Do not read files during the initial scan. Their `NewDocument` events carry empty content
+    /// and the content can be fetched with [`WatcherHandle::load_document`], which also emits it
+    /// as `ContentChanged`. Files that change later are read and emitted as usual.
+    pub lazy: bool,
+    /// When a file changes, also re-read other tracked files that are hard links to the same
+    /// inode. File systems report a change only for the path that was written to. Only effective
+    /// on Unix.
+    pub track_hard_links: bool,
+    /// Encoding of the watched files (default UTF-8). Content is transcoded to UTF-8 before it is
+    /// hashed and emitted. Files starting with a byte order mark are decoded according to the
+    /// mark instead.
+    #[cfg(feature = "encoding")]
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    /// Collect file system events until none was received for this duration and process them
+    /// together (default none: events are processed immediately). Files are read when the events
+    /// are processed, so repeated writes within the window produce a single change. Can be
+    /// changed at runtime with [`WatcherHandle::set_debounce`].
+    pub debounce: Option<Duration>,
+    /// Process debounced events at the latest this long after the first of them arrived, even if
+    /// events keep arriving (default 10 seconds; `None`: wait until the events pause).
+    pub debounce_max_wait: Option<Duration>,
+    /// Process debounced events immediately once this many are buffered (default 10000).
+    pub debounce_max_events: usize,
+    /// Keep watching when a file cannot be read after the initial scan, and stop reading it after
+    /// this many consecutive failures (default none: a read error ends the watcher). Failures are
+    /// logged and, for tracked files, emitted as [`DocumentEvent::ReadFailed`]; reaching the limit
+    /// is logged once. The file is read again once it is created,
+    /// removed or renamed, its permissions change, the ignore file changes or it is loaded with
+    /// [`WatcherHandle::load_document`].
+    pub read_failure_limit: Option<u32>,
+    /// How often to check whether a missing watch path has been created (default 1 second
+    /// ± 20%). While the watch path is missing, at startup or after it was removed, no documents
+    /// are tracked; once it exists, it is scanned and its files are reported as new documents.
+    pub retry_delay: RetryDelay,
+    /// Hold back the removal of a file for this duration (default none: removals are reported
+    /// immediately). If the file reappears within the window, nothing is reported if its content
+    /// is unchanged, and only `ContentChanged` otherwise. Useful for tools that delete and
+    /// recreate files instead of writing them in place. Removals caused by the ignore file or by
+    /// removal of the watch path are reported immediately.
+    pub removal_grace: Option<Duration>,
+    /// Order of the `NewDocument` events of the initial scan (default
+    /// [`InitialOrder::Unspecified`]). Also applies when a removed watch path is scanned again.
+    pub initial_order: InitialOrder,
+    /// Number of files read concurrently during the initial scan (default 1). The events are
+    /// still emitted in the order given by `initial_order`.
+    pub initial_read_concurrency: usize,
+    /// Emit [`DocumentEvent::Touched`] when a tracked file is read again because of a file system
+    /// event and its content is unchanged, but its modification time is newer than at the
+    /// previous read, e.g. after `touch` or after saving without changes. Only events that cause
+    /// a read count, see `event_policy`; with [`FileEventPolicy::Standard`] a `touch` is reported
+    /// on Linux only.
+    pub emit_on_touch: bool,
+    /// Resolves the files a document includes, e.g. from `include:` directives. A document is
+    /// emitted with the content of its included files appended in the returned order, each
+    /// starting on a new line. Includes are resolved recursively and each file is included at most
+    /// once; relative paths are resolved against the directory of the including file.
+    ///
+    /// Included files are watched as well, also outside the watch path, as long as their
+    /// directory exists. When one of them changes, the documents including it are read again.
+    /// Included files that cannot be read are logged and skipped.
+    pub include_resolver: Option<IncludeResolver>,
+    /// Handle directories written like Kubernetes ConfigMap and Secret volumes (default `true`).
+    /// Such a directory holds the files as symbolic links through a `..data` symbolic link to a
+    /// hidden timestamped directory, and is updated by atomically replacing `..data`. Files below
+    /// entries starting with `..` are not tracked, and when a `..data` symbolic link is replaced,
+    /// the files in its directory are read again.
+    pub handle_data_swap: bool,
+    /// Only track files whose content is of this type (default none: all files matching the
+    /// pattern are tracked). The first [`CONTENT_SNIFF_LIMIT`] bytes of a file matching the
+    /// pattern are classified with [`ContentType::detect`] before it is read, so files without
+    /// an extension can be selected by their content. Binary files and files that cannot be
+    /// decoded are skipped without an error. A tracked file whose content no longer matches is
+    /// reported as removed.
+    pub content_type_filter: Option<ContentType>,
+    /// Treat zero-byte files as not present (default `false`), e.g. because editors create them
+    /// before writing the content. They are skipped by the initial scan and reported as new once
+    /// they have content. A tracked file that is truncated to zero bytes is reported as removed,
+    /// after the `removal_grace` if set, so a truncate followed by a write within the grace period
+    /// is reported as a single change.
+    pub ignore_empty_files: bool,
+    /// Guard against reading files that are still being written (default none). When set, every
+    /// file is read again after this delay until two consecutive reads return the same content,
+    /// which is then emitted. Content that still differs after [`STABLE_READ_ATTEMPTS`] reads is
+    /// not emitted and a warning is logged; the next change of the file starts over. Reads
+    /// failing in between, e.g. because a multi-byte character is only partially written, are
+    /// retried as well, and the error of the last attempt is handled like any read error.
+    ///
+    /// After the initial scan, the delays run alongside other events and commands. During the
+    /// initial scan every read waits for at least one delay, and files that do not settle are
+    /// reported as new documents once they do.
+    pub stable_read: Option<Duration>,
+    /// Categories of events to emit (default [`EventMask::ALL`]). Suppressed changes are still
+    /// recorded, so a later `ContentChanged` is only emitted for content that differs from the
+    /// latest read.
+    pub event_mask: EventMask,
+    /// Source of the time for the debounce, removal grace and retry timers (default
+    /// [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+
+impl Default for ConfigFileWatcherOptions {
+    fn default() -> Self {
+        Self {
+            read_limit: None,
+            internal_channel_capacity: 100,
+            event_channel_capacity: 100,
+            event_policy: FileEventPolicy::default(),
+            lazy: false,
+            track_hard_links: false,
+            #[cfg(feature = "encoding")]
+            encoding: None,
+            debounce: None,
+            debounce_max_wait: Some(Duration::from_secs(10)),
+            debounce_max_events: 10_000,
+            read_failure_limit: None,
+            retry_delay: RetryDelay::new(Duration::from_secs(1), 0.2),
+            removal_grace: None,
+            initial_order: InitialOrder::default(),
+            initial_read_concurrency: 1,
+            emit_on_touch: false,
+            include_resolver: None,
+            handle_data_swap: true,
+            content_type_filter: None,
+            ignore_empty_files: false,
+            stable_read: None,
+            event_mask: EventMask::ALL,
+            clock: default_clock(),
+        }
+    }
+}
 
 /// Starts watching the directory for changes in a background task.
 ///
+/// The watch path is canonicalized when the watcher starts, so document ids are absolute paths
+/// without `.`/`..` components or symbolic links, also when `watch_path` is relative (e.g. `.`).
+/// Paths of file system events and of [`WatcherHandle::load_document`] are normalized lexically,
+/// so differently spelled paths of a file map to the same document.
+///
+/// `file_pattern` is matched against the path relative to the watch path. Without `/` it matches
+/// files at any depth; with `/`, wildcards match within a path segment and `**` matches any number
+/// of directories, e.g. `**/secrets/*.yaml`.
+///
+/// The file system watch is registered before the initial scan. Events that arrive during the
+/// scan are buffered and processed after `InitialScanComplete`, so a file modified while the
+/// scan runs is reported as `ContentChanged` unless the scan already read the new content.
+///
 /// # Returns
 /// A tuple containing:
 /// * A `ConfigFileWatcherHandle` for the background watcher task.
@@ -25,82 +341,558 @@ pub fn run_config_file_watcher<P: AsRef<Path>>(
     watch_path: P,
     file_pattern: impl Into<String>,
 ) -> Result<(WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>), WatcherError> {
-    let (event_sender, event_receiver) = mpsc::channel(100);
-    let (command_sender, mut command_receiver) = mpsc::channel(1);
+    run_config_file_watcher_with_options(
+        watch_path,
+        file_pattern,
+        ConfigFileWatcherOptions::default(),
+    )
+}
+
+/// Same as [`run_config_file_watcher`] but with additional [`ConfigFileWatcherOptions`].
+pub fn run_config_file_watcher_with_options<P: AsRef<Path>>(
+    watch_path: P,
+    file_pattern: impl Into<String>,
+    options: ConfigFileWatcherOptions,
+) -> Result<(WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) =
+        command_receiver.event_channel(options.event_channel_capacity);
 
     let watch_path = watch_path.as_ref().to_path_buf();
-    let file_pattern = file_pattern.into();
+    let file_patterns = FilePatterns::new(&file_pattern.into())?;
 
     let handle = tokio::spawn(async move {
         // Wait for a start command before we begin
-        match command_receiver.recv().await {
-            Some(WatcherCommand::Stop) | None => {
-                // Exit early if Stop command is received or channel is closed
-                log::info!("Watcher received stop command before starting or channel closed");
-                return Ok(());
-            }
-            _ => {}
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
         }
 
-        // Compute initial file hashes
-        let mut file_hashes =
-            initial_file_search(&watch_path, &file_pattern, &event_sender).await?;
+        let (wh, rx) = AsyncWatcherHandler::with_capacity(options.internal_channel_capacity);
+        let bridge = wh.tx.downgrade();
+        let watcher = notify::recommended_watcher(wh)?;
+        let state = FileWatcher::new(watch_path, file_patterns, options, event_sender).await;
+        let events = notify_events(rx).map(|event| Ok(vec![event]));
+        watch_files(state, Some(watcher), events, Some(bridge), command_receiver).await
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::File,
+        },
+        event_receiver,
+    ))
+}
 
-        let (wh, mut rx) = AsyncWatcherHandler::new();
-        let mut watcher = notify::recommended_watcher(wh)?;
+/// A batch of file system events, or the errors that occurred instead, as fed to
+/// [`run_config_file_watcher_from_stream`].
+pub type FileEventBatch = Result<Vec<notify::Event>, Vec<notify::Error>>;
 
-        watcher.watch(&watch_path, RecursiveMode::Recursive)?;
-        let gp = Pattern::new(&file_pattern)?;
+/// Same as [`run_config_file_watcher_with_options`], but processes the event batches of `events`
+/// instead of watching the file system.
+///
+/// The events of a batch are processed together, like a debounced batch: each affected file is
+/// read at most once. Errors are logged. The watch path is still scanned initially and the files
+/// the events refer to are read from disk, so synthetic events can drive the watcher
+/// deterministically, e.g. in tests or for sources notify does not support. The watcher ends
+/// when the stream ends.
+pub fn run_config_file_watcher_from_stream<P, S>(
+    watch_path: P,
+    file_pattern: impl Into<String>,
+    options: ConfigFileWatcherOptions,
+    events: S,
+) -> Result<(WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    P: AsRef<Path>,
+    S: Stream<Item = FileEventBatch> + Send + Unpin + 'static,
+{
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) =
+        command_receiver.event_channel(options.event_channel_capacity);
 
-        loop {
-            tokio::select! {
-                // Process file system events
-                Some(res) = rx.recv() => {
-                    handle_fs_event(res, &mut file_hashes, &event_sender, &watch_path, &gp).await?;
-                }
+    let watch_path = watch_path.as_ref().to_path_buf();
+    let file_patterns = FilePatterns::new(&file_pattern.into())?;
 
-                // Check for control commands
-                Some(command) = command_receiver.recv() => {
-                    if let WatcherCommand::Stop = command {
-                        log::info!("Watcher received stop command");
-                        break;
-                    }
-                }
-            }
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
         }
 
-        log::debug!("Exiting ConfigFileWatcher loop");
-
-        Ok(())
+        let state = FileWatcher::new(watch_path, file_patterns, options, event_sender).await;
+        watch_files(
+            state,
+            None::<notify::RecommendedWatcher>,
+            events,
+            None,
+            command_receiver,
+        )
+        .await
     });
 
     Ok((
         WatcherHandle {
             command_sender,
             handle: Some(handle),
+            source_kind: SourceKind::File,
         },
         event_receiver,
     ))
 }
 
-/// Recursively walks the specified path and collects files matching the specified pattern.
+/// Turns the receiver of an [`AsyncWatcherHandler`] into a stream.
+fn notify_events(
+    mut rx: mpsc::Receiver<notify::Event>,
+) -> impl Stream<Item = notify::Event> + Send + Unpin {
+    futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+}
+
+/// Scans the watch path and processes `events` and commands until the watcher is stopped. If a
+/// `watcher` is given, it is pointed at the watch path whenever the path (re)appears. `bridge` is
+/// the sending side of the channel `events` are received from, if any, to report its depth.
+async fn watch_files<W, S>(
+    mut state: FileWatcher,
+    mut watcher: Option<W>,
+    mut events: S,
+    bridge: Option<mpsc::WeakSender<notify::Event>>,
+    mut command_receiver: CommandReceiver,
+) -> Result<(), WatcherError>
+where
+    W: Watcher,
+    S: Stream<Item = FileEventBatch> + Unpin,
+{
+    // Compute initial file hashes
+    // When to check again whether the missing watch path has been (re)created; set once per
+    // check, so that other events do not postpone it
+    let mut root_retry_at = None;
+    if !state.watch_path.exists() {
+        root_retry_at = Some(state.retry_deadline());
+        log::warn!(
+            "Watch path {:?} does not exist, waiting for it to be created",
+            state.watch_path
+        );
+    } else {
+        // Watch before scanning: changes made while the scan runs queue up in `events` and are
+        // only processed after `InitialScanComplete`, against the hashes the scan recorded
+        if let Some(watcher) = watcher.as_mut() {
+            watcher.watch(&state.watch_path, RecursiveMode::Recursive)?;
+        }
+        state.initial_file_search().await?;
+    }
+    state
+        .event_sender
+        .send(DocumentEvent::InitialScanComplete)
+        .await
+        .ok();
+
+    let mut pending_events = Vec::new();
+    let mut flush_at: Option<Instant> = None;
+    let mut pending_since: Option<Instant> = None;
+    // Wall-clock time of the latest pending event, the events of a batch are stamped with it
+    let mut pending_observed_at: Option<SystemTime> = None;
+    let mut metrics = DebounceMetrics::default();
+    loop {
+        if let Some(watcher) = watcher.as_mut() {
+            state.watch_includes(watcher);
+        }
+        let removal_due = state.pending_removals.values().min().copied();
+        let stable_read_due = state.stable_reads.values().map(|read| read.due).min();
+        tokio::select! {
+            // Process file system events
+            batch = events.next() => {
+                let mut batch = match batch {
+                    Some(Ok(batch)) => batch,
+                    Some(Err(errors)) => {
+                        for err in errors {
+                            log::error!("Error watching files: {}", err);
+                        }
+                        continue;
+                    }
+                    None => {
+                        log::info!("File event stream ended");
+                        break;
+                    }
+                };
+                let received_at = SystemTime::now();
+                metrics.raw_events_total += batch.len() as u64;
+                // A file is tracked under one spelling of its path, whatever the source reported
+                for event in &mut batch {
+                    for path in &mut event.paths {
+                        *path = normalize_path(path);
+                    }
+                }
+                if batch.iter().any(|event| event.paths.contains(&state.watch_path))
+                    && !state.watch_path.exists()
+                {
+                    pending_events.clear();
+                    flush_at = None;
+                    pending_since = None;
+                    pending_observed_at = None;
+                    state.event_sender.observed_at = Some(received_at);
+                    state.root_removed().await;
+                    state.event_sender.observed_at = None;
+                    root_retry_at = Some(state.retry_deadline());
+                    continue;
+                }
+                match state.options.debounce.filter(|debounce| !debounce.is_zero()) {
+                    Some(debounce) => {
+                        let now = state.options.clock.now();
+                        let since = *pending_since.get_or_insert(now);
+                        pending_events.extend(batch);
+                        pending_observed_at = Some(received_at);
+                        flush_at = Some(flush_deadline(&state.options, pending_events.len(), since, now, debounce));
+                    }
+                    None => {
+                        metrics.debounced_batches_total += 1;
+                        state.event_sender.observed_at = Some(received_at);
+                        match <[notify::Event; 1]>::try_from(batch) {
+                            Ok([event]) => state.handle_fs_event(event).await?,
+                            Err(batch) => state.handle_fs_batch(batch).await?,
+                        }
+                        state.event_sender.observed_at = None;
+                    }
+                }
+            }
+
+            // Report removals whose grace period ended
+            _ = state.options.clock.sleep_until(removal_due.unwrap_or_else(|| state.options.clock.now())), if removal_due.is_some() => {
+                state.expire_removals().await?;
+            }
+
+            // Read files again whose content may have settled
+            _ = state.options.clock.sleep_until(stable_read_due.unwrap_or_else(|| state.options.clock.now())), if stable_read_due.is_some() => {
+                state.expire_stable_reads().await?;
+            }
+
+            // Check whether a missing watch path has been (re)created
+            _ = state.options.clock.sleep_until(root_retry_at.unwrap_or_else(|| state.options.clock.now())), if root_retry_at.is_some() => {
+                root_retry_at = if state.root_restored(watcher.as_mut()).await? {
+                    None
+                } else {
+                    Some(state.retry_deadline())
+                };
+            }
+
+            // Process debounced events once no further event arrived
+            _ = state.options.clock.sleep_until(flush_at.unwrap_or_else(|| state.options.clock.now())), if flush_at.is_some() => {
+                flush_at = None;
+                pending_since = None;
+                metrics.debounced_batches_total += 1;
+                state.event_sender.observed_at = pending_observed_at.take();
+                state.handle_fs_batch(std::mem::take(&mut pending_events)).await?;
+                state.event_sender.observed_at = None;
+            }
+
+            // Check for control commands
+            Some(command) = command_receiver.recv() => {
+                match command {
+                    WatcherCommand::Stop => {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                    WatcherCommand::LoadDocument(id, reply_sender) => {
+                        reply_sender.send(state.load_document(&id).await).ok();
+                    }
+                    WatcherCommand::RefreshDocument(id, reply_sender) => {
+                        reply_sender.send(state.refresh_document(&id).await).ok();
+                    }
+                    WatcherCommand::DebounceMetrics(reply_sender) => {
+                        let internal_channel_depth = bridge
+                            .as_ref()
+                            .and_then(mpsc::WeakSender::upgrade)
+                            .map_or(0, |tx| tx.max_capacity() - tx.capacity());
+                        reply_sender
+                            .send(DebounceMetrics {
+                                internal_channel_depth,
+                                ..metrics
+                            })
+                            .ok();
+                    }
+                    WatcherCommand::SetDebounce(debounce) => {
+                        state.options.debounce = Some(debounce);
+                        if let Some(since) = pending_since {
+                            let now = state.options.clock.now();
+                            flush_at = Some(flush_deadline(&state.options, pending_events.len(), since, now, debounce));
+                        }
+                    }
+                    WatcherCommand::Start | WatcherCommand::EmitTimestamps => {}
+                }
+            }
+        }
+    }
+
+    log::debug!("Exiting ConfigFileWatcher loop");
+
+    Ok(())
+}
+
+/// Name of the file in the watch path listing glob patterns of files to ignore.
+const IGNORE_FILE_NAME: &str = ".configwatcherignore";
+
+/// State of a running file watcher task.
+struct FileWatcher {
+    watch_path: PathBuf,
+    file_patterns: FilePatterns,
+    options: ConfigFileWatcherOptions,
+    /// Content hashes of all tracked files.
+    file_hashes: HashMap<PathBuf, u64>,
+    /// Paths to reconcile at the end of the debounced batch being processed, see
+    /// [`handle_fs_batch`](Self::handle_fs_batch).
+    deferred_paths: Option<Vec<PathBuf>>,
+    /// Consecutive read failures per path, see [`ConfigFileWatcherOptions::read_failure_limit`].
+    read_failures: HashMap<PathBuf, u32>,
+    /// Tracked files whose last read failed, see [`DocumentEvent::ReadFailed`].
+    unreadable: HashSet<PathBuf>,
+    /// Removed files that are still tracked during the `removal_grace`, and when it ends.
+    pending_removals: HashMap<PathBuf, Instant>,
+    /// Files waiting for their content to settle, see [`ConfigFileWatcherOptions::stable_read`].
+    stable_reads: HashMap<PathBuf, StableRead>,
+    /// Modification times of tracked files at their last read, see
+    /// [`ConfigFileWatcherOptions::emit_on_touch`].
+    modified: HashMap<PathBuf, SystemTime>,
+    /// Files included by each tracked file, see [`ConfigFileWatcherOptions::include_resolver`].
+    includes: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Directories outside the watch path that are watched for included files.
+    include_dirs: HashSet<PathBuf>,
+    event_sender: EventSender,
+}
+
+/// A file read with [`ConfigFileWatcherOptions::stable_read`] whose content has not settled yet.
+struct StableRead {
+    /// When the file is read again.
+    due: Instant,
+    /// Hash of the content of the previous read, `None` if it failed.
+    hash: Option<u64>,
+    /// Number of reads so far.
+    reads: usize,
+    /// Emit the settled content even if it is unchanged, see
+    /// [`refresh_document`](FileWatcher::refresh_document).
+    force: bool,
+}
+
+impl StableRead {
+    fn new(due: Instant, hash: Option<u64>, force: bool) -> Self {
+        Self {
+            due,
+            hash,
+            reads: 0,
+            force,
+        }
+    }
+}
+
+/// A set of glob patterns of which at least one has to match a path, and none of the ignore
+/// patterns.
+///
+/// Brace groups are expanded before compiling, so `*.{yaml,yml}` results in the patterns
+/// `*.yaml` and `*.yml`.
+///
+/// Patterns are matched against the path relative to the watch path. A pattern without `/`
+/// matches files at any depth, since its wildcards also match `/`: `*.yaml` matches `a.yaml` and
+/// `sub/a.yaml`. In a pattern with `/`, `*`, `?` and `[...]` match within a single path
+/// segment, and a `**` segment matches any number of directories, including none: `**/sub/*.yaml`
+/// matches `sub/a.yaml` and `a/b/sub/a.yaml`, but not `sub/c/a.yaml` or `mysub/a.yaml`.
+#[derive(Clone, Debug)]
+struct FilePatterns {
+    patterns: Vec<Pattern>,
+    /// Patterns from the ignore file. Patterns without `/` also match the file name.
+    ignored: Vec<Pattern>,
+    /// Reject paths of and below entries starting with `..`, see
+    /// [`ConfigFileWatcherOptions::handle_data_swap`].
+    skip_data_dirs: bool,
+}
+
+impl FilePatterns {
+    fn new(file_pattern: &str) -> Result<Self, WatcherError> {
+        let patterns = expand_braces(file_pattern)
+            .iter()
+            .map(|pattern| Pattern::new(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            patterns,
+            ignored: Vec::new(),
+            skip_data_dirs: false,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(path, pattern_match_options(pattern)))
+            && !self.is_ignored(path)
+            && !(self.skip_data_dirs && is_below_data_dir(path))
+    }
+
+    fn is_ignored(&self, path: &str) -> bool {
+        let file_name = Path::new(path).file_name().and_then(|name| name.to_str());
+        self.ignored.iter().any(|pattern| {
+            pattern.matches(path)
+                || (!pattern.as_str().contains('/')
+                    && file_name.is_some_and(|name| pattern.matches(name)))
+        })
+    }
+}
+
+/// Returns when `pending` debounced events, the first of which arrived at `since`, are processed:
+/// `debounce` after the latest event, but no later than `debounce_max_wait` after the first, and
+/// immediately once `debounce_max_events` are buffered.
+fn flush_deadline(
+    options: &ConfigFileWatcherOptions,
+    pending: usize,
+    since: Instant,
+    now: Instant,
+    debounce: Duration,
+) -> Instant {
+    if pending >= options.debounce_max_events {
+        return now;
+    }
+    let deadline = now + debounce;
+    match options.debounce_max_wait {
+        Some(max_wait) => deadline.min(since + max_wait),
+        None => deadline,
+    }
+}
+
+/// Returns the options to match a file pattern with, see [`FilePatterns`]. Wildcards only match
+/// `/` in patterns without `/`, which keeps `*.yaml` matching files in subdirectories.
+fn pattern_match_options(pattern: &Pattern) -> MatchOptions {
+    MatchOptions {
+        require_literal_separator: pattern.as_str().contains('/'),
+        ..MatchOptions::new()
+    }
+}
+
+/// Name of the symbolic link to the current data directory in a Kubernetes volume.
+const DATA_LINK_NAME: &str = "..data";
+
+/// Returns true if a component of the relative `path` starts with `..`, like the `..data` link and
+/// the timestamped data directories of a Kubernetes volume.
+fn is_below_data_dir(path: &str) -> bool {
+    Path::new(path).components().any(|component| {
+        matches!(component, std::path::Component::Normal(name)
+            if name.to_str().is_some_and(|name| name.starts_with("..")))
+    })
+}
+
+/// Parses the content of an ignore file: one glob pattern per line, blank lines and lines
+/// starting with `#` are skipped. Invalid patterns are logged and skipped.
+fn parse_ignore_patterns(content: &str) -> Vec<Pattern> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| match Pattern::new(line) {
+            Ok(pattern) => Some(pattern),
+            Err(err) => {
+                log::warn!("Skipping invalid ignore pattern [{}]: {}", line, err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Expands brace groups (`{a,b}`) in a glob pattern into all alternatives. Groups may be nested.
+/// Unbalanced braces are kept as they are.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    start = i;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let prefix = &pattern[..start];
+                    let suffix = &pattern[i + 1..];
+                    return split_alternatives(&pattern[start + 1..i])
+                        .into_iter()
+                        .flat_map(|alt| expand_braces(&format!("{prefix}{alt}{suffix}")))
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+    }
+    vec![pattern.to_string()]
+}
+
+/// Splits the content of a brace group at all commas that are not part of a nested group.
+fn split_alternatives(group: &str) -> Vec<&str> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in group.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&group[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&group[start..]);
+    alternatives
+}
+
+/// Sorts the files of the initial scan according to `order`.
+async fn sort_files(mut files: Vec<PathBuf>, order: InitialOrder) -> Vec<PathBuf> {
+    match order {
+        InitialOrder::Unspecified => {}
+        InitialOrder::PathAsc => files.sort(),
+        InitialOrder::PathDesc => files.sort_by(|a, b| b.cmp(a)),
+        InitialOrder::Mtime => {
+            let mut timed = Vec::with_capacity(files.len());
+            for file in files {
+                let modified = tokio::fs::metadata(&file)
+                    .await
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                timed.push((modified, file));
+            }
+            timed.sort();
+            files = timed.into_iter().map(|(_, file)| file).collect();
+        }
+    }
+    files
+}
+
+/// Recursively walks the specified path and collects files matching the specified patterns.
 ///
 /// # Arguments
 /// * `watch_path` - The path to search for files.
-/// * `file_pattern` - The glob pattern for matching files.
+/// * `file_patterns` - The glob patterns for matching files.
 ///
 /// # Returns
 /// A list of paths matching the given criteria.
 async fn find_matching_files<P: AsRef<Path>>(
     watch_path: P,
-    file_pattern: &str,
+    file_patterns: &FilePatterns,
 ) -> Result<Vec<PathBuf>, WatcherError> {
-    let watch_path = watch_path.as_ref().to_path_buf();
-    let gp = Pattern::new(file_pattern)?;
+    walk_matching_files(watch_path.as_ref(), watch_path.as_ref(), file_patterns).await
+}
+
+/// Walks `scan_path`, which has to be located below `watch_path`, and collects files whose path
+/// relative to `watch_path` matches the specified patterns.
+async fn walk_matching_files(
+    watch_path: &Path,
+    scan_path: &Path,
+    file_patterns: &FilePatterns,
+) -> Result<Vec<PathBuf>, WatcherError> {
+    let watch_path = watch_path.to_path_buf();
+    let scan_path = scan_path.to_path_buf();
+    let gp = file_patterns.clone();
 
     task::spawn_blocking(move || {
         let mut matching_files = Vec::new();
-        for entry in WalkDir::new(&watch_path).into_iter().filter_map(|e| e.ok()) {
+        for entry in WalkDir::new(&scan_path).into_iter().filter_map(|e| e.ok()) {
             let path = entry.path();
             if path.is_file() {
                 if let Ok(Some(file_name)) = path.strip_prefix(&watch_path).map(|f| f.to_str()) {
@@ -116,200 +908,1068 @@ async fn find_matching_files<P: AsRef<Path>>(
     .unwrap_or(Ok(vec![]))
 }
 
-/// Computes a hash for each file matching the given pattern in the specified path.
-///
-/// # Arguments
-/// * `watch_path` - The path to search for files.
-/// * `file_pattern` - The glob pattern for matching files.
-/// * `sender` - Sender channel to notify about found files
-///
-/// # Returns
-/// A `HashMap` where the keys are file paths and the values are their respective hashes.
-async fn initial_file_search<P: AsRef<Path>>(
-    watch_path: P,
-    file_pattern: &str,
-    sender: &mpsc::Sender<DocumentEvent>,
-) -> Result<HashMap<PathBuf, u64>, WatcherError> {
-    let files = find_matching_files(watch_path, file_pattern).await?;
-
-    let mut file_hashes = HashMap::new();
-    for file in files {
-        let content = read_file(&file).await?;
-        file_hashes.insert(file.clone(), hash_str(&content));
-        sender
-            .send(DocumentEvent::NewDocument(
-                file.to_string_lossy().into_owned(),
-                content,
-            ))
+impl FileWatcher {
+    async fn new(
+        watch_path: PathBuf,
+        file_patterns: FilePatterns,
+        options: ConfigFileWatcherOptions,
+        event_sender: EventSender,
+    ) -> Self {
+        // notify reports absolute paths, which only match the watch path as a prefix if it is
+        // absolute and normalized as well
+        let watch_path = match tokio::fs::canonicalize(&watch_path).await {
+            Ok(canonical_path) => canonical_path,
+            Err(err) => {
+                log::warn!("Cannot canonicalize watch path {:?}: {}", watch_path, err);
+                watch_path
+            }
+        };
+
+        let mut state = FileWatcher {
+            watch_path,
+            file_patterns,
+            options,
+            file_hashes: HashMap::new(),
+            deferred_paths: None,
+            read_failures: HashMap::new(),
+            unreadable: HashSet::new(),
+            pending_removals: HashMap::new(),
+            stable_reads: HashMap::new(),
+            modified: HashMap::new(),
+            includes: HashMap::new(),
+            include_dirs: HashSet::new(),
+            event_sender,
+        };
+        state.file_patterns.ignored = state.read_ignore_file().await;
+        state.file_patterns.skip_data_dirs = state.options.handle_data_swap;
+        state
+    }
+
+    /// Reads all files matching the patterns below the watch path, records their hashes and
+    /// emits a `NewDocument` event for each of them.
+    ///
+    /// The watch path is watched before, so changes during the scan are buffered as file system
+    /// events and processed afterwards against the recorded state: a file changed after it was
+    /// read is reported as changed, one read after the change produces no further event, and
+    /// files that vanished between listing and reading are skipped.
+    async fn initial_file_search(&mut self) -> Result<(), WatcherError> {
+        let files = find_matching_files(&self.watch_path, &self.file_patterns).await?;
+        let files = sort_files(files, self.options.initial_order).await;
+
+        // Files are read ahead concurrently, `buffered` yields them in the sorted order
+        let options = &self.options;
+        let mut contents = futures::stream::iter(files)
+            .map(|file| async move {
+                if is_ignored_empty(&file, options).await
+                    || !content_type_matches(&file, options).await
+                {
+                    return None;
+                }
+                // In lazy mode the empty placeholder content is what consumers have seen
+                let content = if options.lazy {
+                    Ok(Some((String::new(), Vec::new())))
+                } else {
+                    read_settled_document(&file, options).await
+                };
+                // Taken after the read, so a write in between is detected as a change later
+                let modified = match options.emit_on_touch {
+                    true => tokio::fs::metadata(&file)
+                        .await
+                        .and_then(|metadata| metadata.modified())
+                        .ok(),
+                    false => None,
+                };
+                Some((file, content, modified))
+            })
+            .buffered(options.initial_read_concurrency.max(1));
+
+        while let Some(next) = contents.next().await {
+            let Some((file, content, modified)) = next else {
+                continue;
+            };
+            let (content, includes) = match content {
+                Ok(Some(content)) => content,
+                // Still being written; reported as new once its content settles
+                Ok(None) => {
+                    log::warn!(
+                        "Content of {:?} did not settle after {} reads, reading it again later",
+                        file,
+                        STABLE_READ_ATTEMPTS
+                    );
+                    self.stable_reads
+                        .insert(file, StableRead::new(self.options.clock.now(), None, false));
+                    continue;
+                }
+                // Removed since it was listed; its removal event finds it untracked
+                Err(err) if is_not_found(&err) => {
+                    log::debug!("File {:?} was removed during the scan", file);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if let Some(modified) = modified {
+                self.modified.insert(file.clone(), modified);
+            }
+            if !includes.is_empty() {
+                self.includes.insert(file.clone(), includes);
+            }
+            self.file_hashes.insert(file.clone(), hash_str(&content));
+            send_masked(
+                &self.event_sender,
+                self.options.event_mask,
+                DocumentEvent::NewDocument(file.to_string_lossy().into_owned(), content),
+            )
             .await
             .map_err(|_| WatcherError::Notify(notify::Error::generic("Failed to send event")))?;
+        }
+
+        Ok(())
     }
 
-    Ok(file_hashes)
-}
+    /// Reads the tracked file at `path` with its includes and records the included files.
+    async fn read_document(&mut self, path: &Path) -> Result<String, WatcherError> {
+        let (content, includes) = read_document(path, &self.options).await?;
+        self.set_includes(path, includes);
+        Ok(content)
+    }
 
-async fn read_file(path: &Path) -> Result<String, WatcherError> {
-    let file = File::open(path)
-        .await
-        .map_err(|e| WatcherError::FileReadError(path.to_path_buf(), e))?;
+    fn set_includes(&mut self, path: &Path, includes: Vec<PathBuf>) {
+        if includes.is_empty() {
+            self.includes.remove(path);
+        } else {
+            let includes = includes
+                .iter()
+                .map(|include| normalize_path(include))
+                .collect();
+            self.includes.insert(path.to_path_buf(), includes);
+        }
+    }
 
-    let mut reader = BufReader::new(file);
+    /// Watches the directories of included files outside the watch path, which the recursive
+    /// watch does not cover, and stops watching directories that are no longer needed.
+    fn watch_includes(&mut self, watcher: &mut impl Watcher) {
+        if self.options.include_resolver.is_none() {
+            return;
+        }
+        let dirs: HashSet<PathBuf> = self
+            .includes
+            .values()
+            .flatten()
+            .filter(|path| !path.starts_with(&self.watch_path))
+            .filter_map(|path| path.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in self.include_dirs.difference(&dirs) {
+            watcher.unwatch(dir).ok();
+        }
+        for dir in dirs.difference(&self.include_dirs) {
+            if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                log::warn!(
+                    "Cannot watch directory {:?} of included files: {}",
+                    dir,
+                    err
+                );
+            }
+        }
+        self.include_dirs = dirs;
+    }
 
-    let mut content = String::new();
+    /// Reconciles the files in `dir` after its `..data` symbolic link was replaced: tracked files
+    /// are read again or removed, new matching files are read.
+    async fn data_swapped(&mut self, dir: &Path) -> Result<(), WatcherError> {
+        if !dir.starts_with(&self.watch_path) {
+            return Ok(());
+        }
+        log::debug!("Data link in {:?} was replaced, reading its files", dir);
+        let tracked: Vec<PathBuf> = self
+            .file_hashes
+            .keys()
+            .filter(|path| path.starts_with(dir))
+            .cloned()
+            .collect();
+        for path in tracked {
+            self.sync_file(&path).await?;
+        }
+        for path in walk_matching_files(&self.watch_path, dir, &self.file_patterns).await? {
+            if !self.file_hashes.contains_key(&path) {
+                self.update_file(&path).await?;
+            }
+        }
+        Ok(())
+    }
 
-    reader
-        .read_to_string(&mut content)
-        .await
-        .map_err(|e| WatcherError::FileReadError(path.to_path_buf(), e))?;
+    /// Reads the tracked files that include one of `paths` again.
+    async fn update_including(&mut self, paths: &[PathBuf]) -> Result<(), WatcherError> {
+        let including: Vec<PathBuf> = self
+            .includes
+            .iter()
+            .filter(|(_, includes)| includes.iter().any(|include| paths.contains(include)))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in including {
+            self.sync_file(&path).await?;
+        }
+        Ok(())
+    }
 
-    Ok(content)
-}
+    /// Reads a tracked file on request and records its hash. Content that differs from what was
+    /// emitted before, like the placeholder of a lazily scanned file, is also emitted as
+    /// `ContentChanged`, so that consumers of the event channel see it too.
+    async fn load_document(&mut self, id: &str) -> Result<String, WatcherError> {
+        let path = normalize_path(Path::new(id));
+        let Some(&previous_hash) = self.file_hashes.get(&path) else {
+            return Err(WatcherError::DocumentUnavailable(id.to_string()));
+        };
+        let content = self.read_document(&path).await?;
+        self.read_failures.remove(&path);
+        let new_hash = hash_str(&content);
+        self.file_hashes.insert(path.clone(), new_hash);
+        if new_hash != previous_hash {
+            send_masked(
+                &self.event_sender,
+                self.options.event_mask,
+                DocumentEvent::ContentChanged(path.to_string_lossy().into_owned(), content.clone()),
+            )
+            .await
+            .ok();
+        }
+        Ok(content)
+    }
 
-/// Processes file system events.
-async fn handle_fs_event(
-    //res: Result<Vec<DebouncedEvent>, Vec<notify::Error>>,
-    event: notify::Event,
-    file_hashes: &mut HashMap<PathBuf, u64>,
-    event_sender: &tokio::sync::mpsc::Sender<DocumentEvent>,
-    watch_path: &PathBuf,
-    gp: &Pattern,
-) -> Result<(), WatcherError> {
-    //log::debug!("EVENT: {:?}", event);
-    if match_path(watch_path, gp, &event) {
-        match event.kind {
-            EventKind::Create(CreateKind::File)
-            | EventKind::Modify(ModifyKind::Data(_))
-            | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
-                if let Some(path) = event.paths.first() {
-                    let content = read_file(path).await?;
-                    // Compute the new hash for the file
-                    let new_hash = hash_str(&content);
-
-                    if let Some(existing_hash) = file_hashes.get(path) {
-                        // File exists: Check if the hash has changed
-                        if existing_hash != &new_hash {
-                            // Content changed: Update the hash and emit `ContentChanged`
-                            file_hashes.insert(path.to_path_buf(), new_hash);
-                            event_sender
-                                .send(DocumentEvent::ContentChanged(
-                                    path.to_string_lossy().into_owned(),
-                                    content,
-                                ))
-                                .await
-                                .unwrap();
-                        }
-                    } else {
-                        // File does not exist in `file_hashes`: It's a new file
-                        file_hashes.insert(path.to_path_buf(), new_hash);
-                        event_sender
-                            .send(DocumentEvent::NewDocument(
-                                path.to_string_lossy().into_owned(),
-                                content,
-                            ))
-                            .await
-                            .unwrap();
-                    }
+    /// Reads the document `id` again and emits its current state, even if its content is
+    /// unchanged. A tracked file that no longer exists is reported as removed right away.
+    async fn refresh_document(&mut self, id: &str) -> Result<(), WatcherError> {
+        let path = normalize_path(Path::new(id));
+        let tracked = self.file_hashes.contains_key(&path);
+        if !path.is_file() {
+            if !tracked {
+                return Err(WatcherError::DocumentUnavailable(id.to_string()));
+            }
+            self.forget_file(&path).await;
+            return Ok(());
+        }
+        if !tracked && !path_matches(&self.watch_path, &self.file_patterns, &path) {
+            return Err(WatcherError::DocumentUnavailable(id.to_string()));
+        }
+        // A refresh retries files whose reads were suspended
+        self.read_failures.remove(&path);
+        self.read_file_changes(&path, true).await
+    }
+
+    /// Reads the ignore patterns from the ignore file in the watch path, if present.
+    async fn read_ignore_file(&self) -> Vec<Pattern> {
+        let path = self.watch_path.join(IGNORE_FILE_NAME);
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => parse_ignore_patterns(&content),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                log::warn!("Cannot read ignore file {:?}: {}", path, err);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Re-reads the ignore file and reconciles the tracked files with the new patterns: files
+    /// that are ignored now are reported as removed, files that are no longer ignored as new.
+    async fn reload_ignore_file(&mut self) -> Result<(), WatcherError> {
+        let ignored = self.read_ignore_file().await;
+        if ignored == self.file_patterns.ignored {
+            return Ok(());
+        }
+        log::info!("Ignore file in {:?} changed", self.watch_path);
+        self.file_patterns.ignored = ignored;
+        self.read_failures.clear();
+
+        let files = find_matching_files(&self.watch_path, &self.file_patterns).await?;
+        let removed: Vec<PathBuf> = self
+            .file_hashes
+            .keys()
+            .filter(|path| !files.contains(path))
+            .cloned()
+            .collect();
+        for path in removed {
+            self.forget_file(&path).await;
+        }
+        for path in files {
+            if !self.file_hashes.contains_key(&path) {
+                self.update_file(&path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports all tracked files as removed after the watch path itself was removed, e.g. because
+    /// a volume was unmounted.
+    async fn root_removed(&mut self) {
+        log::warn!(
+            "Watch path {:?} was removed, waiting for it to be recreated",
+            self.watch_path
+        );
+        let paths: Vec<PathBuf> = self.file_hashes.keys().cloned().collect();
+        for path in paths {
+            self.forget_file(&path).await;
+        }
+        self.read_failures.clear();
+        self.stable_reads.clear();
+    }
+
+    /// Returns when to check next whether the missing watch path has been (re)created.
+    fn retry_deadline(&self) -> Instant {
+        self.options.clock.now() + self.options.retry_delay.sample()
+    }
+
+    /// Watches and scans the watch path again if it exists. Returns false if it is still missing.
+    async fn root_restored(
+        &mut self,
+        watcher: Option<&mut impl Watcher>,
+    ) -> Result<bool, WatcherError> {
+        let Ok(watch_path) = tokio::fs::canonicalize(&self.watch_path).await else {
+            return Ok(false);
+        };
+        self.watch_path = watch_path;
+        if let Some(watcher) = watcher {
+            // notify still knows the removed path; a failure here only means it already forgot it
+            watcher.unwatch(&self.watch_path).ok();
+            if let Err(err) = watcher.watch(&self.watch_path, RecursiveMode::Recursive) {
+                log::warn!("Cannot watch {:?}: {}", self.watch_path, err);
+                return Ok(false);
+            }
+        }
+        log::info!("Watch path {:?} was created, scanning it", self.watch_path);
+
+        // Watch before scanning, so that files created in between are not missed. Their events
+        // are processed after the scan and find the files already tracked.
+        self.file_patterns.ignored = self.read_ignore_file().await;
+        self.initial_file_search().await?;
+        Ok(true)
+    }
+
+    /// Processes a debounced batch of events, reading each affected file at most once.
+    ///
+    /// The events are handled in order, but instead of reading or removing files, the affected
+    /// paths are collected. Afterwards each path is reconciled with its final state on disk: read
+    /// if it is a matching file, removed otherwise. A file moved into place and modified within
+    /// the batch is thus read once, and the stored hash always belongs to its final content,
+    /// whatever order the events arrived in.
+    async fn handle_fs_batch(&mut self, events: Vec<notify::Event>) -> Result<(), WatcherError> {
+        self.deferred_paths = Some(Vec::new());
+        for event in events {
+            self.handle_fs_event(event).await?;
+        }
+
+        let mut reconciled = HashSet::new();
+        for path in self.deferred_paths.take().unwrap_or_default() {
+            if !reconciled.insert(path.clone()) {
+                continue;
+            }
+            if path.is_file() && path_matches(&self.watch_path, &self.file_patterns, &path) {
+                self.update_file(&path).await?;
+            } else {
+                self.remove_file(&path).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes file system events.
+    async fn handle_fs_event(&mut self, event: notify::Event) -> Result<(), WatcherError> {
+        //log::debug!("EVENT: {:?}", event);
+
+        let ignore_file = self.watch_path.join(IGNORE_FILE_NAME);
+        if event.paths.contains(&ignore_file) {
+            self.reload_ignore_file().await?;
+        }
+
+        if !self.read_failures.is_empty() {
+            self.reset_read_failures(&event).await?;
+        }
+
+        // Renamed directories are reported with the directory path only, which usually does not
+        // match the file pattern. Reconcile the files below them first.
+        if let EventKind::Modify(ModifyKind::Name(mode)) = event.kind {
+            self.handle_dir_rename(mode, &event.paths).await?;
+        }
+
+        // Files created in a new directory before the watch on it was registered produce no
+        // events of their own
+        if let (EventKind::Create(CreateKind::Folder | CreateKind::Any), [dir, ..]) =
+            (event.kind, &event.paths[..])
+        {
+            if dir.is_dir() {
+                for path in walk_matching_files(&self.watch_path, dir, &self.file_patterns).await? {
+                    self.update_file(&path).await?;
                 }
             }
-            EventKind::Remove(RemoveKind::File) => {
-                if let Some(path) = event.paths.first() {
-                    if file_hashes.remove(path).is_some() {
-                        event_sender
-                            .send(DocumentEvent::DocumentRemoved(
-                                path.to_string_lossy().into_owned(),
-                            ))
-                            .await
-                            .unwrap();
+        }
+
+        if self.options.handle_data_swap {
+            if let Some(dir) = data_swap_dir(&event) {
+                self.data_swapped(dir).await?;
+            }
+        }
+
+        if !self.includes.is_empty()
+            && (matches!(
+                event.kind,
+                EventKind::Create(_)
+                    | EventKind::Remove(_)
+                    | EventKind::Modify(ModifyKind::Name(_))
+            ) || self.options.event_policy.triggers_read(&event.kind))
+        {
+            self.update_including(&event.paths).await?;
+        }
+
+        if match_path(&self.watch_path, &self.file_patterns, &event) {
+            match event.kind {
+                // Windows reports removals without the kind of the removed entry. FSEvents
+                // (macOS) coalesces events, so the path may have been recreated since.
+                EventKind::Remove(RemoveKind::File | RemoveKind::Any) => {
+                    if let Some(path) = event.paths.first() {
+                        self.sync_file(path).await?;
                     }
                 }
-            }
-            EventKind::Modify(ModifyKind::Name(mode)) => {
-                match mode {
-                    RenameMode::To => {
-                        if let Some(path) = event.paths.first() {
-                            let content = read_file(path).await?;
-                            // Compute the new hash for the file
-                            let new_hash = hash_str(&content);
-
-                            if let Some(existing_hash) = file_hashes.get(path) {
-                                // File exists: Check if the hash has changed
-                                if existing_hash != &new_hash {
-                                    // Content changed: Update the hash and emit `ContentChanged`
-                                    file_hashes.insert(path.to_path_buf(), new_hash);
-                                    event_sender
-                                        .send(DocumentEvent::ContentChanged(
-                                            path.to_string_lossy().into_owned(),
-                                            content,
-                                        ))
-                                        .await
-                                        .unwrap();
+                EventKind::Modify(ModifyKind::Name(mode)) => {
+                    match mode {
+                        RenameMode::To => {
+                            if let Some(path) = event.paths.first() {
+                                self.update_file(path).await?;
+                            }
+                        }
+                        RenameMode::From => {
+                            if let Some(path) = event.paths.first() {
+                                self.remove_file(path).await;
+                            }
+                        }
+                        RenameMode::Both => {
+                            if let [from, to, ..] = &event.paths[..] {
+                                self.remove_file(from).await;
+                                if path_matches(&self.watch_path, &self.file_patterns, to)
+                                    && to.is_file()
+                                {
+                                    self.update_file(to).await?;
                                 }
-                            } else {
-                                // File does not exist in `file_hashes`: It's a new file
-                                file_hashes.insert(path.to_path_buf(), new_hash);
-                                event_sender
-                                    .send(DocumentEvent::NewDocument(
-                                        path.to_string_lossy().into_owned(),
-                                        content,
-                                    ))
-                                    .await
-                                    .unwrap();
                             }
                         }
-                    }
-                    RenameMode::From => {
-                        if let Some(path) = event.paths.first() {
-                            if file_hashes.remove(path).is_some() {
-                                event_sender
-                                    .send(DocumentEvent::DocumentRemoved(
-                                        path.to_string_lossy().into_owned(),
-                                    ))
-                                    .await
-                                    .unwrap();
+                        // FSEvents (macOS) does not tell whether a path was renamed from or to.
+                        RenameMode::Any | RenameMode::Other => {
+                            for path in &event.paths {
+                                if path_matches(&self.watch_path, &self.file_patterns, path) {
+                                    self.sync_file(path).await?;
+                                }
                             }
                         }
                     }
-                    RenameMode::Both => {
-                        if let [from, to, ..] = &event.paths[..] {
-                            // Remove the hash for the `from` file
-                            if file_hashes.remove(from).is_some() {
-                                event_sender
-                                    .send(DocumentEvent::DocumentRemoved(
-                                        from.to_string_lossy().into_owned(),
-                                    ))
-                                    .await
-                                    .unwrap();
-
-                                // Compute the hash for the `to` file to check for changes
-                                let content = read_file(from).await?;
-
-                                // Compute the new hash for the file
-                                let new_hash = hash_str(&content);
-
-                                file_hashes.insert(to.to_path_buf(), new_hash);
-                                event_sender
-                                    .send(DocumentEvent::NewDocument(
-                                        to.to_string_lossy().into_owned(),
-                                        content,
-                                    ))
-                                    .await
-                                    .unwrap();
-                            }
+                }
+                kind if self.options.event_policy.triggers_read(&kind) => {
+                    // Coalesced events may refer to files that are already gone again, and
+                    // Windows does not tell files and directories apart.
+                    if let Some(path) = event.paths.first().filter(|path| path.is_file()) {
+                        self.update_file(path).await?;
+                        if self.options.track_hard_links {
+                            self.update_hard_links(path).await?;
                         }
                     }
-                    _ => {
-                        //log::debug!("Unhandled Rename Event: {:?}", event);
+                }
+                _ => {
+                    // log::debug!("Unhandled Event: {:?}", event);
+                }
+            }
+        } else {
+            // log::debug!("Filepattern not matched for event:  {:?}", event);
+        }
+        Ok(())
+    }
+
+    /// Reconciles tracked files when a directory below the watch path is renamed.
+    ///
+    /// Files tracked below a directory that was renamed away are reported as removed. Matching
+    /// files below a directory that was renamed into place are read and reported as new (or
+    /// changed, if they are already tracked).
+    async fn handle_dir_rename(
+        &mut self,
+        mode: RenameMode,
+        paths: &[PathBuf],
+    ) -> Result<(), WatcherError> {
+        let (from, to) = match (mode, paths) {
+            (RenameMode::From, [from, ..]) => (Some(from), None),
+            (RenameMode::To, [to, ..]) => (None, Some(to)),
+            (RenameMode::Both, [from, to, ..]) => (Some(from), Some(to)),
+            (RenameMode::Any | RenameMode::Other, [path, ..]) if path.is_dir() => {
+                (None, Some(path))
+            }
+            (RenameMode::Any | RenameMode::Other, [path, ..]) => (Some(path), None),
+            _ => return Ok(()),
+        };
+
+        if let Some(from) = from {
+            let moved: Vec<PathBuf> = self
+                .file_hashes
+                .keys()
+                .filter(|path| path.starts_with(from) && *path != from)
+                .cloned()
+                .collect();
+            for path in moved {
+                self.remove_file(&path).await;
+            }
+        }
+
+        if let Some(to) = to.filter(|to| to.is_dir()) {
+            for path in walk_matching_files(&self.watch_path, to, &self.file_patterns).await? {
+                self.update_file(&path).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the file at `path` if it exists and removes it otherwise. Used for events that do
+    /// not reliably tell whether the file is still there.
+    async fn sync_file(&mut self, path: &Path) -> Result<(), WatcherError> {
+        if path.is_file() {
+            self.update_file(path).await
+        } else {
+            self.remove_file(path).await;
+            Ok(())
+        }
+    }
+
+    /// Reads the file at `path` and emits `NewDocument` if it is not tracked yet or
+    /// `ContentChanged` if its content hash changed, or `Touched` if only its modification time
+    /// changed and `emit_on_touch` is set.
+    /// Deferred while a debounced batch is processed.
+    async fn update_file(&mut self, path: &Path) -> Result<(), WatcherError> {
+        self.read_file_changes(path, false).await
+    }
+
+    /// Implements [`update_file`](Self::update_file). With `force`, `ContentChanged` is also
+    /// emitted for unchanged content.
+    async fn read_file_changes(&mut self, path: &Path, force: bool) -> Result<(), WatcherError> {
+        if let Some(deferred_paths) = self.deferred_paths.as_mut() {
+            deferred_paths.push(path.to_path_buf());
+            return Ok(());
+        }
+        if self.reads_suspended(path) {
+            return Ok(());
+        }
+        if is_ignored_empty(path, &self.options).await {
+            if self.file_hashes.contains_key(path) {
+                log::debug!("File {:?} is empty, treating it as removed", path);
+                self.remove_file(path).await;
+            }
+            return Ok(());
+        }
+        if !content_type_matches(path, &self.options).await {
+            if self.file_hashes.contains_key(path) {
+                log::info!("Content type of {:?} no longer matches", path);
+                self.forget_file(path).await;
+            }
+            return Ok(());
+        }
+        if let Some(delay) = self.options.stable_read {
+            // Starts over if the file changed again while it was settling
+            let force = force || self.stable_reads.get(path).is_some_and(|read| read.force);
+            let mut read = StableRead::new(self.options.clock.now() + delay, None, force);
+            read.reads = 1;
+            match self.read_document(path).await {
+                Ok(content) => read.hash = Some(hash_str(&content)),
+                Err(err) if is_not_found(&err) => {
+                    self.stable_reads.remove(path);
+                    return Ok(());
+                }
+                Err(_) => {}
+            }
+            self.stable_reads.insert(path.to_path_buf(), read);
+            return Ok(());
+        }
+        match self.read_document(path).await {
+            Ok(content) => self.apply_read(path, content, force).await,
+            // Removed since the event was reported; its removal event follows
+            Err(err) if is_not_found(&err) => {
+                log::debug!("File {:?} was removed before it was read", path);
+                Ok(())
+            }
+            Err(err) => self.read_failed(path, err).await,
+        }
+    }
+
+    /// Reads the files whose `stable_read` delay ended again. Settled content is emitted, files
+    /// whose content still changes are read again after the delay, up to
+    /// [`STABLE_READ_ATTEMPTS`] reads.
+    async fn expire_stable_reads(&mut self) -> Result<(), WatcherError> {
+        let now = self.options.clock.now();
+        let due: Vec<PathBuf> = self
+            .stable_reads
+            .iter()
+            .filter(|(_, read)| read.due <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in due {
+            let Some(mut read) = self.stable_reads.remove(&path) else {
+                continue;
+            };
+            read.reads += 1;
+            let result = self.read_document(&path).await;
+            let hash = result.as_ref().ok().map(|content| hash_str(content));
+            match result {
+                Err(err) if is_not_found(&err) => {}
+                Ok(content) if hash == read.hash => {
+                    self.apply_read(&path, content, read.force).await?;
+                }
+                Err(err) if read.reads >= STABLE_READ_ATTEMPTS => {
+                    self.read_failed(&path, err).await?;
+                }
+                _ if read.reads >= STABLE_READ_ATTEMPTS => {
+                    log::warn!(
+                        "Content of {:?} did not settle after {} reads, not emitting it",
+                        path,
+                        read.reads
+                    );
+                }
+                _ => {
+                    read.hash = hash;
+                    read.due = now + self.options.stable_read.unwrap_or_default();
+                    self.stable_reads.insert(path, read);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the successfully read `content` of `path` and emits it if it is new or changed,
+    /// or if `force` is set.
+    async fn apply_read(
+        &mut self,
+        path: &Path,
+        content: String,
+        force: bool,
+    ) -> Result<(), WatcherError> {
+        self.read_failures.remove(path);
+        self.pending_removals.remove(path);
+        // Compute the new hash for the file
+        let new_hash = hash_str(&content);
+        let touched = self.record_modified(path).await;
+        // A successful read after a reported failure is always emitted
+        let recovered = self.unreadable.remove(path);
+
+        if let Some(existing_hash) = self.file_hashes.get(path) {
+            // File exists: Check if the hash has changed
+            if existing_hash != &new_hash || recovered || force {
+                // Content changed: Update the hash and emit `ContentChanged`
+                self.file_hashes.insert(path.to_path_buf(), new_hash);
+                send_masked(
+                    &self.event_sender,
+                    self.options.event_mask,
+                    DocumentEvent::ContentChanged(path.to_string_lossy().into_owned(), content),
+                )
+                .await
+                .ok();
+            } else if touched {
+                send_masked(
+                    &self.event_sender,
+                    self.options.event_mask,
+                    DocumentEvent::Touched(path.to_string_lossy().into_owned()),
+                )
+                .await
+                .ok();
+            }
+        } else {
+            // File does not exist in `file_hashes`: It's a new file
+            self.file_hashes.insert(path.to_path_buf(), new_hash);
+            send_masked(
+                &self.event_sender,
+                self.options.event_mask,
+                DocumentEvent::NewDocument(path.to_string_lossy().into_owned(), content),
+            )
+            .await
+            .ok();
+        }
+        Ok(())
+    }
+
+    /// Records the modification time of `path` if `emit_on_touch` is set. Returns true if it
+    /// differs from the time recorded at the previous read, or if none was recorded.
+    async fn record_modified(&mut self, path: &Path) -> bool {
+        if !self.options.emit_on_touch {
+            return false;
+        }
+        match tokio::fs::metadata(path)
+            .await
+            .and_then(|metadata| metadata.modified())
+        {
+            Ok(modified) => self.modified.insert(path.to_path_buf(), modified) != Some(modified),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns true if reading `path` failed `read_failure_limit` times in a row.
+    fn reads_suspended(&self, path: &Path) -> bool {
+        self.options.read_failure_limit.is_some_and(|limit| {
+            self.read_failures
+                .get(path)
+                .is_some_and(|failures| *failures >= limit)
+        })
+    }
+
+    /// Counts a failed read of `path`. Returns the error if no `read_failure_limit` is set.
+    async fn read_failed(&mut self, path: &Path, err: WatcherError) -> Result<(), WatcherError> {
+        let Some(limit) = self.options.read_failure_limit else {
+            return Err(err);
+        };
+        let failures = self.read_failures.entry(path.to_path_buf()).or_default();
+        *failures += 1;
+        if *failures >= limit {
+            log::error!(
+                "{} ({} failures in a row); not reading {:?} again until it is recreated or its permissions change",
+                err,
+                failures,
+                path
+            );
+        } else {
+            log::warn!("{}", err);
+        }
+        if self.file_hashes.contains_key(path) {
+            self.unreadable.insert(path.to_path_buf());
+            send_masked(
+                &self.event_sender,
+                self.options.event_mask,
+                DocumentEvent::ReadFailed(path.to_string_lossy().into_owned(), err),
+            )
+            .await
+            .ok();
+        }
+        Ok(())
+    }
+
+    /// Resets the read failures of the paths of `event` if it may have fixed them: the path was
+    /// created, removed or renamed, or its metadata changed. In the latter case, a file whose
+    /// reads were suspended is read again right away, since metadata changes do not trigger reads.
+    async fn reset_read_failures(&mut self, event: &notify::Event) -> Result<(), WatcherError> {
+        match event.kind {
+            EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_)) => {
+                for path in &event.paths {
+                    self.read_failures.remove(path);
+                }
+            }
+            EventKind::Modify(ModifyKind::Metadata(_)) => {
+                for path in &event.paths {
+                    let suspended = self.reads_suspended(path);
+                    self.read_failures.remove(path);
+                    if suspended
+                        && path.is_file()
+                        && path_matches(&self.watch_path, &self.file_patterns, path)
+                    {
+                        self.update_file(path).await?;
                     }
                 }
             }
-            _ => {
-                // log::debug!("Unhandled Event: {:?}", event);
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Updates all other tracked files that share the inode of `path`.
+    async fn update_hard_links(&mut self, path: &Path) -> Result<(), WatcherError> {
+        let Some(inode) = hard_linked_inode(path) else {
+            return Ok(());
+        };
+        let linked: Vec<PathBuf> = self
+            .file_hashes
+            .keys()
+            .filter(|other| *other != path && hard_linked_inode(other) == Some(inode))
+            .cloned()
+            .collect();
+        for other in linked {
+            self.update_file(&other).await?;
+        }
+        Ok(())
+    }
+
+    /// Stops tracking the file at `path` and emits `DocumentRemoved` if it was tracked, once the
+    /// `removal_grace` has passed. Deferred while a debounced batch is processed.
+    async fn remove_file(&mut self, path: &Path) {
+        if let Some(deferred_paths) = self.deferred_paths.as_mut() {
+            deferred_paths.push(path.to_path_buf());
+            return;
+        }
+        match self.options.removal_grace.filter(|grace| !grace.is_zero()) {
+            Some(grace) if self.file_hashes.contains_key(path) => {
+                self.pending_removals
+                    .entry(path.to_path_buf())
+                    .or_insert_with(|| self.options.clock.now() + grace);
+            }
+            _ => self.forget_file(path).await,
+        }
+    }
+
+    /// Reports the removals whose grace period ended. Files that exist again, but whose events
+    /// have not been processed yet, are read instead.
+    async fn expire_removals(&mut self) -> Result<(), WatcherError> {
+        let now = self.options.clock.now();
+        let due: Vec<PathBuf> = self
+            .pending_removals
+            .iter()
+            .filter(|(_, due)| **due <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in due {
+            self.pending_removals.remove(&path);
+            if path.is_file()
+                && path_matches(&self.watch_path, &self.file_patterns, &path)
+                && !is_ignored_empty(&path, &self.options).await
+            {
+                self.update_file(&path).await?;
+            } else {
+                self.forget_file(&path).await;
             }
         }
+        Ok(())
+    }
+
+    /// Stops tracking the file at `path` and emits `DocumentRemoved` if it was tracked.
+    async fn forget_file(&mut self, path: &Path) {
+        self.pending_removals.remove(path);
+        self.stable_reads.remove(path);
+        self.modified.remove(path);
+        self.includes.remove(path);
+        self.unreadable.remove(path);
+        if self.file_hashes.remove(path).is_some() {
+            send_masked(
+                &self.event_sender,
+                self.options.event_mask,
+                DocumentEvent::DocumentRemoved(path.to_string_lossy().into_owned()),
+            )
+            .await
+            .ok();
+        }
+    }
+}
+
+/// Returns the directory of the `..data` symbolic link if `event` created it or renamed it into
+/// place.
+fn data_swap_dir(event: &notify::Event) -> Option<&Path> {
+    let path = match (event.kind, &event.paths[..]) {
+        (EventKind::Create(_), [path, ..])
+        | (EventKind::Modify(ModifyKind::Name(RenameMode::To)), [path, ..])
+        | (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [_, path, ..])
+        | (EventKind::Modify(ModifyKind::Name(RenameMode::Any | RenameMode::Other)), [path, ..]) => {
+            path
+        }
+        _ => return None,
+    };
+    (path.file_name()? == DATA_LINK_NAME).then(|| path.parent())?
+}
+
+/// Returns the device and inode of `path` if it has more than one hard link.
+#[cfg(unix)]
+fn hard_linked_inode(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn hard_linked_inode(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Reads the document at `path` and appends the content of the files it includes, see
+/// [`ConfigFileWatcherOptions::include_resolver`]. Returns the content and the included files,
+/// including those that could not be read.
+async fn read_document(
+    path: &Path,
+    options: &ConfigFileWatcherOptions,
+) -> Result<(String, Vec<PathBuf>), WatcherError> {
+    let mut content = read_file(path, options).await?;
+    let Some(resolver) = &options.include_resolver else {
+        return Ok((content, Vec::new()));
+    };
+
+    // Depth first, so that the includes of a file follow its content
+    let mut included = Vec::new();
+    let mut seen = HashSet::from([path.to_path_buf()]);
+    let mut stack = resolve_includes(resolver, &content, path).await;
+    stack.reverse();
+    while let Some(include) = stack.pop() {
+        if !seen.insert(include.clone()) {
+            continue;
+        }
+        match read_file(&include, options).await {
+            Ok(include_content) => {
+                let mut nested = resolve_includes(resolver, &include_content, &include).await;
+                nested.reverse();
+                stack.extend(nested);
+                if !content.is_empty() && !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str(&include_content);
+            }
+            Err(err) => log::warn!("Skipping file included by {:?}: {}", path, err),
+        }
+        included.push(include);
+    }
+    Ok((content, included))
+}
+
+/// Returns the files included by `content` of the file at `path`, relative paths resolved against
+/// its directory. Existing files are canonicalized to match the paths of file system events.
+async fn resolve_includes(resolver: &IncludeResolver, content: &str, path: &Path) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut includes = Vec::new();
+    for include in (resolver.0)(content, path) {
+        let include = dir.join(include);
+        includes.push(tokio::fs::canonicalize(&include).await.unwrap_or(include));
+    }
+    includes
+}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Number of bytes read to classify a file, see [`ConfigFileWatcherOptions::content_type_filter`].
+pub const CONTENT_SNIFF_LIMIT: usize = 4096;
+
+/// Returns true if the content of the file at `path` satisfies the `content_type_filter`. Files
+/// that cannot be read are accepted, so that the error surfaces when they are read.
+async fn content_type_matches(path: &Path, options: &ConfigFileWatcherOptions) -> bool {
+    let Some(content_type) = options.content_type_filter else {
+        return true;
+    };
+    let options = ConfigFileWatcherOptions {
+        read_limit: Some(CONTENT_SNIFF_LIMIT),
+        ..options.clone()
+    };
+    match read_file(path, &options).await {
+        Ok(text) => {
+            let detected = ContentType::detect(&text);
+            log::debug!("Content type of {:?}: {:?}", path, detected);
+            detected.is_some_and(|detected| content_type.accepts(detected))
+        }
+        Err(WatcherError::BinaryFile(_)) => {
+            log::debug!("Skipping binary file {:?}", path);
+            false
+        }
+        Err(_) => true,
+    }
+}
+
+/// Returns true if the file at `path` has zero bytes and `ignore_empty_files` is set.
+async fn is_ignored_empty(path: &Path, options: &ConfigFileWatcherOptions) -> bool {
+    options.ignore_empty_files
+        && tokio::fs::metadata(path)
+            .await
+            .is_ok_and(|metadata| metadata.len() == 0)
+}
+
+/// Maximum number of reads of a file until its content is stable, see
+/// [`ConfigFileWatcherOptions::stable_read`].
+pub const STABLE_READ_ATTEMPTS: usize = 5;
+
+/// Reads a document with [`read_document`]. With `stable_read`, it is read again after the delay
+/// until two consecutive reads agree. Returns `None` if they still differ after
+/// [`STABLE_READ_ATTEMPTS`] reads, and the error of the last read if it failed.
+async fn read_settled_document(
+    path: &Path,
+    options: &ConfigFileWatcherOptions,
+) -> Result<Option<(String, Vec<PathBuf>)>, WatcherError> {
+    let mut previous = read_document(path, options).await;
+    let Some(delay) = options.stable_read else {
+        return previous.map(Some);
+    };
+    for _ in 1..STABLE_READ_ATTEMPTS {
+        // A missing file does not appear by waiting
+        if matches!(&previous, Err(err) if is_not_found(err)) {
+            return previous.map(Some);
+        }
+        options.clock.sleep(delay).await;
+        let next = read_document(path, options).await;
+        if matches!((&previous, &next), (Ok((previous, _)), Ok((next, _))) if previous == next) {
+            return next.map(Some);
+        }
+        previous = next;
+    }
+    previous.map(|_| None)
+}
+
+/// Returns true if `err` is a read error of a file that does not exist.
+fn is_not_found(err: &WatcherError) -> bool {
+    matches!(err, WatcherError::FileReadError(_, err) if err.kind() == io::ErrorKind::NotFound)
+}
+
+/// Reads a file to a string. If `read_limit` is set, at most that many bytes are read; a
+/// character cut off at the limit is dropped. A leading byte order mark is removed.
+///
+/// Content that cannot be decoded or that contains NUL bytes is rejected with
+/// [`WatcherError::BinaryFile`].
+async fn read_file(
+    path: &Path,
+    options: &ConfigFileWatcherOptions,
+) -> Result<String, WatcherError> {
+    let file = File::open(path)
+        .await
+        .map_err(|e| WatcherError::FileReadError(path.to_path_buf(), e))?;
+
+    let mut reader = BufReader::new(file);
+    let mut bytes = Vec::new();
+    match options.read_limit {
+        Some(read_limit) => reader.take(read_limit as u64).read_to_end(&mut bytes).await,
+        None => reader.read_to_end(&mut bytes).await,
+    }
+    .map_err(|e| WatcherError::FileReadError(path.to_path_buf(), e))?;
+    let truncated = options.read_limit.is_some_and(|limit| bytes.len() >= limit);
+
+    #[cfg(feature = "encoding")]
+    if let Some(encoding) = options.encoding {
+        return decode_with_encoding(path, &bytes, truncated, encoding);
+    }
+
+    if bytes.starts_with(UTF8_BOM) {
+        bytes.drain(..UTF8_BOM.len());
+    }
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        // Incomplete sequence at the end: truncated by the read limit
+        Err(err) if truncated && err.utf8_error().error_len().is_none() => {
+            let valid_up_to = err.utf8_error().valid_up_to();
+            let mut bytes = err.into_bytes();
+            bytes.truncate(valid_up_to);
+            String::from_utf8(bytes).expect("truncated to valid UTF-8")
+        }
+        Err(_) => return Err(WatcherError::BinaryFile(path.to_path_buf())),
+    };
+    reject_binary(path, content)
+}
+
+/// Decodes `bytes` from `encoding` (or the encoding of a byte order mark) to UTF-8. If the read
+/// was `truncated`, a character cut off at the end is dropped.
+#[cfg(feature = "encoding")]
+fn decode_with_encoding(
+    path: &Path,
+    bytes: &[u8],
+    truncated: bool,
+    encoding: &'static encoding_rs::Encoding,
+) -> Result<String, WatcherError> {
+    let mut decoder = encoding.new_decoder();
+    let mut content = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length_without_replacement(bytes.len())
+            .unwrap_or(bytes.len()),
+    );
+    let (result, _) = decoder.decode_to_string_without_replacement(bytes, &mut content, !truncated);
+    match result {
+        encoding_rs::DecoderResult::InputEmpty => reject_binary(path, content),
+        _ => Err(WatcherError::BinaryFile(path.to_path_buf())),
+    }
+}
+
+/// Rejects content with NUL bytes, which do not occur in text files.
+fn reject_binary(path: &Path, content: String) -> Result<String, WatcherError> {
+    if content.contains('\0') {
+        Err(WatcherError::BinaryFile(path.to_path_buf()))
     } else {
-        // log::debug!("Filepattern not matched for event:  {:?}", event);
+        Ok(content)
     }
-    Ok(())
 }
 
 pub struct AsyncWatcherHandler {
@@ -319,7 +1979,12 @@ pub struct AsyncWatcherHandler {
 
 impl AsyncWatcherHandler {
     pub fn new() -> (Self, mpsc::Receiver<notify::Event>) {
-        let (tx, rx) = mpsc::channel(100);
+        Self::with_capacity(100)
+    }
+
+    /// Creates a handler whose event channel buffers up to `capacity` events.
+    pub fn with_capacity(capacity: usize) -> (Self, mpsc::Receiver<notify::Event>) {
+        let (tx, rx) = mpsc::channel(capacity);
         let runtime = tokio::runtime::Runtime::new().unwrap();
         (Self { tx, runtime }, rx)
     }
@@ -350,18 +2015,47 @@ impl notify::EventHandler for AsyncWatcherHandler {
     }
 }
 
-/// Matches a path against the file pattern.
+/// Matches a path against the file patterns.
 ///
 /// # Arguments
 /// * `watch_path` - The base path to watch.
-/// * `gp` - The glob pattern for filtering.
+/// * `gp` - The glob patterns for filtering.
 /// * `event` - The file system event to match.
-fn match_path<P: AsRef<Path>>(watch_path: P, gp: &Pattern, event: &notify::Event) -> bool {
-    event.paths.iter().any(|path| {
-        if let Ok(removed_base) = path.strip_prefix(&watch_path) {
-            gp.matches(removed_base.to_str().unwrap_or_default())
-        } else {
-            false
+fn match_path<P: AsRef<Path>>(watch_path: P, gp: &FilePatterns, event: &notify::Event) -> bool {
+    event
+        .paths
+        .iter()
+        .any(|path| path_matches(&watch_path, gp, path))
+}
+
+/// Normalizes `path` lexically: removes `.` components and resolves `..` against the preceding
+/// component. Unlike [`std::fs::canonicalize`] this works for files that no longer exist, which
+/// is needed to match removals. Symbolic links are not resolved, so `..` after a link may refer
+/// to a different directory than on disk.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // The parent of the root is the root itself
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
         }
-    })
+    }
+    normalized
+}
+
+/// Matches a single path relative to the watch path against the file patterns.
+fn path_matches<P: AsRef<Path>>(watch_path: P, gp: &FilePatterns, path: &Path) -> bool {
+    if let Ok(removed_base) = path.strip_prefix(&watch_path) {
+        gp.matches(removed_base.to_str().unwrap_or_default())
+    } else {
+        false
+    }
 }
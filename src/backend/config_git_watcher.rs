@@ -0,0 +1,299 @@
+use glob::Pattern;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::{process::Command, sync::mpsc, time::MissedTickBehavior};
+
+use super::{
+    command_channel, remove_document, update_document, wait_for_start, DocumentEvent, EventSender,
+    SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::WatcherError;
+
+/// Number of git watchers created by this process, to give each its own clone directory.
+static CLONE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Tracks the files of a branch of a git repository and emits each as a document.
+///
+/// `repository` is anything `git clone` accepts, e.g. a URL or a local path. The branch is cloned
+/// into a temporary bare repository, which is removed when the watcher stops, and fetched every
+/// `poll_interval`. When the branch points to a new commit, the files below `subdir` (the whole
+/// repository if empty) whose path relative to `subdir` matches `inner_pattern` are compared with
+/// the previous commit, and only new, changed and removed files produce events. Document ids are
+/// the paths relative to the repository root. Files that are not valid UTF-8 are skipped.
+///
+/// The `git` executable is run for all operations, so its configuration, e.g. credential helpers
+/// and SSH keys, applies; interactive prompts are disabled. `repository` is passed after `--`,
+/// so it is never taken for an option, and a `branch` that is empty or starts with `-` is
+/// rejected with [`WatcherError::Git`]. Failures, for example because the
+/// remote is unreachable, are logged and the known documents are kept until the next poll.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_git_watcher(
+    repository: impl Into<String>,
+    branch: impl Into<String>,
+    subdir: impl Into<String>,
+    inner_pattern: &str,
+    poll_interval: Duration,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let inner_pattern = Pattern::new(inner_pattern)?;
+    let subdir = subdir.into().trim_matches('/').to_string();
+    let branch = branch.into();
+    if branch.is_empty() || branch.starts_with('-') {
+        return Err(WatcherError::Git(format!("invalid branch name [{branch}]")));
+    }
+    let mut repository = GitRepository {
+        url: repository.into(),
+        branch,
+        dir: std::env::temp_dir().join(format!(
+            "config-watcher-git-{}-{}",
+            std::process::id(),
+            CLONE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        )),
+        cloned: false,
+    };
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut tree = TrackedTree::default();
+        let mut initial_scan_done = false;
+        let mut poll = tokio::time::interval(poll_interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    match tree.sync(&mut repository, &subdir, &inner_pattern, &event_sender).await {
+                        Ok(()) => {
+                            if !initial_scan_done {
+                                initial_scan_done = true;
+                                event_sender
+                                    .send(DocumentEvent::InitialScanComplete)
+                                    .await
+                                    .ok();
+                            }
+                        }
+                        Err(err) => {
+                            log::error!("Error syncing git repository [{}]: {}", repository.url, err);
+                        }
+                    }
+                }
+
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                }
+            }
+        }
+
+        repository.remove_clone().await;
+        log::debug!("Exiting git config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Git,
+        },
+        event_receiver,
+    ))
+}
+
+/// The bare clone of the watched branch.
+struct GitRepository {
+    url: String,
+    branch: String,
+    dir: PathBuf,
+    cloned: bool,
+}
+
+impl GitRepository {
+    /// Clones or fetches the branch and returns the id of the commit it points to.
+    async fn fetch(&mut self) -> Result<String, WatcherError> {
+        let branch_ref = format!("refs/heads/{}", self.branch);
+        if self.cloned {
+            self.git(&[
+                "fetch",
+                "--quiet",
+                "origin",
+                &format!("+{branch_ref}:{branch_ref}"),
+            ])
+            .await?;
+        } else {
+            // A failed clone may have left a partial directory behind
+            self.remove_clone().await;
+            run_git(
+                Command::new("git")
+                    .args(["clone", "--quiet", "--bare", "--single-branch", "--branch"])
+                    .arg(&self.branch)
+                    .arg("--")
+                    .arg(&self.url)
+                    .arg(&self.dir),
+            )
+            .await?;
+            self.cloned = true;
+        }
+        let commit = self
+            .git(&["rev-parse", "--verify", &format!("{branch_ref}^{{commit}}")])
+            .await?;
+        Ok(String::from_utf8_lossy(&commit).trim().to_string())
+    }
+
+    /// Returns the blob id of each file below `subdir` in `commit`, by path.
+    async fn list_files(
+        &self,
+        commit: &str,
+        subdir: &str,
+    ) -> Result<HashMap<String, String>, WatcherError> {
+        let mut args = vec!["ls-tree", "-r", "-z", "--full-tree", commit];
+        let prefix = format!("{subdir}/");
+        if !subdir.is_empty() {
+            args.extend(["--", prefix.as_str()]);
+        }
+        let output = self.git(&args).await?;
+
+        // Entries are `<mode> <type> <object>\t<path>`, separated by NUL
+        let mut files = HashMap::new();
+        for entry in output
+            .split(|byte| *byte == 0)
+            .filter(|entry| !entry.is_empty())
+        {
+            let entry = String::from_utf8_lossy(entry);
+            let Some((info, path)) = entry.split_once('\t') else {
+                continue;
+            };
+            // Submodules are listed as commits
+            if let ["blob", object] = info.split(' ').skip(1).collect::<Vec<_>>()[..] {
+                files.insert(path.to_string(), object.to_string());
+            }
+        }
+        Ok(files)
+    }
+
+    async fn read_blob(&self, object: &str) -> Result<Vec<u8>, WatcherError> {
+        self.git(&["cat-file", "blob", object]).await
+    }
+
+    /// Runs git in the clone directory and returns its output.
+    async fn git(&self, args: &[&str]) -> Result<Vec<u8>, WatcherError> {
+        run_git(Command::new("git").arg("-C").arg(&self.dir).args(args)).await
+    }
+
+    async fn remove_clone(&mut self) {
+        self.cloned = false;
+        if let Err(err) = tokio::fs::remove_dir_all(&self.dir).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Cannot remove git clone {:?}: {}", self.dir, err);
+            }
+        }
+    }
+}
+
+async fn run_git(command: &mut Command) -> Result<Vec<u8>, WatcherError> {
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|err| WatcherError::Git(format!("cannot run git: {err}")))?;
+    if !output.status.success() {
+        return Err(WatcherError::Git(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+/// The commit the documents were taken from, with the blob ids and content hashes of its files.
+#[derive(Default)]
+struct TrackedTree {
+    commit: Option<String>,
+    // Blob id per matching file, including files skipped for not being UTF-8
+    blobs: HashMap<String, String>,
+    hashes: HashMap<String, u64>,
+}
+
+impl TrackedTree {
+    /// Fetches the branch and emits the differences between its commit and the tracked one.
+    async fn sync(
+        &mut self,
+        repository: &mut GitRepository,
+        subdir: &str,
+        inner_pattern: &Pattern,
+        event_sender: &EventSender,
+    ) -> Result<(), WatcherError> {
+        let commit = repository.fetch().await?;
+        if self.commit.as_ref() == Some(&commit) {
+            return Ok(());
+        }
+
+        let files: HashMap<String, String> = repository
+            .list_files(&commit, subdir)
+            .await?
+            .into_iter()
+            .filter(|(path, _)| {
+                let relative = match subdir.is_empty() {
+                    true => Some(path.as_str()),
+                    false => path
+                        .strip_prefix(subdir)
+                        .and_then(|path| path.strip_prefix('/')),
+                };
+                relative.is_some_and(|relative| inner_pattern.matches(relative))
+            })
+            .collect();
+
+        let removed: Vec<String> = self
+            .blobs
+            .keys()
+            .filter(|id| !files.contains_key(*id))
+            .cloned()
+            .collect();
+        for id in removed {
+            self.blobs.remove(&id);
+            remove_document(id, &mut self.hashes, event_sender).await;
+        }
+        for (id, object) in files {
+            if self.blobs.get(&id) == Some(&object) {
+                continue;
+            }
+            match String::from_utf8(repository.read_blob(&object).await?) {
+                Ok(content) => {
+                    update_document(id.clone(), content, &mut self.hashes, event_sender).await
+                }
+                Err(err) => {
+                    log::warn!(
+                        "Skipping git file [{}] that is not valid UTF-8: {}",
+                        id,
+                        err
+                    );
+                    remove_document(id.clone(), &mut self.hashes, event_sender).await;
+                }
+            }
+            self.blobs.insert(id, object);
+        }
+
+        log::debug!(
+            "Git repository [{}] synced to commit {}",
+            repository.url,
+            commit
+        );
+        self.commit = Some(commit);
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use futures::{stream::BoxStream, Stream, StreamExt};
+use rskafka::{
+    client::{
+        consumer::{StartOffset, StreamConsumerBuilder},
+        partition::{OffsetAt, PartitionClient, UnknownTopicHandling},
+        Client, ClientBuilder,
+    },
+    record::{Record, RecordAndOffset},
+};
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::Instant};
+
+use super::{
+    command_channel, remove_document, update_document, wait_for_start, CommandReceiver,
+    DocumentEvent, EventSender, RetryDelay, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::{
+    clock::{default_clock, SharedClock},
+    WatcherError,
+};
+
+/// How often partitions that have not reached their startup high-water mark are checked for
+/// remaining records, see [`run_kafka_watcher`].
+const CATCH_UP_PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Optional settings for [`run_kafka_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct KafkaWatcherOptions {
+    /// Delay before reopening the partitions after an error (default 5 seconds ± 20%).
+    pub retry_delay: RetryDelay,
+    /// Source of the time for the retry and catch-up timers (default [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+
+impl Default for KafkaWatcherOptions {
+    fn default() -> Self {
+        Self {
+            retry_delay: RetryDelay::default(),
+            clock: default_clock(),
+        }
+    }
+}
+
+/// Consumes a log-compacted Kafka topic and emits the latest value of each key as a document.
+///
+/// All partitions are read from the earliest offset, so every watcher instance sees the full
+/// state. A record with a null value (tombstone) removes the document of its key. Records without
+/// a key or with a non UTF-8 key or value are skipped.
+///
+/// Consumer groups are not supported: a group would assign each instance only some of the
+/// partitions, and thereby only part of the configuration, and the Kafka client used does not
+/// implement the group protocol. Consequently there are no rebalances and no committed offsets.
+///
+/// `InitialScanComplete` is sent once all partitions have been read up to the high-water mark
+/// they had at startup. A partition whose remaining offsets below that mark hold no records, e.g.
+/// transaction markers at the end of the log, counts as read once a fetch from its next offset
+/// returns no records; this is checked every second. On errors, the partitions are reopened after
+/// the retry delay (about 5 seconds by default) at the next unread offset; partitions added to the
+/// topic in the meantime are read from the beginning.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_kafka_watcher(
+    brokers: Vec<String>,
+    topic: String,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    run_kafka_watcher_with_options(brokers, topic, KafkaWatcherOptions::default())
+}
+
+/// Same as [`run_kafka_watcher`] but with additional [`KafkaWatcherOptions`].
+pub fn run_kafka_watcher_with_options(
+    brokers: Vec<String>,
+    topic: String,
+    options: KafkaWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let client = ClientBuilder::new(brokers).build().await?;
+        let mut source = TopicConnection {
+            client,
+            topic,
+            partition_clients: HashMap::new(),
+        };
+        consume(&mut source, &options, &mut command_receiver, &event_sender).await;
+
+        log::debug!("Exiting Kafka config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Kafka,
+        },
+        event_receiver,
+    ))
+}
+
+/// Future returned by the methods of [`KafkaSource`].
+pub type KafkaFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, WatcherError>> + Send + 'a>>;
+
+/// The records of all partitions of a topic, each with its partition.
+pub type KafkaRecordStream = BoxStream<'static, (i32, Result<RecordAndOffset, WatcherError>)>;
+
+/// The partitions of the topic, opened by [`KafkaSource::open`].
+pub struct KafkaPartitions {
+    /// The merged records of all partitions. An error or the end of the stream reopens the
+    /// partitions after the retry delay.
+    pub records: KafkaRecordStream,
+    /// Start offset and current high-water mark of each partition.
+    pub high_watermarks: HashMap<i32, (i64, i64)>,
+}
+
+/// Partitions of the watched topic, see [`run_kafka_watcher_from_source`].
+///
+/// [`run_kafka_watcher`] reads the topic from the brokers. Other implementations can serve the
+/// records from elsewhere, e.g. from memory in tests.
+pub trait KafkaSource: Send + 'static {
+    /// (Re)opens all partitions of the topic, each at its offset in `offsets` or, if it is not
+    /// listed, at its earliest offset.
+    fn open<'a>(&'a mut self, offsets: &'a HashMap<i32, i64>) -> KafkaFuture<'a, KafkaPartitions>;
+
+    /// Returns true if `partition` holds no records at or after `offset`, e.g. because its
+    /// remaining offsets are transaction markers.
+    fn is_caught_up(&self, partition: i32, offset: i64) -> KafkaFuture<'_, bool>;
+}
+
+/// Same as [`run_kafka_watcher_with_options`], but reads the partitions from `source` instead of
+/// the brokers, e.g. to drive the watcher with in-memory partitions in tests.
+///
+/// Records, `InitialScanComplete` and errors are handled as described for [`run_kafka_watcher`];
+/// partitions that have not reached their startup high-water mark are checked with
+/// [`KafkaSource::is_caught_up`] every second on the options clock.
+pub fn run_kafka_watcher_from_source<S: KafkaSource>(
+    source: S,
+    options: KafkaWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut source = source;
+        consume(&mut source, &options, &mut command_receiver, &event_sender).await;
+
+        log::debug!("Exiting Kafka config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Kafka,
+        },
+        event_receiver,
+    ))
+}
+
+/// Consumes the partitions of `source` until the watcher is stopped, reopening them after errors.
+async fn consume(
+    source: &mut impl KafkaSource,
+    options: &KafkaWatcherOptions,
+    command_receiver: &mut CommandReceiver,
+    event_sender: &EventSender,
+) {
+    let mut hashes: HashMap<String, u64> = HashMap::new();
+    // Next offset to read per partition
+    let mut offsets: HashMap<i32, i64> = HashMap::new();
+    // Partitions that have not yet reached their startup high-water mark
+    let mut pending: Option<HashMap<i32, i64>> = None;
+    // Next check of the pending partitions. Kept across records, so that records of other
+    // partitions do not postpone it.
+    let mut probe_at: Option<Instant> = None;
+
+    'consume: loop {
+        let KafkaPartitions {
+            mut records,
+            high_watermarks,
+        } = match source.open(&offsets).await {
+            Ok(partitions) => partitions,
+            Err(err) => {
+                log::error!("Error opening Kafka topic: {}", err);
+                match wait_for_retry(options, command_receiver).await {
+                    true => continue 'consume,
+                    false => break 'consume,
+                }
+            }
+        };
+
+        for (partition, (start, _)) in &high_watermarks {
+            offsets.entry(*partition).or_insert(*start);
+        }
+        if pending.is_none() {
+            let targets: HashMap<i32, i64> = high_watermarks
+                .into_iter()
+                .filter(|(_, (start, high_watermark))| start < high_watermark)
+                .map(|(partition, (_, high_watermark))| (partition, high_watermark))
+                .collect();
+            if targets.is_empty() {
+                event_sender
+                    .send(DocumentEvent::InitialScanComplete)
+                    .await
+                    .ok();
+            } else {
+                probe_at = Some(options.clock.now() + CATCH_UP_PROBE_INTERVAL);
+            }
+            pending = Some(targets);
+        }
+
+        loop {
+            tokio::select! {
+                item = records.next() => {
+                    match item {
+                        Some((partition, Ok(record))) => {
+                            offsets.insert(partition, record.offset + 1);
+                            handle_record(record.record, &mut hashes, event_sender).await;
+
+                            if let Some(targets) = pending.as_mut().filter(|t| !t.is_empty()) {
+                                if targets.get(&partition).is_some_and(|t| record.offset + 1 >= *t) {
+                                    targets.remove(&partition);
+                                    if targets.is_empty() {
+                                        probe_at = None;
+                                        event_sender
+                                            .send(DocumentEvent::InitialScanComplete)
+                                            .await
+                                            .ok();
+                                    }
+                                }
+                            }
+                        }
+                        Some((partition, Err(err))) => {
+                            log::error!("Error consuming Kafka partition {}: {}. Reopening partitions.", partition, err);
+                            match wait_for_retry(options, command_receiver).await {
+                                true => continue 'consume,
+                                false => break 'consume,
+                            }
+                        }
+                        None => {
+                            log::warn!("Kafka partition streams ended. Reopening partitions.");
+                            match wait_for_retry(options, command_receiver).await {
+                                true => continue 'consume,
+                                false => break 'consume,
+                            }
+                        }
+                    }
+                }
+                // Partitions may end in offsets without records, e.g. transaction markers
+                _ = options.clock.sleep_until(probe_at.unwrap_or_else(|| options.clock.now())), if probe_at.is_some() => {
+                    let targets = pending.as_mut().expect("probes are only scheduled while pending");
+                    let partitions: Vec<i32> = targets.keys().copied().collect();
+                    for partition in partitions {
+                        let caught_up = match source.is_caught_up(partition, offsets[&partition]).await {
+                            Ok(caught_up) => caught_up,
+                            Err(err) => {
+                                // The partition stream reports the error as well
+                                log::debug!("Error checking Kafka partition {}: {}", partition, err);
+                                false
+                            }
+                        };
+                        if caught_up {
+                            targets.remove(&partition);
+                        }
+                    }
+                    if targets.is_empty() {
+                        probe_at = None;
+                        event_sender
+                            .send(DocumentEvent::InitialScanComplete)
+                            .await
+                            .ok();
+                    } else {
+                        probe_at = Some(options.clock.now() + CATCH_UP_PROBE_INTERVAL);
+                    }
+                }
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break 'consume;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sleeps for the retry delay. Returns false if the watcher was stopped in the meantime.
+async fn wait_for_retry(
+    options: &KafkaWatcherOptions,
+    command_receiver: &mut CommandReceiver,
+) -> bool {
+    tokio::select! {
+        _ = options.retry_delay.sleep_on(&*options.clock) => true,
+        Some(WatcherCommand::Stop) = command_receiver.recv() => false,
+    }
+}
+
+/// An item of the stream fed to [`run_kafka_watcher_from_stream`].
+#[derive(Clone, Debug)]
+pub enum KafkaStreamEvent {
+    /// A record of the topic, handled like a record read from a partition.
+    Record(Record),
+    /// All records present at startup have been delivered; emits `InitialScanComplete` once.
+    CaughtUp,
+}
+
+/// Same as [`run_kafka_watcher`], but processes the records of `records` instead of consuming a
+/// topic, e.g. to drive the watcher with synthetic records in tests or to reuse an existing
+/// consumer. Keys, values and tombstones are handled as described for [`run_kafka_watcher`]. The
+/// watcher ends when the stream ends.
+pub fn run_kafka_watcher_from_stream<S>(
+    records: S,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    S: Stream<Item = KafkaStreamEvent> + Send + Unpin + 'static,
+{
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut records = records;
+        let mut hashes: HashMap<String, u64> = HashMap::new();
+        let mut ready_sent = false;
+        loop {
+            tokio::select! {
+                event = records.next() => match event {
+                    Some(KafkaStreamEvent::Record(record)) => {
+                        handle_record(record, &mut hashes, &event_sender).await;
+                    }
+                    Some(KafkaStreamEvent::CaughtUp) => {
+                        if !ready_sent {
+                            ready_sent = true;
+                            event_sender
+                                .send(DocumentEvent::InitialScanComplete)
+                                .await
+                                .ok();
+                        }
+                    }
+                    None => {
+                        log::debug!("Kafka record stream ended");
+                        break;
+                    }
+                },
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                }
+            }
+        }
+
+        log::debug!("Exiting Kafka config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Kafka,
+        },
+        event_receiver,
+    ))
+}
+
+/// Connection to the brokers serving the watched topic.
+struct TopicConnection {
+    client: Client,
+    topic: String,
+    // Clients of the partitions opened last, used by `is_caught_up`
+    partition_clients: HashMap<i32, Arc<PartitionClient>>,
+}
+
+impl KafkaSource for TopicConnection {
+    fn open<'a>(&'a mut self, offsets: &'a HashMap<i32, i64>) -> KafkaFuture<'a, KafkaPartitions> {
+        Box::pin(async move {
+            let partitions = self
+                .client
+                .list_topics()
+                .await?
+                .into_iter()
+                .find(|t| t.name == self.topic)
+                .map(|t| t.partitions)
+                .unwrap_or_default();
+            if partitions.is_empty() {
+                log::warn!("Kafka topic [{}] has no partitions", self.topic);
+            }
+
+            let mut records = futures::stream::SelectAll::new();
+            let mut high_watermarks = HashMap::new();
+            self.partition_clients.clear();
+            for partition in partitions {
+                let partition_client = Arc::new(
+                    self.client
+                        .partition_client(&self.topic, partition, UnknownTopicHandling::Retry)
+                        .await?,
+                );
+                let start_offset = match offsets.get(&partition) {
+                    Some(offset) => *offset,
+                    None => partition_client.get_offset(OffsetAt::Earliest).await?,
+                };
+                high_watermarks.insert(
+                    partition,
+                    (
+                        start_offset,
+                        partition_client.get_offset(OffsetAt::Latest).await?,
+                    ),
+                );
+                self.partition_clients
+                    .insert(partition, partition_client.clone());
+                let stream: KafkaRecordStream =
+                    StreamConsumerBuilder::new(partition_client, StartOffset::At(start_offset))
+                        .build()
+                        .map(move |result| {
+                            (
+                                partition,
+                                result.map(|(record, _)| record).map_err(WatcherError::from),
+                            )
+                        })
+                        .boxed();
+                records.push(stream);
+            }
+            Ok(KafkaPartitions {
+                records: records.boxed(),
+                high_watermarks,
+            })
+        })
+    }
+
+    /// Fetches from `offset`; offsets without records, like transaction markers, are skipped by
+    /// the fetch.
+    fn is_caught_up(&self, partition: i32, offset: i64) -> KafkaFuture<'_, bool> {
+        let client = self.partition_clients.get(&partition).cloned();
+        Box::pin(async move {
+            let Some(client) = client else {
+                return Ok(false);
+            };
+            let (records, _) = client.fetch_records(offset, 1..1_000_000, 0).await?;
+            Ok(records.is_empty())
+        })
+    }
+}
+
+async fn handle_record(
+    record: Record,
+    hashes: &mut HashMap<String, u64>,
+    event_sender: &EventSender,
+) {
+    let Some(key) = record.key.and_then(|key| String::from_utf8(key).ok()) else {
+        log::warn!("Skipping Kafka record without a valid UTF-8 key");
+        return;
+    };
+
+    let Some(value) = record.value else {
+        // tombstone
+        remove_document(key, hashes, event_sender).await;
+        return;
+    };
+
+    match String::from_utf8(value) {
+        Ok(content) => update_document(key, content, hashes, event_sender).await,
+        Err(err) => {
+            log::warn!(
+                "Cannot parse Kafka value for key [{}] to string. Error: {}",
+                key,
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+use tokio::sync::mpsc;
+
+use super::{command_channel, wait_for_start, DocumentEvent, WatcherCommand, WatcherHandle};
+use crate::WatcherError;
+
+/// A document assembled from several documents of a backend, e.g. a base file and environment
+/// specific overrides, see [`run_layered_watcher`].
+#[derive(Clone, Debug)]
+pub struct LayeredDocument {
+    /// Id of the assembled document.
+    pub id: String,
+    /// Ids of the layers as reported by the backend, lowest precedence first. With the file
+    /// backend these are absolute paths.
+    pub layers: Vec<String>,
+}
+
+impl LayeredDocument {
+    pub fn new(id: impl Into<String>, layers: Vec<String>) -> Self {
+        Self {
+            id: id.into(),
+            layers,
+        }
+    }
+}
+
+/// Merges the contents of the present layers of a [`LayeredDocument`], lowest precedence first,
+/// into one document. Returns `None` if they cannot be merged, e.g. because a layer is invalid.
+/// With the `yaml` feature, [`merge_yaml_layers`](crate::merge_yaml_layers) deep-merges YAML
+/// documents like Helm values files.
+pub type LayerMergeFn = Arc<dyn Fn(&[&str]) -> Option<String> + Send + Sync>;
+
+/// Assembles layered documents from the documents of a backend.
+///
+/// The layers of each [`LayeredDocument`] are not emitted themselves. Instead, whenever one of
+/// them is added, changed or removed, the contents of the present layers are merged with `merge`
+/// and the result is emitted under the id of the layered document: as `NewDocument` when the
+/// first layer appears, as `ContentChanged` when the merged content changes and as
+/// `DocumentRemoved` when the last layer is removed. If `merge` returns `None`, an error is
+/// logged and the previous content is kept. Documents that are no layer are passed on unchanged.
+///
+/// During the initial scan of the backend the layers are collected, and each layered document is
+/// emitted once before `InitialScanComplete`.
+///
+/// The backend is started and stopped with the returned handle, and the handle reports its
+/// [`SourceKind`](super::SourceKind). [`WatcherHandle::load_document`] returns the merged content
+/// for layered documents and is forwarded to the backend otherwise.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_layered_watcher(
+    source: (WatcherHandle, mpsc::Receiver<DocumentEvent>),
+    documents: Vec<LayeredDocument>,
+    merge: LayerMergeFn,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (mut source_handle, mut receiver) = source;
+    let source_kind = source_handle.source_kind;
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin. Dropping the handle stops the source.
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+        if command_receiver.timestamps() {
+            source_handle.emit_timestamps().await?;
+        }
+        source_handle.start().await?;
+        let mut state = LayerState::new(documents, merge);
+
+        loop {
+            tokio::select! {
+                event = receiver.recv() => {
+                    let Some(event) = event else {
+                        log::debug!("Source of the layered watcher ended");
+                        break;
+                    };
+                    // Keep the time the source detected the event
+                    let (observed_at, event) = event.into_observed();
+                    let observed_at = observed_at.unwrap_or_else(SystemTime::now);
+                    for event in state.handle_event(event) {
+                        event_sender.send_observed(observed_at, event).await.ok();
+                    }
+                }
+                // Check for control commands
+                Some(command) = command_receiver.recv() => match command {
+                    WatcherCommand::Stop => {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                    WatcherCommand::LoadDocument(id, reply_sender) => {
+                        if let Some(content) = state.merged_content(&id) {
+                            reply_sender.send(Ok(content)).ok();
+                            continue;
+                        }
+                        // Sent from a separate task, since the source may wait for this loop to
+                        // take its events before it reads commands
+                        let sender = source_handle.command_sender.clone();
+                        tokio::spawn(async move {
+                            sender
+                                .send(WatcherCommand::LoadDocument(id, reply_sender))
+                                .await
+                                .ok();
+                        });
+                    }
+                    WatcherCommand::RefreshDocument(id, reply_sender) => {
+                        if let Some(content) = state.merged_content(&id) {
+                            event_sender
+                                .send(DocumentEvent::ContentChanged(id, content))
+                                .await
+                                .ok();
+                            reply_sender.send(Ok(())).ok();
+                            continue;
+                        }
+                        let sender = source_handle.command_sender.clone();
+                        tokio::spawn(async move {
+                            sender
+                                .send(WatcherCommand::RefreshDocument(id, reply_sender))
+                                .await
+                                .ok();
+                        });
+                    }
+                    WatcherCommand::DebounceMetrics(reply_sender) => {
+                        let sender = source_handle.command_sender.clone();
+                        tokio::spawn(async move {
+                            sender
+                                .send(WatcherCommand::DebounceMetrics(reply_sender))
+                                .await
+                                .ok();
+                        });
+                    }
+                    WatcherCommand::SetDebounce(debounce) => {
+                        let sender = source_handle.command_sender.clone();
+                        tokio::spawn(async move {
+                            sender.send(WatcherCommand::SetDebounce(debounce)).await.ok();
+                        });
+                    }
+                    WatcherCommand::Start | WatcherCommand::EmitTimestamps => {}
+                }
+            }
+        }
+
+        // Keep taking events while the source stops, so it does not block on a full channel
+        let mut stopping = std::pin::pin!(source_handle.stop());
+        let result = loop {
+            tokio::select! {
+                result = &mut stopping => break result,
+                Some(_) = receiver.recv() => {}
+            }
+        };
+        log::debug!("Exiting layered config watcher");
+        result
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind,
+        },
+        event_receiver,
+    ))
+}
+
+/// A layered document with the contents of its present layers.
+struct Layered {
+    document: LayeredDocument,
+    contents: Vec<Option<String>>,
+    // Latest merged content that was emitted
+    merged: Option<String>,
+}
+
+/// Tracks the layers of the layered documents.
+struct LayerState {
+    layered: Vec<Layered>,
+    // Indices of the layered documents by layer id
+    layers: HashMap<String, Vec<usize>>,
+    merge: LayerMergeFn,
+    scan_complete: bool,
+}
+
+impl LayerState {
+    fn new(documents: Vec<LayeredDocument>, merge: LayerMergeFn) -> Self {
+        let mut layers: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, document) in documents.iter().enumerate() {
+            for layer in &document.layers {
+                layers.entry(layer.clone()).or_default().push(index);
+            }
+        }
+        let layered = documents
+            .into_iter()
+            .map(|document| Layered {
+                contents: vec![None; document.layers.len()],
+                document,
+                merged: None,
+            })
+            .collect();
+        Self {
+            layered,
+            layers,
+            merge,
+            scan_complete: false,
+        }
+    }
+
+    /// Returns the merged content of the layered document `id`, if it was emitted.
+    fn merged_content(&self, id: &str) -> Option<String> {
+        self.layered
+            .iter()
+            .find(|layered| layered.document.id == id)?
+            .merged
+            .clone()
+    }
+
+    /// Returns the events to emit for `event` of the source.
+    fn handle_event(&mut self, event: DocumentEvent) -> Vec<DocumentEvent> {
+        let (id, content) = match event {
+            DocumentEvent::NewDocument(id, content)
+            | DocumentEvent::ContentChanged(id, content)
+                if self.layers.contains_key(&id) =>
+            {
+                (id, Some(content))
+            }
+            DocumentEvent::DocumentRemoved(id) if self.layers.contains_key(&id) => (id, None),
+            DocumentEvent::Touched(id) if self.layers.contains_key(&id) => {
+                return self.layers[&id]
+                    .iter()
+                    .map(|index| &self.layered[*index])
+                    .filter(|layered| layered.merged.is_some())
+                    .map(|layered| DocumentEvent::Touched(layered.document.id.clone()))
+                    .collect();
+            }
+            // The error is reported for the first emitted document using the layer
+            DocumentEvent::ReadFailed(id, error) if self.layers.contains_key(&id) => {
+                return self.layers[&id]
+                    .iter()
+                    .map(|index| &self.layered[*index])
+                    .find(|layered| layered.merged.is_some())
+                    .map(|layered| DocumentEvent::ReadFailed(layered.document.id.clone(), error))
+                    .into_iter()
+                    .collect();
+            }
+            DocumentEvent::InitialScanComplete if !self.scan_complete => {
+                self.scan_complete = true;
+                let mut events: Vec<DocumentEvent> = (0..self.layered.len())
+                    .filter_map(|index| self.remerge(index))
+                    .collect();
+                events.push(DocumentEvent::InitialScanComplete);
+                return events;
+            }
+            event => return vec![event],
+        };
+
+        let indices = self.layers[&id].clone();
+        for index in &indices {
+            let layered = &mut self.layered[*index];
+            for (layer, slot) in layered.document.layers.iter().zip(&mut layered.contents) {
+                if *layer == id {
+                    *slot = content.clone();
+                }
+            }
+        }
+        // Layered documents are emitted once the initial scan collected all layers
+        if !self.scan_complete {
+            return vec![];
+        }
+        indices
+            .into_iter()
+            .filter_map(|index| self.remerge(index))
+            .collect()
+    }
+
+    /// Merges the present layers of the layered document at `index` and returns the event for
+    /// the change of the merged content, if any.
+    fn remerge(&mut self, index: usize) -> Option<DocumentEvent> {
+        let layered = &mut self.layered[index];
+        let id = layered.document.id.clone();
+        let contents: Vec<&str> = layered
+            .contents
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        if contents.is_empty() {
+            layered.merged.take()?;
+            return Some(DocumentEvent::DocumentRemoved(id));
+        }
+        let Some(merged) = (self.merge)(&contents) else {
+            log::error!(
+                "Cannot merge the layers of document [{}], keeping its content",
+                id
+            );
+            return None;
+        };
+        match layered.merged.replace(merged.clone()) {
+            None => Some(DocumentEvent::NewDocument(id, merged)),
+            Some(previous) if previous != merged => Some(DocumentEvent::ContentChanged(id, merged)),
+            Some(_) => None,
+        }
+    }
+}
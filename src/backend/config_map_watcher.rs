@@ -1,18 +1,104 @@
-use futures::{StreamExt, TryStreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
+use glob::Pattern;
 use k8s_openapi::api::core::v1::ConfigMap;
 use kube::{api::Api, runtime::watcher, Client};
 use std::{
     borrow::Cow,
     collections::{BTreeMap, HashMap},
+    path::Path,
     time::Duration,
 };
 use tokio::sync::mpsc;
 
-use super::{DocumentEvent, WatcherCommand, WatcherHandle};
-use crate::{hash_str, WatcherError};
+use super::{
+    command_channel, send_masked, sync_documents, wait_for_start, DocumentEvent, EventMask,
+    EventSender, RetryDelay, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::{
+    clock::{default_clock, SharedClock},
+    WatcherError,
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+
+/// Optional settings for [`run_configmap_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct ConfigMapWatcherOptions {
+    /// Restart the Kubernetes watch stream when it ends (default `false`: the watcher task exits
+    /// and its join handle resolves cleanly).
+    pub restart_on_source_end: bool,
+    /// Delay before restarting the watch stream or retrying after an error (default 3 seconds
+    /// ± 20%).
+    pub retry_delay: RetryDelay,
+    /// Only emit data keys matching this glob pattern (e.g. `*.yaml`). Keys that stop matching,
+    /// for example because they were renamed, are reported as removed.
+    pub key_pattern: Option<String>,
+    /// Which value to use for a key present in both `data` and `binaryData` (default:
+    /// [`KeyCollisionPolicy::PreferBinaryData`]).
+    pub key_collisions: KeyCollisionPolicy,
+    /// Handling of `binaryData` values that are not valid UTF-8 (default:
+    /// [`BinaryDataPolicy::Skip`]).
+    pub invalid_binary_data: BinaryDataPolicy,
+    /// Re-read the ConfigMap with a plain `get` at this interval and reconcile it with the known
+    /// documents, emitting changes the watch missed without reporting an error (default `None`).
+    pub resync_interval: Option<Duration>,
+    /// Categories of events to emit (default [`EventMask::ALL`]). Suppressed changes are still
+    /// recorded, so later events are based on the latest content.
+    pub event_mask: EventMask,
+    /// Source of the time for the retry and resync timers (default [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+impl Default for ConfigMapWatcherOptions {
+    fn default() -> Self {
+        Self {
+            restart_on_source_end: false,
+            retry_delay: RetryDelay::new(Duration::from_secs(3), 0.2),
+            clock: default_clock(),
+            key_pattern: None,
+            key_collisions: KeyCollisionPolicy::default(),
+            invalid_binary_data: BinaryDataPolicy::default(),
+            resync_interval: None,
+            event_mask: EventMask::ALL,
+        }
+    }
+}
+
+/// Handling of keys present in both `data` and `binaryData` of a ConfigMap. Every collision is
+/// logged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyCollisionPolicy {
+    /// Emit the value from `data`.
+    PreferData,
+    /// Emit the value from `binaryData`, or the one from `data` if it is not valid UTF-8 and not
+    /// base64 encoded, see [`BinaryDataPolicy`].
+    #[default]
+    PreferBinaryData,
+    /// Emit neither value, so the key is reported as removed until the collision is resolved.
+    Skip,
+}
+
+/// Handling of `binaryData` values that are not valid UTF-8. A value from `data` for the same key
+/// is used instead if [`KeyCollisionPolicy::PreferBinaryData`] applies, except with
+/// [`Base64`](Self::Base64).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinaryDataPolicy {
+    /// Log an error and treat the key as absent.
+    #[default]
+    Skip,
+    /// Emit the value encoded as standard base64.
+    Base64,
+    /// Emit [`DocumentEvent::ReadFailed`] with [`WatcherError::InvalidBinaryData`], once per
+    /// value. A key that was emitted before stays tracked with its previous content, so a later
+    /// valid value is reported as changed.
+    Error,
+}
 
 /// Starts watching a ConfigMap in the given namespace.
 ///
+/// Whenever the Kubernetes watch is (re-)started, the ConfigMap is re-listed and compared with
+/// the known documents once the listing is complete, so keys (or the whole ConfigMap) removed
+/// while the watch was interrupted are emitted as removed documents. `InitialScanComplete` is
+/// only sent after the first listing.
+///
 /// # Returns
 /// - A `ConfigMapWatcherHandle` for controlling the watcher.
 /// - A `Receiver` that streams file-like events.
@@ -20,54 +106,125 @@ pub fn run_configmap_watcher(
     configmap_name: String,
     namespace: String,
 ) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
-    let (event_sender, event_receiver) = mpsc::channel(100);
-    let (command_sender, mut command_receiver) = mpsc::channel(1);
+    run_configmap_watcher_with_options(
+        configmap_name,
+        namespace,
+        ConfigMapWatcherOptions::default(),
+    )
+}
+
+/// Service account file holding the namespace of the pod.
+const SERVICE_ACCOUNT_NAMESPACE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Starts watching a ConfigMap in the namespace of the pod the watcher runs in.
+///
+/// The namespace is read from the service account mount
+/// (`/var/run/secrets/kubernetes.io/serviceaccount/namespace`), falling back to the
+/// `POD_NAMESPACE` environment variable (e.g. set via the downward API).
+///
+/// # Errors
+/// Returns [`WatcherError::NamespaceUnavailable`] if neither is available.
+pub fn run_configmap_watcher_in_cluster(
+    configmap_name: String,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let namespace = detect_namespace(
+        Path::new(SERVICE_ACCOUNT_NAMESPACE),
+        std::env::var("POD_NAMESPACE").ok(),
+    )?;
+    run_configmap_watcher(configmap_name, namespace)
+}
+
+/// Determines the namespace the way [`run_configmap_watcher_in_cluster`] does, given the service
+/// account namespace file and the value of the `POD_NAMESPACE` environment variable.
+///
+/// Returns the trimmed content of `namespace_file` or else `env_namespace`, ignoring empty values.
+///
+/// # Errors
+/// Returns [`WatcherError::NamespaceUnavailable`] with `namespace_file` if neither is available.
+pub fn detect_namespace(
+    namespace_file: &Path,
+    env_namespace: Option<String>,
+) -> Result<String, WatcherError> {
+    std::fs::read_to_string(namespace_file)
+        .ok()
+        .into_iter()
+        .chain(env_namespace)
+        .map(|namespace| namespace.trim().to_string())
+        .find(|namespace| !namespace.is_empty())
+        .ok_or_else(|| WatcherError::NamespaceUnavailable(namespace_file.to_path_buf()))
+}
+
+/// Same as [`run_configmap_watcher`] but with additional [`ConfigMapWatcherOptions`].
+pub fn run_configmap_watcher_with_options(
+    configmap_name: String,
+    namespace: String,
+    options: ConfigMapWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    spawn_configmap_watcher(None, configmap_name, namespace, options)
+}
+
+/// Same as [`run_configmap_watcher_with_options`], but talks to the cluster through `client`
+/// instead of the default client, e.g. to use a custom configuration or a mocked API in tests.
+pub fn run_configmap_watcher_with_client(
+    client: Client,
+    configmap_name: String,
+    namespace: String,
+    options: ConfigMapWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    spawn_configmap_watcher(Some(client), configmap_name, namespace, options)
+}
+
+/// Spawns the watcher task, which creates the default client on start if `client` is `None`.
+fn spawn_configmap_watcher(
+    client: Option<Client>,
+    configmap_name: String,
+    namespace: String,
+    options: ConfigMapWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let key_pattern = options
+        .key_pattern
+        .as_deref()
+        .map(Pattern::new)
+        .transpose()?;
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
 
     let handle = tokio::spawn(async move {
         // Wait for a start command before we begin
-        match command_receiver.recv().await {
-            Some(WatcherCommand::Stop) | None => {
-                // Exit early if Stop command is received or channel is closed
-                log::info!("Watcher received stop command before starting or channel closed");
-                return Ok(());
-            }
-            _ => {}
-        }
-        let Ok(client) = Client::try_default().await else {
-            log::error!("Cannot create kubernetes client. Configmap watcher will exit!");
+        if !wait_for_start(&mut command_receiver).await {
             return Ok(());
+        }
+        let client = match client {
+            Some(client) => client,
+            None => match Client::try_default().await {
+                Ok(client) => client,
+                Err(_) => {
+                    log::error!("Cannot create kubernetes client. Configmap watcher will exit!");
+                    return Ok(());
+                }
+            },
         };
         let api: Api<ConfigMap> = Api::namespaced(client, &namespace);
         let config =
             watcher::Config::default().fields(format!("metadata.name={}", configmap_name).as_str());
-        let mut file_hashes: HashMap<String, u64> = HashMap::new();
+        let mut watch = ConfigMapWatch::new(configmap_name.clone(), key_pattern);
+        let mut resync_at = options
+            .resync_interval
+            .map(|interval| options.clock.now() + interval);
 
-        let mut stream = watcher(api, config).boxed();
+        let mut stream = watcher(api.clone(), config.clone()).boxed();
         loop {
             tokio::select! {
                event = stream.try_next() =>
                     {
                         match event {
-                            Ok(Some(watcher::Event::Apply(cm))) | Ok(Some(watcher::Event::InitApply(cm))) => {
-                                if cm.metadata.name.as_deref() == Some(&configmap_name) {
-                                    handle_configmap_update(
-                                        combine_configmap_data(&cm),
-                                        &mut file_hashes,
-                                        &event_sender,
-                                    )
-                                    .await;
-                                }
+                            Ok(Some(event)) => {
+                                watch.handle_event(event, &event_sender, &options).await;
                             }
-                            Ok(Some(watcher::Event::Delete(cm))) => {
-                                if cm.metadata.name.as_deref() == Some(&configmap_name) {
-                                    for key in file_hashes.keys() {
-                                        event_sender
-                                            .send(DocumentEvent::DocumentRemoved(key.clone()))
-                                            .await
-                                            .ok();
-                                    }
-                                    file_hashes.clear();
-                                }
+                            Ok(None) if options.restart_on_source_end => {
+                                log::warn!("==> Kubernetes ConfigMap Watcher stream has ended. Restarting watch.");
+                                options.retry_delay.sleep_on(&*options.clock).await;
+                                stream = watcher(api.clone(), config.clone()).boxed();
                             }
                             Ok(None) => {
                                 log::warn!("==> Kubernetes ConfigMap Watcher stream has ended. There will not be any more config updates.");
@@ -75,12 +232,24 @@ pub fn run_configmap_watcher(
                             }
                             Err(err) => {
                                 log::error!("==> Error in Kubernetes ConfigMap Watcher: {}", err);
-                                // wait for 3 seconds before retrying
-                                tokio::time::sleep(Duration::from_secs(3)).await;
+                                options.retry_delay.sleep_on(&*options.clock).await;
                             }
-                            _ => {}
                         }
                     },
+                // Catch up on events the watch may have missed; skipped while (re-)listing
+                _ = options.clock.sleep_until(resync_at.unwrap_or_else(|| options.clock.now())), if resync_at.is_some() && watch.is_synced() => {
+                    match api.get_opt(&configmap_name).await {
+                        Ok(cm) => {
+                            watch.sync(cm.as_ref(), &event_sender, &options).await;
+                        }
+                        Err(err) => {
+                            log::error!("==> Error resyncing Kubernetes ConfigMap [{}]: {}", configmap_name, err);
+                        }
+                    }
+                    resync_at = options
+                        .resync_interval
+                        .map(|interval| options.clock.now() + interval);
+                },
                 // Check for control commands
                 Some(command) = command_receiver.recv() => {
                     if let WatcherCommand::Stop = command {
@@ -97,78 +266,380 @@ pub fn run_configmap_watcher(
         WatcherHandle {
             command_sender,
             handle: Some(handle),
+            source_kind: SourceKind::ConfigMap,
         },
         event_receiver,
     ))
 }
 
-fn combine_configmap_data(cm: &'_ ConfigMap) -> BTreeMap<String, Cow<'_, str>> {
-    let mut result = BTreeMap::new();
+/// Same as [`run_configmap_watcher_with_options`], but processes the watch events of `events`
+/// instead of watching the cluster, e.g. to drive the watcher with synthetic events in tests.
+/// Events of ConfigMaps other than `configmap_name` are ignored. `Init` … `InitDone` is handled
+/// like a re-listing after a restarted watch. The options `restart_on_source_end`, `retry_delay`
+/// and `resync_interval` have no effect; the watcher ends when the stream ends.
+pub fn run_configmap_watcher_from_stream<S>(
+    configmap_name: String,
+    events: S,
+    options: ConfigMapWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    S: Stream<Item = watcher::Event<ConfigMap>> + Send + Unpin + 'static,
+{
+    let key_pattern = options
+        .key_pattern
+        .as_deref()
+        .map(Pattern::new)
+        .transpose()?;
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
 
-    if let Some(data) = &cm.data {
-        for (key, value) in data {
-            result.insert(key.clone(), Cow::Borrowed(value.as_str()));
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut events = events;
+        let mut watch = ConfigMapWatch::new(configmap_name, key_pattern);
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => watch.handle_event(event, &event_sender, &options).await,
+                    None => {
+                        log::debug!("ConfigMap event stream ended");
+                        break;
+                    }
+                },
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::ConfigMap,
+        },
+        event_receiver,
+    ))
+}
+
+/// Reads a ConfigMap once, without watching it.
+///
+/// Returns its data and UTF-8 binary data as `(key, content)` pairs ordered by key, i.e. the
+/// documents [`run_configmap_watcher`] would emit initially. Useful to bootstrap or validate the
+/// configuration before starting a watcher.
+///
+/// # Errors
+/// Returns [`WatcherError::KubeError`] if no client can be created or the ConfigMap cannot be
+/// read, including when it does not exist.
+pub async fn fetch_configmap_once(
+    configmap_name: &str,
+    namespace: &str,
+) -> Result<Vec<(String, String)>, WatcherError> {
+    let client = Client::try_default().await?;
+    fetch_configmap_once_with_client(client, configmap_name, namespace).await
+}
+
+/// Same as [`fetch_configmap_once`], but reads the ConfigMap through `client` instead of the
+/// default client.
+///
+/// # Errors
+/// Returns [`WatcherError::KubeError`] if the ConfigMap cannot be read, including when it does
+/// not exist.
+pub async fn fetch_configmap_once_with_client(
+    client: Client,
+    configmap_name: &str,
+    namespace: &str,
+) -> Result<Vec<(String, String)>, WatcherError> {
+    let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let cm = api.get(configmap_name).await?;
+    Ok(combine_configmap_data(
+        &cm,
+        None,
+        KeyCollisionPolicy::default(),
+        BinaryDataPolicy::Skip,
+    )
+    .documents
+    .into_iter()
+    .map(|(key, content)| (key, content.into_owned()))
+    .collect())
+}
+
+/// Applies the watch events of one ConfigMap to the known documents.
+struct ConfigMapWatch {
+    configmap_name: String,
+    key_pattern: Option<Pattern>,
+    state: ConfigMapState,
+    // ConfigMap listed between `Init` and `InitDone` of a (re-)started watch; `Some(None)`
+    // while listing and the ConfigMap was not (yet) seen
+    init_buffer: Option<Option<ConfigMap>>,
+    initial_scan_done: bool,
+}
+
+impl ConfigMapWatch {
+    fn new(configmap_name: String, key_pattern: Option<Pattern>) -> Self {
+        Self {
+            configmap_name,
+            key_pattern,
+            state: ConfigMapState::default(),
+            init_buffer: None,
+            initial_scan_done: false,
         }
     }
 
-    if let Some(binary_data) = &cm.binary_data {
-        for (key, value) in binary_data {
-            match std::str::from_utf8(&value.0) {
-                Ok(as_str) => {
-                    result.insert(key.clone(), Cow::Borrowed(as_str));
+    /// Returns true once the initial listing is complete and no re-listing is in progress.
+    fn is_synced(&self) -> bool {
+        self.initial_scan_done && self.init_buffer.is_none()
+    }
+
+    async fn handle_event(
+        &mut self,
+        event: watcher::Event<ConfigMap>,
+        event_sender: &EventSender,
+        options: &ConfigMapWatcherOptions,
+    ) {
+        match event {
+            watcher::Event::Init => {
+                self.init_buffer = Some(None);
+            }
+            watcher::Event::InitApply(cm) => {
+                if self.is_watched(&cm) {
+                    match self.init_buffer.as_mut() {
+                        Some(buffered) => *buffered = Some(cm),
+                        None => self.sync(Some(&cm), event_sender, options).await,
+                    }
                 }
-                Err(e) => {
-                    log::error!("Cannot utf8 decode value to string: {:?}", e);
+            }
+            watcher::Event::InitDone => {
+                // Diff the complete (re-)listed state against the known documents
+                // so that keys removed while the watch was down are reported too
+                if let Some(buffered) = self.init_buffer.take() {
+                    self.sync(buffered.as_ref(), event_sender, options).await;
+                }
+                if !self.initial_scan_done {
+                    self.initial_scan_done = true;
+                    event_sender
+                        .send(DocumentEvent::InitialScanComplete)
+                        .await
+                        .ok();
+                }
+            }
+            watcher::Event::Apply(cm) => {
+                if self.is_watched(&cm) {
+                    self.sync(Some(&cm), event_sender, options).await;
+                }
+            }
+            watcher::Event::Delete(cm) => {
+                if self.is_watched(&cm) {
+                    self.sync(None, event_sender, options).await;
                 }
             }
         }
     }
 
-    result
+    fn is_watched(&self, cm: &ConfigMap) -> bool {
+        cm.metadata.name.as_deref() == Some(self.configmap_name.as_str())
+    }
+
+    async fn sync(
+        &mut self,
+        cm: Option<&ConfigMap>,
+        event_sender: &EventSender,
+        options: &ConfigMapWatcherOptions,
+    ) {
+        sync_configmap(
+            cm,
+            self.key_pattern.as_ref(),
+            &mut self.state,
+            event_sender,
+            options,
+        )
+        .await;
+    }
+}
+
+/// Documents of a watched ConfigMap known to the watcher.
+#[derive(Default)]
+struct ConfigMapState {
+    // Content hash by key of the emitted documents
+    file_hashes: HashMap<String, u64>,
+    // Hash of the value by key of the reported undecodable binaryData values
+    invalid: HashMap<String, u64>,
 }
 
-/// Handles updates to the ConfigMap, detecting per-field changes.
-async fn handle_configmap_update(
-    new_data: BTreeMap<String, Cow<'_, str>>,
-    file_hashes: &mut HashMap<String, u64>,
-    event_sender: &mpsc::Sender<DocumentEvent>,
+/// Emits the changes of the documents of `cm`, or the removal of all documents if it is `None`.
+async fn sync_configmap(
+    cm: Option<&ConfigMap>,
+    key_pattern: Option<&Pattern>,
+    state: &mut ConfigMapState,
+    event_sender: &EventSender,
+    options: &ConfigMapWatcherOptions,
 ) {
-    let mut new_hashes: HashMap<String, u64> = HashMap::new();
-
-    // Detect new files and content changes
-    for (key, value) in &new_data {
-        let new_hash = hash_str(value);
-        new_hashes.insert(key.clone(), new_hash);
-
-        match file_hashes.get(key) {
-            Some(&existing_hash) if existing_hash != new_hash => {
-                event_sender
-                    .send(DocumentEvent::ContentChanged(
-                        key.clone(),
-                        value.to_string(),
-                    ))
-                    .await
-                    .ok();
-            }
-            None => {
-                event_sender
-                    .send(DocumentEvent::NewDocument(key.clone(), value.to_string()))
-                    .await
-                    .ok();
-            }
-            _ => {}
+    let data = cm
+        .map(|cm| {
+            combine_configmap_data(
+                cm,
+                key_pattern,
+                options.key_collisions,
+                options.invalid_binary_data,
+            )
+        })
+        .unwrap_or_default();
+    // Undecodable keys keep their previous content, like unreadable files
+    let kept: Vec<(String, u64)> = data
+        .invalid
+        .iter()
+        .filter_map(|invalid| state.file_hashes.remove_entry(&invalid.key))
+        .collect();
+    sync_documents(
+        data.documents,
+        &mut state.file_hashes,
+        event_sender,
+        options.event_mask,
+    )
+    .await;
+    state.file_hashes.extend(kept);
+
+    let configmap = cm
+        .and_then(|cm| cm.metadata.name.clone())
+        .unwrap_or_default();
+    let mut invalid = HashMap::new();
+    for InvalidValue { key, hash, error } in data.invalid {
+        invalid.insert(key.clone(), hash);
+        if state.invalid.get(&key) == Some(&hash) {
+            continue;
+        }
+        let error = WatcherError::InvalidBinaryData {
+            configmap: configmap.clone(),
+            key: key.clone(),
+            error,
+        };
+        log::error!("{}", error);
+        send_masked(
+            event_sender,
+            options.event_mask,
+            DocumentEvent::ReadFailed(key, error),
+        )
+        .await
+        .ok();
+    }
+    state.invalid = invalid;
+}
+
+/// Hashes a `binaryData` value to detect when it changes.
+fn hash_bytes(data: &[u8]) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = twox_hash::XxHash64::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Documents of a ConfigMap by key, see [`combine_configmap_data`].
+#[derive(Default)]
+struct ConfigMapData<'a> {
+    documents: BTreeMap<String, Cow<'a, str>>,
+    // Undecodable binaryData values to report with `BinaryDataPolicy::Error`
+    invalid: Vec<InvalidValue>,
+}
+
+/// A `binaryData` value that is not valid UTF-8.
+struct InvalidValue {
+    key: String,
+    hash: u64,
+    error: std::str::Utf8Error,
+}
+
+fn combine_configmap_data<'a>(
+    cm: &'a ConfigMap,
+    key_pattern: Option<&Pattern>,
+    key_collisions: KeyCollisionPolicy,
+    invalid_binary_data: BinaryDataPolicy,
+) -> ConfigMapData<'a> {
+    let mut result = BTreeMap::new();
+    let mut invalid = Vec::new();
+    let included = |key: &str| key_pattern.is_none_or(|pattern| pattern.matches(key));
+    let name = cm.metadata.name.as_deref().unwrap_or_default();
+
+    if let Some(data) = &cm.data {
+        for (key, value) in data.iter().filter(|(key, _)| included(key)) {
+            result.insert(key.clone(), Cow::Borrowed(value.as_str()));
         }
     }
 
-    // Detect removed files
-    for key in file_hashes.keys() {
-        if !new_data.contains_key(key) {
-            event_sender
-                .send(DocumentEvent::DocumentRemoved(key.clone()))
-                .await
-                .ok();
+    if let Some(binary_data) = &cm.binary_data {
+        for (key, value) in binary_data.iter().filter(|(key, _)| included(key)) {
+            if result.contains_key(key) {
+                match key_collisions {
+                    KeyCollisionPolicy::PreferData => {
+                        log::warn!(
+                            "Key [{}] is in both data and binaryData of ConfigMap [{}], using data",
+                            key,
+                            name
+                        );
+                        continue;
+                    }
+                    KeyCollisionPolicy::PreferBinaryData => {
+                        log::warn!(
+                            "Key [{}] is in both data and binaryData of ConfigMap [{}], using binaryData",
+                            key,
+                            name
+                        );
+                    }
+                    KeyCollisionPolicy::Skip => {
+                        log::error!(
+                            "Key [{}] is in both data and binaryData of ConfigMap [{}], skipping it",
+                            key,
+                            name
+                        );
+                        result.remove(key);
+                        continue;
+                    }
+                }
+            }
+            match (std::str::from_utf8(&value.0), invalid_binary_data) {
+                (Ok(as_str), _) => {
+                    result.insert(key.clone(), Cow::Borrowed(as_str));
+                }
+                (Err(_), BinaryDataPolicy::Base64) => {
+                    result.insert(key.clone(), Cow::Owned(BASE64_STANDARD.encode(&value.0)));
+                }
+                // The value from data is used instead
+                (Err(e), _) if result.contains_key(key) => {
+                    log::warn!(
+                        "binaryData key [{}] of ConfigMap [{}] is not valid UTF-8, using data: {}",
+                        key,
+                        name,
+                        e
+                    );
+                }
+                (Err(e), BinaryDataPolicy::Skip) => {
+                    log::error!(
+                        "binaryData key [{}] of ConfigMap [{}] is not valid UTF-8, skipping it: {}",
+                        key,
+                        name,
+                        e
+                    );
+                }
+                (Err(error), BinaryDataPolicy::Error) => invalid.push(InvalidValue {
+                    key: key.clone(),
+                    hash: hash_bytes(&value.0),
+                    error,
+                }),
+            }
         }
     }
 
-    *file_hashes = new_hashes; // Update stored hashes
+    ConfigMapData {
+        documents: result,
+        invalid,
+    }
 }
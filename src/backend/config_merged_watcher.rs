@@ -0,0 +1,301 @@
+use futures::StreamExt;
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+use tokio::sync::mpsc;
+
+use super::{
+    command_channel, wait_for_start, DocumentEvent, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::{hash_str, into_stream, WatcherError};
+
+/// Combines the documents of several backends into one watcher.
+///
+/// Each source is given with a name that identifies it in [`DocumentEvent::DuplicateId`]
+/// events. The sources are started and stopped together with the returned handle, and
+/// `InitialScanComplete` is emitted once every source has completed its initial scan or ended.
+///
+/// A document id belongs to the first source that reports it. If another source reports the same
+/// id, its content is held back, a warning is logged and `DuplicateId` is emitted, naming all
+/// sources of the id with the owner first. When the owner removes the document, the next source
+/// takes over and its content is emitted as a change.
+///
+/// [`WatcherHandle::load_document`] and [`WatcherHandle::refresh_document`] are forwarded to the
+/// owner of the document and [`WatcherHandle::set_debounce`] to all sources. The watcher ends
+/// when all sources ended.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_merged_watcher(
+    sources: Vec<(String, (WatcherHandle, mpsc::Receiver<DocumentEvent>))>,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let mut names = Vec::with_capacity(sources.len());
+    let mut handles = Vec::with_capacity(sources.len());
+    let mut receivers = Vec::with_capacity(sources.len());
+    for (name, (handle, receiver)) in sources {
+        names.push(name);
+        handles.push(handle);
+        receivers.push(receiver);
+    }
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin. Dropping the handles stops the sources.
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+        for handle in &handles {
+            if command_receiver.timestamps() {
+                handle.emit_timestamps().await?;
+            }
+            handle.start().await?;
+        }
+
+        // Events tagged with the index of their source, `None` once the source has ended
+        let mut events = futures::stream::select_all(receivers.into_iter().enumerate().map(
+            |(index, receiver)| {
+                into_stream(receiver)
+                    .map(Some)
+                    .chain(futures::stream::iter([None]))
+                    .map(move |event| (index, event))
+            },
+        ));
+        let mut state = MergeState::new(names);
+
+        loop {
+            tokio::select! {
+                event = events.next() => {
+                    let Some((index, event)) = event else {
+                        log::debug!("All sources of the merged watcher ended");
+                        break;
+                    };
+                    // Keep the time the source detected the event
+                    let (observed_at, event) = match event.map(DocumentEvent::into_observed) {
+                        Some((observed_at, event)) => (observed_at, Some(event)),
+                        None => (None, None),
+                    };
+                    let observed_at = observed_at.unwrap_or_else(SystemTime::now);
+                    for event in state.handle_event(index, event) {
+                        event_sender.send_observed(observed_at, event).await.ok();
+                    }
+                }
+                // Check for control commands
+                Some(command) = command_receiver.recv() => match command {
+                    WatcherCommand::Stop => {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                    // Sent from separate tasks, since a source may wait for this loop to take
+                    // its events before it reads commands
+                    WatcherCommand::LoadDocument(id, reply_sender) => {
+                        if let Some(index) = state.owner(&id) {
+                            let sender = handles[index].command_sender.clone();
+                            tokio::spawn(async move {
+                                sender
+                                    .send(WatcherCommand::LoadDocument(id, reply_sender))
+                                    .await
+                                    .ok();
+                            });
+                        }
+                    }
+                    WatcherCommand::RefreshDocument(id, reply_sender) => {
+                        if let Some(index) = state.refresh(&id) {
+                            let sender = handles[index].command_sender.clone();
+                            tokio::spawn(async move {
+                                sender
+                                    .send(WatcherCommand::RefreshDocument(id, reply_sender))
+                                    .await
+                                    .ok();
+                            });
+                        }
+                    }
+                    WatcherCommand::SetDebounce(debounce) => {
+                        for handle in &handles {
+                            let sender = handle.command_sender.clone();
+                            tokio::spawn(async move {
+                                sender.send(WatcherCommand::SetDebounce(debounce)).await.ok();
+                            });
+                        }
+                    }
+                    // The counters of several sources are not combined
+                    WatcherCommand::DebounceMetrics(_)
+                    | WatcherCommand::Start
+                    | WatcherCommand::EmitTimestamps => {}
+                }
+            }
+        }
+
+        // Keep taking events while the sources stop, so none of them blocks on a full channel
+        let mut stopping = std::pin::pin!(futures::future::join_all(
+            handles.iter_mut().map(WatcherHandle::stop)
+        ));
+        let results = loop {
+            tokio::select! {
+                results = &mut stopping => break results,
+                Some(_) = events.next() => {}
+            }
+        };
+        log::debug!("Exiting merged config watcher");
+        results.into_iter().collect()
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Merged,
+        },
+        event_receiver,
+    ))
+}
+
+/// A source reporting a document id, with the hash of its latest content. The content is only
+/// kept for sources that do not own the id.
+struct Claim {
+    source: usize,
+    hash: u64,
+    content: Option<String>,
+}
+
+/// Tracks which sources report which document ids.
+struct MergeState {
+    names: Vec<String>,
+    // Claims per document id, the owner first
+    claims: HashMap<String, Vec<Claim>>,
+    // Ids refreshed by their owner, whose next content is emitted even if unchanged
+    refreshing: HashSet<String>,
+    scan_complete: Vec<bool>,
+    scan_reported: bool,
+}
+
+impl MergeState {
+    fn new(names: Vec<String>) -> Self {
+        Self {
+            scan_complete: vec![false; names.len()],
+            names,
+            claims: HashMap::new(),
+            refreshing: HashSet::new(),
+            scan_reported: false,
+        }
+    }
+
+    /// Returns the index of the source that owns the document `id`.
+    fn owner(&self, id: &str) -> Option<usize> {
+        Some(self.claims.get(id)?.first()?.source)
+    }
+
+    /// Returns the owner of the document `id` to refresh it, and emits the next content it
+    /// reports even if unchanged.
+    fn refresh(&mut self, id: &str) -> Option<usize> {
+        let owner = self.owner(id)?;
+        self.refreshing.insert(id.to_string());
+        Some(owner)
+    }
+
+    /// Returns the events to emit for `event` of source `index`. `None` means the source ended.
+    fn handle_event(&mut self, index: usize, event: Option<DocumentEvent>) -> Vec<DocumentEvent> {
+        match event {
+            Some(DocumentEvent::NewDocument(id, content))
+            | Some(DocumentEvent::ContentChanged(id, content)) => {
+                self.update(index, id, content).into_iter().collect()
+            }
+            Some(DocumentEvent::DocumentRemoved(id)) => {
+                self.remove(index, id).into_iter().collect()
+            }
+            // Only the content of the owner is used
+            Some(DocumentEvent::Touched(id)) => match self.owner(&id) == Some(index) {
+                true => vec![DocumentEvent::Touched(id)],
+                false => vec![],
+            },
+            Some(DocumentEvent::ReadFailed(id, error)) => match self.owner(&id) == Some(index) {
+                true => vec![DocumentEvent::ReadFailed(id, error)],
+                false => vec![],
+            },
+            Some(DocumentEvent::InitialScanComplete) | None => {
+                self.scan_complete[index] = true;
+                if !self.scan_reported && self.scan_complete.iter().all(|complete| *complete) {
+                    self.scan_reported = true;
+                    return vec![DocumentEvent::InitialScanComplete];
+                }
+                vec![]
+            }
+            // Nested merged watchers: keep the duplicates reported by the inner watcher
+            Some(event @ DocumentEvent::DuplicateId { .. }) => vec![event],
+            Some(DocumentEvent::Observed { event, .. }) => self.handle_event(index, Some(*event)),
+        }
+    }
+
+    fn update(&mut self, index: usize, id: String, content: String) -> Option<DocumentEvent> {
+        let hash = hash_str(&content);
+        let claims = self.claims.entry(id.clone()).or_default();
+        match claims.iter().position(|claim| claim.source == index) {
+            // New document
+            None if claims.is_empty() => {
+                claims.push(Claim {
+                    source: index,
+                    hash,
+                    content: None,
+                });
+                Some(DocumentEvent::NewDocument(id, content))
+            }
+            // Owner changed the document
+            Some(0) => {
+                let changed = self.refreshing.remove(&id) || claims[0].hash != hash;
+                claims[0].hash = hash;
+                changed.then_some(DocumentEvent::ContentChanged(id, content))
+            }
+            // Another source changed its held back copy
+            Some(position) => {
+                claims[position].hash = hash;
+                claims[position].content = Some(content);
+                None
+            }
+            // Another source reports the id for the first time
+            None => {
+                claims.push(Claim {
+                    source: index,
+                    hash,
+                    content: Some(content),
+                });
+                let sources: Vec<String> = claims
+                    .iter()
+                    .map(|claim| self.names[claim.source].clone())
+                    .collect();
+                log::warn!(
+                    "Document [{}] is reported by several sources, using the content of [{}]: {:?}",
+                    id,
+                    sources[0],
+                    sources
+                );
+                Some(DocumentEvent::DuplicateId { id, sources })
+            }
+        }
+    }
+
+    fn remove(&mut self, index: usize, id: String) -> Option<DocumentEvent> {
+        let claims = self.claims.get_mut(&id)?;
+        let position = claims.iter().position(|claim| claim.source == index)?;
+        let removed = claims.remove(position);
+        if position > 0 {
+            return None;
+        }
+        self.refreshing.remove(&id);
+        let Some(owner) = claims.first_mut() else {
+            self.claims.remove(&id);
+            return Some(DocumentEvent::DocumentRemoved(id));
+        };
+        // The next source takes over the document
+        log::info!(
+            "Document [{}] removed by [{}], using the content of [{}]",
+            id,
+            self.names[removed.source],
+            self.names[owner.source]
+        );
+        let content = owner.content.take().unwrap_or_default();
+        (owner.hash != removed.hash).then_some(DocumentEvent::ContentChanged(id, content))
+    }
+}
@@ -1,8 +1,69 @@
-use super::{DocumentEvent, WatcherHandle};
-use crate::{backend::WatcherCommand, hash_str, WatcherError};
+use super::{
+    command_channel, send_masked, wait_for_start, DocumentEvent, EventMask, EventSender,
+    RetryDelay, SourceKind, WatcherHandle,
+};
+use crate::{
+    backend::WatcherCommand,
+    clock::{default_clock, SharedClock},
+    hash_str, WatcherError,
+};
+use futures::{Stream, StreamExt};
 use rumqttc::{AsyncClient, ConnectionError, QoS};
 use std::{collections::HashMap, time::Duration};
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, time::Instant};
+
+/// Default of [`MqttWatcherOptions::retained_settle`].
+const READY_SETTLE_WINDOW: Duration = Duration::from_millis(500);
+
+/// Wildcard appended to the config topic when subscribing.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Wildcard {
+    /// `{topic}/#`: all topics below the config topic.
+    #[default]
+    MultiLevel,
+    /// `{topic}/+`: only direct children of the config topic.
+    SingleLevel,
+    /// The config topic as is. It may contain wildcards itself.
+    None,
+}
+
+/// Optional settings for [`run_mqtt_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct MqttWatcherOptions {
+    /// Delay before polling the connection again after a connection error (default 5 seconds
+    /// ± 20%).
+    pub retry_delay: RetryDelay,
+    /// Give up after this many consecutive connection errors without a successful connection in
+    /// between (default none: retry forever). The watcher task then ends with
+    /// [`WatcherError::MqttReconnectExhausted`], which [`WatcherHandle::stop`] returns.
+    pub max_reconnect_attempts: Option<u32>,
+    /// Which topics below the config topic are watched (default [`Wildcard::MultiLevel`]).
+    pub wildcard: Wildcard,
+    /// Categories of events to emit (default [`EventMask::ALL`]). Suppressed changes are still
+    /// recorded, so later events are based on the latest content.
+    pub event_mask: EventMask,
+    /// Quiet period that ends the burst of retained messages after the first connection (default
+    /// 500 ms). `InitialScanComplete` is emitted once no retained message arrived for this long
+    /// after the connection acknowledgement, or right before the first message that is not
+    /// retained, since brokers deliver the retained messages of a subscription first.
+    pub retained_settle: Duration,
+    /// Source of the time for the retry and initial scan timers (default
+    /// [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+
+impl Default for MqttWatcherOptions {
+    fn default() -> Self {
+        Self {
+            retry_delay: RetryDelay::default(),
+            max_reconnect_attempts: None,
+            wildcard: Wildcard::default(),
+            event_mask: EventMask::ALL,
+            retained_settle: READY_SETTLE_WINDOW,
+            clock: default_clock(),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct MqttPublishEvent {
@@ -27,24 +88,36 @@ pub fn run_mqtt_watcher(
     config_topic: &str,
     channel_size: usize,
 ) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
-    let (event_sender, receiver) = mpsc::channel(channel_size);
+    run_mqtt_watcher_with_options(
+        mqttoptions,
+        config_topic,
+        channel_size,
+        MqttWatcherOptions::default(),
+    )
+}
+
+/// Same as [`run_mqtt_watcher`] but with additional [`MqttWatcherOptions`].
+pub fn run_mqtt_watcher_with_options(
+    mqttoptions: rumqttc::MqttOptions,
+    config_topic: &str,
+    channel_size: usize,
+    options: MqttWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, receiver) = command_receiver.event_channel(channel_size);
 
     let (mqtt_client, mut eventloop) = AsyncClient::new(mqttoptions, channel_size);
-    let (command_sender, mut command_receiver) = mpsc::channel(1);
 
-    let config_topic = format!("{}/#", config_topic.trim_end_matches('/'));
+    let config_topic = subscription_topic(config_topic, options.wildcard)?;
 
     let handle = tokio::task::spawn(async move {
         // Wait for a start command before we begin
-        match command_receiver.recv().await {
-            Some(WatcherCommand::Stop) | None => {
-                // Exit early if Stop command is received or channel is closed
-                log::info!("Watcher received stop command before starting or channel closed");
-                return Ok(());
-            }
-            _ => {}
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
         }
-        let mut hashes: HashMap<String, u64> = HashMap::new();
+        let mut state = MqttState::default();
+        // Connection errors since the last successful connection
+        let mut failed_attempts: u32 = 0;
 
         loop {
             tokio::select! {
@@ -52,52 +125,14 @@ pub fn run_mqtt_watcher(
                     match poll_res {
                         Ok(event) => match event {
                             rumqttc::Event::Incoming(rumqttc::Packet::Publish(p)) => {
-                                let topic = p.topic;
-                                if p.payload.is_empty() {
-                                    // deleted topic
-                                    if hashes.remove(&topic).is_some() {
-                                        event_sender
-                                            .send(DocumentEvent::DocumentRemoved(topic))
-                                            .await
-                                            .unwrap();
-                                    }
-                                } else {
-                                    // published new or updated content
-                                    let content = match String::from_utf8(p.payload.to_vec()) {
-                                        Ok(payload) => payload,
-                                        Err(err) => {
-                                            log::warn!(
-                                            "Cannot parse mqtt payload for topic [{}] to string. Error: {}",
-                                            topic,
-                                            err
-                                        );
-                                            continue;
-                                        }
-                                    };
-
-                                    let new_hash = hash_str(&content);
-                                    if let Some(existing_hash) = hashes.get(&topic) {
-                                        // File exists: Check if the hash has changed
-                                        if existing_hash != &new_hash {
-                                            // Content changed: Update the hash and emit `ContentChanged`
-                                            hashes.insert(topic.clone(), new_hash);
-                                            event_sender
-                                                .send(DocumentEvent::ContentChanged(topic, content))
-                                                .await
-                                                .unwrap();
-                                        }
-                                    } else {
-                                        // File does not exist in `file_hashes`: It's a new file
-                                        hashes.insert(topic.clone(), new_hash);
-                                        event_sender
-                                            .send(DocumentEvent::NewDocument(topic, content))
-                                            .await
-                                            .unwrap();
-                                    }
-                                }
+                                state
+                                    .handle_publish(p.topic, &p.payload, p.retain, &event_sender, &options)
+                                    .await;
                             }
                             rumqttc::Event::Incoming(rumqttc::Incoming::ConnAck(_)) => {
                                 log::debug!("HOMIE: Connected");
+                                failed_attempts = 0;
+                                state.connected(&options);
                                 // subscribe to config topic
                                 mqtt_client
                                     .subscribe(&config_topic, rumqttc::QoS::ExactlyOnce)
@@ -112,11 +147,22 @@ pub fn run_mqtt_watcher(
 
                         Err(err) => {
                             log::error!("Error connecting mqtt. {:#?}", err);
-                            tokio::time::sleep(Duration::from_secs(5)).await;
+                            failed_attempts += 1;
+                            if options
+                                .max_reconnect_attempts
+                                .is_some_and(|max_attempts| failed_attempts >= max_attempts)
+                            {
+                                return Err(WatcherError::MqttReconnectExhausted(failed_attempts));
+                            }
+                            options.retry_delay.sleep_on(&*options.clock).await;
                         }
                     };
 
                 },
+                // Signal the initial scan as complete once the retained burst has settled
+                _ = options.clock.sleep_until(state.ready_at.unwrap_or_else(|| options.clock.now())), if state.ready_at.is_some() => {
+                    state.complete_initial_scan(&event_sender).await;
+                }
                 // Check for control commands
                 Some(command) = command_receiver.recv() => {
                     if let WatcherCommand::Stop = command {
@@ -134,7 +180,187 @@ pub fn run_mqtt_watcher(
         WatcherHandle {
             handle: Some(handle),
             command_sender,
+            source_kind: SourceKind::Mqtt,
+        },
+        receiver,
+    ))
+}
+
+/// Same as [`run_mqtt_watcher_with_options`], but processes the client events of `events`
+/// instead of connecting to a broker, e.g. to drive the watcher with synthetic messages in tests.
+/// [`MqttClientEvent::Connect`] stands for a connection acknowledgement and starts the retained
+/// burst, [`MqttClientEvent::Stop`] or the end of the stream ends the watcher. Errors are only
+/// logged, so `retry_delay` and `max_reconnect_attempts` have no effect, and `wildcard` does not
+/// apply since nothing is subscribed.
+pub fn run_mqtt_watcher_from_stream<S>(
+    events: S,
+    options: MqttWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    S: Stream<Item = MqttClientEvent> + Send + Unpin + 'static,
+{
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::task::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+        let mut events = events;
+        let mut state = MqttState::default();
+
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(MqttClientEvent::PublishMessage(p)) => {
+                        state
+                            .handle_publish(p.topic, p.payload.as_bytes(), p.retain, &event_sender, &options)
+                            .await;
+                    }
+                    Some(MqttClientEvent::Connect) => state.connected(&options),
+                    Some(MqttClientEvent::Disconnect) => log::debug!("MQTT client disconnected"),
+                    Some(MqttClientEvent::Error(err)) => log::error!("Error connecting mqtt. {:#?}", err),
+                    Some(MqttClientEvent::Stop) | None => {
+                        log::debug!("MQTT client event stream ended");
+                        break;
+                    }
+                },
+                // Signal the initial scan as complete once the retained burst has settled
+                _ = options.clock.sleep_until(state.ready_at.unwrap_or_else(|| options.clock.now())), if state.ready_at.is_some() => {
+                    state.complete_initial_scan(&event_sender).await;
+                }
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                }
+            };
+        }
+        log::debug!("Exiting mqtt config watcher eventloop...");
+        Ok(())
+    });
+    Ok((
+        WatcherHandle {
+            handle: Some(handle),
+            command_sender,
+            source_kind: SourceKind::Mqtt,
         },
         receiver,
     ))
 }
+
+/// Documents and initial scan progress of a running MQTT watcher.
+#[derive(Default)]
+struct MqttState {
+    hashes: HashMap<String, u64>,
+    // Deadline for signaling the initial scan as complete, set on the first ConnAck
+    ready_at: Option<Instant>,
+    ready_sent: bool,
+}
+
+impl MqttState {
+    /// Starts the retained burst on the first connection.
+    fn connected(&mut self, options: &MqttWatcherOptions) {
+        if !self.ready_sent && self.ready_at.is_none() {
+            self.ready_at = Some(options.clock.now() + options.retained_settle);
+        }
+    }
+
+    async fn complete_initial_scan(&mut self, event_sender: &EventSender) {
+        self.ready_at = None;
+        self.ready_sent = true;
+        event_sender
+            .send(DocumentEvent::InitialScanComplete)
+            .await
+            .ok();
+    }
+
+    /// Emits the change of the document of `topic`; an empty payload deletes it.
+    async fn handle_publish(
+        &mut self,
+        topic: String,
+        payload: &[u8],
+        retain: bool,
+        event_sender: &EventSender,
+        options: &MqttWatcherOptions,
+    ) {
+        // The retained burst continues until a quiet period or a live message
+        if self.ready_at.is_some() {
+            if retain {
+                self.ready_at = Some(options.clock.now() + options.retained_settle);
+            } else {
+                self.complete_initial_scan(event_sender).await;
+            }
+        }
+        // Retained messages delivered again after a reconnect have the
+        // known hash and produce no event
+        if payload.is_empty() {
+            // deleted topic
+            if self.hashes.remove(&topic).is_some() {
+                send_masked(
+                    event_sender,
+                    options.event_mask,
+                    DocumentEvent::DocumentRemoved(topic),
+                )
+                .await
+                .ok();
+            }
+            return;
+        }
+        // published new or updated content
+        let content = match String::from_utf8(payload.to_vec()) {
+            Ok(payload) => payload,
+            Err(err) => {
+                log::warn!(
+                    "Cannot parse mqtt payload for topic [{}] to string. Error: {}",
+                    topic,
+                    err
+                );
+                return;
+            }
+        };
+
+        let new_hash = hash_str(&content);
+        if let Some(existing_hash) = self.hashes.get(&topic) {
+            // File exists: Check if the hash has changed
+            if existing_hash != &new_hash {
+                // Content changed: Update the hash and emit `ContentChanged`
+                self.hashes.insert(topic.clone(), new_hash);
+                send_masked(
+                    event_sender,
+                    options.event_mask,
+                    DocumentEvent::ContentChanged(topic, content),
+                )
+                .await
+                .ok();
+            }
+        } else {
+            // File does not exist in `file_hashes`: It's a new file
+            self.hashes.insert(topic.clone(), new_hash);
+            send_masked(
+                event_sender,
+                options.event_mask,
+                DocumentEvent::NewDocument(topic, content),
+            )
+            .await
+            .ok();
+        }
+    }
+}
+
+/// Builds the topic filter to subscribe to and checks that it is a valid MQTT filter.
+fn subscription_topic(config_topic: &str, wildcard: Wildcard) -> Result<String, WatcherError> {
+    let topic = match wildcard {
+        Wildcard::MultiLevel => format!("{}/#", config_topic.trim_end_matches('/')),
+        Wildcard::SingleLevel => format!("{}/+", config_topic.trim_end_matches('/')),
+        Wildcard::None => config_topic.to_string(),
+    };
+    if rumqttc::valid_filter(&topic) {
+        Ok(topic)
+    } else {
+        Err(WatcherError::InvalidTopic(topic))
+    }
+}
@@ -0,0 +1,283 @@
+use async_nats::jetstream::kv::{Entry, Operation};
+use async_nats::Message;
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+
+use super::{
+    command_channel, remove_document, update_document, wait_for_start, CommandReceiver,
+    DocumentEvent, EventSender, RetryDelay, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::{
+    clock::{default_clock, SharedClock},
+    WatcherError,
+};
+
+/// Subscribes to a NATS subject and emits each message subject as a document.
+///
+/// `subject` may contain wildcards (e.g. `config.>`). A message with an empty payload removes
+/// the document of its subject, like a deleted retained message in the MQTT watcher. Reconnects
+/// are handled by the NATS client, which restores the subscription.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_nats_watcher(
+    url: String,
+    subject: String,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let client = async_nats::connect(&url)
+            .await
+            .map_err(|err| WatcherError::Nats(err.into()))?;
+        let subscriber = client
+            .subscribe(subject)
+            .await
+            .map_err(|err| WatcherError::Nats(err.into()))?;
+        watch_messages(subscriber, command_receiver, event_sender).await;
+        log::debug!("Exiting NATS config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Nats,
+        },
+        event_receiver,
+    ))
+}
+
+/// Same as [`run_nats_watcher`], but processes the messages of `messages` instead of subscribing
+/// to a NATS server, e.g. to drive the watcher with synthetic messages in tests or to share one
+/// subscription. The watcher ends when the stream ends.
+pub fn run_nats_watcher_from_stream<S>(
+    messages: S,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    S: Stream<Item = Message> + Send + Unpin + 'static,
+{
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+        watch_messages(messages, command_receiver, event_sender).await;
+        log::debug!("Exiting NATS config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Nats,
+        },
+        event_receiver,
+    ))
+}
+
+/// Emits the messages of a subscription as documents until it ends or the watcher is stopped.
+async fn watch_messages(
+    mut messages: impl Stream<Item = Message> + Unpin,
+    mut command_receiver: CommandReceiver,
+    event_sender: EventSender,
+) {
+    let mut hashes: HashMap<String, u64> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            message = messages.next() => {
+                let Some(message) = message else {
+                    log::warn!("NATS subscription ended. There will not be any more config updates.");
+                    break;
+                };
+                let id = message.subject.to_string();
+                if message.payload.is_empty() {
+                    remove_document(id, &mut hashes, &event_sender).await;
+                } else {
+                    update_payload(id, &message.payload, &mut hashes, &event_sender).await;
+                }
+            }
+            // Check for control commands
+            Some(command) = command_receiver.recv() => {
+                if let WatcherCommand::Stop = command {
+                    log::info!("Watcher received stop command");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Optional settings for [`run_nats_kv_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct NatsKvWatcherOptions {
+    /// Delay before re-establishing a failed watch (default 5 seconds ± 20%).
+    pub retry_delay: RetryDelay,
+    /// Source of the time for the retry timer (default [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+
+impl Default for NatsKvWatcherOptions {
+    fn default() -> Self {
+        Self {
+            retry_delay: RetryDelay::default(),
+            clock: default_clock(),
+        }
+    }
+}
+
+/// Watches all keys of a NATS JetStream key-value bucket and emits each key as a document.
+///
+/// The current value of every key is emitted first, followed by `InitialScanComplete` once the
+/// watch has caught up (not sent for an empty bucket). Deleted and purged keys are reported as
+/// removed. If the watch fails, it is re-established after the retry delay (about 5 seconds by default);
+/// the replayed current values (including delete markers) are diffed against the tracked documents.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_nats_kv_watcher(
+    url: String,
+    bucket: String,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    run_nats_kv_watcher_with_options(url, bucket, NatsKvWatcherOptions::default())
+}
+
+/// Same as [`run_nats_kv_watcher`] but with additional [`NatsKvWatcherOptions`].
+pub fn run_nats_kv_watcher_with_options(
+    url: String,
+    bucket: String,
+    options: NatsKvWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let client = async_nats::connect(&url)
+            .await
+            .map_err(|err| WatcherError::Nats(err.into()))?;
+        let store = async_nats::jetstream::new(client)
+            .get_key_value(bucket)
+            .await
+            .map_err(|err| WatcherError::Nats(err.into()))?;
+        let mut hashes: HashMap<String, u64> = HashMap::new();
+        let mut ready_sent = false;
+
+        'watch: loop {
+            let mut watch = match store.watch_with_history(">").await {
+                Ok(watch) => watch,
+                Err(err) => {
+                    log::error!("Error watching NATS key-value bucket: {}", err);
+                    tokio::select! {
+                        _ = options.retry_delay.sleep_on(&*options.clock) => continue 'watch,
+                        Some(WatcherCommand::Stop) = command_receiver.recv() => break 'watch,
+                    }
+                }
+            };
+
+            loop {
+                tokio::select! {
+                    entry = watch.next() => {
+                        match entry {
+                            Some(Ok(entry)) => {
+                                let seen_current = entry.seen_current;
+                                handle_kv_entry(entry, &mut hashes, &event_sender).await;
+                                if seen_current && !ready_sent {
+                                    ready_sent = true;
+                                    event_sender
+                                        .send(DocumentEvent::InitialScanComplete)
+                                        .await
+                                        .ok();
+                                }
+                            }
+                            Some(Err(err)) => {
+                                log::error!("Error in NATS key-value watch: {}. Restarting watch.", err);
+                                tokio::select! {
+                                    _ = options.retry_delay.sleep_on(&*options.clock) => continue 'watch,
+                                    Some(WatcherCommand::Stop) = command_receiver.recv() => break 'watch,
+                                }
+                            }
+                            None => {
+                                log::warn!("NATS key-value watch ended. Restarting watch.");
+                                tokio::select! {
+                                    _ = options.retry_delay.sleep_on(&*options.clock) => continue 'watch,
+                                    Some(WatcherCommand::Stop) = command_receiver.recv() => break 'watch,
+                                }
+                            }
+                        }
+                    }
+                    // Check for control commands
+                    Some(command) = command_receiver.recv() => {
+                        if let WatcherCommand::Stop = command {
+                            log::info!("Watcher received stop command");
+                            break 'watch;
+                        }
+                    }
+                }
+            }
+        }
+
+        log::debug!("Exiting NATS key-value config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Nats,
+        },
+        event_receiver,
+    ))
+}
+
+async fn handle_kv_entry(
+    entry: Entry,
+    hashes: &mut HashMap<String, u64>,
+    event_sender: &EventSender,
+) {
+    match entry.operation {
+        Operation::Put => update_payload(entry.key, &entry.value, hashes, event_sender).await,
+        Operation::Delete | Operation::Purge => {
+            remove_document(entry.key, hashes, event_sender).await
+        }
+    }
+}
+
+/// Decodes a payload and emits `NewDocument` or `ContentChanged` for `id` if it changed.
+async fn update_payload(
+    id: String,
+    payload: &[u8],
+    hashes: &mut HashMap<String, u64>,
+    event_sender: &EventSender,
+) {
+    match String::from_utf8(payload.to_vec()) {
+        Ok(content) => update_document(id, content, hashes, event_sender).await,
+        Err(err) => {
+            log::warn!(
+                "Cannot parse NATS payload for [{}] to string. Error: {}",
+                id,
+                err
+            );
+        }
+    }
+}
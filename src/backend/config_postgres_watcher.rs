@@ -0,0 +1,375 @@
+use futures::StreamExt;
+use std::{collections::HashMap, future::Future, pin::Pin, time::Duration};
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, Client, NoTls};
+
+use super::{
+    command_channel, remove_document, update_document, wait_for_start, CommandReceiver,
+    DocumentEvent, EventSender, RetryDelay, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::{
+    clock::{default_clock, SharedClock},
+    WatcherError,
+};
+
+/// Optional settings for [`run_postgres_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct PostgresWatcherOptions {
+    /// Delay before reconnecting after a connection or query error (default 5 seconds ± 20%).
+    pub retry_delay: RetryDelay,
+    /// Interval of the full re-query that catches missed notifications (default 60 seconds).
+    pub reconcile_interval: Duration,
+    /// Source of the time for the retry and reconcile timers (default
+    /// [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+}
+
+impl Default for PostgresWatcherOptions {
+    fn default() -> Self {
+        Self {
+            retry_delay: RetryDelay::default(),
+            reconcile_interval: Duration::from_secs(60),
+            clock: default_clock(),
+        }
+    }
+}
+
+/// Watches a PostgreSQL table and emits each row as a document.
+///
+/// The table is queried once to seed the documents, using `id_col` as document id and
+/// `content_col` as content (both are cast to `text`). The watcher then `LISTEN`s on `channel`:
+/// a notification whose payload is a row id re-queries that row, a notification with an empty
+/// payload re-queries the whole table. Rows that disappear are emitted as removed documents.
+///
+/// The table is additionally re-queried every 60 seconds by default. On connection errors, the
+/// watcher reconnects after the retry delay (about 5 seconds by default) and reconciles the full
+/// table. The connection is made without TLS.
+///
+/// `table` may be schema-qualified (`schema.table`); identifiers are quoted.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_postgres_watcher(
+    conn: String,
+    table: String,
+    id_col: String,
+    content_col: String,
+    channel: String,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    run_postgres_watcher_with_options(
+        conn,
+        table,
+        id_col,
+        content_col,
+        channel,
+        PostgresWatcherOptions::default(),
+    )
+}
+
+/// Same as [`run_postgres_watcher`] but with additional [`PostgresWatcherOptions`].
+pub fn run_postgres_watcher_with_options(
+    conn: String,
+    table: String,
+    id_col: String,
+    content_col: String,
+    channel: String,
+    options: PostgresWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let source = TableConnection {
+        conn,
+        listen: format!("LISTEN {}", quote_ident(&channel)),
+        queries: TableQueries::new(&table, &id_col, &content_col),
+        client: None,
+    };
+    run_postgres_watcher_from_source(source, options)
+}
+
+/// Future returned by the methods of [`PostgresSource`].
+pub type PostgresFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, WatcherError>> + Send + 'a>>;
+
+/// Connection to the watched table, see [`run_postgres_watcher_from_source`].
+///
+/// [`run_postgres_watcher`] reads the table over a `tokio_postgres` connection. Other
+/// implementations can serve the rows from elsewhere, e.g. from memory in tests.
+pub trait PostgresSource: Send + 'static {
+    /// (Re)connects and starts listening for notifications. Returns the receiver of the
+    /// notification payloads, which is closed when the connection is lost.
+    fn connect(&mut self) -> PostgresFuture<'_, mpsc::UnboundedReceiver<String>>;
+
+    /// Returns the content of all rows by id.
+    fn query_all(&mut self) -> PostgresFuture<'_, HashMap<String, String>>;
+
+    /// Returns the content of the row `id`, or `None` if it does not exist.
+    fn query_one<'a>(&'a mut self, id: &'a str) -> PostgresFuture<'a, Option<String>>;
+}
+
+/// Same as [`run_postgres_watcher_with_options`], but reads the table from `source` instead of
+/// connecting to PostgreSQL, e.g. to drive the watcher with an in-memory table in tests.
+///
+/// The watcher connects, seeds the documents from [`PostgresSource::query_all`] and then handles
+/// the notifications as described for [`run_postgres_watcher`]. When the connection is lost or a
+/// query fails, it reconnects after the retry delay and reconciles the full table.
+pub fn run_postgres_watcher_from_source<S: PostgresSource>(
+    source: S,
+    options: PostgresWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut source = source;
+        let mut hashes: HashMap<String, u64> = HashMap::new();
+        let mut initial_scan_done = false;
+
+        'connect: loop {
+            let mut notifications = match source.connect().await {
+                Ok(notifications) => notifications,
+                Err(err) => {
+                    log::error!("Error connecting to PostgreSQL: {}", err);
+                    match wait_for_retry(&options, &mut command_receiver).await {
+                        true => continue 'connect,
+                        false => break 'connect,
+                    }
+                }
+            };
+
+            // Seed the documents, or catch up on the notifications missed while disconnected
+            if let Err(err) = reconcile(&mut source, &mut hashes, &event_sender).await {
+                log::error!("Error querying table: {}. Reconnecting.", err);
+                match wait_for_retry(&options, &mut command_receiver).await {
+                    true => continue 'connect,
+                    false => break 'connect,
+                }
+            }
+            if !initial_scan_done {
+                initial_scan_done = true;
+                event_sender
+                    .send(DocumentEvent::InitialScanComplete)
+                    .await
+                    .ok();
+            }
+
+            let mut reconcile_at = options.clock.now() + options.reconcile_interval;
+            loop {
+                tokio::select! {
+                    _ = options.clock.sleep_until(reconcile_at) => {
+                        reconcile_at = options.clock.now() + options.reconcile_interval;
+                        if let Err(err) = reconcile(&mut source, &mut hashes, &event_sender).await {
+                            log::error!("Error querying table: {}. Reconnecting.", err);
+                            match wait_for_retry(&options, &mut command_receiver).await {
+                                true => continue 'connect,
+                                false => break 'connect,
+                            }
+                        }
+                    }
+                    notification = notifications.recv() => {
+                        let Some(payload) = notification else {
+                            log::warn!("PostgreSQL connection closed. Reconnecting.");
+                            match wait_for_retry(&options, &mut command_receiver).await {
+                                true => continue 'connect,
+                                false => break 'connect,
+                            }
+                        };
+                        let result = match payload.as_str() {
+                            "" => reconcile(&mut source, &mut hashes, &event_sender).await,
+                            id => refresh(&mut source, id, &mut hashes, &event_sender).await,
+                        };
+                        if let Err(err) = result {
+                            log::error!("Error querying table: {}. Reconnecting.", err);
+                            match wait_for_retry(&options, &mut command_receiver).await {
+                                true => continue 'connect,
+                                false => break 'connect,
+                            }
+                        }
+                    }
+                    // Check for control commands
+                    Some(command) = command_receiver.recv() => {
+                        if let WatcherCommand::Stop = command {
+                            log::info!("Watcher received stop command");
+                            break 'connect;
+                        }
+                    }
+                }
+            }
+        }
+
+        log::debug!("Exiting PostgreSQL config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Postgres,
+        },
+        event_receiver,
+    ))
+}
+
+/// Waits for the retry delay. Returns false if the watcher was stopped in the meantime.
+async fn wait_for_retry(
+    options: &PostgresWatcherOptions,
+    command_receiver: &mut CommandReceiver,
+) -> bool {
+    tokio::select! {
+        _ = options.retry_delay.sleep_on(&*options.clock) => true,
+        Some(WatcherCommand::Stop) = command_receiver.recv() => false,
+    }
+}
+
+/// Queries all rows and emits the differences to the known documents.
+async fn reconcile(
+    source: &mut impl PostgresSource,
+    hashes: &mut HashMap<String, u64>,
+    event_sender: &EventSender,
+) -> Result<(), WatcherError> {
+    let rows = source.query_all().await?;
+    let removed: Vec<String> = hashes
+        .keys()
+        .filter(|id| !rows.contains_key(*id))
+        .cloned()
+        .collect();
+    for id in removed {
+        remove_document(id, hashes, event_sender).await;
+    }
+    for (id, content) in rows {
+        update_document(id, content, hashes, event_sender).await;
+    }
+    Ok(())
+}
+
+/// Queries the row `id` and emits its change or removal.
+async fn refresh(
+    source: &mut impl PostgresSource,
+    id: &str,
+    hashes: &mut HashMap<String, u64>,
+    event_sender: &EventSender,
+) -> Result<(), WatcherError> {
+    match source.query_one(id).await? {
+        Some(content) => update_document(id.to_string(), content, hashes, event_sender).await,
+        None => remove_document(id.to_string(), hashes, event_sender).await,
+    }
+    Ok(())
+}
+
+/// Connects to PostgreSQL and listens for notifications.
+///
+/// The connection is driven by a separate task that forwards the notification payloads; the task
+/// ends and closes the notification channel when the connection is lost or the client is dropped.
+async fn connect(
+    conn: &str,
+    listen: &str,
+) -> Result<(Client, mpsc::UnboundedReceiver<String>), WatcherError> {
+    let (client, mut connection) = tokio_postgres::connect(conn, NoTls).await?;
+    let (notification_sender, notification_receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if notification_sender
+                        .send(notification.payload().to_string())
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    log::error!("PostgreSQL connection error: {}", err);
+                    break;
+                }
+            }
+        }
+    });
+
+    client.batch_execute(listen).await?;
+    Ok((client, notification_receiver))
+}
+
+/// [`PostgresSource`] reading a table over a `tokio_postgres` connection.
+struct TableConnection {
+    conn: String,
+    listen: String,
+    queries: TableQueries,
+    // Client of the current connection, set by `connect`
+    client: Option<Client>,
+}
+
+impl TableConnection {
+    fn client(&self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("the table is only queried while connected")
+    }
+}
+
+impl PostgresSource for TableConnection {
+    fn connect(&mut self) -> PostgresFuture<'_, mpsc::UnboundedReceiver<String>> {
+        Box::pin(async move {
+            self.client = None;
+            let (client, notifications) = connect(&self.conn, &self.listen).await?;
+            self.client = Some(client);
+            Ok(notifications)
+        })
+    }
+
+    fn query_all(&mut self) -> PostgresFuture<'_, HashMap<String, String>> {
+        Box::pin(async move {
+            let mut rows = HashMap::new();
+            for row in self.client().query(&self.queries.select_all, &[]).await? {
+                let id: Option<String> = row.get(0);
+                let content: Option<String> = row.get(1);
+                if let (Some(id), Some(content)) = (id, content) {
+                    rows.insert(id, content);
+                }
+            }
+            Ok(rows)
+        })
+    }
+
+    fn query_one<'a>(&'a mut self, id: &'a str) -> PostgresFuture<'a, Option<String>> {
+        Box::pin(async move {
+            Ok(self
+                .client()
+                .query_opt(&self.queries.select_one, &[&id])
+                .await?
+                .and_then(|row| row.get::<_, Option<String>>(0)))
+        })
+    }
+}
+
+struct TableQueries {
+    select_all: String,
+    select_one: String,
+}
+
+impl TableQueries {
+    fn new(table: &str, id_col: &str, content_col: &str) -> Self {
+        let table = table
+            .split('.')
+            .map(quote_ident)
+            .collect::<Vec<_>>()
+            .join(".");
+        let id_col = quote_ident(id_col);
+        let content_col = quote_ident(content_col);
+        Self {
+            select_all: format!("SELECT {id_col}::text, {content_col}::text FROM {table}"),
+            select_one: format!(
+                "SELECT {content_col}::text FROM {table} WHERE {id_col}::text = $1"
+            ),
+        }
+    }
+}
+
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
@@ -0,0 +1,86 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+
+use super::{
+    command_channel, wait_for_start, DocumentEvent, SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::WatcherError;
+
+/// Reads a single document from standard input.
+///
+/// See [`run_reader_watcher`] for details. The document id is `"stdin"`.
+pub fn run_stdin_watcher(
+    terminate_on_source_end: bool,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    run_reader_watcher("stdin", tokio::io::stdin(), terminate_on_source_end)
+}
+
+/// Reads a single document from `reader` until EOF and emits it as a `NewDocument` event.
+///
+/// If `terminate_on_source_end` is true, the watcher task exits cleanly once the document has been
+/// sent. Otherwise it keeps running until it is stopped.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams the document event.
+pub fn run_reader_watcher<R>(
+    document_id: impl Into<String>,
+    mut reader: R,
+    terminate_on_source_end: bool,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(2);
+    let document_id = document_id.into();
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut content = String::new();
+        tokio::select! {
+            res = reader.read_to_string(&mut content) => {
+                res.map_err(|e| WatcherError::FileReadError(document_id.clone().into(), e))?;
+            }
+            Some(WatcherCommand::Stop) = command_receiver.recv() => {
+                log::info!("Watcher received stop command");
+                return Ok(());
+            }
+        }
+
+        event_sender
+            .send(DocumentEvent::NewDocument(document_id, content))
+            .await
+            .ok();
+        event_sender
+            .send(DocumentEvent::InitialScanComplete)
+            .await
+            .ok();
+
+        if terminate_on_source_end {
+            log::debug!("Reader source ended. Exiting reader watcher.");
+            return Ok(());
+        }
+
+        while let Some(command) = command_receiver.recv().await {
+            if let WatcherCommand::Stop = command {
+                log::info!("Watcher received stop command");
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Reader,
+        },
+        event_receiver,
+    ))
+}
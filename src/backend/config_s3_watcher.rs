@@ -0,0 +1,247 @@
+use futures::TryStreamExt;
+use object_store::{aws::AmazonS3Builder, path::Path, ObjectMeta, ObjectStore};
+use std::{collections::HashMap, fmt, time::Duration};
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+
+use super::{
+    command_channel, remove_document, update_document, wait_for_start, DocumentEvent, EventSender,
+    SourceKind, WatcherCommand, WatcherHandle,
+};
+use crate::WatcherError;
+
+/// Static credentials for [`run_s3_watcher`].
+#[derive(Clone)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Optional settings for [`run_s3_watcher_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct S3WatcherOptions {
+    /// AWS region of the bucket (default: `AWS_REGION` / `AWS_DEFAULT_REGION`, else `us-east-1`).
+    pub region: Option<String>,
+    /// Endpoint of an S3 compatible object storage such as MinIO, e.g. `http://minio:9000`.
+    /// Objects are then addressed path-style (`{endpoint}/{bucket}/{key}`).
+    pub endpoint: Option<String>,
+    /// Allow plain HTTP endpoints.
+    pub allow_http: bool,
+}
+
+/// Polls the objects below `prefix` in an S3 bucket and emits each object as a document.
+///
+/// The bucket is listed every `interval`. Objects whose ETag (or, if missing, last modification
+/// time) changed since the previous listing are downloaded and emitted with their key, without
+/// the prefix and a following `/`, as document id. Objects that are no longer listed are emitted
+/// as removed documents. Objects that are not valid UTF-8 are skipped.
+///
+/// With `credentials` set to `None`, credentials are taken from the environment
+/// (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN`), a web identity token or
+/// the instance metadata service. If a listing or download fails, the error is logged and the
+/// known documents are kept until the next poll.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_s3_watcher(
+    bucket: impl Into<String>,
+    prefix: impl Into<String>,
+    interval: Duration,
+    credentials: Option<S3Credentials>,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    run_s3_watcher_with_options(
+        bucket,
+        prefix,
+        interval,
+        credentials,
+        S3WatcherOptions::default(),
+    )
+}
+
+/// Same as [`run_s3_watcher`] but with additional [`S3WatcherOptions`].
+pub fn run_s3_watcher_with_options(
+    bucket: impl Into<String>,
+    prefix: impl Into<String>,
+    interval: Duration,
+    credentials: Option<S3Credentials>,
+    options: S3WatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let store = build_store(&bucket.into(), credentials, &options)?;
+    run_s3_watcher_from_store(store, prefix, interval)
+}
+
+/// Same as [`run_s3_watcher`], but lists and downloads the objects from `store` instead of an S3
+/// bucket, e.g. from an `object_store::memory::InMemory` store in tests.
+pub fn run_s3_watcher_from_store<S: ObjectStore>(
+    store: S,
+    prefix: impl Into<String>,
+    interval: Duration,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let prefix = Path::from(prefix.into());
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut objects = ObjectVersions::default();
+        let mut initial_scan_done = false;
+        let mut poll = tokio::time::interval(interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    match objects.sync(&store, &prefix, &event_sender).await {
+                        Ok(()) if !initial_scan_done => {
+                            initial_scan_done = true;
+                            event_sender
+                                .send(DocumentEvent::InitialScanComplete)
+                                .await
+                                .ok();
+                        }
+                        Ok(()) => {}
+                        Err(err) => {
+                            log::error!("Error listing objects of {}: {}", store, err);
+                        }
+                    }
+                }
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break;
+                    }
+                }
+            }
+        }
+
+        log::debug!("Exiting S3 config watcher loop");
+        Ok(())
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::S3,
+        },
+        event_receiver,
+    ))
+}
+
+fn build_store(
+    bucket: &str,
+    credentials: Option<S3Credentials>,
+    options: &S3WatcherOptions,
+) -> Result<impl ObjectStore, WatcherError> {
+    let mut builder = AmazonS3Builder::from_env()
+        .with_bucket_name(bucket)
+        .with_allow_http(options.allow_http);
+    if let Some(credentials) = credentials {
+        builder = builder
+            .with_access_key_id(credentials.access_key_id)
+            .with_secret_access_key(credentials.secret_access_key);
+        if let Some(session_token) = credentials.session_token {
+            builder = builder.with_token(session_token);
+        }
+    }
+    if let Some(region) = &options.region {
+        builder = builder.with_region(region);
+    }
+    if let Some(endpoint) = &options.endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+    Ok(builder.build()?)
+}
+
+/// Version (ETag or modification time) and content hash of the known objects, by document id.
+#[derive(Default)]
+struct ObjectVersions {
+    versions: HashMap<String, String>,
+    hashes: HashMap<String, u64>,
+}
+
+impl ObjectVersions {
+    /// Lists the objects below `prefix`, downloads the changed ones and emits the differences.
+    async fn sync(
+        &mut self,
+        store: &impl ObjectStore,
+        prefix: &Path,
+        event_sender: &EventSender,
+    ) -> Result<(), WatcherError> {
+        let listing: Vec<ObjectMeta> = store.list(Some(prefix)).try_collect().await?;
+        let listed: HashMap<String, ObjectMeta> = listing
+            .into_iter()
+            .map(|meta| (object_id(prefix, &meta.location), meta))
+            .collect();
+
+        let removed: Vec<String> = self
+            .versions
+            .keys()
+            .filter(|id| !listed.contains_key(*id))
+            .cloned()
+            .collect();
+        for id in removed {
+            self.versions.remove(&id);
+            remove_document(id, &mut self.hashes, event_sender).await;
+        }
+
+        for (id, meta) in listed {
+            let version = meta
+                .e_tag
+                .clone()
+                .unwrap_or_else(|| meta.last_modified.to_rfc3339());
+            if self.versions.get(&id) == Some(&version) {
+                continue;
+            }
+            let bytes = match store.get(&meta.location).await {
+                Ok(result) => result.bytes().await,
+                Err(err) => Err(err),
+            };
+            // Failed downloads are retried on the next poll
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    log::error!("Cannot download S3 object [{}]: {}", meta.location, err);
+                    continue;
+                }
+            };
+            self.versions.insert(id.clone(), version);
+            match String::from_utf8(bytes.to_vec()) {
+                Ok(content) => update_document(id, content, &mut self.hashes, event_sender).await,
+                Err(err) => {
+                    log::warn!(
+                        "Skipping S3 object [{}] that is not valid UTF-8: {}",
+                        meta.location,
+                        err
+                    );
+                    remove_document(id, &mut self.hashes, event_sender).await;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key of the object at `location` relative to `prefix`.
+fn object_id(prefix: &Path, location: &Path) -> String {
+    location
+        .as_ref()
+        .strip_prefix(prefix.as_ref())
+        .map(|id| id.trim_start_matches('/'))
+        .unwrap_or(location.as_ref())
+        .to_string()
+}
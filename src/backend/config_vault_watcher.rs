@@ -0,0 +1,333 @@
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::{borrow::Cow, collections::HashMap, fmt, time::Duration};
+use tokio::{
+    sync::mpsc,
+    time::{Instant, MissedTickBehavior},
+};
+
+use super::{
+    command_channel, sync_documents, wait_for_start, DocumentEvent, EventMask, SourceKind,
+    WatcherCommand, WatcherHandle,
+};
+use crate::WatcherError;
+
+/// Optional settings for [`run_vault_watcher_with_options`].
+#[derive(Clone, Debug)]
+pub struct VaultWatcherOptions {
+    /// Vault Enterprise namespace, sent as `X-Vault-Namespace` header.
+    pub namespace: Option<String>,
+    /// Renew the token when half of its TTL has elapsed, if it is renewable (default: true).
+    pub renew_token: bool,
+}
+impl Default for VaultWatcherOptions {
+    fn default() -> Self {
+        Self {
+            namespace: None,
+            renew_token: true,
+        }
+    }
+}
+
+/// Polls a secret of a Vault KV version 2 engine and emits each of its fields as a document.
+///
+/// The secret at `{mount}/data/{path}` is read every `interval`. Its fields are emitted with the
+/// field name as document id; string values are emitted as they are, other values as JSON. A new
+/// version of the secret is compared with the known documents, so only changed fields produce
+/// events, and reads that return an already known version are skipped. A deleted or missing
+/// secret has no fields.
+///
+/// Vault has no watch API for KV secrets, hence the polling. If the token is renewable, it is
+/// renewed before it expires (see [`VaultWatcherOptions::renew_token`]). Once Vault rejects the
+/// token, e.g. because it expired, the watcher ends with [`WatcherError::VaultAccessDenied`].
+/// Other failures are logged and the known documents are kept until the next poll.
+///
+/// # Returns
+/// - A `WatcherHandle` for controlling the watcher.
+/// - A `Receiver` that streams document events.
+pub fn run_vault_watcher(
+    addr: impl Into<String>,
+    token: impl Into<String>,
+    mount: impl Into<String>,
+    path: impl Into<String>,
+    interval: Duration,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    run_vault_watcher_with_options(
+        addr,
+        token,
+        mount,
+        path,
+        interval,
+        VaultWatcherOptions::default(),
+    )
+}
+
+/// Same as [`run_vault_watcher`] but with additional [`VaultWatcherOptions`].
+pub fn run_vault_watcher_with_options(
+    addr: impl Into<String>,
+    token: impl Into<String>,
+    mount: impl Into<String>,
+    path: impl Into<String>,
+    interval: Duration,
+    options: VaultWatcherOptions,
+) -> Result<(WatcherHandle, mpsc::Receiver<DocumentEvent>), WatcherError> {
+    let client = VaultClient {
+        http: reqwest::Client::builder().build()?,
+        addr: addr.into().trim_end_matches('/').to_string(),
+        token: token.into(),
+        namespace: options.namespace.clone(),
+    };
+    let secret_path = format!(
+        "{}/data/{}",
+        mount.into().trim_matches('/'),
+        path.into().trim_matches('/')
+    );
+    let (command_sender, mut command_receiver) = command_channel();
+    let (event_sender, event_receiver) = command_receiver.event_channel(100);
+
+    let handle = tokio::spawn(async move {
+        // Wait for a start command before we begin
+        if !wait_for_start(&mut command_receiver).await {
+            return Ok(());
+        }
+
+        let mut renew_at = if options.renew_token {
+            client.token_renewal().await
+        } else {
+            None
+        };
+        let mut hashes: HashMap<String, u64> = HashMap::new();
+        // Version of the secret the documents were taken from, `None` if it does not exist
+        let mut version: Option<Option<u64>> = None;
+        let mut initial_scan_done = false;
+        let mut poll = tokio::time::interval(interval);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let result = loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    match client.read_secret(&secret_path).await {
+                        Ok(secret) => {
+                            let secret_version = secret.as_ref().map(|secret| secret.version);
+                            if version != Some(secret_version) {
+                                version = Some(secret_version);
+                                let fields = secret.map(|secret| secret.data).unwrap_or_default();
+                                let new_data = fields
+                                    .iter()
+                                    .map(|(field, value)| (field.clone(), field_content(value)))
+                                    .collect();
+                                sync_documents(new_data, &mut hashes, &event_sender, EventMask::ALL).await;
+                            }
+                            if !initial_scan_done {
+                                initial_scan_done = true;
+                                event_sender
+                                    .send(DocumentEvent::InitialScanComplete)
+                                    .await
+                                    .ok();
+                            }
+                        }
+                        Err(WatcherError::VaultAccessDenied) => break Err(WatcherError::VaultAccessDenied),
+                        Err(err) => {
+                            log::error!("Error reading Vault secret [{}]: {}", secret_path, err);
+                        }
+                    }
+                }
+
+                // Renew the token before it expires
+                _ = tokio::time::sleep_until(renew_at.unwrap_or_else(Instant::now)), if renew_at.is_some() => {
+                    renew_at = match client.renew_token().await {
+                        Ok(lease) => lease.map(renewal_time),
+                        Err(WatcherError::VaultAccessDenied) => break Err(WatcherError::VaultAccessDenied),
+                        Err(err) => {
+                            log::error!("Error renewing Vault token: {}", err);
+                            Some(Instant::now() + interval)
+                        }
+                    };
+                }
+
+                // Check for control commands
+                Some(command) = command_receiver.recv() => {
+                    if let WatcherCommand::Stop = command {
+                        log::info!("Watcher received stop command");
+                        break Ok(());
+                    }
+                }
+            }
+        };
+
+        if let Err(err) = &result {
+            log::error!("==> Vault watcher stopped: {}", err);
+        }
+        log::debug!("Exiting Vault config watcher loop");
+        result
+    });
+
+    Ok((
+        WatcherHandle {
+            command_sender,
+            handle: Some(handle),
+            source_kind: SourceKind::Vault,
+        },
+        event_receiver,
+    ))
+}
+
+/// Returns a string field as it is and any other value as JSON.
+fn field_content(value: &serde_json::Value) -> Cow<'_, str> {
+    match value {
+        serde_json::Value::String(content) => Cow::Borrowed(content),
+        value => Cow::Owned(value.to_string()),
+    }
+}
+
+/// Time to renew a token with the given lease duration: after half of it has elapsed.
+fn renewal_time(lease: Duration) -> Instant {
+    Instant::now() + lease / 2
+}
+
+struct VaultClient {
+    http: reqwest::Client,
+    addr: String,
+    token: String,
+    namespace: Option<String>,
+}
+
+impl fmt::Debug for VaultClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VaultClient")
+            .field("addr", &self.addr)
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct KvSecret {
+    /// Fields of the secret, `null` if the version was deleted.
+    data: Option<serde_json::Map<String, serde_json::Value>>,
+    metadata: KvMetadata,
+}
+
+#[derive(Deserialize)]
+struct KvMetadata {
+    version: u64,
+}
+
+/// The fields of a secret and their version.
+struct SecretVersion {
+    data: serde_json::Map<String, serde_json::Value>,
+    version: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenLookup {
+    #[serde(default)]
+    ttl: u64,
+    #[serde(default)]
+    renewable: bool,
+}
+
+#[derive(Deserialize)]
+struct TokenRenewal {
+    auth: TokenAuth,
+}
+
+#[derive(Deserialize)]
+struct TokenAuth {
+    lease_duration: u64,
+    #[serde(default)]
+    renewable: bool,
+}
+
+impl VaultClient {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}/v1/{}", self.addr, path))
+            .header("X-Vault-Token", &self.token);
+        match &self.namespace {
+            Some(namespace) => request.header("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    /// Reads the latest version of a KV v2 secret, `None` if it does not exist or is deleted.
+    async fn read_secret(&self, path: &str) -> Result<Option<SecretVersion>, WatcherError> {
+        let response = self.request(Method::GET, path).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let secret: VaultResponse<KvSecret> = check_status(response).await?.json().await?;
+        Ok(secret.data.data.map(|data| SecretVersion {
+            data,
+            version: secret.data.metadata.version,
+        }))
+    }
+
+    /// Looks up the token and returns when to renew it, `None` if it cannot be renewed.
+    async fn token_renewal(&self) -> Option<Instant> {
+        let lookup = match self
+            .request(Method::GET, "auth/token/lookup-self")
+            .send()
+            .await
+        {
+            Ok(response) => match check_status(response).await {
+                Ok(response) => response.json::<VaultResponse<TokenLookup>>().await,
+                Err(err) => {
+                    log::warn!(
+                        "Cannot look up Vault token, it will not be renewed: {}",
+                        err
+                    );
+                    return None;
+                }
+            },
+            Err(err) => Err(err),
+        };
+        match lookup {
+            Ok(lookup) if lookup.data.renewable && lookup.data.ttl > 0 => {
+                Some(renewal_time(Duration::from_secs(lookup.data.ttl)))
+            }
+            Ok(_) => None,
+            Err(err) => {
+                log::warn!(
+                    "Cannot look up Vault token, it will not be renewed: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Renews the token and returns its new lease duration, `None` if it cannot be renewed again.
+    async fn renew_token(&self) -> Result<Option<Duration>, WatcherError> {
+        let response = self
+            .request(Method::POST, "auth/token/renew-self")
+            .send()
+            .await?;
+        let renewal: TokenRenewal = check_status(response).await?.json().await?;
+        log::debug!(
+            "Renewed Vault token for {} seconds",
+            renewal.auth.lease_duration
+        );
+        Ok((renewal.auth.renewable && renewal.auth.lease_duration > 0)
+            .then(|| Duration::from_secs(renewal.auth.lease_duration)))
+    }
+}
+
+/// Maps error responses to [`WatcherError::VaultAccessDenied`] (403) or
+/// [`WatcherError::VaultStatus`].
+async fn check_status(response: Response) -> Result<Response, WatcherError> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::FORBIDDEN => Err(WatcherError::VaultAccessDenied),
+        status => Err(WatcherError::VaultStatus(
+            status.as_u16(),
+            response.text().await.unwrap_or_default(),
+        )),
+    }
+}
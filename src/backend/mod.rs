@@ -1,28 +1,293 @@
+#[cfg(feature = "bundle")]
+mod config_bundle_watcher;
+#[cfg(feature = "configmap")]
+mod config_crd_watcher;
+#[cfg(feature = "file")]
 mod config_file_watcher;
+#[cfg(feature = "git")]
+mod config_git_watcher;
+#[cfg(feature = "kafka")]
+mod config_kafka_watcher;
+mod config_layered_watcher;
+#[cfg(feature = "configmap")]
 mod config_map_watcher;
+mod config_merged_watcher;
+#[cfg(feature = "mqtt")]
 mod config_mqtt_watcher;
+#[cfg(feature = "nats")]
+mod config_nats_watcher;
+#[cfg(feature = "postgres")]
+mod config_postgres_watcher;
+mod config_reader_watcher;
+#[cfg(feature = "s3")]
+mod config_s3_watcher;
+#[cfg(feature = "vault")]
+mod config_vault_watcher;
 
+#[cfg(feature = "bundle")]
+pub use config_bundle_watcher::*;
+#[cfg(feature = "configmap")]
+pub use config_crd_watcher::*;
+#[cfg(feature = "file")]
 pub use config_file_watcher::*;
+#[cfg(feature = "git")]
+pub use config_git_watcher::*;
+#[cfg(feature = "kafka")]
+pub use config_kafka_watcher::*;
+pub use config_layered_watcher::*;
+#[cfg(feature = "configmap")]
 pub use config_map_watcher::*;
+pub use config_merged_watcher::*;
+#[cfg(feature = "mqtt")]
 pub use config_mqtt_watcher::*;
-use tokio::sync::mpsc;
+#[cfg(feature = "nats")]
+pub use config_nats_watcher::*;
+#[cfg(feature = "postgres")]
+pub use config_postgres_watcher::*;
+pub use config_reader_watcher::*;
+#[cfg(feature = "s3")]
+pub use config_s3_watcher::*;
+#[cfg(feature = "vault")]
+pub use config_vault_watcher::*;
+use std::{
+    collections::VecDeque,
+    hash::{BuildHasher, RandomState},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::sync::{mpsc, oneshot};
 
 use crate::WatcherError;
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum DocumentEvent {
     NewDocument(String, String), // New document (ID, Content) added with content
     ContentChanged(String, String), // Content of an existing document changed (ID, Content)
     DocumentRemoved(String),     // Document removed (ID)
+    InitialScanComplete,         // All documents present at startup have been emitted
+    /// A tracked document was written without changing its content (ID). Only emitted by the file
+    /// watcher with [`ConfigFileWatcherOptions::emit_on_touch`].
+    Touched(String),
+    /// A tracked document could not be read (ID, error), e.g. because of its permissions. It is
+    /// still tracked with its previous content; the next successful read is emitted as
+    /// `ContentChanged`, even if the content is unchanged. Only emitted by the file watcher with
+    /// [`ConfigFileWatcherOptions::read_failure_limit`], without it a read error ends the watcher.
+    ReadFailed(String, WatcherError),
+    /// A document with this id was reported by several sources of a merged watcher. `sources`
+    /// names them, the source whose content is used first. See [`run_merged_watcher`].
+    DuplicateId {
+        id: String,
+        sources: Vec<String>,
+    },
+    /// `event` with the wall-clock time the backend detected it. Only emitted after
+    /// [`WatcherHandle::emit_timestamps`]; every event of the watcher is wrapped then.
+    Observed {
+        observed_at: SystemTime,
+        event: Box<DocumentEvent>,
+    },
 }
 
+impl DocumentEvent {
+    /// Returns the [`hash_str`](crate::hash_str) of the content for `NewDocument` and
+    /// `ContentChanged`, e.g. to deduplicate documents downstream.
+    ///
+    /// All backends detect changes with this hash over the emitted content, so equal content
+    /// has the same hash regardless of the backend.
+    pub fn content_hash(&self) -> Option<u64> {
+        match self {
+            Self::NewDocument(_, content) | Self::ContentChanged(_, content) => {
+                Some(crate::hash_str(content))
+            }
+            Self::DocumentRemoved(_)
+            | Self::InitialScanComplete
+            | Self::Touched(_)
+            | Self::ReadFailed(..)
+            | Self::DuplicateId { .. } => None,
+            Self::Observed { event, .. } => event.content_hash(),
+        }
+    }
+
+    /// Returns the time the backend detected the event, if it is wrapped in `Observed`.
+    pub fn observed_at(&self) -> Option<SystemTime> {
+        match self {
+            Self::Observed { observed_at, .. } => Some(*observed_at),
+            _ => None,
+        }
+    }
+
+    /// Removes the `Observed` wrapper, returning the time the backend detected the event, if
+    /// any, and the event.
+    pub fn into_observed(self) -> (Option<SystemTime>, DocumentEvent) {
+        match self {
+            Self::Observed { observed_at, event } => (Some(observed_at), *event),
+            event => (None, event),
+        }
+    }
+
+    /// Wraps the event in `Observed`, keeping the time of an event that is wrapped already.
+    fn observed(self, observed_at: SystemTime) -> Self {
+        match self {
+            Self::Observed { .. } => self,
+            event => Self::Observed {
+                observed_at,
+                event: Box::new(event),
+            },
+        }
+    }
+
+    /// Prefixes the document id with `{label}:`, e.g. to keep ids of several backends apart when
+    /// their event streams are merged.
+    pub fn with_source_label(self, label: &str) -> Self {
+        match self {
+            Self::NewDocument(id, content) => Self::NewDocument(format!("{label}:{id}"), content),
+            Self::ContentChanged(id, content) => {
+                Self::ContentChanged(format!("{label}:{id}"), content)
+            }
+            Self::DocumentRemoved(id) => Self::DocumentRemoved(format!("{label}:{id}")),
+            Self::InitialScanComplete => Self::InitialScanComplete,
+            Self::Touched(id) => Self::Touched(format!("{label}:{id}")),
+            Self::ReadFailed(id, error) => Self::ReadFailed(format!("{label}:{id}"), error),
+            Self::DuplicateId { id, sources } => Self::DuplicateId {
+                id: format!("{label}:{id}"),
+                sources,
+            },
+            Self::Observed { observed_at, event } => Self::Observed {
+                observed_at,
+                event: Box::new(event.with_source_label(label)),
+            },
+        }
+    }
+}
+
+/// Categories of [`DocumentEvent`]s to forward, combined with `|` and `-`, e.g.
+/// `EventMask::ALL - EventMask::CHANGED` for additions and removals only (default
+/// [`EventMask::ALL`]).
+///
+/// Suppressed events are still tracked by the emitter, so later events stay consistent.
+/// `InitialScanComplete`, `ReadFailed` and `DuplicateId` are always forwarded.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct EventMask(u8);
+
+impl EventMask {
+    /// `NewDocument`.
+    pub const NEW: Self = Self(1);
+    /// `ContentChanged` and `Touched`.
+    pub const CHANGED: Self = Self(1 << 1);
+    /// `DocumentRemoved`.
+    pub const REMOVED: Self = Self(1 << 2);
+    pub const NONE: Self = Self(0);
+    pub const ALL: Self = Self(Self::NEW.0 | Self::CHANGED.0 | Self::REMOVED.0);
+
+    /// Returns true if all categories of `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if `event` belongs to a category of this mask or is always forwarded.
+    pub fn allows(self, event: &DocumentEvent) -> bool {
+        match event {
+            DocumentEvent::NewDocument(..) => self.contains(Self::NEW),
+            DocumentEvent::ContentChanged(..) | DocumentEvent::Touched(_) => {
+                self.contains(Self::CHANGED)
+            }
+            DocumentEvent::DocumentRemoved(_) => self.contains(Self::REMOVED),
+            DocumentEvent::InitialScanComplete
+            | DocumentEvent::ReadFailed(..)
+            | DocumentEvent::DuplicateId { .. } => true,
+            DocumentEvent::Observed { event, .. } => self.allows(event),
+        }
+    }
+}
+
+impl Default for EventMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl std::ops::BitOr for EventMask {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl std::ops::Sub for EventMask {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+/// The kind of source a backend reads its documents from, see [`WatcherHandle::source_kind`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, serde::Serialize)]
+pub enum SourceKind {
+    File,
+    Bundle,
+    ConfigMap,
+    CustomResource,
+    Mqtt,
+    Reader,
+    Nats,
+    Kafka,
+    Postgres,
+    S3,
+    Vault,
+    Git,
+    /// Several backends combined with [`run_merged_watcher`].
+    Merged,
+}
+
+/// Counters of the file system events a file watcher received and processed, e.g. to tune its
+/// `debounce` and channel capacities, see [`WatcherHandle::debounce_metrics`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DebounceMetrics {
+    /// File system events received since the watcher started.
+    pub raw_events_total: u64,
+    /// Batches the received events were processed in. With a `debounce`, the events arriving
+    /// within a debounce window form one batch; without, every event is processed on its own.
+    pub debounced_batches_total: u64,
+    /// Events currently waiting in the channel between the notify thread and the watcher task,
+    /// whose size is set with the `internal_channel_capacity` option.
+    pub internal_channel_depth: usize,
+}
+
+/// Handle of a backend task. It is the only owner of the task: use
+/// [`controller`](Self::controller) to control the watcher from several places.
 pub struct WatcherHandle {
     pub(crate) command_sender: mpsc::Sender<WatcherCommand>, // Shutdown signal
     pub(crate) handle: Option<tokio::task::JoinHandle<Result<(), WatcherError>>>,
+    pub(crate) source_kind: SourceKind,
 }
 
 impl WatcherHandle {
-    /// starts the watcher. Can only be used once!
+    /// Returns a cloneable controller of the watcher, e.g. for another subsystem that needs to
+    /// stop it.
+    pub fn controller(&self) -> WatcherController {
+        WatcherController {
+            command_sender: self.command_sender.clone(),
+            source_kind: self.source_kind,
+        }
+    }
+
+    /// Splits the handle into a cloneable [`WatcherController`] and the [`WatcherJoin`] owning
+    /// the task.
+    pub fn into_parts(mut self) -> (WatcherController, WatcherJoin) {
+        let controller = self.controller();
+        let join = WatcherJoin {
+            handle: self.handle.take(),
+        };
+        (controller, join)
+    }
+
+    /// Starts the watcher. Starting an already started watcher has no effect; starting a stopped
+    /// watcher fails.
     pub async fn start(&self) -> Result<(), WatcherError> {
         self.command_sender.send(WatcherCommand::Start).await?;
         Ok(())
@@ -30,7 +295,18 @@ impl WatcherHandle {
 
     /// Stops the watcher task.
     pub async fn stop(&mut self) -> Result<(), WatcherError> {
-        self.command_sender.send(WatcherCommand::Stop).await?; // Send the shutdown signal
+        // Send the shutdown signal
+        if self
+            .command_sender
+            .send(WatcherCommand::Stop)
+            .await
+            .is_err()
+            && !self.is_finished()
+        {
+            return Err(WatcherError::SendError(mpsc::error::SendError(
+                WatcherCommand::Stop,
+            )));
+        }
         if let Some(handle) = self.handle.take() {
             handle.await??;
         } else {
@@ -39,9 +315,530 @@ impl WatcherHandle {
 
         Ok(())
     }
+
+    /// Loads the current content of the document `id` from the source.
+    ///
+    /// Only supported by the file watcher, for files it tracks; this is mostly useful with
+    /// [`ConfigFileWatcherOptions::lazy`]. Content that differs from what the watcher emitted
+    /// before is also emitted as `ContentChanged`. Fails with [`WatcherError::DocumentUnavailable`] for
+    /// other backends or unknown documents. Requests made before the watcher was started are
+    /// answered once it runs.
+    pub async fn load_document(&self, id: &str) -> Result<String, WatcherError> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(WatcherCommand::LoadDocument(id.to_string(), reply_sender))
+            .await?;
+        reply_receiver
+            .await
+            .map_err(|_| WatcherError::DocumentUnavailable(id.to_string()))?
+    }
+
+    /// Reads the document `id` from the source again and emits its current state, even if its
+    /// content did not change, e.g. to invalidate caches after an external dependency changed.
+    ///
+    /// Only supported by the file watcher: a file matching the pattern is emitted as
+    /// `ContentChanged`, or as `NewDocument` if it was not tracked yet, and a tracked file that no
+    /// longer exists as `DocumentRemoved`, without waiting for a `removal_grace`. Fails with
+    /// [`WatcherError::DocumentUnavailable`] for other backends and for ids that are neither
+    /// tracked nor a matching file. Requests made before the watcher was started are answered
+    /// once it runs.
+    pub async fn refresh_document(&self, id: &str) -> Result<(), WatcherError> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(WatcherCommand::RefreshDocument(
+                id.to_string(),
+                reply_sender,
+            ))
+            .await?;
+        reply_receiver
+            .await
+            .map_err(|_| WatcherError::DocumentUnavailable(id.to_string()))?
+    }
+
+    /// Returns the event counters of a file watcher, e.g. to see how many events a `debounce`
+    /// coalesces or whether the internal channel fills up under load. Returns `None` for other
+    /// backends and if the watcher has terminated. Requests made before the watcher was started
+    /// are answered once it runs.
+    pub async fn debounce_metrics(&self) -> Option<DebounceMetrics> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(WatcherCommand::DebounceMetrics(reply_sender))
+            .await
+            .ok()?;
+        reply_receiver.await.ok()
+    }
+
+    /// Makes the watcher wrap each event in [`DocumentEvent::Observed`] with the wall-clock time
+    /// the backend detected it, taken before the event is sent. The file watcher uses the time
+    /// it received the latest file system event of a debounced batch. Merged and layered
+    /// watchers pass the time of their sources on.
+    ///
+    /// Only effective before the watcher is started.
+    pub async fn emit_timestamps(&self) -> Result<(), WatcherError> {
+        self.command_sender
+            .send(WatcherCommand::EmitTimestamps)
+            .await?;
+        Ok(())
+    }
+
+    /// Changes the debounce duration of a running watcher. `Duration::ZERO` disables debouncing.
+    ///
+    /// The new duration applies to events that are pending or received afterwards; tracked
+    /// documents are kept, so no events are lost or repeated. Only the file and bundle watchers
+    /// debounce, other backends ignore the command.
+    pub async fn set_debounce(&self, debounce: Duration) -> Result<(), WatcherError> {
+        self.command_sender
+            .send(WatcherCommand::SetDebounce(debounce))
+            .await?;
+        Ok(())
+    }
+
+    /// Returns true if the watcher task has terminated, e.g. because its source ended.
+    pub fn is_finished(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_none_or(|handle| handle.is_finished())
+    }
+
+    /// Returns the kind of source the backend reads from, e.g. to route or count the events of
+    /// merged watchers by source.
+    pub fn source_kind(&self) -> SourceKind {
+        self.source_kind
+    }
+}
+
+/// Cloneable control side of a [`WatcherHandle`], see [`WatcherHandle::controller`].
+///
+/// Controllers send commands to the watcher task but do not own it: [`stop`](Self::stop) waits
+/// until the task has terminated, while its result is only returned to the owner of the task by
+/// [`WatcherHandle::stop`] or [`WatcherJoin::join`].
+#[derive(Clone)]
+pub struct WatcherController {
+    command_sender: mpsc::Sender<WatcherCommand>,
+    source_kind: SourceKind,
+}
+
+impl WatcherController {
+    /// Starts the watcher, see [`WatcherHandle::start`].
+    pub async fn start(&self) -> Result<(), WatcherError> {
+        self.command_sender.send(WatcherCommand::Start).await?;
+        Ok(())
+    }
+
+    /// Stops the watcher task and waits until it has terminated. Succeeds if the task has
+    /// already terminated.
+    pub async fn stop(&self) {
+        self.command_sender.send(WatcherCommand::Stop).await.ok();
+        // The task drops its command receiver when it terminates
+        self.command_sender.closed().await;
+    }
+
+    /// Loads the current content of the document `id`, see [`WatcherHandle::load_document`].
+    pub async fn load_document(&self, id: &str) -> Result<String, WatcherError> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(WatcherCommand::LoadDocument(id.to_string(), reply_sender))
+            .await?;
+        reply_receiver
+            .await
+            .map_err(|_| WatcherError::DocumentUnavailable(id.to_string()))?
+    }
+
+    /// Emits the current state of the document `id`, see [`WatcherHandle::refresh_document`].
+    pub async fn refresh_document(&self, id: &str) -> Result<(), WatcherError> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(WatcherCommand::RefreshDocument(
+                id.to_string(),
+                reply_sender,
+            ))
+            .await?;
+        reply_receiver
+            .await
+            .map_err(|_| WatcherError::DocumentUnavailable(id.to_string()))?
+    }
+
+    /// Returns the event counters of a file watcher, see [`WatcherHandle::debounce_metrics`].
+    pub async fn debounce_metrics(&self) -> Option<DebounceMetrics> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(WatcherCommand::DebounceMetrics(reply_sender))
+            .await
+            .ok()?;
+        reply_receiver.await.ok()
+    }
+
+    /// Changes the debounce duration, see [`WatcherHandle::set_debounce`].
+    pub async fn set_debounce(&self, debounce: Duration) -> Result<(), WatcherError> {
+        self.command_sender
+            .send(WatcherCommand::SetDebounce(debounce))
+            .await?;
+        Ok(())
+    }
+
+    /// Returns true if the watcher task has terminated.
+    pub fn is_finished(&self) -> bool {
+        self.command_sender.is_closed()
+    }
+
+    /// Returns the kind of source the backend reads from.
+    pub fn source_kind(&self) -> SourceKind {
+        self.source_kind
+    }
+}
+
+/// Owner of a backend task, see [`WatcherHandle::into_parts`].
+pub struct WatcherJoin {
+    handle: Option<tokio::task::JoinHandle<Result<(), WatcherError>>>,
+}
+
+impl WatcherJoin {
+    /// Waits until the watcher task has terminated, e.g. after a [`WatcherController::stop`],
+    /// and returns its result. Only the first call returns the result; later calls return
+    /// `Ok(())`.
+    pub async fn join(&mut self) -> Result<(), WatcherError> {
+        match self.handle.take() {
+            Some(handle) => handle.await?,
+            None => Ok(()),
+        }
+    }
+
+    /// Returns true if the watcher task has terminated.
+    pub fn is_finished(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_none_or(|handle| handle.is_finished())
+    }
 }
 
 pub enum WatcherCommand {
     Start,
     Stop,
+    /// Requests the current content of a document, see [`WatcherHandle::load_document`].
+    /// Backends that do not support it drop the reply sender.
+    LoadDocument(String, oneshot::Sender<Result<String, WatcherError>>),
+    /// Requests to emit the current state of a document, see
+    /// [`WatcherHandle::refresh_document`]. Backends that do not support it drop the reply sender.
+    RefreshDocument(String, oneshot::Sender<Result<(), WatcherError>>),
+    /// Changes the debounce duration, see [`WatcherHandle::set_debounce`]. Backends without a
+    /// debounce ignore it.
+    SetDebounce(Duration),
+    /// Requests the event counters, see [`WatcherHandle::debounce_metrics`]. Backends other than
+    /// the file watcher drop the reply sender.
+    DebounceMetrics(oneshot::Sender<DebounceMetrics>),
+    /// Requests [`DocumentEvent::Observed`] events, see [`WatcherHandle::emit_timestamps`].
+    /// Taken by the command receiver of the backend, backends do not see it.
+    EmitTimestamps,
+}
+
+/// Creates the command channel of a backend task.
+pub(crate) fn command_channel() -> (mpsc::Sender<WatcherCommand>, CommandReceiver) {
+    let (command_sender, receiver) = mpsc::channel(1);
+    (
+        command_sender,
+        CommandReceiver {
+            receiver,
+            deferred: VecDeque::new(),
+            timestamps: Arc::new(AtomicBool::new(false)),
+        },
+    )
+}
+
+/// Receiving end of a backend command channel.
+///
+/// Commands received by [`wait_for_start`] before the start command are kept and returned by
+/// [`recv`](Self::recv) first, so that the backend loop processes them once it runs.
+pub(crate) struct CommandReceiver {
+    receiver: mpsc::Receiver<WatcherCommand>,
+    deferred: VecDeque<WatcherCommand>,
+    // Set by `EmitTimestamps` before the start, shared with the event senders of the backend
+    timestamps: Arc<AtomicBool>,
+}
+
+impl CommandReceiver {
+    /// Returns the next command. Cancel safe.
+    pub(crate) async fn recv(&mut self) -> Option<WatcherCommand> {
+        if let Some(command) = self.deferred.pop_front() {
+            return Some(command);
+        }
+        loop {
+            match self.receiver.recv().await {
+                Some(WatcherCommand::EmitTimestamps) => {
+                    log::warn!("Timestamps can only be requested before the watcher is started");
+                }
+                command => return command,
+            }
+        }
+    }
+
+    /// Returns true if timestamps were requested with [`WatcherHandle::emit_timestamps`].
+    pub(crate) fn timestamps(&self) -> bool {
+        self.timestamps.load(Ordering::Relaxed)
+    }
+
+    /// Creates the event channel of the backend, with senders that wrap the events in
+    /// [`DocumentEvent::Observed`] if timestamps are requested.
+    pub(crate) fn event_channel(
+        &self,
+        capacity: usize,
+    ) -> (EventSender, mpsc::Receiver<DocumentEvent>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (
+            EventSender {
+                sender,
+                timestamps: self.timestamps.clone(),
+                observed_at: None,
+            },
+            receiver,
+        )
+    }
+}
+
+/// Sending end of the event channel of a backend, see [`CommandReceiver::event_channel`].
+#[derive(Clone, Debug)]
+pub(crate) struct EventSender {
+    sender: mpsc::Sender<DocumentEvent>,
+    timestamps: Arc<AtomicBool>,
+    /// Time to stamp the events with instead of the current time, e.g. the time of the file
+    /// system events a debounced batch is processed for.
+    pub(crate) observed_at: Option<SystemTime>,
+}
+
+impl EventSender {
+    /// Sends `event`, stamped with the current time or `observed_at` if timestamps are
+    /// requested.
+    pub(crate) async fn send(
+        &self,
+        event: DocumentEvent,
+    ) -> Result<(), mpsc::error::SendError<DocumentEvent>> {
+        let observed_at = self.observed_at.unwrap_or_else(SystemTime::now);
+        self.send_observed(observed_at, event).await
+    }
+
+    /// Sends `event`, stamped with `observed_at` if timestamps are requested. Events that are
+    /// stamped already keep their time.
+    pub(crate) async fn send_observed(
+        &self,
+        observed_at: SystemTime,
+        event: DocumentEvent,
+    ) -> Result<(), mpsc::error::SendError<DocumentEvent>> {
+        let event = match self.timestamps.load(Ordering::Relaxed) {
+            true => event.observed(observed_at),
+            false => event,
+        };
+        self.sender.send(event).await
+    }
+}
+
+/// Waits for the start command. Returns `false` if a stop command was received or the handle
+/// was dropped before.
+///
+/// Further start commands are ignored. Other commands are deferred until the backend loop runs.
+pub(crate) async fn wait_for_start(command_receiver: &mut CommandReceiver) -> bool {
+    loop {
+        match command_receiver.receiver.recv().await {
+            Some(WatcherCommand::Start) => return true,
+            Some(WatcherCommand::EmitTimestamps) => {
+                command_receiver.timestamps.store(true, Ordering::Relaxed)
+            }
+            Some(WatcherCommand::Stop) | None => {
+                // Exit early if Stop command is received or channel is closed
+                log::info!("Watcher received stop command before starting or channel closed");
+                return false;
+            }
+            Some(command) => command_receiver.deferred.push_back(command),
+        }
+    }
+}
+
+/// Emits `NewDocument` or `ContentChanged` for `id` if `content` differs from the tracked content
+/// and records its hash.
+#[cfg(any(
+    feature = "bundle",
+    feature = "configmap",
+    feature = "git",
+    feature = "kafka",
+    feature = "nats",
+    feature = "postgres",
+    feature = "s3"
+))]
+pub(crate) async fn update_document(
+    id: String,
+    content: String,
+    hashes: &mut std::collections::HashMap<String, u64>,
+    event_sender: &EventSender,
+) {
+    let new_hash = crate::hash_str(&content);
+    match hashes.insert(id.clone(), new_hash) {
+        Some(existing_hash) if existing_hash == new_hash => {}
+        Some(_) => {
+            event_sender
+                .send(DocumentEvent::ContentChanged(id, content))
+                .await
+                .ok();
+        }
+        None => {
+            event_sender
+                .send(DocumentEvent::NewDocument(id, content))
+                .await
+                .ok();
+        }
+    }
+}
+
+/// Emits `DocumentRemoved` for `id` if it is tracked and stops tracking it.
+#[cfg(any(
+    feature = "bundle",
+    feature = "configmap",
+    feature = "git",
+    feature = "kafka",
+    feature = "nats",
+    feature = "postgres",
+    feature = "s3"
+))]
+pub(crate) async fn remove_document(
+    id: String,
+    hashes: &mut std::collections::HashMap<String, u64>,
+    event_sender: &EventSender,
+) {
+    if hashes.remove(&id).is_some() {
+        event_sender
+            .send(DocumentEvent::DocumentRemoved(id))
+            .await
+            .ok();
+    }
+}
+
+/// Replaces the tracked documents with `new_data`, emitting events for new, changed and removed
+/// documents that `event_mask` allows.
+#[cfg(any(feature = "configmap", feature = "vault"))]
+pub(crate) async fn sync_documents(
+    new_data: std::collections::BTreeMap<String, std::borrow::Cow<'_, str>>,
+    hashes: &mut std::collections::HashMap<String, u64>,
+    event_sender: &EventSender,
+    event_mask: EventMask,
+) {
+    let mut new_hashes = std::collections::HashMap::new();
+
+    // Detect new documents and content changes
+    for (id, content) in &new_data {
+        let new_hash = crate::hash_str(content);
+        new_hashes.insert(id.clone(), new_hash);
+
+        match hashes.get(id) {
+            Some(&existing_hash) if existing_hash != new_hash => {
+                send_masked(
+                    event_sender,
+                    event_mask,
+                    DocumentEvent::ContentChanged(id.clone(), content.to_string()),
+                )
+                .await
+                .ok();
+            }
+            None => {
+                send_masked(
+                    event_sender,
+                    event_mask,
+                    DocumentEvent::NewDocument(id.clone(), content.to_string()),
+                )
+                .await
+                .ok();
+            }
+            _ => {}
+        }
+    }
+
+    // Detect removed documents
+    for id in hashes.keys() {
+        if !new_data.contains_key(id) {
+            send_masked(
+                event_sender,
+                event_mask,
+                DocumentEvent::DocumentRemoved(id.clone()),
+            )
+            .await
+            .ok();
+        }
+    }
+
+    *hashes = new_hashes;
+}
+
+/// Sends `event` unless `event_mask` suppresses it.
+#[cfg(any(
+    feature = "file",
+    feature = "configmap",
+    feature = "mqtt",
+    feature = "vault"
+))]
+pub(crate) async fn send_masked(
+    event_sender: &EventSender,
+    event_mask: EventMask,
+    event: DocumentEvent,
+) -> Result<(), mpsc::error::SendError<DocumentEvent>> {
+    match event_mask.allows(&event) {
+        true => event_sender.send(event).await,
+        false => Ok(()),
+    }
+}
+
+/// Delay before a backend retries after an error or reconnects after its source ended.
+///
+/// The delay is randomized by up to `jitter` (a fraction of `delay`) in both directions, so
+/// replicas that lost their connection at the same time do not reconnect in lockstep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryDelay {
+    delay: Duration,
+    jitter: f64,
+}
+
+impl RetryDelay {
+    /// Creates a delay of `delay` ± `jitter * delay`. `jitter` is clamped to `0.0..=1.0`; NaN
+    /// disables the jitter.
+    pub const fn new(delay: Duration, jitter: f64) -> Self {
+        let jitter = if jitter.is_nan() {
+            0.0
+        } else {
+            jitter.clamp(0.0, 1.0)
+        };
+        Self { delay, jitter }
+    }
+
+    /// Returns the delay without jitter.
+    pub const fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Returns the maximum deviation as a fraction of [`delay`](Self::delay), in `0.0..=1.0`.
+    pub const fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Returns a delay chosen uniformly from `delay * (1 ± jitter)`, saturating at
+    /// [`Duration::MAX`].
+    pub fn sample(&self) -> Duration {
+        // RandomState is seeded randomly per instance, which is sufficient for spreading delays
+        let random = RandomState::new().hash_one(0u8) as f64 / u64::MAX as f64;
+        let factor = 1.0 + self.jitter * (2.0 * random - 1.0);
+        Duration::try_from_secs_f64(self.delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+
+    /// Sleeps for a sampled delay on `clock`.
+    #[cfg(any(
+        feature = "configmap",
+        feature = "kafka",
+        feature = "mqtt",
+        feature = "nats",
+        feature = "postgres"
+    ))]
+    pub(crate) fn sleep_on(&self, clock: &dyn crate::clock::Clock) -> crate::clock::ClockSleep {
+        clock.sleep(self.sample())
+    }
+}
+
+impl Default for RetryDelay {
+    /// 5 seconds ± 20%.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5), 0.2)
+    }
 }
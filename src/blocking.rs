@@ -0,0 +1,154 @@
+use std::time::Duration;
+use tokio::{
+    runtime::Runtime,
+    sync::mpsc::{error::TryRecvError, Receiver},
+};
+
+use crate::backend::{DocumentEvent, WatcherHandle};
+use crate::{
+    run_config_item_watcher_with_options, ConfigItemEvent, ConfigItemWatcherHandle,
+    ConfigItemWatcherOptions, Tokenizer, WatcherError,
+};
+
+/// Blocking wrapper around [`run_config_item_watcher`](crate::run_config_item_watcher) for code
+/// without a Tokio runtime.
+///
+/// The watcher creates and owns a multi-threaded Tokio runtime with a single worker thread on
+/// which the backend and the item watcher run. It is started on creation and stopped when it is
+/// dropped. Events are received with [`recv`](Self::recv), [`try_recv`](Self::try_recv) or by
+/// iterating over the watcher.
+///
+/// Like any blocking Tokio API, the methods of this type (including `drop`) panic when called
+/// from within an asynchronous context.
+pub struct BlockingConfigItemWatcher<T> {
+    runtime: Runtime,
+    handle: Option<ConfigItemWatcherHandle<T>>,
+    receiver: Receiver<ConfigItemEvent<T>>,
+}
+
+impl<T> BlockingConfigItemWatcher<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Creates a runtime and starts an item watcher on it. See
+    /// [`run_config_item_watcher`](crate::run_config_item_watcher) for the parameters.
+    pub fn new<E>(
+        make_watcher_backend: impl Fn() -> std::result::Result<
+            (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+            WatcherError,
+        >,
+        tokenizer: &'static dyn Tokenizer,
+        deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    ) -> Result<Self, WatcherError>
+    where
+        E: Send + Sync + std::fmt::Debug + 'static,
+    {
+        Self::with_options(
+            make_watcher_backend,
+            tokenizer,
+            deserialize,
+            ConfigItemWatcherOptions::default(),
+        )
+    }
+
+    /// Same as [`BlockingConfigItemWatcher::new`] but with additional
+    /// [`ConfigItemWatcherOptions`].
+    pub fn with_options<E>(
+        make_watcher_backend: impl Fn() -> std::result::Result<
+            (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+            WatcherError,
+        >,
+        tokenizer: &'static dyn Tokenizer,
+        deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+        options: ConfigItemWatcherOptions<T>,
+    ) -> Result<Self, WatcherError>
+    where
+        E: Send + Sync + std::fmt::Debug + 'static,
+    {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(WatcherError::Runtime)?;
+
+        let (handle, receiver) = {
+            let _guard = runtime.enter();
+            run_config_item_watcher_with_options(
+                make_watcher_backend,
+                tokenizer,
+                deserialize,
+                options,
+            )?
+        };
+        runtime.block_on(handle.start())?;
+
+        Ok(Self {
+            runtime,
+            handle: Some(handle),
+            receiver,
+        })
+    }
+
+    /// Blocks until the next event is available. Returns `None` once the watcher has terminated.
+    pub fn recv(&mut self) -> Option<ConfigItemEvent<T>> {
+        self.receiver.blocking_recv()
+    }
+
+    /// Returns the next event if one is available without blocking.
+    pub fn try_recv(&mut self) -> Result<ConfigItemEvent<T>, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// Blocks until the next event is available or `timeout` elapsed. Returns `None` on timeout
+    /// or once the watcher has terminated.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<ConfigItemEvent<T>> {
+        self.runtime
+            .block_on(async { tokio::time::timeout(timeout, self.receiver.recv()).await })
+            .ok()
+            .flatten()
+    }
+
+    /// Blocks until the initial scan of the backend has been processed. Returns `false` if the
+    /// watcher terminated before. The events of the initial scan remain queued for `recv`.
+    pub fn wait_ready(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| self.runtime.block_on(handle.wait_ready()))
+    }
+
+    /// Stops the watcher and returns its result. Dropping the watcher does the same but only
+    /// logs errors.
+    pub fn stop(mut self) -> Result<(), WatcherError> {
+        self.shutdown()
+    }
+}
+
+impl<T> BlockingConfigItemWatcher<T> {
+    fn shutdown(&mut self) -> Result<(), WatcherError> {
+        // Unblock the watcher task if it waits for room in the event channel
+        self.receiver.close();
+        match self.handle.take() {
+            Some(mut handle) => self.runtime.block_on(handle.stop()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> Iterator for BlockingConfigItemWatcher<T>
+where
+    T: Send + Sync + 'static,
+{
+    type Item = ConfigItemEvent<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+impl<T> Drop for BlockingConfigItemWatcher<T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            log::warn!("Error stopping blocking config item watcher: {}", err);
+        }
+    }
+}
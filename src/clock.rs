@@ -0,0 +1,129 @@
+//! Time source of the watchers' timers.
+//!
+//! The watchers take their debounce, grace period, rate limit and retry timers from a [`Clock`].
+//! [`TokioClock`] is used by default; [`TestClock`] only advances when told to, which makes
+//! timing-dependent behavior testable without real sleeping.
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::oneshot, time::Instant};
+
+/// Future returned by [`Clock::sleep_until`].
+pub type ClockSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Source of the current time and of timers.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Completes once the clock has reached `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep;
+
+    /// Completes once `duration` has passed on this clock.
+    fn sleep(&self, duration: Duration) -> ClockSleep {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// Clock shared between a watcher and its options.
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Returns the default clock, a [`TokioClock`].
+pub fn default_clock() -> SharedClock {
+    Arc::new(TokioClock)
+}
+
+/// Clock based on [`tokio::time`], so it also follows tokio's paused time in tests.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// Clock for tests that stands still until it is moved with [`TestClock::advance`].
+///
+/// Clones share the same time, so a clone can be passed to a watcher and the original advanced
+/// by the test.
+#[derive(Clone, Debug)]
+pub struct TestClock {
+    state: Arc<Mutex<TestClockState>>,
+}
+
+#[derive(Debug)]
+struct TestClockState {
+    now: Instant,
+    sleepers: Vec<(Instant, oneshot::Sender<()>)>,
+}
+
+impl TestClock {
+    /// Creates a clock standing at the current time.
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(TestClockState {
+                now: Instant::now(),
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Moves the clock forward by `duration` and completes all sleeps that are due.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += duration;
+        let now = state.now;
+        let (due, waiting) = std::mem::take(&mut state.sleepers)
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= now);
+        state.sleepers = waiting;
+        drop(state);
+        for (_, sender) in due {
+            sender.send(()).ok();
+        }
+    }
+
+    /// Returns the number of sleeps that have not completed yet, so a test can wait until a
+    /// watcher has set its timer before advancing the clock.
+    pub fn pending_sleeps(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.sleepers.retain(|(_, sender)| !sender.is_closed());
+        state.sleepers.len()
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> ClockSleep {
+        let mut state = self.state.lock().unwrap();
+        if deadline <= state.now {
+            return Box::pin(std::future::ready(()));
+        }
+        let (sender, receiver) = oneshot::channel();
+        state.sleepers.push((deadline, sender));
+        Box::pin(async move {
+            // The clock is dropped with all its clones only when nothing can advance it anymore
+            if receiver.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        })
+    }
+}
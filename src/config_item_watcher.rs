@@ -1,18 +1,30 @@
-use crate::backend::{DocumentEvent, WatcherHandle};
-use crate::{hash_str, Tokenizer, WatcherError};
+use crate::backend::{
+    DocumentEvent, EventMask, SourceKind, WatcherCommand, WatcherController, WatcherHandle,
+};
+use crate::{
+    clock::{default_clock, Clock, SharedClock},
+    hash_str,
+    persistence::{EventStore, PersistedRecord, PersistedState},
+    Tokenizer, WatcherError,
+};
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::Display,
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
 };
 use tokio::{
     sync::{
         mpsc::{self, Receiver},
-        watch,
+        oneshot, watch,
     },
     task::JoinHandle,
+    time::Instant,
 };
 
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct ConfigItemHash(u64, u64);
 
 impl ConfigItemHash {
@@ -32,39 +44,604 @@ impl Display for ConfigItemHash {
 }
 
 // Event types for configuration items
+//
+// The events caused by one document event are framed consistently: the document event opening
+// the frame first (`NewDocument`, or `DocumentChanged` for a known document), the item events
+// next (`New`, `NewWithRaw`, `Removed`, `ValidationError`, `Error`), then `DocumentEmptied` if the
+// document lost its last item, and for a removal `RemoveDocument` last, after the removals of all
+// its items. Item events of a document therefore always occur between its `NewDocument` and its
+// `RemoveDocument`.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ConfigItemEvent<T> {
     NewDocument(u64, String),
     RemoveDocument(u64),
-    New(ConfigItemHash, T),  // Hash and Item
+    DocumentChanged(u64), // Content of a tracked document changed, precedes its item events
+    DocumentEmptied(u64), // A document with items became empty, follows its item removals (emit_emptied)
+    DocumentTouched(u64), // A tracked document was written without content change (DocumentEvent::Touched)
+    New(ConfigItemHash, T), // Hash and Item
+    NewWithRaw(ConfigItemHash, T, String), // Like New, plus the raw item text (include_raw)
     Removed(ConfigItemHash), // Hash of the removed item
+    Batch(Vec<ConfigItemEvent<T>>), // All events caused by a single document event
+    // An item was rejected by the validate hook and is not tracked
+    ValidationError {
+        item_hash: ConfigItemHash,
+        error: ValidationError,
+    },
+    Error(WatcherError), // A non-fatal error, e.g. WatcherError::LimitExceeded
+    // A tracked document could not be read (DocumentEvent::ReadFailed). Its items are kept; the
+    // next successful read is reported with DocumentChanged and the item diff
+    DocumentError {
+        filename_hash: u64,
+        error: WatcherError,
+    },
+    // All events preceding a ConfigItemWatcherHandle::checkpoint have been sent. Acknowledge it
+    // once they are applied
+    Checkpoint(CheckpointAck),
+}
+
+/// Barrier of a [`ConfigItemWatcherController::checkpoint`], received with
+/// [`ConfigItemEvent::Checkpoint`].
+///
+/// [`checkpoint`](ConfigItemWatcherController::checkpoint) returns once it is acknowledged or
+/// dropped, so a consumer that does not use checkpoints can ignore it.
+#[derive(Debug)]
+pub struct CheckpointAck(oneshot::Sender<()>);
+
+impl CheckpointAck {
+    /// Releases the caller of [`checkpoint`](ConfigItemWatcherController::checkpoint).
+    pub fn ack(self) {
+        self.0.send(()).ok();
+    }
+}
+
+/// Error returned by a [`Validator`] to reject an item.
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("{0}")]
+pub struct ValidationError(pub String);
+
+impl From<String> for ValidationError {
+    fn from(message: String) -> Self {
+        Self(message)
+    }
+}
+
+impl From<&str> for ValidationError {
+    fn from(message: &str) -> Self {
+        Self(message.to_string())
+    }
+}
+
+/// Determines how configuration items are identified.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ConfigIdentity {
+    /// Items are identified by the document they belong to and their content. The same content
+    /// in two documents results in two items.
+    #[default]
+    FileScoped,
+    /// Items are identified by their content only. Identical content in several documents is
+    /// reported as a single item with a filename hash of `0`. It is removed once the last
+    /// document containing it drops it.
+    Content,
+}
+
+/// How deserialization errors of items, and documents the tokenizer fails to split (see
+/// [`Tokenizer::try_tokenize`]), are handled.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorMode {
+    /// Log the error and skip the item, or all items of a document that cannot be split.
+    #[default]
+    Skip,
+    /// Terminate the watcher task with [`WatcherError::Deserialize`] or
+    /// [`WatcherError::Tokenize`].
+    Fail,
+    /// Skip like `Skip` and emit the error as [`ConfigItemEvent::Error`] with
+    /// [`WatcherError::Deserialize`] or [`WatcherError::Tokenize`] among the events of its
+    /// document.
+    Report,
+}
+
+/// Predicate over a document's id and content. See [`ConfigItemWatcherOptions::content_filter`].
+pub type ContentFilter = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// Predicate over a document's id. See [`ConfigItemWatcherOptions::enabled`].
+pub type DocumentPredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Semantic check of a deserialized item. See [`ConfigItemWatcherOptions::validate`].
+pub type Validator<T> = Arc<dyn Fn(&T) -> Result<(), ValidationError> + Send + Sync>;
+
+/// Maps the text of an item to a canonical form. See [`ConfigItemWatcherOptions::canonicalize`].
+pub type Canonicalizer = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Derives a document id from the document content. See
+/// [`ConfigItemWatcherOptions::id_from_content`].
+pub type IdFromContent = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Computes the filename hash of a document id. See [`ConfigItemWatcherOptions::filename_id_fn`].
+pub type FilenameId = Arc<dyn Fn(&str) -> u64 + Send + Sync>;
+
+/// How long the item watcher waits for the new name of a document whose content-derived id lost
+/// its last source, see [`ConfigItemWatcherOptions::id_from_content`].
+const RENAME_WINDOW: Duration = Duration::from_millis(100);
+
+/// Optional settings for [`run_config_item_watcher_with_options`].
+pub struct ConfigItemWatcherOptions<T> {
+    /// Called with (document id, content) before a document is tokenized. If it returns `false`
+    /// no item events are emitted for the document and items previously tracked from it are
+    /// reported as removed.
+    pub content_filter: Option<ContentFilter>,
+    /// Called with the document id whenever a change of the document is emitted and on
+    /// [`reevaluate`](ConfigItemWatcherController::reevaluate). While it returns `false` the
+    /// document is reported as removed and its changes are recorded but not emitted. Once it
+    /// returns `true` again, the current content is emitted as a new document. The predicate may
+    /// depend on state that changes at runtime, e.g. whether a tenant is enabled.
+    pub enabled: Option<DocumentPredicate>,
+    /// Emit all events caused by a single document event as one [`ConfigItemEvent::Batch`]
+    /// instead of one message per event. Document events that cause no changes emit nothing.
+    pub batch_events: bool,
+    /// How items are identified across documents.
+    pub identity: ConfigIdentity,
+    /// What to do when an item fails to deserialize. With [`ErrorMode::Fail`] the watcher stops
+    /// and the error is returned from [`ConfigItemWatcherHandle::stop`]; the event receiver is
+    /// closed. With [`ErrorMode::Report`] the error is emitted as an event.
+    pub on_deserialize_error: ErrorMode,
+    /// Called with each successfully deserialized item. Rejected items are not tracked and are
+    /// reported as [`ConfigItemEvent::ValidationError`] instead of `New`, each time their document
+    /// is processed. An item whose content changes to something invalid is thus reported as
+    /// `Removed`.
+    pub validate: Option<Validator<T>>,
+    /// Emit [`ConfigItemEvent::NewWithRaw`] with the trimmed source text of the item instead of
+    /// `New`. Costs a copy of the text per new item.
+    pub include_raw: bool,
+    /// Computes item hashes from the canonical form returned by this function instead of the
+    /// item text, so that semantically equal items get equal hashes. Items for which it returns
+    /// `None` are hashed as they are. With the `yaml` feature, [`crate::canonicalize_yaml`]
+    /// expands YAML anchors and merge keys. Note that enabling it changes all item hashes.
+    pub canonicalize: Option<Canonicalizer>,
+    /// Derives the document id from the document content (e.g. from a `metadata.name` field)
+    /// instead of using the backend id (path, topic, key). Documents for which it returns `None`
+    /// keep their backend id.
+    ///
+    /// All item events, document events, mute ids and item hashes use the derived id, so renaming
+    /// a file without changing its id is not reported. Backends report a rename as a removal
+    /// followed by a new document; when a derived id loses its last source, the watcher waits up
+    /// to 100 ms for a document with the same id before reporting it as removed. Several backend
+    /// documents deriving the same id are treated as one document holding the latest content.
+    pub id_from_content: Option<IdFromContent>,
+    /// Hold back changes of a document until it has not changed for this duration, then emit
+    /// only the net change: nothing if the content ended up as before, so a file that is saved
+    /// several times in quick succession produces a single update and a removal followed by
+    /// identical content produces none. Documents of the initial scan are not delayed.
+    pub settle: Option<Duration>,
+    /// Emit at most one change per document within this interval. Further changes are held back
+    /// and the latest content is emitted when the interval has passed (nothing if it ended up
+    /// unchanged). Unlike `settle`, a document that keeps changing is still updated regularly.
+    /// Documents of the initial scan are not delayed.
+    pub min_interval: Option<Duration>,
+    /// Emit [`ConfigItemEvent::DocumentEmptied`] after the removals of its items when a document
+    /// that had items becomes empty, i.e. contains only whitespace or empty tokens.
+    pub emit_emptied: bool,
+    /// Prefixes every document id with `{source_label}:` (e.g. `file:config/db`), so that
+    /// documents of different backends with the same id get distinct filenames and filename
+    /// hashes when their events are merged. Applied after `id_from_content`; mute ids must include
+    /// the prefix.
+    pub source_label: Option<String>,
+    /// Track at most this many documents. Further documents are not processed and are reported
+    /// once as [`ConfigItemEvent::Error`] with [`WatcherError::LimitExceeded`]. A rejected
+    /// document is admitted on its next change if other documents were removed in the meantime.
+    pub max_documents: Option<usize>,
+    /// Track at most this many items. The items of a document that exceed the limit are skipped
+    /// and reported as [`ConfigItemEvent::Error`] with [`WatcherError::LimitExceeded`] each time
+    /// the document is processed, so they are picked up on a later change once items were removed.
+    pub max_items: Option<usize>,
+    /// Log one line per processed document content with the number of its items that were
+    /// added, removed and kept, e.g. for auditing. Counts refer to the items of the document,
+    /// so with [`ConfigIdentity::Content`] they can differ from the reported item events.
+    pub log_diff_summary: bool,
+    /// Computes the filename hash of a document, i.e. the first part of its [`ConfigItemHash`]es,
+    /// from its id instead of hashing the id with [`hash_str`]. Use it to derive the hash from a
+    /// stable identity, e.g. the path relative to the watch root, so that item hashes persisted
+    /// elsewhere stay valid when the root moves between restarts. The function is called with
+    /// the final document id, i.e. after `id_from_content` and `source_label`.
+    pub filename_id_fn: Option<FilenameId>,
+    /// Tokenize and deserialize documents with `spawn_blocking` on Tokio's blocking thread pool
+    /// instead of on the watcher task, so that CPU heavy deserialization, e.g. of large documents
+    /// or with schema validation, does not block the runtime. Documents are still processed one
+    /// at a time and in order; the `validate` hook and the tracking of items run on the watcher
+    /// task. Replays for [`ConfigItemWatcherController::resubscribe`] are parsed on the watcher task.
+    pub parse_on_blocking_pool: bool,
+    /// Write-ahead log of the reported documents and items. The state it holds is restored on
+    /// startup: documents and items that were reported before are not reported again, a
+    /// restored document whose items changed is reported with `DocumentChanged` instead of
+    /// `NewDocument`, and restored documents that are missing after the initial scan are
+    /// removed. Records are appended before the events are sent; failures to append are logged.
+    /// Fails with [`WatcherError::Persistence`] if the log cannot be loaded.
+    pub persistence: Option<Arc<dyn EventStore>>,
+    /// Categories of backend events to process (default [`EventMask::ALL`]). Suppressed events
+    /// are ignored as if the backend had not sent them, so a later removal reports the items
+    /// that were reported before. Works with every backend; the file, ConfigMap and MQTT
+    /// backends also accept a mask to suppress the events at the source.
+    pub event_mask: EventMask,
+    /// Source of the time for `settle`, `min_interval` and the rename window of
+    /// `id_from_content` (default [`TokioClock`](crate::clock::TokioClock)).
+    pub clock: SharedClock,
+    /// Retain the content of each tracked document, so that
+    /// [`ConfigItemWatcherController::resubscribe`] can replay the current items. Without it,
+    /// only the content hashes are kept and the new receiver only gets the live events. The
+    /// content is also retained if `enabled` is set, to emit documents it disables on
+    /// [`reevaluate`](ConfigItemWatcherController::reevaluate) again once they are enabled.
+    pub replay_on_resubscribe: bool,
 }
 
-pub struct ConfigItemWatcherHandle {
+impl<T> Default for ConfigItemWatcherOptions<T> {
+    fn default() -> Self {
+        Self {
+            content_filter: None,
+            enabled: None,
+            batch_events: false,
+            identity: ConfigIdentity::default(),
+            on_deserialize_error: ErrorMode::default(),
+            validate: None,
+            include_raw: false,
+            canonicalize: None,
+            id_from_content: None,
+            settle: None,
+            min_interval: None,
+            emit_emptied: false,
+            source_label: None,
+            max_documents: None,
+            max_items: None,
+            log_diff_summary: false,
+            filename_id_fn: None,
+            parse_on_blocking_pool: false,
+            persistence: None,
+            event_mask: EventMask::ALL,
+            clock: default_clock(),
+            replay_on_resubscribe: false,
+        }
+    }
+}
+
+impl<T> Clone for ConfigItemWatcherOptions<T> {
+    fn clone(&self) -> Self {
+        Self {
+            content_filter: self.content_filter.clone(),
+            enabled: self.enabled.clone(),
+            batch_events: self.batch_events,
+            identity: self.identity,
+            on_deserialize_error: self.on_deserialize_error,
+            validate: self.validate.clone(),
+            include_raw: self.include_raw,
+            canonicalize: self.canonicalize.clone(),
+            id_from_content: self.id_from_content.clone(),
+            settle: self.settle,
+            min_interval: self.min_interval,
+            emit_emptied: self.emit_emptied,
+            source_label: self.source_label.clone(),
+            max_documents: self.max_documents,
+            max_items: self.max_items,
+            log_diff_summary: self.log_diff_summary,
+            filename_id_fn: self.filename_id_fn.clone(),
+            parse_on_blocking_pool: self.parse_on_blocking_pool,
+            persistence: self.persistence.clone(),
+            event_mask: self.event_mask,
+            clock: self.clock.clone(),
+            replay_on_resubscribe: self.replay_on_resubscribe,
+        }
+    }
+}
+
+/// An item event with its sequence number, see [`run_config_item_watcher_sequenced`].
+#[derive(Debug)]
+pub struct SequencedEvent<T> {
+    pub seq: u64,
+    pub event: ConfigItemEvent<T>,
+}
+
+/// Handle returned by [`run_config_item_watcher_sequenced`].
+pub type SequencedWatcherHandle<T> = ConfigItemWatcherHandle<T, SequencedEvent<T>>;
+
+/// An item event with the wall-clock time its change was observed and its sequence number, see
+/// [`run_config_item_watcher_timestamped`]. Sequence numbers are assigned like for
+/// [`SequencedEvent`]s.
+#[derive(Debug)]
+pub struct TimestampedEvent<T> {
+    pub seq: u64,
+    pub observed_at: SystemTime,
+    pub event: ConfigItemEvent<T>,
+}
+
+/// Handle returned by [`run_config_item_watcher_timestamped`].
+pub type TimestampedWatcherHandle<T> = ConfigItemWatcherHandle<T, TimestampedEvent<T>>;
+
+/// Requests from a [`ConfigItemWatcherHandle`] to its watcher task.
+enum ItemWatcherCommand<O> {
+    Resubscribe(oneshot::Sender<Receiver<O>>),
+    Mute(Vec<String>, oneshot::Sender<()>),
+    Unmute(Vec<String>, oneshot::Sender<()>),
+    Reevaluate(oneshot::Sender<()>),
+    Checkpoint(oneshot::Sender<()>),
+}
+
+/// Handle of an item watcher emitting items of type `T` as messages of type `O`.
+///
+/// The handle is the only owner of the watcher tasks. The methods of its
+/// [`ConfigItemWatcherController`] are available on the handle as well; use
+/// [`controller`](Self::controller) to control the watcher from several places.
+pub struct ConfigItemWatcherHandle<T, O = ConfigItemEvent<T>> {
     task_handle: Option<JoinHandle<Result<(), WatcherError>>>,
     watcher_backend_handle: WatcherHandle,
-    stop_sender: watch::Sender<bool>, // Shutdown signal
+    controller: ConfigItemWatcherController<T, O>,
 }
 
-impl ConfigItemWatcherHandle {
-    /// starts the watcher. Can only be used once!
-    pub async fn start(&self) -> Result<(), WatcherError> {
-        self.watcher_backend_handle.start().await?;
-        Ok(())
+/// Cloneable control side of a [`ConfigItemWatcherHandle`], see
+/// [`ConfigItemWatcherHandle::controller`].
+///
+/// Controllers do not own the watcher tasks: [`stop`](Self::stop) waits until the tasks have
+/// terminated, while their errors are only returned by [`ConfigItemWatcherHandle::stop`].
+pub struct ConfigItemWatcherController<T, O = ConfigItemEvent<T>> {
+    backend: WatcherController,
+    stop_sender: watch::Sender<bool>,      // Shutdown signal
+    ready_receiver: watch::Receiver<bool>, // Initial scan completion
+    command_sender: mpsc::Sender<ItemWatcherCommand<O>>,
+    document_ids: DocumentIds, // Ids of the documents reported to the consumer
+    digests: DocumentDigests,  // Content hashes of the processed documents
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T, O> Clone for ConfigItemWatcherController<T, O> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            stop_sender: self.stop_sender.clone(),
+            ready_receiver: self.ready_receiver.clone(),
+            command_sender: self.command_sender.clone(),
+            document_ids: self.document_ids.clone(),
+            digests: self.digests.clone(),
+            _item: PhantomData,
+        }
+    }
+}
+
+/// Document ids by filename hash, as reported by `NewDocument` and `RemoveDocument` events.
+type DocumentIds = Arc<RwLock<HashMap<u64, String>>>;
+
+/// Content hash by document id of the latest processed content of each document.
+type DocumentDigests = Arc<RwLock<HashMap<String, u64>>>;
+
+impl<T, O> ConfigItemWatcherHandle<T, O> {
+    /// Returns a cloneable controller of the watcher, e.g. for another subsystem that needs to
+    /// stop it.
+    pub fn controller(&self) -> ConfigItemWatcherController<T, O> {
+        self.controller.clone()
     }
 
     /// Stops the watcher task.
+    ///
+    /// If the item watcher task failed, its error is returned in preference to errors of the
+    /// backend.
     pub async fn stop(&mut self) -> Result<(), WatcherError> {
-        self.watcher_backend_handle.stop().await?;
-        let _ = self.stop_sender.send(true); // Send the shutdown signal
+        let backend_result = self.watcher_backend_handle.stop().await;
+        let _ = self.controller.stop_sender.send(true); // Send the shutdown signal
 
         if let Some(handle) = self.task_handle.take() {
             handle.await??;
         } else {
             log::warn!("Task handle was already taken or not initialized.");
         }
+        backend_result
+    }
+
+    /// Stops the watcher like [`stop`](Self::stop) and returns the events that were sent to
+    /// `receiver` but not received yet, e.g. to process every event at least once.
+    ///
+    /// The handle does not own the receiver, so the current one (the last one returned by
+    /// [`resubscribe`](ConfigItemWatcherController::resubscribe), if any) has to be passed in. The events, including
+    /// their items, are moved out of the channel, and the channel is closed. If stopping fails,
+    /// the error is returned and the events remain in `receiver`.
+    pub async fn stop_draining(
+        &mut self,
+        receiver: &mut Receiver<O>,
+    ) -> Result<Vec<O>, WatcherError> {
+        self.stop().await?;
+        receiver.close();
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    /// Returns true if the item watcher task has terminated. This happens after `stop` or when
+    /// the backend terminated because its source ended.
+    pub fn is_finished(&self) -> bool {
+        self.task_handle
+            .as_ref()
+            .is_none_or(|handle| handle.is_finished())
+    }
+}
+
+impl<T, O> std::ops::Deref for ConfigItemWatcherHandle<T, O> {
+    type Target = ConfigItemWatcherController<T, O>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.controller
+    }
+}
+
+impl<T, O> ConfigItemWatcherController<T, O> {
+    /// starts the watcher. Can only be used once!
+    pub async fn start(&self) -> Result<(), WatcherError> {
+        self.backend.start().await?;
         Ok(())
     }
+
+    /// Stops the backend and the item watcher task and waits until both have terminated.
+    /// Returns immediately if they have already terminated.
+    pub async fn stop(&self) {
+        self.backend.stop().await;
+        // Send the shutdown signal
+        let _ = self.stop_sender.send(true);
+        // The task drops its command receiver when it terminates
+        self.command_sender.closed().await;
+    }
+
+    /// Waits until the backend has emitted all documents present at startup and the resulting
+    /// item events have been sent.
+    ///
+    /// For MQTT this is the case a short settle window after the first connection was
+    /// acknowledged. Returns `false` if the watcher terminated before becoming ready.
+    pub async fn wait_ready(&self) -> bool {
+        self.ready_receiver
+            .clone()
+            .wait_for(|ready| *ready)
+            .await
+            .is_ok()
+    }
+
+    /// Returns true once the watcher became ready, see [`wait_ready`](Self::wait_ready).
+    pub fn is_ready(&self) -> bool {
+        *self.ready_receiver.borrow()
+    }
+
+    /// Replaces the event receiver with a new one, for example after the consumer restarted.
+    ///
+    /// With [`ConfigItemWatcherOptions::replay_on_resubscribe`], the new receiver first gets a
+    /// `NewDocument` event for each tracked document followed by `New` events for its current
+    /// items (as one `Batch` if `batch_events` is enabled), then the live events; otherwise only
+    /// the live events. The previous receiver is closed. Returns `None` if the watcher has
+    /// terminated.
+    ///
+    /// The items are replayed by tokenizing and deserializing the retained document contents
+    /// again.
+    pub async fn resubscribe(&self) -> Option<Receiver<O>> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(ItemWatcherCommand::Resubscribe(reply_sender))
+            .await
+            .ok()?;
+        reply_receiver.await.ok()
+    }
+
+    /// Holds back events for the documents `ids`, e.g. during a coordinated edit of several
+    /// documents.
+    ///
+    /// While a document is muted, its changes are recorded but not processed. When it is unmuted,
+    /// the net change since muting is emitted as a single document event: nothing if the content
+    /// ended up unchanged. A change held back by `settle` or `min_interval` when the document is
+    /// muted is part of that net change. Returns `false` if the watcher has terminated.
+    pub async fn mute(&self, ids: Vec<String>) -> bool {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(ItemWatcherCommand::Mute(ids, reply_sender))
+            .await
+            .is_ok()
+            && reply_receiver.await.is_ok()
+    }
+
+    /// Stops holding back events for the documents `ids` and emits their pending changes. With
+    /// `batch_events`, the changes of all unmuted documents are emitted as one `Batch`. Returns
+    /// `false` if the watcher has terminated.
+    pub async fn unmute(&self, ids: Vec<String>) -> bool {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(ItemWatcherCommand::Unmute(ids, reply_sender))
+            .await
+            .is_ok()
+            && reply_receiver.await.is_ok()
+    }
+
+    /// Calls the [`enabled`](ConfigItemWatcherOptions::enabled) predicate for all documents,
+    /// e.g. after the state it depends on changed. Documents that are no longer enabled are
+    /// reported as removed, documents that are enabled again are emitted with their current
+    /// content. Returns `false` if the watcher has terminated.
+    pub async fn reevaluate(&self) -> bool {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        self.command_sender
+            .send(ItemWatcherCommand::Reevaluate(reply_sender))
+            .await
+            .is_ok()
+            && reply_receiver.await.is_ok()
+    }
+
+    /// Brings the event stream to a consistent point, e.g. before applying a new configuration
+    /// version as a whole.
+    ///
+    /// The watcher first processes the events the backend has already emitted, then emits the
+    /// changes of documents held back by `settle` or `min_interval` regardless of their due time,
+    /// and finally sends a [`ConfigItemEvent::Checkpoint`]. This returns once the consumer has
+    /// acknowledged it with [`CheckpointAck::ack`] (or dropped it), so the consumer has received
+    /// every event that preceded the call. Changes the backend has not emitted yet, e.g. within
+    /// its debounce window, and changes of [muted](Self::mute) documents are not included. With
+    /// `batch_events`, the checkpoint is the last event of a `Batch`. Returns immediately if the
+    /// watcher has terminated.
+    pub async fn checkpoint(&self) {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+        if self
+            .command_sender
+            .send(ItemWatcherCommand::Checkpoint(reply_sender))
+            .await
+            .is_ok()
+        {
+            reply_receiver.await.ok();
+        }
+    }
+
+    /// Returns the ids of the documents currently tracked, sorted, i.e. of those reported by a
+    /// `NewDocument` event and not removed since. Documents held back by `settle`, `min_interval`
+    /// or [`mute`](Self::mute) are listed once their change has been emitted.
+    pub fn document_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .document_ids
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the [`hash_str`] of the latest content of each document by document id, e.g. to
+    /// compare the view of the watcher with an external source of truth. The hashes equal
+    /// [`DocumentEvent::content_hash`] of the backend events. Documents that produce no items, e.g.
+    /// because of `content_filter`, are included. Documents held back by `settle`, `min_interval`
+    /// or [`mute`](Self::mute) are updated once their change has been emitted.
+    pub fn digest_map(&self) -> HashMap<String, u64> {
+        self.digests.read().unwrap().clone()
+    }
+
+    /// Returns the kind of source the backend of the watcher reads from, see
+    /// [`WatcherHandle::source_kind`].
+    pub fn source_kind(&self) -> SourceKind {
+        self.backend.source_kind()
+    }
+
+    /// Loads the document `id` from the backend, see [`WatcherHandle::load_document`]. With a
+    /// lazy file watcher, the items of the loaded content are emitted like those of a change.
+    pub async fn load_document(&self, id: &str) -> Result<String, WatcherError> {
+        self.backend.load_document(id).await
+    }
+
+    /// Returns true if the item watcher task has terminated, see
+    /// [`ConfigItemWatcherHandle::is_finished`].
+    pub fn is_finished(&self) -> bool {
+        self.command_sender.is_closed()
+    }
+}
+
+/// Turns the receiver of a watcher into a [`Stream`](futures::Stream), to use combinators such
+/// as `filter`, `map` or `take_while` from [`StreamExt`](futures::StreamExt).
+///
+/// Works for item events, [`SequencedEvent`]s and the `DocumentEvent`s of a backend alike. The
+/// stream ends when the watcher terminates. Streams of several watchers can be merged with
+/// `futures::stream::select`. After [`resubscribe`](ConfigItemWatcherController::resubscribe), the
+/// stream of the previous receiver ends and the new receiver has to be converted.
+pub fn into_stream<O>(mut receiver: Receiver<O>) -> impl futures::Stream<Item = O> + Send + Unpin
+where
+    O: Send,
+{
+    futures::stream::poll_fn(move |cx| receiver.poll_recv(cx))
 }
 
 // Watcher function
@@ -75,27 +652,568 @@ pub fn run_config_item_watcher<T, E>(
     >,
     tokenizer: &'static dyn Tokenizer,
     deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
-) -> Result<(ConfigItemWatcherHandle, Receiver<ConfigItemEvent<T>>), WatcherError>
+) -> Result<(ConfigItemWatcherHandle<T>, Receiver<ConfigItemEvent<T>>), WatcherError>
+where
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+{
+    run_config_item_watcher_with_options(
+        make_watcher_backend,
+        tokenizer,
+        deserialize,
+        ConfigItemWatcherOptions::default(),
+    )
+}
+
+/// Same as [`run_config_item_watcher`] but with additional [`ConfigItemWatcherOptions`].
+pub fn run_config_item_watcher_with_options<T, E>(
+    make_watcher_backend: impl Fn() -> std::result::Result<
+        (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+        WatcherError,
+    >,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    options: ConfigItemWatcherOptions<T>,
+) -> Result<(ConfigItemWatcherHandle<T>, Receiver<ConfigItemEvent<T>>), WatcherError>
 where
     T: Send + Sync + 'static,
     E: Send + Sync + std::fmt::Debug + 'static,
 {
-    let (watcher_backend_handle, mut receiver) = make_watcher_backend()?;
     let (event_tx, event_rx) = mpsc::channel(100);
+    let handle = spawn_item_watcher(
+        make_watcher_backend,
+        tokenizer,
+        deserialize,
+        options,
+        EventSink::Channel(event_tx),
+        |_, _, event| event,
+        false,
+    )?;
+    Ok((handle, event_rx))
+}
+
+/// Same as [`run_config_item_watcher_with_options`] but calls `on_event` with each event instead
+/// of sending it to a channel.
+///
+/// The callback is invoked inline on the watcher task, in order. It must return quickly and must
+/// not block, e.g. on locks held across `.await` or on blocking I/O, since that stalls the
+/// watcher and, on a current-thread runtime, the whole runtime; hand slow work off to another
+/// task. A panicking callback ends the watcher. [`ConfigItemWatcherController::resubscribe`] replaces
+/// the callback with the returned channel.
+pub fn run_config_item_watcher_with_callback<T, E>(
+    make_watcher_backend: impl Fn() -> std::result::Result<
+        (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+        WatcherError,
+    >,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    options: ConfigItemWatcherOptions<T>,
+    on_event: Arc<dyn Fn(ConfigItemEvent<T>) + Send + Sync>,
+) -> Result<ConfigItemWatcherHandle<T>, WatcherError>
+where
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+{
+    spawn_item_watcher(
+        make_watcher_backend,
+        tokenizer,
+        deserialize,
+        options,
+        EventSink::Callback(on_event),
+        |_, _, event| event,
+        false,
+    )
+}
+
+/// Same as [`run_config_item_watcher_with_options`] but every message carries a sequence number.
+///
+/// Sequence numbers start at 0 and are assigned per sent message (a `Batch` counts as one), so
+/// they are strictly increasing and gap-free within a watcher. Messages sent while no receiver
+/// exists are dropped with their sequence numbers; after [`ConfigItemWatcherController::resubscribe`]
+/// the numbering continues, so a gap shows that events were lost in between.
+pub fn run_config_item_watcher_sequenced<T, E>(
+    make_watcher_backend: impl Fn() -> std::result::Result<
+        (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+        WatcherError,
+    >,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    options: ConfigItemWatcherOptions<T>,
+) -> Result<(SequencedWatcherHandle<T>, Receiver<SequencedEvent<T>>), WatcherError>
+where
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+{
+    let (event_tx, event_rx) = mpsc::channel(100);
+    let handle = spawn_item_watcher(
+        make_watcher_backend,
+        tokenizer,
+        deserialize,
+        options,
+        EventSink::Channel(event_tx),
+        |seq, _, event| SequencedEvent { seq, event },
+        false,
+    )?;
+    Ok((handle, event_rx))
+}
+
+/// Same as [`run_config_item_watcher_with_options`] but every message carries the wall-clock
+/// time the change it reports was observed, e.g. for audit trails.
+///
+/// The time is taken by the backend when it detects the change, before it sends the event, see
+/// [`WatcherHandle::emit_timestamps`]. For the file watcher, this is when the latest file system
+/// event of a debounced batch was received, not the end of the debounce window. Messages of
+/// the same backend event share its time. Every message also carries a sequence number, assigned
+/// like by [`run_config_item_watcher_sequenced`]. Messages emitted later by the item
+/// watcher itself carry the time they are emitted: changes held back by `settle` or
+/// `min_interval` when they are due, [`unmute`](ConfigItemWatcherController::unmute)d changes and
+/// replays after [`resubscribe`](ConfigItemWatcherController::resubscribe). Timestamps never
+/// decrease within a watcher, also if the system clock is set back.
+pub fn run_config_item_watcher_timestamped<T, E>(
+    make_watcher_backend: impl Fn() -> std::result::Result<
+        (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+        WatcherError,
+    >,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    options: ConfigItemWatcherOptions<T>,
+) -> Result<(TimestampedWatcherHandle<T>, Receiver<TimestampedEvent<T>>), WatcherError>
+where
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+{
+    let (event_tx, event_rx) = mpsc::channel(100);
+    let handle = spawn_item_watcher(
+        make_watcher_backend,
+        tokenizer,
+        deserialize,
+        options,
+        EventSink::Channel(event_tx),
+        |seq, observed_at, event| TimestampedEvent {
+            seq,
+            observed_at,
+            event,
+        },
+        true,
+    )?;
+    Ok((handle, event_rx))
+}
+
+/// Convenience constructors wiring a backend to an item watcher.
+///
+/// Each constructor creates the backend and the item watcher in one step and returns the same
+/// handle and receiver as [`run_config_item_watcher`]. The watcher still has to be started with
+/// [`ConfigItemWatcherController::start`]; [`ConfigItemWatcherHandle::stop`] stops both.
+pub struct ConfigItemWatcher;
+
+impl ConfigItemWatcher {
+    /// Item watcher over the files below `watch_path` matching `file_pattern`, see
+    /// [`run_config_file_watcher_with_options`](crate::backend::run_config_file_watcher_with_options).
+    /// Pass `ConfigFileWatcherOptions::default()` for the default debounce and settings.
+    #[cfg(feature = "file")]
+    pub fn file<T, E>(
+        watch_path: impl AsRef<std::path::Path>,
+        file_pattern: impl Into<String>,
+        options: crate::backend::ConfigFileWatcherOptions,
+        tokenizer: &'static dyn Tokenizer,
+        deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    ) -> Result<(ConfigItemWatcherHandle<T>, Receiver<ConfigItemEvent<T>>), WatcherError>
+    where
+        T: Send + Sync + 'static,
+        E: Send + Sync + std::fmt::Debug + 'static,
+    {
+        let watch_path = watch_path.as_ref().to_path_buf();
+        let file_pattern = file_pattern.into();
+        run_config_item_watcher(
+            move || {
+                crate::backend::run_config_file_watcher_with_options(
+                    &watch_path,
+                    file_pattern.clone(),
+                    options.clone(),
+                )
+            },
+            tokenizer,
+            deserialize,
+        )
+    }
+
+    /// Item watcher over the data keys of a ConfigMap, see
+    /// [`run_configmap_watcher_with_options`](crate::backend::run_configmap_watcher_with_options).
+    #[cfg(feature = "configmap")]
+    pub fn configmap<T, E>(
+        configmap_name: impl Into<String>,
+        namespace: impl Into<String>,
+        options: crate::backend::ConfigMapWatcherOptions,
+        tokenizer: &'static dyn Tokenizer,
+        deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    ) -> Result<(ConfigItemWatcherHandle<T>, Receiver<ConfigItemEvent<T>>), WatcherError>
+    where
+        T: Send + Sync + 'static,
+        E: Send + Sync + std::fmt::Debug + 'static,
+    {
+        let configmap_name = configmap_name.into();
+        let namespace = namespace.into();
+        run_config_item_watcher(
+            move || {
+                crate::backend::run_configmap_watcher_with_options(
+                    configmap_name.clone(),
+                    namespace.clone(),
+                    options.clone(),
+                )
+            },
+            tokenizer,
+            deserialize,
+        )
+    }
+
+    /// Item watcher over the retained messages below an MQTT topic, see
+    /// [`run_mqtt_watcher_with_options`](crate::backend::run_mqtt_watcher_with_options).
+    #[cfg(feature = "mqtt")]
+    pub fn mqtt<T, E>(
+        mqttoptions: rumqttc::MqttOptions,
+        config_topic: impl Into<String>,
+        channel_size: usize,
+        options: crate::backend::MqttWatcherOptions,
+        tokenizer: &'static dyn Tokenizer,
+        deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    ) -> Result<(ConfigItemWatcherHandle<T>, Receiver<ConfigItemEvent<T>>), WatcherError>
+    where
+        T: Send + Sync + 'static,
+        E: Send + Sync + std::fmt::Debug + 'static,
+    {
+        let config_topic = config_topic.into();
+        run_config_item_watcher(
+            move || {
+                crate::backend::run_mqtt_watcher_with_options(
+                    mqttoptions.clone(),
+                    &config_topic,
+                    channel_size,
+                    options.clone(),
+                )
+            },
+            tokenizer,
+            deserialize,
+        )
+    }
+}
+
+/// Where the item watcher task delivers its messages.
+enum EventSink<O> {
+    Channel(mpsc::Sender<O>),
+    Callback(Arc<dyn Fn(O) + Send + Sync>),
+}
+
+impl<O> EventSink<O> {
+    /// Delivers `message`. A dropped receiver is not an error, the consumer may resubscribe
+    /// later.
+    async fn send(&self, message: O) {
+        match self {
+            Self::Channel(sender) => {
+                sender.send(message).await.ok();
+            }
+            Self::Callback(callback) => callback(message),
+        }
+    }
+}
+
+/// Spawns the item watcher task. `wrap` turns each outgoing event, its sequence number and the
+/// time its change was observed into the message delivered to `sink`. With `timestamps`, the
+/// backend stamps its events when it detects them, see [`WatcherHandle::emit_timestamps`], and
+/// that time is passed to `wrap`; otherwise the time the item watcher received the event.
+fn spawn_item_watcher<T, E, O>(
+    make_watcher_backend: impl Fn() -> std::result::Result<
+        (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>),
+        WatcherError,
+    >,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: impl Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+    options: ConfigItemWatcherOptions<T>,
+    sink: EventSink<O>,
+    wrap: fn(u64, SystemTime, ConfigItemEvent<T>) -> O,
+    timestamps: bool,
+) -> Result<ConfigItemWatcherHandle<T, O>, WatcherError>
+where
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+    O: Send + 'static,
+{
+    let (watcher_backend_handle, mut receiver) = make_watcher_backend()?;
+    if timestamps {
+        // The backend has not been started, so its command channel has room
+        watcher_backend_handle
+            .command_sender
+            .try_send(WatcherCommand::EmitTimestamps)
+            .ok();
+    }
     let (stop_sender, mut stop_receiver) = watch::channel(false);
+    let (ready_sender, ready_receiver) = watch::channel(false);
+    let (command_sender, mut command_receiver) = mpsc::channel::<ItemWatcherCommand<O>>(1);
 
-    let mut item_hashes = HashSet::new();
+    let deserialize = Arc::new(deserialize);
+    let mut item_hashes = ItemTracker::new(&options);
+    let document_ids = DocumentIds::default();
+    let digests = DocumentDigests::default();
+    if let Some(persistence) = &options.persistence {
+        let state = restore_state(&**persistence)?;
+        document_ids
+            .write()
+            .unwrap()
+            .extend(state.documents.clone());
+        item_hashes.restore(state);
+    }
+    // Latest content of each document, used to replay the current items on resubscribe. Empty
+    // unless the content is retained.
+    let mut documents: BTreeMap<String, String> = BTreeMap::new();
+    let retain = options.replay_on_resubscribe || options.enabled.is_some();
+    // Muted documents and their latest content, if they changed while muted (`Some(None)` if
+    // removed)
+    let mut muted: HashMap<String, Option<Option<String>>> = HashMap::new();
+    // Documents rejected by `enabled` and their latest content
+    let mut disabled: BTreeMap<String, String> = BTreeMap::new();
+    let mut pacer = ChangePacer::new(&options);
+    let mut content_ids = options.id_from_content.clone().map(ContentIds::new);
+    // Backend event received while waiting for a rename, processed next
+    let mut lookahead: Option<DocumentEvent> = None;
+    // Requested checkpoints, sent once the backend events received so far are processed
+    let mut checkpoints: Vec<oneshot::Sender<()>> = Vec::new();
 
     let handle = tokio::spawn({
-        let event_tx = event_tx.clone();
+        let mut sink = sink;
+        let mut seq: u64 = 0;
+        let mut last_observed = SystemTime::UNIX_EPOCH;
+        let document_ids = document_ids.clone();
+        let digests = digests.clone();
 
         async move {
             loop {
                 // log::warn!("waiting for file events: {}", fp);
+                let next_due = pacer.next_due();
+                let mut became_ready = false;
+                let mut observed_at = None;
+                // Events already emitted by the backend are processed before a checkpoint
+                let checkpoint_due = !checkpoints.is_empty()
+                    && lookahead.is_none()
+                    && match receiver.try_recv() {
+                        Ok(event) => {
+                            lookahead = Some(event);
+                            false
+                        }
+                        Err(_) => true,
+                    };
                 let events = tokio::select! {
                     // Wait for file events
-                    Some(event) = receiver.recv() => {
-                        handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize).await
+                    event = next_event(&mut receiver, &mut lookahead) => {
+                        let Some(event) = event else {
+                            log::debug!("Backend event stream ended. Exiting watcher.");
+                            break;
+                        };
+                        // The time the backend detected the event, if it stamped it
+                        let (detected_at, event) = event.into_observed();
+                        observed_at = Some(detected_at.unwrap_or_else(SystemTime::now));
+                        let document_events = match content_ids.as_mut() {
+                            Some(content_ids) => {
+                                content_ids
+                                    .translate(event, &mut receiver, &mut lookahead, &*options.clock, &stop_receiver)
+                                    .await
+                            }
+                            None => vec![event],
+                        };
+                        let mut events = Vec::new();
+                        for event in document_events {
+                            let event = match &options.source_label {
+                                Some(label) => event.with_source_label(label),
+                                None => event,
+                            };
+                            if !options.event_mask.allows(&event) {
+                                continue;
+                            }
+                            match &event {
+                                DocumentEvent::InitialScanComplete => {
+                                    events.extend(remove_unseen_restored(&mut item_hashes));
+                                    became_ready = true;
+                                    continue;
+                                }
+                                DocumentEvent::NewDocument(filename, content)
+                                | DocumentEvent::ContentChanged(filename, content) => {
+                                    if let Some(pending) = muted.get_mut(filename) {
+                                        *pending = Some(Some(content.clone()));
+                                        continue;
+                                    }
+                                    if *ready_sender.borrow() && pacer.hold(filename, || Some(content.clone())) {
+                                        continue;
+                                    }
+                                    if !is_enabled(&options, filename) {
+                                        disabled.insert(filename.clone(), content.clone());
+                                        if let Some(event) = net_change(&mut documents, &digests, retain, filename.clone(), None) {
+                                            events.extend(
+                                                handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize, &options).await?,
+                                            );
+                                        }
+                                        pacer.emitted(filename);
+                                        continue;
+                                    }
+                                    disabled.remove(filename);
+                                    record_document(&mut documents, &digests, retain, filename, Some(content));
+                                    pacer.emitted(filename);
+                                }
+                                DocumentEvent::DocumentRemoved(filename) => {
+                                    if let Some(pending) = muted.get_mut(filename) {
+                                        *pending = Some(None);
+                                        continue;
+                                    }
+                                    if *ready_sender.borrow() && pacer.hold(filename, || None) {
+                                        continue;
+                                    }
+                                    disabled.remove(filename);
+                                    record_document(&mut documents, &digests, retain, filename, None);
+                                    pacer.emitted(filename);
+                                }
+                                // Held back or muted documents are reported by their net change
+                                DocumentEvent::Touched(filename) => {
+                                    if muted.contains_key(filename)
+                                        || pacer.is_pending(filename)
+                                        || !documents.contains_key(filename)
+                                    {
+                                        continue;
+                                    }
+                                }
+                                // Also reported for muted and held back documents
+                                DocumentEvent::ReadFailed(filename, _) => {
+                                    if !documents.contains_key(filename) {
+                                        continue;
+                                    }
+                                }
+                                DocumentEvent::DuplicateId { .. } | DocumentEvent::Observed { .. } => {}
+                            }
+                            events.extend(
+                                handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize, &options).await?,
+                            );
+                        }
+                        events
+                    }
+                    Some(command) = command_receiver.recv() => {
+                        match command {
+                            // Replace the consumer channel and replay the current state into it
+                            ItemWatcherCommand::Resubscribe(reply_sender) => {
+                                let (new_event_tx, new_event_rx) = mpsc::channel(100);
+                                if reply_sender.send(new_event_rx).is_err() {
+                                    continue;
+                                }
+                                sink = EventSink::Channel(new_event_tx);
+                                match options.replay_on_resubscribe {
+                                    true => replay_events(&documents, &item_hashes, tokenizer, &*deserialize, &options),
+                                    false => continue,
+                                }
+                            }
+                            ItemWatcherCommand::Mute(ids, reply_sender) => {
+                                for id in ids {
+                                    muted.entry(id).or_insert(None);
+                                }
+                                reply_sender.send(()).ok();
+                                continue;
+                            }
+                            // Emit the net change of each unmuted document
+                            ItemWatcherCommand::Unmute(ids, reply_sender) => {
+                                let mut events = Vec::new();
+                                for id in ids {
+                                    let Some(Some(latest)) = muted.remove(&id) else {
+                                        continue;
+                                    };
+                                    let latest = enabled_change(&options, &mut disabled, &id, latest);
+                                    let Some(event) = net_change(&mut documents, &digests, retain, id, latest) else {
+                                        continue;
+                                    };
+                                    events.extend(
+                                        handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize, &options).await?,
+                                    );
+                                }
+                                reply_sender.send(()).ok();
+                                events
+                            }
+                            // Remove the documents that are no longer enabled, then emit the ones
+                            // that are enabled again
+                            ItemWatcherCommand::Reevaluate(reply_sender) => {
+                                let disabling: Vec<String> = documents
+                                    .keys()
+                                    .filter(|id| !is_enabled(&options, id))
+                                    .cloned()
+                                    .collect();
+                                let enabling: Vec<String> = disabled
+                                    .keys()
+                                    .filter(|id| is_enabled(&options, id))
+                                    .cloned()
+                                    .collect();
+                                let mut changes = Vec::new();
+                                for id in disabling {
+                                    disabled.insert(id.clone(), documents[&id].clone());
+                                    changes.push((id, None));
+                                }
+                                for id in enabling {
+                                    let latest = disabled.remove(&id);
+                                    changes.push((id, latest));
+                                }
+                                let mut events = Vec::new();
+                                for (id, latest) in changes {
+                                    let Some(event) = net_change(&mut documents, &digests, retain, id, latest) else {
+                                        continue;
+                                    };
+                                    events.extend(
+                                        handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize, &options).await?,
+                                    );
+                                }
+                                reply_sender.send(()).ok();
+                                events
+                            }
+                            ItemWatcherCommand::Checkpoint(reply_sender) => {
+                                checkpoints.push(reply_sender);
+                                continue;
+                            }
+                        }
+                    }
+                    // Emit all held back documents, then the checkpoints
+                    _ = std::future::ready(()), if checkpoint_due => {
+                        let mut events = Vec::new();
+                        for (id, latest) in pacer.take_all() {
+                            // Muted while held back: reported by its net change on unmute
+                            if let Some(pending) = muted.get_mut(&id) {
+                                *pending = Some(latest);
+                                continue;
+                            }
+                            let latest = enabled_change(&options, &mut disabled, &id, latest);
+                            let Some(event) = net_change(&mut documents, &digests, retain, id.clone(), latest) else {
+                                continue;
+                            };
+                            pacer.emitted(&id);
+                            events.extend(
+                                handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize, &options).await?,
+                            );
+                        }
+                        events.extend(
+                            checkpoints
+                                .drain(..)
+                                .map(|reply_sender| ConfigItemEvent::Checkpoint(CheckpointAck(reply_sender))),
+                        );
+                        events
+                    }
+                    // Emit the net change of held back documents that are due
+                    _ = options.clock.sleep_until(next_due.unwrap_or_else(|| options.clock.now())), if next_due.is_some() => {
+                        let mut events = Vec::new();
+                        for (id, latest) in pacer.take_due() {
+                            // Muted while held back: reported by its net change on unmute
+                            if let Some(pending) = muted.get_mut(&id) {
+                                *pending = Some(latest);
+                                continue;
+                            }
+                            let latest = enabled_change(&options, &mut disabled, &id, latest);
+                            let Some(event) = net_change(&mut documents, &digests, retain, id.clone(), latest) else {
+                                continue;
+                            };
+                            pacer.emitted(&id);
+                            events.extend(
+                                handle_config_file_event(event, &mut item_hashes, tokenizer, &deserialize, &options).await?,
+                            );
+                        }
+                        events
                     }
                     // Check for shutdown signal
                     result = stop_receiver.changed() => {
@@ -113,9 +1231,32 @@ where
                     }
                 };
 
+                track_document_ids(&document_ids, &events);
+                if let Some(persistence) = &options.persistence {
+                    persist(&**persistence, &events, &mut item_hashes);
+                }
+
+                // Timestamps do not decrease when the system clock is set back
+                let observed_at = observed_at
+                    .unwrap_or_else(SystemTime::now)
+                    .max(last_observed);
+                last_observed = observed_at;
+
                 // Send events for new or changed items
-                for event in events {
-                    event_tx.send(event).await.unwrap();
+                if options.batch_events {
+                    if !events.is_empty() {
+                        sink.send(wrap(seq, observed_at, ConfigItemEvent::Batch(events)))
+                            .await;
+                        seq += 1;
+                    }
+                } else {
+                    for event in events {
+                        sink.send(wrap(seq, observed_at, event)).await;
+                        seq += 1;
+                    }
+                }
+                if became_ready {
+                    ready_sender.send_replace(true);
                 }
             }
 
@@ -124,109 +1265,968 @@ where
         }
     });
 
-    Ok((
-        ConfigItemWatcherHandle {
-            task_handle: Some(handle),
-            watcher_backend_handle,
+    Ok(ConfigItemWatcherHandle {
+        task_handle: Some(handle),
+        controller: ConfigItemWatcherController {
+            backend: watcher_backend_handle.controller(),
             stop_sender,
+            ready_receiver,
+            command_sender,
+            document_ids,
+            digests,
+            _item: PhantomData,
         },
-        event_rx,
-    ))
+        watcher_backend_handle,
+    })
+}
+
+/// Loads the state persisted in `store` and compacts its log.
+fn restore_state(store: &dyn EventStore) -> Result<PersistedState, WatcherError> {
+    let state = PersistedState::replay(store.load().map_err(WatcherError::Persistence)?);
+    store
+        .compact(&state.records())
+        .map_err(WatcherError::Persistence)?;
+    log::debug!(
+        "Restored {} documents and {} items",
+        state.documents.len(),
+        state.items.len()
+    );
+    Ok(state)
+}
+
+/// Appends the documents reported by `events` and the item changes since the last call to
+/// `store`.
+fn persist<T>(
+    store: &dyn EventStore,
+    events: &[ConfigItemEvent<T>],
+    item_hashes: &mut ItemTracker,
+) {
+    let mut records: Vec<PersistedRecord> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::NewDocument(filename_hash, id) => Some(PersistedRecord::Document {
+                filename_hash: *filename_hash,
+                id: id.clone(),
+            }),
+            ConfigItemEvent::RemoveDocument(filename_hash) => {
+                Some(PersistedRecord::DocumentRemoved {
+                    filename_hash: *filename_hash,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+    records.extend(item_hashes.take_journal());
+    if records.is_empty() {
+        return;
+    }
+    if let Err(err) = store.append(&records) {
+        log::error!("Cannot persist {} records: {}", records.len(), err);
+    }
+}
+
+/// Removes the restored documents that the backend did not report in its initial scan.
+fn remove_unseen_restored<T>(item_hashes: &mut ItemTracker) -> Vec<ConfigItemEvent<T>> {
+    let mut events = Vec::new();
+    for filename_hash in item_hashes.take_unseen_restored() {
+        item_hashes.remove_document(filename_hash);
+        let removed = item_hashes
+            .remove_where(|hash| hash.0 == filename_hash)
+            .into_iter()
+            .map(ConfigItemEvent::Removed)
+            .collect();
+        events.extend(frame_document(
+            None,
+            removed,
+            Some(ConfigItemEvent::RemoveDocument(filename_hash)),
+        ));
+    }
+    events
+}
+
+/// Records the documents added and removed by `events` in `document_ids`.
+fn track_document_ids<T>(document_ids: &DocumentIds, events: &[ConfigItemEvent<T>]) {
+    let mut document_ids = document_ids.write().unwrap();
+    for event in events {
+        match event {
+            ConfigItemEvent::NewDocument(filename_hash, id) => {
+                document_ids.insert(*filename_hash, id.clone());
+            }
+            ConfigItemEvent::RemoveDocument(filename_hash) => {
+                document_ids.remove(filename_hash);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Holds back document changes according to [`ConfigItemWatcherOptions::settle`] and
+/// [`ConfigItemWatcherOptions::min_interval`].
+struct ChangePacer {
+    settle: Option<Duration>,
+    min_interval: Option<Duration>,
+    // Held back documents: latest content (`None` if removed) and the time it is due
+    pending: BTreeMap<String, (Option<String>, Instant)>,
+    // Time of the last change processed per document, within `min_interval`
+    last_emitted: HashMap<String, Instant>,
+    clock: SharedClock,
+}
+
+impl ChangePacer {
+    fn new<T>(options: &ConfigItemWatcherOptions<T>) -> Self {
+        Self {
+            settle: options.settle,
+            min_interval: options.min_interval,
+            pending: BTreeMap::new(),
+            last_emitted: HashMap::new(),
+            clock: options.clock.clone(),
+        }
+    }
+
+    /// Holds back the change of document `id` if it is settling, was changed within
+    /// `min_interval` or is already held back. Returns `false` if the change is to be processed
+    /// now.
+    fn hold(&mut self, id: &str, latest: impl FnOnce() -> Option<String>) -> bool {
+        let now = self.clock.now();
+        let settle_until = self.settle.map(|settle| now + settle);
+        let throttle_until = self
+            .min_interval
+            .and_then(|min_interval| Some(*self.last_emitted.get(id)? + min_interval))
+            .filter(|until| *until > now);
+        let until = settle_until
+            .max(throttle_until)
+            .or_else(|| self.pending.get(id).map(|(_, until)| *until));
+        match until {
+            Some(until) => {
+                self.pending.insert(id.to_string(), (latest(), until));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records that a change of document `id` was processed.
+    fn emitted(&mut self, id: &str) {
+        if let Some(min_interval) = self.min_interval {
+            let now = self.clock.now();
+            self.last_emitted
+                .retain(|_, emitted| now.duration_since(*emitted) < min_interval);
+            self.last_emitted.insert(id.to_string(), now);
+        }
+    }
+
+    /// Returns true if a change of document `id` is held back.
+    fn is_pending(&self, id: &str) -> bool {
+        self.pending.contains_key(id)
+    }
+
+    /// Returns the time the next held back document is due.
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|(_, until)| *until).min()
+    }
+
+    /// Removes and returns all held back documents, whether due or not.
+    fn take_all(&mut self) -> Vec<(String, Option<String>)> {
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(id, (latest, _))| (id, latest))
+            .collect()
+    }
+
+    /// Removes and returns all held back documents that are due.
+    fn take_due(&mut self) -> Vec<(String, Option<String>)> {
+        let now = self.clock.now();
+        let due: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, until))| *until <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|id| {
+                let (latest, _) = self.pending.remove(&id)?;
+                Some((id, latest))
+            })
+            .collect()
+    }
+}
+
+/// Returns true if the document `id` passes the [`ConfigItemWatcherOptions::enabled`] predicate.
+fn is_enabled<T>(options: &ConfigItemWatcherOptions<T>, id: &str) -> bool {
+    options.enabled.as_ref().is_none_or(|enabled| enabled(id))
+}
+
+/// Returns the content to emit for the latest content of document `id` (`None` if removed).
+/// Documents that are not enabled are emitted as removed, and their content is kept in
+/// `disabled` until they are enabled again.
+fn enabled_change<T>(
+    options: &ConfigItemWatcherOptions<T>,
+    disabled: &mut BTreeMap<String, String>,
+    id: &str,
+    latest: Option<String>,
+) -> Option<String> {
+    match latest {
+        Some(content) if !is_enabled(options, id) => {
+            disabled.insert(id.to_string(), content);
+            None
+        }
+        latest => {
+            disabled.remove(id);
+            latest
+        }
+    }
+}
+
+/// Records `latest` (`None` if removed) as the content of document `id`. Returns the document
+/// event for the change, or `None` if the document ended up unchanged.
+fn net_change(
+    documents: &mut BTreeMap<String, String>,
+    digests: &DocumentDigests,
+    retain: bool,
+    id: String,
+    latest: Option<String>,
+) -> Option<DocumentEvent> {
+    let current = digests.read().unwrap().get(&id).copied();
+    match (current, latest) {
+        (None, Some(content)) => {
+            record_document(documents, digests, retain, &id, Some(&content));
+            Some(DocumentEvent::NewDocument(id, content))
+        }
+        (Some(current), Some(content)) if current != hash_str(&content) => {
+            record_document(documents, digests, retain, &id, Some(&content));
+            Some(DocumentEvent::ContentChanged(id, content))
+        }
+        (Some(_), None) => {
+            record_document(documents, digests, retain, &id, None);
+            Some(DocumentEvent::DocumentRemoved(id))
+        }
+        _ => None,
+    }
+}
+
+/// Records `content` (`None` if removed) as the latest content of document `id` and its hash in
+/// `digests`. The content itself is only kept with `retain`, an empty string otherwise.
+fn record_document(
+    documents: &mut BTreeMap<String, String>,
+    digests: &DocumentDigests,
+    retain: bool,
+    id: &str,
+    content: Option<&str>,
+) {
+    match content {
+        Some(content) => {
+            let retained = match retain {
+                true => content.to_string(),
+                false => String::new(),
+            };
+            documents.insert(id.to_string(), retained);
+            digests
+                .write()
+                .unwrap()
+                .insert(id.to_string(), hash_str(content));
+        }
+        None => {
+            documents.remove(id);
+            digests.write().unwrap().remove(id);
+        }
+    }
+}
+
+/// Returns the event received during a rename wait, if any, or else the next backend event.
+async fn next_event(
+    receiver: &mut Receiver<DocumentEvent>,
+    lookahead: &mut Option<DocumentEvent>,
+) -> Option<DocumentEvent> {
+    match lookahead.take() {
+        Some(event) => Some(event),
+        None => receiver.recv().await,
+    }
+}
+
+/// Maps backend document ids to ids derived from the document content, see
+/// [`ConfigItemWatcherOptions::id_from_content`].
+struct ContentIds {
+    id_from_content: IdFromContent,
+    // Backend id -> derived id
+    sources: HashMap<String, String>,
+    // Derived id -> content hash
+    hashes: HashMap<String, u64>,
+}
+
+impl ContentIds {
+    fn new(id_from_content: IdFromContent) -> Self {
+        Self {
+            id_from_content,
+            sources: HashMap::new(),
+            hashes: HashMap::new(),
+        }
+    }
+
+    fn derive(&self, source: &str, content: &str) -> String {
+        (self.id_from_content)(content).unwrap_or_else(|| source.to_string())
+    }
+
+    /// Translates a backend event into the document events of the derived ids.
+    ///
+    /// If a removal leaves a derived id without source, the next backend event is awaited for up
+    /// to [`RENAME_WINDOW`] on `clock`. A new document with the same id is treated as a rename of
+    /// the removed one; any other event is stored in `lookahead` to be processed next. A stop
+    /// signaled on `stop_receiver` ends the wait right away.
+    async fn translate(
+        &mut self,
+        event: DocumentEvent,
+        receiver: &mut Receiver<DocumentEvent>,
+        lookahead: &mut Option<DocumentEvent>,
+        clock: &dyn Clock,
+        stop_receiver: &watch::Receiver<bool>,
+    ) -> Vec<DocumentEvent> {
+        match event {
+            DocumentEvent::NewDocument(source, content)
+            | DocumentEvent::ContentChanged(source, content) => self.update(source, content),
+            DocumentEvent::DocumentRemoved(source) => {
+                let Some(id) = self.remove(&source) else {
+                    return vec![];
+                };
+                // A clone, so that the item watcher loop still sees the stop signal
+                let mut stop_receiver = stop_receiver.clone();
+                let next = tokio::select! {
+                    next = receiver.recv() => next,
+                    _ = clock.sleep(RENAME_WINDOW) => None,
+                    _ = stop_receiver.wait_for(|stop| *stop) => None,
+                };
+                let (observed_at, next) = match next.map(DocumentEvent::into_observed) {
+                    Some((observed_at, event)) => (observed_at, Some(event)),
+                    None => (None, None),
+                };
+                match next {
+                    Some(DocumentEvent::NewDocument(new_source, content))
+                        if self.derive(&new_source, &content) == id =>
+                    {
+                        log::debug!("Document {:?} renamed to {:?}", source, new_source);
+                        self.update(new_source, content)
+                    }
+                    next => {
+                        // Processed next, with the time the backend detected it
+                        *lookahead = match (next, observed_at) {
+                            (Some(event), Some(observed_at)) => Some(DocumentEvent::Observed {
+                                observed_at,
+                                event: Box::new(event),
+                            }),
+                            (next, _) => next,
+                        };
+                        self.hashes.remove(&id);
+                        vec![DocumentEvent::DocumentRemoved(id)]
+                    }
+                }
+            }
+            DocumentEvent::Touched(source) => match self.sources.get(&source) {
+                Some(id) => vec![DocumentEvent::Touched(id.clone())],
+                None => vec![],
+            },
+            DocumentEvent::ReadFailed(source, error) => match self.sources.get(&source) {
+                Some(id) => vec![DocumentEvent::ReadFailed(id.clone(), error)],
+                None => vec![],
+            },
+            DocumentEvent::InitialScanComplete
+            | DocumentEvent::DuplicateId { .. }
+            | DocumentEvent::Observed { .. } => vec![event],
+        }
+    }
+
+    fn update(&mut self, source: String, content: String) -> Vec<DocumentEvent> {
+        let id = self.derive(&source, &content);
+        let mut events = Vec::new();
+        if let Some(previous) = self.sources.insert(source, id.clone()) {
+            if previous != id && !self.is_referenced(&previous) {
+                self.hashes.remove(&previous);
+                events.push(DocumentEvent::DocumentRemoved(previous));
+            }
+        }
+        let hash = hash_str(&content);
+        match self.hashes.insert(id.clone(), hash) {
+            None => events.push(DocumentEvent::NewDocument(id, content)),
+            Some(previous_hash) if previous_hash != hash => {
+                events.push(DocumentEvent::ContentChanged(id, content))
+            }
+            _ => {}
+        }
+        events
+    }
+
+    /// Forgets the backend document `source`. Returns its derived id if no other backend
+    /// document refers to it anymore.
+    fn remove(&mut self, source: &str) -> Option<String> {
+        let id = self.sources.remove(source)?;
+        (!self.is_referenced(&id)).then_some(id)
+    }
+
+    fn is_referenced(&self, id: &str) -> bool {
+        self.sources.values().any(|source_id| source_id == id)
+    }
 }
 
-async fn handle_config_file_event<T, E>(
+async fn handle_config_file_event<T, E, D>(
     event: DocumentEvent,
-    item_hashes: &mut HashSet<ConfigItemHash>,
-    tokenizer: &dyn Tokenizer,
-    deserialize: &(impl Fn(&str) -> std::result::Result<T, E> + Send + Sync),
-) -> Vec<ConfigItemEvent<T>>
+    item_hashes: &mut ItemTracker,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: &Arc<D>,
+    options: &ConfigItemWatcherOptions<T>,
+) -> Result<Vec<ConfigItemEvent<T>>, WatcherError>
 where
-    T: Send + Sync,
-    E: Send + Sync + std::fmt::Debug,
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+    D: Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
 {
-    match event {
+    let event = match admit_document_event(event, item_hashes, options) {
+        Ok(event) => event,
+        Err(events) => return Ok(events),
+    };
+
+    let events = match event {
         DocumentEvent::NewDocument(filename, content) => {
             log::debug!("Processing document: {:?}", filename);
 
-            let mut events =
-                match process_file(&filename, content, item_hashes, tokenizer, deserialize).await {
-                    Ok(events) => events,
-                    Err(err) => {
-                        log::error!("Failed to process document {:?}: {:?}", filename, err);
-                        vec![] // Skip this event
-                    }
-                };
-            events.insert(
-                0,
-                ConfigItemEvent::NewDocument(hash_str(&filename), filename),
-            );
-            events
+            let events = if !is_included(options, &filename, &content) {
+                log::debug!("Document {:?} rejected by content filter", filename);
+                file_removed(&filename, item_hashes, options)
+            } else {
+                process_file(
+                    &filename,
+                    content,
+                    item_hashes,
+                    tokenizer,
+                    deserialize,
+                    options,
+                )
+                .await?
+            };
+            // The consumer already knows restored documents
+            let filename_hash = filename_hash(options, &filename);
+            if !item_hashes.take_restored(filename_hash) {
+                frame_document(
+                    Some(ConfigItemEvent::NewDocument(filename_hash, filename)),
+                    events,
+                    None,
+                )
+            } else if !events.is_empty() {
+                frame_document(
+                    Some(ConfigItemEvent::DocumentChanged(filename_hash)),
+                    events,
+                    None,
+                )
+            } else {
+                events
+            }
         }
         DocumentEvent::ContentChanged(filename, content) => {
             log::debug!("Processing document: {:?}", filename);
-            match process_file(&filename, content, item_hashes, tokenizer, deserialize).await {
-                Ok(events) => events,
-                Err(err) => {
-                    log::error!("Failed to process document {:?}: {:?}", filename, err);
-                    vec![] // Skip this event
-                }
-            }
+            let events = if !is_included(options, &filename, &content) {
+                log::debug!("Document {:?} rejected by content filter", filename);
+                file_removed(&filename, item_hashes, options)
+            } else {
+                process_file(
+                    &filename,
+                    content,
+                    item_hashes,
+                    tokenizer,
+                    deserialize,
+                    options,
+                )
+                .await?
+            };
+            frame_document(
+                Some(ConfigItemEvent::DocumentChanged(filename_hash(
+                    options, &filename,
+                ))),
+                events,
+                None,
+            )
         }
         // Handle file removal
         DocumentEvent::DocumentRemoved(filename) => {
             log::debug!("Document removed: {:?}", filename);
 
-            let mut events = file_removed(&filename, item_hashes);
-            events.push(ConfigItemEvent::RemoveDocument(hash_str(&filename)));
-            events
+            let events = file_removed(&filename, item_hashes, options);
+            frame_document(
+                None,
+                events,
+                Some(ConfigItemEvent::RemoveDocument(filename_hash(
+                    options, &filename,
+                ))),
+            )
+        }
+        DocumentEvent::InitialScanComplete => vec![],
+        DocumentEvent::Touched(filename) => {
+            let filename_hash = filename_hash(options, &filename);
+            match item_hashes.is_rejected(filename_hash) {
+                true => vec![],
+                false => vec![ConfigItemEvent::DocumentTouched(filename_hash)],
+            }
+        }
+        DocumentEvent::ReadFailed(filename, error) => {
+            let filename_hash = filename_hash(options, &filename);
+            match item_hashes.is_rejected(filename_hash) {
+                true => vec![],
+                false => vec![ConfigItemEvent::DocumentError {
+                    filename_hash,
+                    error,
+                }],
+            }
+        }
+        DocumentEvent::DuplicateId { id, sources } => {
+            vec![ConfigItemEvent::Error(WatcherError::DuplicateId {
+                id,
+                sources,
+            })]
+        }
+        // Unwrapped when the event is received
+        DocumentEvent::Observed { .. } => vec![],
+    };
+    Ok(events)
+}
+
+/// Frames the item events of a document: the event opening the frame first, the item events next
+/// and the event closing it last, see [`ConfigItemEvent`].
+fn frame_document<T>(
+    open: Option<ConfigItemEvent<T>>,
+    items: Vec<ConfigItemEvent<T>>,
+    close: Option<ConfigItemEvent<T>>,
+) -> Vec<ConfigItemEvent<T>> {
+    open.into_iter().chain(items).chain(close).collect()
+}
+
+/// Applies [`ConfigItemWatcherOptions::max_documents`] to a document event. Returns the events to
+/// emit instead if the document is rejected.
+fn admit_document_event<T>(
+    event: DocumentEvent,
+    item_hashes: &mut ItemTracker,
+    options: &ConfigItemWatcherOptions<T>,
+) -> Result<DocumentEvent, Vec<ConfigItemEvent<T>>> {
+    let (filename, content, is_new) = match event {
+        DocumentEvent::NewDocument(filename, content) => (filename, content, true),
+        DocumentEvent::ContentChanged(filename, content) => (filename, content, false),
+        DocumentEvent::DocumentRemoved(filename) => {
+            // The consumer has not seen a rejected document
+            return match item_hashes.remove_document(filename_hash(options, &filename)) {
+                true => Ok(DocumentEvent::DocumentRemoved(filename)),
+                false => Err(Vec::new()),
+            };
         }
+        event => return Ok(event),
+    };
+    let filename_hash = filename_hash(options, &filename);
+    let was_rejected = item_hashes.is_rejected(filename_hash);
+    if !item_hashes.admit_document(filename_hash) {
+        if was_rejected {
+            return Err(Vec::new());
+        }
+        let error = WatcherError::LimitExceeded {
+            kind: "documents",
+            limit: options.max_documents.unwrap_or_default(),
+            document: filename,
+        };
+        log::warn!("{}", error);
+        return Err(vec![ConfigItemEvent::Error(error)]);
+    }
+    // A document admitted after it was rejected is new to the consumer
+    Ok(if is_new || was_rejected {
+        DocumentEvent::NewDocument(filename, content)
+    } else {
+        DocumentEvent::ContentChanged(filename, content)
+    })
+}
+
+/// Returns the filename hash of the document `filename`, see
+/// [`ConfigItemWatcherOptions::filename_id_fn`].
+fn filename_hash<T>(options: &ConfigItemWatcherOptions<T>, filename: &str) -> u64 {
+    match &options.filename_id_fn {
+        Some(filename_id_fn) => filename_id_fn(filename),
+        None => hash_str(filename),
     }
 }
 
+fn is_included<T>(options: &ConfigItemWatcherOptions<T>, filename: &str, content: &str) -> bool {
+    options
+        .content_filter
+        .as_ref()
+        .is_none_or(|filter| filter(filename, content))
+}
+
 fn file_removed<T>(
     filename: &str,
-    item_hashes: &mut HashSet<ConfigItemHash>,
+    item_hashes: &mut ItemTracker,
+    options: &ConfigItemWatcherOptions<T>,
 ) -> Vec<ConfigItemEvent<T>>
 where
     T: Send + Sync,
 {
-    let mut events = Vec::new();
+    let filepath_hash = filename_hash(options, filename);
 
-    let filepath_hash = hash_str(filename);
+    item_hashes
+        .remove_where(|hash| hash.0 == filepath_hash)
+        .into_iter()
+        .map(ConfigItemEvent::Removed)
+        .collect()
+}
 
-    item_hashes.retain(|hash| {
-        if hash.0 == filepath_hash {
-            events.push(ConfigItemEvent::Removed(*hash));
-            false
-        } else {
-            true
+/// Keeps track of the items of all documents.
+///
+/// Items are always stored per document. With [`ConfigIdentity::Content`] the tracker additionally
+/// counts how many documents reference each item content and only reports an item as
+/// added/removed when the first/last reference appears/disappears.
+///
+/// It also enforces [`ConfigItemWatcherOptions::max_documents`] and
+/// [`ConfigItemWatcherOptions::max_items`].
+struct ItemTracker {
+    identity: ConfigIdentity,
+    item_hashes: BTreeSet<ConfigItemHash>,
+    content_refs: HashMap<u64, usize>,
+    max_documents: Option<usize>,
+    max_items: Option<usize>,
+    // Filename hashes of the admitted and of the rejected documents
+    documents: HashSet<u64>,
+    rejected_documents: HashSet<u64>,
+    // Documents restored from `persistence` that the backend has not reported yet
+    restored_documents: HashSet<u64>,
+    // Changes of `item_hashes` not persisted yet, if `persistence` is set
+    journal: Option<Vec<PersistedRecord>>,
+}
+
+impl ItemTracker {
+    fn new<T>(options: &ConfigItemWatcherOptions<T>) -> Self {
+        Self {
+            identity: options.identity,
+            item_hashes: BTreeSet::new(),
+            content_refs: HashMap::new(),
+            max_documents: options.max_documents,
+            max_items: options.max_items,
+            documents: HashSet::new(),
+            rejected_documents: HashSet::new(),
+            restored_documents: HashSet::new(),
+            journal: options.persistence.is_some().then(Vec::new),
         }
-    });
-    events
+    }
+
+    /// Tracks the documents and items of a persisted state without journaling them. The restored
+    /// documents count towards `max_documents`.
+    fn restore(&mut self, state: PersistedState) {
+        let journal = self.journal.take();
+        for (filename_hash, item_hash) in state.items {
+            self.insert(ConfigItemHash(filename_hash, item_hash));
+        }
+        self.restored_documents = state.documents.into_keys().collect();
+        self.documents.extend(&self.restored_documents);
+        self.journal = journal;
+    }
+
+    /// Returns true if the document `filename_hash` was restored and not reported since.
+    fn take_restored(&mut self, filename_hash: u64) -> bool {
+        self.restored_documents.remove(&filename_hash)
+    }
+
+    /// Returns the restored documents that were not reported since.
+    fn take_unseen_restored(&mut self) -> Vec<u64> {
+        let mut unseen: Vec<u64> = self.restored_documents.drain().collect();
+        unseen.sort();
+        unseen
+    }
+
+    /// Returns the item changes since the last call.
+    fn take_journal(&mut self) -> Vec<PersistedRecord> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Admits the document `filename_hash` unless `max_documents` documents are admitted already.
+    /// Returns `false` if it is rejected.
+    fn admit_document(&mut self, filename_hash: u64) -> bool {
+        if self.documents.contains(&filename_hash) {
+            return true;
+        }
+        if self
+            .max_documents
+            .is_some_and(|max_documents| self.documents.len() >= max_documents)
+        {
+            self.rejected_documents.insert(filename_hash);
+            return false;
+        }
+        self.rejected_documents.remove(&filename_hash);
+        self.documents.insert(filename_hash)
+    }
+
+    /// Frees the budget of the document `filename_hash`. Returns `false` if it was rejected.
+    fn remove_document(&mut self, filename_hash: u64) -> bool {
+        self.documents.remove(&filename_hash);
+        !self.rejected_documents.remove(&filename_hash)
+    }
+
+    fn is_rejected(&self, filename_hash: u64) -> bool {
+        self.rejected_documents.contains(&filename_hash)
+    }
+
+    /// Returns true if another item can be tracked within `max_items`.
+    fn has_item_capacity(&self) -> bool {
+        self.max_items
+            .is_none_or(|max_items| self.item_hashes.len() < max_items)
+    }
+
+    /// Tracks a document item. Returns the hash to report as new, if any.
+    fn insert(&mut self, hash: ConfigItemHash) -> Option<ConfigItemHash> {
+        if !self.item_hashes.insert(hash) {
+            return None;
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(PersistedRecord::Item {
+                filename_hash: hash.0,
+                item_hash: hash.1,
+            });
+        }
+        match self.identity {
+            ConfigIdentity::FileScoped => Some(hash),
+            ConfigIdentity::Content => {
+                let refs = self.content_refs.entry(hash.1).or_default();
+                *refs += 1;
+                (*refs == 1).then_some(ConfigItemHash(0, hash.1))
+            }
+        }
+    }
+
+    /// Returns the hash under which a tracked document item is reported, or `None` if the item is
+    /// not tracked.
+    fn reported_hash(&self, hash: ConfigItemHash) -> Option<ConfigItemHash> {
+        if !self.item_hashes.contains(&hash) {
+            return None;
+        }
+        match self.identity {
+            ConfigIdentity::FileScoped => Some(hash),
+            ConfigIdentity::Content => Some(ConfigItemHash(0, hash.1)),
+        }
+    }
+
+    /// Returns true if any item of the document `filename_hash` is tracked.
+    fn contains_document(&self, filename_hash: u64) -> bool {
+        self.item_hashes.iter().any(|hash| hash.0 == filename_hash)
+    }
+
+    /// Stops tracking all document items matching `predicate`. Returns the hashes to report as
+    /// removed.
+    fn remove_where(
+        &mut self,
+        mut predicate: impl FnMut(&ConfigItemHash) -> bool,
+    ) -> Vec<ConfigItemHash> {
+        let mut removed = Vec::new();
+        self.item_hashes.retain(|hash| {
+            if predicate(hash) {
+                removed.push(*hash);
+                false
+            } else {
+                true
+            }
+        });
+        if let Some(journal) = self.journal.as_mut() {
+            journal.extend(removed.iter().map(|hash| PersistedRecord::ItemRemoved {
+                filename_hash: hash.0,
+                item_hash: hash.1,
+            }));
+        }
+        match self.identity {
+            ConfigIdentity::FileScoped => removed,
+            ConfigIdentity::Content => removed
+                .into_iter()
+                .filter_map(|hash| {
+                    let refs = self.content_refs.get_mut(&hash.1)?;
+                    *refs -= 1;
+                    if *refs == 0 {
+                        self.content_refs.remove(&hash.1);
+                        Some(ConfigItemHash(0, hash.1))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+        }
+    }
 }
 
-async fn process_file<T, E>(
+async fn process_file<T, E, D>(
     filename: &str,
-    content: String,
-    item_hashes: &mut HashSet<ConfigItemHash>,
-    tokenizer: &dyn Tokenizer,
-    deserialize: &impl Fn(&str) -> std::result::Result<T, E>,
+    mut content: String,
+    item_hashes: &mut ItemTracker,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: &Arc<D>,
+    options: &ConfigItemWatcherOptions<T>,
 ) -> Result<Vec<ConfigItemEvent<T>>, WatcherError>
 where
-    T: Send + Sync,
-    E: Send + Sync + std::fmt::Debug,
+    T: Send + Sync + 'static,
+    E: Send + Sync + std::fmt::Debug + 'static,
+    D: Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
 {
     let mut events = Vec::new();
 
     // Parse the file into new items and their hashes
+    let filename_hash = filename_hash(options, filename);
+    let mut errors = Vec::new();
+    let mut new_items: BTreeMap<u64, (T, Cow<str>)> = if options.parse_on_blocking_pool {
+        let (returned_content, items, returned_errors) =
+            parse_items_blocking(filename, content, tokenizer, deserialize, options).await?;
+        content = returned_content;
+        errors = returned_errors;
+        items
+    } else {
+        parse_items(
+            filename,
+            &content,
+            tokenizer,
+            &**deserialize,
+            options.on_deserialize_error,
+            options.canonicalize.as_ref(),
+            &mut errors,
+        )?
+        .into_iter()
+        .map(|(hash, (item, raw))| (hash, (item, Cow::Borrowed(raw))))
+        .collect()
+    };
+    events.extend(errors.into_iter().map(ConfigItemEvent::Error));
+
+    // Reject invalid items before they are tracked
+    if let Some(validate) = &options.validate {
+        new_items.retain(|item_hash, (item, _)| match validate(item) {
+            Ok(()) => true,
+            Err(error) => {
+                log::warn!(
+                    "Item {} in document {:?} failed validation: {}",
+                    item_hash,
+                    filename,
+                    error
+                );
+                events.push(ConfigItemEvent::ValidationError {
+                    item_hash: ConfigItemHash(filename_hash, *item_hash),
+                    error,
+                });
+                false
+            }
+        });
+    }
+
+    // Filter and detect removals: items of the current file whose content hash is not found in
+    // new_items were removed
+    let had_items = item_hashes.contains_document(filename_hash);
+    let mut removed = 0;
+    events.extend(
+        item_hashes
+            .remove_where(|hash| {
+                let is_removed = hash.0 == filename_hash && !new_items.contains_key(&hash.1);
+                removed += usize::from(is_removed);
+                is_removed
+            })
+            .into_iter()
+            .map(ConfigItemEvent::Removed),
+    );
+    if options.emit_emptied
+        && had_items
+        && new_items.is_empty()
+        && tokenizer
+            .tokenize(&content)
+            .all(|doc| doc.trim().is_empty())
+    {
+        events.push(ConfigItemEvent::DocumentEmptied(filename_hash));
+    }
+
+    // Detect changes and additions
+    let (mut added, mut unchanged, mut skipped) = (0, 0, 0);
+    for (new_hash, (new_item, raw)) in new_items.into_iter() {
+        let hash = ConfigItemHash(filename_hash, new_hash);
+        if item_hashes.reported_hash(hash).is_some() {
+            unchanged += 1;
+            continue;
+        }
+        if !item_hashes.has_item_capacity() {
+            skipped += 1;
+            continue;
+        }
+        added += 1;
+        if let Some(hash) = item_hashes.insert(hash) {
+            // New item
+            events.push(new_item_event(hash, new_item, &raw, options.include_raw));
+        }
+    }
+    if skipped > 0 {
+        let error = WatcherError::LimitExceeded {
+            kind: "items",
+            limit: options.max_items.unwrap_or_default(),
+            document: filename.to_string(),
+        };
+        log::warn!("{}, skipped {} items", error, skipped);
+        events.push(ConfigItemEvent::Error(error));
+    }
+    if options.log_diff_summary {
+        log::info!(
+            "Items of document {:?}: {{added: {}, removed: {}, unchanged: {}}}",
+            filename,
+            added,
+            removed,
+            unchanged
+        );
+    }
+
+    Ok(events)
+}
 
-    let filename_hash = hash_str(filename);
-    let new_items: HashMap<u64, T> = tokenizer
-        .tokenize(&content)
+/// Splits a document into items and deserializes them, keyed and ordered by the hash of their
+/// content. Each item is returned with its source text. With [`ErrorMode::Report`], deserialization
+/// errors are added to `errors`.
+fn parse_items<'a, T, E>(
+    filename: &str,
+    content: &'a str,
+    tokenizer: &dyn Tokenizer,
+    deserialize: &impl Fn(&str) -> std::result::Result<T, E>,
+    on_deserialize_error: ErrorMode,
+    canonicalize: Option<&Canonicalizer>,
+    errors: &mut Vec<WatcherError>,
+) -> Result<BTreeMap<u64, (T, &'a str)>, WatcherError>
+where
+    E: std::fmt::Debug,
+{
+    let mut items = BTreeMap::new();
+    let documents = match tokenizer.try_tokenize(content) {
+        Ok(documents) => documents,
+        Err(detail) if on_deserialize_error == ErrorMode::Fail => {
+            return Err(WatcherError::Tokenize {
+                document_id: filename.to_string(),
+                detail,
+            });
+        }
+        Err(detail) => {
+            log::error!("Failed to split file {:?} into items: {}", filename, detail);
+            if on_deserialize_error == ErrorMode::Report {
+                errors.push(WatcherError::Tokenize {
+                    document_id: filename.to_string(),
+                    detail,
+                });
+            }
+            return Ok(items);
+        }
+    };
+    for doc in documents
         .map(|doc| doc.trim())
         .filter(|doc| !doc.is_empty())
-        .filter_map(|doc| match deserialize(doc) {
-            Ok(item) => Some((hash_str(doc), item)),
+    {
+        match deserialize(doc) {
+            Ok(item) => {
+                let hash = match canonicalize.and_then(|canonicalize| canonicalize(doc)) {
+                    Some(canonical) => hash_str(&canonical),
+                    None => hash_str(doc),
+                };
+                items.insert(hash, (item, doc));
+            }
+            Err(err) if on_deserialize_error == ErrorMode::Fail => {
+                return Err(WatcherError::Deserialize {
+                    document_id: filename.to_string(),
+                    detail: format!("{:?}", err),
+                });
+            }
             Err(err) => {
                 log::error!(
                     "Failed to deserialize document in file {:?}:\n{}\n{:?}",
@@ -234,35 +2234,126 @@ where
                     doc,
                     err
                 );
-                None
+                if on_deserialize_error == ErrorMode::Report {
+                    errors.push(WatcherError::Deserialize {
+                        document_id: filename.to_string(),
+                        detail: format!("{:?}", err),
+                    });
+                }
             }
+        }
+    }
+    Ok(items)
+}
+
+/// Runs [`parse_items`] with `spawn_blocking`, see
+/// [`ConfigItemWatcherOptions::parse_on_blocking_pool`]. Returns the content along with the items,
+/// whose raw text is only kept for `include_raw`, and the reported deserialization errors.
+async fn parse_items_blocking<T, E, D>(
+    filename: &str,
+    content: String,
+    tokenizer: &'static dyn Tokenizer,
+    deserialize: &Arc<D>,
+    options: &ConfigItemWatcherOptions<T>,
+) -> Result<
+    (
+        String,
+        BTreeMap<u64, (T, Cow<'static, str>)>,
+        Vec<WatcherError>,
+    ),
+    WatcherError,
+>
+where
+    T: Send + 'static,
+    E: std::fmt::Debug + 'static,
+    D: Fn(&str) -> std::result::Result<T, E> + Send + Sync + 'static,
+{
+    let filename = filename.to_string();
+    let deserialize = deserialize.clone();
+    let on_deserialize_error = options.on_deserialize_error;
+    let canonicalize = options.canonicalize.clone();
+    let include_raw = options.include_raw;
+    tokio::task::spawn_blocking(move || {
+        let mut errors = Vec::new();
+        let items = parse_items(
+            &filename,
+            &content,
+            tokenizer,
+            &*deserialize,
+            on_deserialize_error,
+            canonicalize.as_ref(),
+            &mut errors,
+        )?
+        .into_iter()
+        .map(|(hash, (item, raw))| {
+            let raw = match include_raw {
+                true => raw.to_string(),
+                false => String::new(),
+            };
+            (hash, (item, Cow::Owned(raw)))
         })
         .collect();
+        Ok((content, items, errors))
+    })
+    .await?
+}
 
-    // Filter and detect removals
-    item_hashes.retain(|hash| {
-        // if the hash does not belong to the current file, we keep it
-        if hash.0 != filename_hash {
-            return true;
-        }
-        // if the hash belongs to the current file but its content hash is not found in the
-        // new_hashes, the item was removed
-        if !new_items.contains_key(&hash.1) {
-            events.push(ConfigItemEvent::Removed(*hash));
-            false // Remove this item
-        } else {
-            true // Keep this item
-        }
-    });
+fn new_item_event<T>(
+    hash: ConfigItemHash,
+    item: T,
+    raw: &str,
+    include_raw: bool,
+) -> ConfigItemEvent<T> {
+    if include_raw {
+        ConfigItemEvent::NewWithRaw(hash, item, raw.to_string())
+    } else {
+        ConfigItemEvent::New(hash, item)
+    }
+}
 
-    // Detect changes and additions
-    for (new_hash, new_item) in new_items.into_iter() {
-        let hash = ConfigItemHash(filename_hash, new_hash);
-        if item_hashes.insert(hash) {
-            // New item
-            events.push(ConfigItemEvent::New(hash, new_item));
+/// Recreates the events describing the current state: each tracked document followed by its
+/// tracked items.
+fn replay_events<T, E>(
+    documents: &BTreeMap<String, String>,
+    item_hashes: &ItemTracker,
+    tokenizer: &dyn Tokenizer,
+    deserialize: &impl Fn(&str) -> std::result::Result<T, E>,
+    options: &ConfigItemWatcherOptions<T>,
+) -> Vec<ConfigItemEvent<T>>
+where
+    E: std::fmt::Debug,
+{
+    let mut events = Vec::new();
+    let mut replayed = HashSet::new();
+
+    for (filename, content) in documents {
+        let filename_hash = filename_hash(options, filename);
+        if item_hashes.is_rejected(filename_hash) {
+            continue;
+        }
+        events.push(ConfigItemEvent::NewDocument(
+            filename_hash,
+            filename.clone(),
+        ));
+        // Replayed documents were parsed successfully before
+        let items = parse_items(
+            filename,
+            content,
+            tokenizer,
+            deserialize,
+            ErrorMode::Skip,
+            options.canonicalize.as_ref(),
+            &mut Vec::new(),
+        )
+        .unwrap_or_default();
+        for (item_hash, (item, raw)) in items {
+            if let Some(hash) = item_hashes.reported_hash(ConfigItemHash(filename_hash, item_hash))
+            {
+                if replayed.insert(hash) {
+                    events.push(new_item_event(hash, item, raw, options.include_raw));
+                }
+            }
         }
     }
-
-    Ok(events)
+    events
 }
@@ -0,0 +1,150 @@
+use serde::Serialize;
+
+use crate::{backend::WatcherHandle, ConfigItemWatcherHandle};
+
+/// Health of a watcher as reported by [`HealthSource::status`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum WatcherStatus {
+    /// The watcher runs but has not completed its initial scan yet.
+    Starting,
+    /// The watcher runs. Used for backend handles, which do not track the initial scan.
+    Running,
+    /// The watcher runs and has completed its initial scan.
+    Ready,
+    /// The watcher task has terminated, because it was stopped, failed or its source ended.
+    Finished,
+}
+
+impl WatcherStatus {
+    /// Returns true for [`Running`](Self::Running) and [`Ready`](Self::Ready).
+    pub fn is_healthy(self) -> bool {
+        matches!(self, Self::Running | Self::Ready)
+    }
+}
+
+/// A watcher that can report its [`WatcherStatus`], e.g. for liveness and readiness probes.
+pub trait HealthSource {
+    fn status(&self) -> WatcherStatus;
+}
+
+impl HealthSource for WatcherHandle {
+    fn status(&self) -> WatcherStatus {
+        if self.is_finished() {
+            WatcherStatus::Finished
+        } else {
+            WatcherStatus::Running
+        }
+    }
+}
+
+impl<T, O> HealthSource for ConfigItemWatcherHandle<T, O> {
+    fn status(&self) -> WatcherStatus {
+        if self.is_finished() {
+            WatcherStatus::Finished
+        } else if self.is_ready() {
+            WatcherStatus::Ready
+        } else {
+            WatcherStatus::Starting
+        }
+    }
+}
+
+#[cfg(feature = "http-health")]
+pub use server::serve_health;
+
+#[cfg(feature = "http-health")]
+mod server {
+    use futures::{stream::FuturesUnordered, StreamExt};
+    use std::time::Duration;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream, ToSocketAddrs},
+    };
+
+    use super::{HealthSource, WatcherStatus};
+    use crate::WatcherError;
+
+    /// Time a client has to send its request and receive the response before the connection is
+    /// dropped.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Request headers beyond this size are not read.
+    const MAX_REQUEST_SIZE: usize = 8192;
+
+    #[derive(serde::Serialize)]
+    struct HealthReport {
+        healthy: bool,
+        watchers: Vec<WatcherStatus>,
+    }
+
+    /// Serves the health of `sources` over HTTP on `addr`, e.g. for Kubernetes probes.
+    ///
+    /// Every request, regardless of method and path, is answered with `200 OK` if all sources
+    /// are [healthy](WatcherStatus::is_healthy) and `503 Service Unavailable` otherwise. The JSON
+    /// body lists the status of each source in order, e.g.
+    /// `{"healthy":false,"watchers":["Ready","Finished"]}`.
+    ///
+    /// The server borrows the sources and runs until it fails, so it is typically raced against
+    /// the shutdown signal of the application. Connections are served concurrently, and each is
+    /// closed if it has not sent its request and received the response within 5 seconds.
+    ///
+    /// # Errors
+    /// Returns [`WatcherError::Health`] if `addr` cannot be bound or accepting a connection
+    /// fails.
+    pub async fn serve_health(
+        sources: Vec<&dyn HealthSource>,
+        addr: impl ToSocketAddrs,
+    ) -> Result<(), WatcherError> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(WatcherError::Health)?;
+        // Connections are answered concurrently, so a slow client does not delay other probes
+        let mut connections = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, peer) = accepted.map_err(WatcherError::Health)?;
+                    let sources = &sources;
+                    connections.push(async move {
+                        let result = tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, sources))
+                            .await
+                            .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()));
+                        if let Err(err) = result {
+                            log::debug!("Cannot answer health request from {}: {}", peer, err);
+                        }
+                    });
+                }
+                Some(()) = connections.next(), if !connections.is_empty() => {}
+            }
+        }
+    }
+
+    async fn respond(mut stream: TcpStream, sources: &[&dyn HealthSource]) -> std::io::Result<()> {
+        // Only the end of the request headers is of interest
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+
+        let watchers: Vec<WatcherStatus> = sources.iter().map(|source| source.status()).collect();
+        let healthy = watchers.iter().all(|status| status.is_healthy());
+        let body = serde_json::to_string(&HealthReport { healthy, watchers })?;
+        let status_line = if healthy {
+            "200 OK"
+        } else {
+            "503 Service Unavailable"
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status_line,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
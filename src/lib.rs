@@ -1,5 +1,10 @@
 pub mod backend;
+pub mod blocking;
+pub mod clock;
 pub mod config_item_watcher;
+pub mod health;
+pub mod persistence;
+pub mod store;
 mod watcher;
 
 pub use config_item_watcher::*;
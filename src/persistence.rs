@@ -0,0 +1,129 @@
+//! Persistence of the item watcher state across restarts.
+//!
+//! With [`ConfigItemWatcherOptions::persistence`](crate::ConfigItemWatcherOptions::persistence)
+//! set, the item watcher appends a [`PersistedRecord`] to an [`EventStore`] for every document and
+//! item it reports or drops, before the events are sent. On startup the records are replayed, so
+//! documents and items the consumer has already seen are not reported again.
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    sync::{Arc, Mutex},
+};
+
+/// A change of the state reported by an item watcher.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PersistedRecord {
+    /// A document was reported with `NewDocument`.
+    Document { filename_hash: u64, id: String },
+    /// A document was reported with `RemoveDocument`.
+    DocumentRemoved { filename_hash: u64 },
+    /// An item of a document is tracked. With [`ConfigIdentity::Content`](crate::ConfigIdentity)
+    /// the filename hash still refers to the document containing the item.
+    Item { filename_hash: u64, item_hash: u64 },
+    /// An item of a document is no longer tracked.
+    ItemRemoved { filename_hash: u64, item_hash: u64 },
+}
+
+/// Write-ahead log of an item watcher, see [`ConfigItemWatcherOptions::persistence`](
+/// crate::ConfigItemWatcherOptions::persistence).
+///
+/// The methods are called on the watcher task and should return quickly.
+pub trait EventStore: Send + Sync {
+    /// Returns all records appended since the last [`compact`](Self::compact), oldest first.
+    fn load(&self) -> io::Result<Vec<PersistedRecord>>;
+
+    /// Appends records to the log. Called before the corresponding events are sent.
+    fn append(&self, records: &[PersistedRecord]) -> io::Result<()>;
+
+    /// Replaces the log with `records`, which describe the same state. Called on startup after
+    /// [`load`](Self::load).
+    fn compact(&self, records: &[PersistedRecord]) -> io::Result<()>;
+}
+
+/// [`EventStore`] keeping the records in memory. Clones share the same records, e.g. to hand the
+/// store of a stopped watcher to its successor in tests.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryEventStore {
+    records: Arc<Mutex<Vec<PersistedRecord>>>,
+}
+
+impl MemoryEventStore {
+    /// Returns a copy of the current records.
+    pub fn records(&self) -> Vec<PersistedRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl EventStore for MemoryEventStore {
+    fn load(&self) -> io::Result<Vec<PersistedRecord>> {
+        Ok(self.records())
+    }
+
+    fn append(&self, records: &[PersistedRecord]) -> io::Result<()> {
+        self.records.lock().unwrap().extend_from_slice(records);
+        Ok(())
+    }
+
+    fn compact(&self, records: &[PersistedRecord]) -> io::Result<()> {
+        *self.records.lock().unwrap() = records.to_vec();
+        Ok(())
+    }
+}
+
+/// The state described by a sequence of records.
+#[derive(Debug, Default)]
+pub(crate) struct PersistedState {
+    /// Document ids by filename hash.
+    pub(crate) documents: BTreeMap<u64, String>,
+    /// Tracked items as (filename hash, item hash).
+    pub(crate) items: BTreeSet<(u64, u64)>,
+}
+
+impl PersistedState {
+    pub(crate) fn replay(records: Vec<PersistedRecord>) -> Self {
+        let mut state = Self::default();
+        for record in records {
+            match record {
+                PersistedRecord::Document { filename_hash, id } => {
+                    state.documents.insert(filename_hash, id);
+                }
+                PersistedRecord::DocumentRemoved { filename_hash } => {
+                    state.documents.remove(&filename_hash);
+                }
+                PersistedRecord::Item {
+                    filename_hash,
+                    item_hash,
+                } => {
+                    state.items.insert((filename_hash, item_hash));
+                }
+                PersistedRecord::ItemRemoved {
+                    filename_hash,
+                    item_hash,
+                } => {
+                    state.items.remove(&(filename_hash, item_hash));
+                }
+            }
+        }
+        state
+    }
+
+    /// Returns the records describing this state.
+    pub(crate) fn records(&self) -> Vec<PersistedRecord> {
+        let documents =
+            self.documents
+                .iter()
+                .map(|(filename_hash, id)| PersistedRecord::Document {
+                    filename_hash: *filename_hash,
+                    id: id.clone(),
+                });
+        let items = self
+            .items
+            .iter()
+            .map(|(filename_hash, item_hash)| PersistedRecord::Item {
+                filename_hash: *filename_hash,
+                item_hash: *item_hash,
+            });
+        documents.chain(items).collect()
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tokio::{
+    sync::{mpsc::Receiver, watch},
+    task::JoinHandle,
+};
+
+use crate::{CheckpointAck, ConfigItemEvent, ConfigItemHash};
+
+/// Maintains the current set of configuration items by applying the events of an item watcher.
+///
+/// The store consumes the event receiver returned by `run_config_item_watcher` in a background
+/// task. Every applied event message increments the store version, which can be awaited with
+/// [`ConfigStore::changed`] or observed via [`ConfigStore::subscribe`].
+pub struct ConfigStore<T> {
+    items: Arc<RwLock<HashMap<ConfigItemHash, T>>>,
+    version: watch::Receiver<u64>,
+    task_handle: JoinHandle<()>,
+}
+
+impl<T> ConfigStore<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Creates a store that applies all events received from `receiver`.
+    pub fn new(mut receiver: Receiver<ConfigItemEvent<T>>) -> Self {
+        let items = Arc::new(RwLock::new(HashMap::new()));
+        let (version_sender, version) = watch::channel(0);
+
+        let task_handle = tokio::spawn({
+            let items = items.clone();
+            async move {
+                while let Some(event) = receiver.recv().await {
+                    let mut acks = Vec::new();
+                    {
+                        let mut items = items.write().unwrap();
+                        apply_event(&mut items, event, &|item| item, &mut acks);
+                    }
+                    version_sender.send_modify(|version| *version += 1);
+                    // The preceding events are applied, so the store reflects the checkpoint
+                    acks.into_iter().for_each(CheckpointAck::ack);
+                }
+                log::debug!("Config item event stream ended. Exiting ConfigStore task.");
+            }
+        });
+
+        Self {
+            items,
+            version,
+            task_handle,
+        }
+    }
+
+    /// Returns a copy of the item with the given hash.
+    pub fn get(&self, hash: &ConfigItemHash) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.items.read().unwrap().get(hash).cloned()
+    }
+
+    /// Returns a copy of all current items.
+    pub fn iter(&self) -> impl Iterator<Item = (ConfigItemHash, T)>
+    where
+        T: Clone,
+    {
+        self.items
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(hash, item)| (*hash, item.clone()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns true if the store contains an item with the given hash.
+    pub fn contains(&self, hash: &ConfigItemHash) -> bool {
+        self.items.read().unwrap().contains_key(hash)
+    }
+
+    /// Number of items currently in the store.
+    pub fn len(&self) -> usize {
+        self.items.read().unwrap().len()
+    }
+
+    /// Returns true if the store contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.items.read().unwrap().is_empty()
+    }
+
+    /// Waits until the store has applied further events since the last call.
+    ///
+    /// Returns an error once the underlying event stream has ended.
+    pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
+        self.version.changed().await
+    }
+
+    /// Returns a receiver of the store version, which increments on every applied event.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.version.clone()
+    }
+}
+
+impl<T> Drop for ConfigStore<T> {
+    fn drop(&mut self) {
+        self.task_handle.abort();
+    }
+}
+
+/// A snapshot of the items of an item watcher, see [`ConfigSnapshotWatcher`].
+pub type ConfigSnapshot<T> = Arc<HashMap<ConfigItemHash, Arc<T>>>;
+
+/// Publishes the current set of configuration items as a single value, for consumers that want
+/// the latest complete configuration rather than individual events.
+///
+/// Like [`ConfigStore`], it consumes the event receiver of an item watcher in a background task.
+/// After applying the events that are available, it publishes a new [`ConfigSnapshot`] over a
+/// [`watch`] channel: [`borrow`](watch::Receiver::borrow) a receiver for the current snapshot
+/// and await [`changed`](watch::Receiver::changed) for updates. Items are shared between
+/// snapshots, so publishing a snapshot does not clone them. Checkpoints are acknowledged once
+/// the snapshot including the preceding events is published.
+pub struct ConfigSnapshotWatcher<T> {
+    snapshot: watch::Receiver<ConfigSnapshot<T>>,
+    task_handle: JoinHandle<()>,
+}
+
+impl<T> ConfigSnapshotWatcher<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Creates a snapshot watcher that applies all events received from `receiver`. The initial
+    /// snapshot is empty.
+    pub fn new(mut receiver: Receiver<ConfigItemEvent<T>>) -> Self {
+        let (snapshot_sender, snapshot) = watch::channel(ConfigSnapshot::default());
+
+        let task_handle = tokio::spawn(async move {
+            let mut items: HashMap<ConfigItemHash, Arc<T>> = HashMap::new();
+            while let Some(event) = receiver.recv().await {
+                let mut acks = Vec::new();
+                apply_event(&mut items, event, &Arc::new, &mut acks);
+                // Publish once for all events that are already queued
+                while let Ok(event) = receiver.try_recv() {
+                    apply_event(&mut items, event, &Arc::new, &mut acks);
+                }
+                snapshot_sender.send_replace(Arc::new(items.clone()));
+                acks.into_iter().for_each(CheckpointAck::ack);
+            }
+            log::debug!("Config item event stream ended. Exiting ConfigSnapshotWatcher task.");
+        });
+
+        Self {
+            snapshot,
+            task_handle,
+        }
+    }
+
+    /// Returns the current snapshot.
+    pub fn snapshot(&self) -> ConfigSnapshot<T> {
+        self.snapshot.borrow().clone()
+    }
+
+    /// Returns a receiver of the snapshots. [`changed`](watch::Receiver::changed) returns an
+    /// error once the event stream has ended and the last snapshot was published.
+    pub fn subscribe(&self) -> watch::Receiver<ConfigSnapshot<T>> {
+        self.snapshot.clone()
+    }
+}
+
+impl<T> Drop for ConfigSnapshotWatcher<T> {
+    fn drop(&mut self) {
+        self.task_handle.abort();
+    }
+}
+
+/// Applies `event` to `items`, storing items converted with `convert`. Checkpoints are collected
+/// in `acks`, to be acknowledged once the result is visible.
+fn apply_event<T, V>(
+    items: &mut HashMap<ConfigItemHash, V>,
+    event: ConfigItemEvent<T>,
+    convert: &impl Fn(T) -> V,
+    acks: &mut Vec<CheckpointAck>,
+) {
+    match event {
+        ConfigItemEvent::New(hash, item) | ConfigItemEvent::NewWithRaw(hash, item, _) => {
+            items.insert(hash, convert(item));
+        }
+        ConfigItemEvent::Removed(hash) => {
+            items.remove(&hash);
+        }
+        ConfigItemEvent::RemoveDocument(filename_hash) => {
+            items.retain(|hash, _| hash.filename_hash() != filename_hash);
+        }
+        ConfigItemEvent::Batch(events) => {
+            for event in events {
+                apply_event(items, event, convert, acks);
+            }
+        }
+        ConfigItemEvent::Checkpoint(ack) => acks.push(ack),
+        ConfigItemEvent::NewDocument(..)
+        | ConfigItemEvent::DocumentChanged(..)
+        | ConfigItemEvent::DocumentEmptied(..)
+        | ConfigItemEvent::DocumentTouched(..)
+        | ConfigItemEvent::ValidationError { .. }
+        | ConfigItemEvent::DocumentError { .. }
+        | ConfigItemEvent::Error(_) => {}
+    }
+}
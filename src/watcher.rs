@@ -1,4 +1,4 @@
-use rumqttc::ClientError;
+use serde::Serialize;
 use std::hash::Hasher;
 use std::io;
 use std::path::PathBuf;
@@ -10,7 +10,9 @@ use twox_hash::XxHash64;
 use crate::backend::WatcherCommand;
 
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum WatcherError {
+    #[cfg(feature = "file")]
     #[error("Error watching files {:#?}", .0)]
     Notify(#[from] notify::Error),
     #[error("Error matching glob pattern {:#?}", .0)]
@@ -21,14 +23,159 @@ pub enum WatcherError {
     JoinError(#[from] JoinError),
     #[error("Error reading file [{0}]: {1:?}")]
     FileReadError(PathBuf, io::Error),
+    #[cfg(feature = "file")]
+    #[error("File [{0}] is not a text file in the configured encoding")]
+    BinaryFile(PathBuf),
+    #[cfg(feature = "configmap")]
     #[error("Kubernetes API error: {0}")]
     KubeError(#[from] kube::Error),
+    #[cfg(feature = "configmap")]
     #[error("Kubernetes watcher API error: {0}")]
     WatcherError(#[from] kube::runtime::watcher::Error),
+    #[cfg(feature = "mqtt")]
     #[error("Mqtt Client error: {0}")]
-    MqttClient(#[from] ClientError),
+    MqttClient(#[from] rumqttc::ClientError),
     #[error("Error sending command to watcher {0}")]
     SendError(#[from] SendError<WatcherCommand>),
+    #[cfg(feature = "mqtt")]
+    #[error("Invalid MQTT topic filter [{0}]")]
+    InvalidTopic(String),
+    #[cfg(feature = "mqtt")]
+    #[error("Giving up connecting to the MQTT broker after {0} failed attempts")]
+    MqttReconnectExhausted(u32),
+    #[cfg(feature = "configmap")]
+    #[error("Cannot detect Kubernetes namespace: neither [{0}] nor POD_NAMESPACE is available")]
+    NamespaceUnavailable(PathBuf),
+    #[cfg(feature = "configmap")]
+    #[error("binaryData key [{key}] of ConfigMap [{configmap}] is not valid UTF-8: {error}")]
+    InvalidBinaryData {
+        configmap: String,
+        key: String,
+        error: std::str::Utf8Error,
+    },
+    #[error("Document [{0}] cannot be loaded")]
+    DocumentUnavailable(String),
+    #[error("Error creating runtime: {0}")]
+    Runtime(io::Error),
+    #[error("Error deserializing item in document [{document_id}]: {detail}")]
+    Deserialize { document_id: String, detail: String },
+    #[error("Error splitting document [{document_id}] into items: {detail}")]
+    Tokenize { document_id: String, detail: String },
+    #[error("Limit of {limit} {kind} exceeded by document [{document}]")]
+    LimitExceeded {
+        kind: &'static str,
+        limit: usize,
+        document: String,
+    },
+    #[error("Document [{id}] is reported by several sources: {sources:?}")]
+    DuplicateId { id: String, sources: Vec<String> },
+    #[cfg(feature = "bundle")]
+    #[error("Unsupported bundle [{0}], expected a .tar, .tar.gz, .tgz or .zip file")]
+    UnsupportedBundle(PathBuf),
+    #[cfg(feature = "kafka")]
+    #[error("Kafka error: {0}")]
+    Kafka(#[from] rskafka::client::error::Error),
+    #[cfg(feature = "postgres")]
+    #[error("PostgreSQL error: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
+    #[cfg(feature = "nats")]
+    #[error("NATS error: {0}")]
+    Nats(async_nats::Error),
+    #[cfg(feature = "s3")]
+    #[error("S3 error: {0}")]
+    S3(#[from] object_store::Error),
+    #[cfg(feature = "vault")]
+    #[error("Vault request error: {0}")]
+    Vault(#[from] reqwest::Error),
+    #[cfg(feature = "vault")]
+    #[error("Vault returned status {0}: {1}")]
+    VaultStatus(u16, String),
+    #[cfg(feature = "vault")]
+    #[error("Vault denied access: the token is invalid, has expired or lacks permission")]
+    VaultAccessDenied,
+    #[cfg(feature = "http-health")]
+    #[error("Health endpoint error: {0}")]
+    Health(io::Error),
+    #[cfg(feature = "git")]
+    #[error("Git error: {0}")]
+    Git(String),
+    #[error("Error accessing the persisted item watcher state: {0}")]
+    Persistence(io::Error),
+}
+
+/// Cloneable and serializable summary of a [`WatcherError`], e.g. to pass an error on to several
+/// subscribers or to report it in a status.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ErrorSummary {
+    /// Name of the [`WatcherError`] variant, e.g. `"FileReadError"`.
+    pub kind: &'static str,
+    /// The error message (the `Display` output of the error).
+    pub message: String,
+    /// The file the error refers to, if any.
+    pub path: Option<PathBuf>,
+}
+
+impl WatcherError {
+    /// Returns a cloneable summary of the error.
+    pub fn summary(&self) -> ErrorSummary {
+        let (kind, path) = match self {
+            #[cfg(feature = "file")]
+            WatcherError::Notify(_) => ("Notify", None),
+            WatcherError::Pattern(_) => ("Pattern", None),
+            WatcherError::HashError(path, _) => ("HashError", Some(path)),
+            WatcherError::JoinError(_) => ("JoinError", None),
+            WatcherError::FileReadError(path, _) => ("FileReadError", Some(path)),
+            #[cfg(feature = "file")]
+            WatcherError::BinaryFile(path) => ("BinaryFile", Some(path)),
+            #[cfg(feature = "configmap")]
+            WatcherError::KubeError(_) => ("KubeError", None),
+            #[cfg(feature = "configmap")]
+            WatcherError::WatcherError(_) => ("WatcherError", None),
+            #[cfg(feature = "mqtt")]
+            WatcherError::MqttClient(_) => ("MqttClient", None),
+            WatcherError::SendError(_) => ("SendError", None),
+            #[cfg(feature = "mqtt")]
+            WatcherError::InvalidTopic(_) => ("InvalidTopic", None),
+            #[cfg(feature = "mqtt")]
+            WatcherError::MqttReconnectExhausted(_) => ("MqttReconnectExhausted", None),
+            #[cfg(feature = "configmap")]
+            WatcherError::NamespaceUnavailable(path) => ("NamespaceUnavailable", Some(path)),
+            #[cfg(feature = "configmap")]
+            WatcherError::InvalidBinaryData { .. } => ("InvalidBinaryData", None),
+            WatcherError::DocumentUnavailable(_) => ("DocumentUnavailable", None),
+            WatcherError::Runtime(_) => ("Runtime", None),
+            WatcherError::Deserialize { .. } => ("Deserialize", None),
+            WatcherError::Tokenize { .. } => ("Tokenize", None),
+            WatcherError::LimitExceeded { .. } => ("LimitExceeded", None),
+            WatcherError::DuplicateId { .. } => ("DuplicateId", None),
+            #[cfg(feature = "bundle")]
+            WatcherError::UnsupportedBundle(path) => ("UnsupportedBundle", Some(path)),
+            #[cfg(feature = "kafka")]
+            WatcherError::Kafka(_) => ("Kafka", None),
+            #[cfg(feature = "postgres")]
+            WatcherError::Postgres(_) => ("Postgres", None),
+            #[cfg(feature = "nats")]
+            WatcherError::Nats(_) => ("Nats", None),
+            #[cfg(feature = "s3")]
+            WatcherError::S3(_) => ("S3", None),
+            #[cfg(feature = "vault")]
+            WatcherError::Vault(_) => ("Vault", None),
+            #[cfg(feature = "vault")]
+            WatcherError::VaultStatus(..) => ("VaultStatus", None),
+            #[cfg(feature = "vault")]
+            WatcherError::VaultAccessDenied => ("VaultAccessDenied", None),
+            #[cfg(feature = "http-health")]
+            WatcherError::Health(_) => ("Health", None),
+            #[cfg(feature = "git")]
+            WatcherError::Git(_) => ("Git", None),
+            WatcherError::Persistence(_) => ("Persistence", None),
+        };
+        ErrorSummary {
+            kind,
+            message: self.to_string(),
+            path: path.cloned(),
+        }
+    }
 }
 
 pub fn hash_str(data: &str) -> u64 {
@@ -39,6 +186,16 @@ pub fn hash_str(data: &str) -> u64 {
 
 pub trait Tokenizer: Send + Sync {
     fn tokenize<'a>(&self, content: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a>;
+
+    /// Like [`tokenize`](Self::tokenize), but fails with a description of the problem if the
+    /// content cannot be split reliably. The item watcher uses this method and reports the
+    /// failure as [`WatcherError::Tokenize`]. Defaults to `tokenize`, which never fails.
+    fn try_tokenize<'a>(
+        &self,
+        content: &'a str,
+    ) -> Result<Box<dyn Iterator<Item = &'a str> + 'a>, String> {
+        Ok(self.tokenize(content))
+    }
 }
 
 pub struct YamlTokenizer;
@@ -66,3 +223,226 @@ impl Tokenizer for JsonTokenizer {
         )
     }
 }
+
+/// Splits a top-level JSON array into its elements, e.g. `[{"a": 1}, {"b": 2}]` into `{"a": 1}`
+/// and `{"b": 2}`. Content that is not enclosed in `[` and `]` is passed on as it is.
+///
+/// Elements are found by tracking brackets and strings; the content is not validated otherwise.
+/// [`try_tokenize`](Tokenizer::try_tokenize) fails if they are unbalanced.
+pub struct JsonArrayTokenizer;
+
+impl JsonArrayTokenizer {
+    /// Returns the elements of the array `content` and whether its brackets and strings are
+    /// balanced, or `None` if `content` is no array.
+    fn elements(content: &str) -> Option<(Vec<&str>, bool)> {
+        let inner = content.strip_prefix('[')?.strip_suffix(']')?;
+        let mut elements = Vec::new();
+        let (mut depth, mut start) = (0usize, 0);
+        let (mut in_string, mut escaped, mut balanced) = (false, false, true);
+        for (i, c) in inner.char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '[' | '{' => depth += 1,
+                ']' | '}' if depth == 0 => balanced = false,
+                ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    elements.push(&inner[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        elements.push(&inner[start..]);
+        Some((elements, balanced && depth == 0 && !in_string))
+    }
+}
+
+impl Tokenizer for JsonArrayTokenizer {
+    fn tokenize<'a>(&self, content: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        let content = content.trim();
+        let Some((elements, _)) = Self::elements(content) else {
+            return Box::new(std::iter::once(content).filter(|s| !s.is_empty()));
+        };
+        Box::new(
+            elements
+                .into_iter()
+                .map(str::trim)
+                .filter(|s| !s.is_empty()),
+        )
+    }
+
+    /// Fails if the brackets or strings of the array are unbalanced.
+    fn try_tokenize<'a>(
+        &self,
+        content: &'a str,
+    ) -> Result<Box<dyn Iterator<Item = &'a str> + 'a>, String> {
+        match Self::elements(content.trim()) {
+            Some((_, false)) => Err("unbalanced brackets or strings in JSON array".to_string()),
+            _ => Ok(self.tokenize(content)),
+        }
+    }
+}
+
+/// Applies several tokenizers in sequence: every document produced by a stage is split again by
+/// the next stage. Empty documents are dropped between stages.
+///
+/// For example, `ChainTokenizer::new(vec![Box::new(YamlTokenizer), Box::new(JsonArrayTokenizer)])`
+/// splits a file at `---` and each resulting JSON array into its elements.
+pub struct ChainTokenizer {
+    pub stages: Vec<Box<dyn Tokenizer>>,
+}
+
+impl ChainTokenizer {
+    pub fn new(stages: Vec<Box<dyn Tokenizer>>) -> Self {
+        Self { stages }
+    }
+}
+
+impl Tokenizer for ChainTokenizer {
+    fn tokenize<'a>(&self, content: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        let mut documents = vec![content];
+        for stage in &self.stages {
+            documents = documents
+                .into_iter()
+                .flat_map(|document| stage.tokenize(document))
+                .filter(|document| !document.trim().is_empty())
+                .collect();
+        }
+        Box::new(documents.into_iter())
+    }
+
+    /// Fails if any stage fails.
+    fn try_tokenize<'a>(
+        &self,
+        content: &'a str,
+    ) -> Result<Box<dyn Iterator<Item = &'a str> + 'a>, String> {
+        let mut documents = vec![content];
+        for stage in &self.stages {
+            let mut next = Vec::new();
+            for document in documents {
+                next.extend(
+                    stage
+                        .try_tokenize(document)?
+                        .filter(|document| !document.trim().is_empty()),
+                );
+            }
+            documents = next;
+        }
+        Ok(Box::new(documents.into_iter()))
+    }
+}
+
+/// Splits content at every match of a separator pattern, e.g. `^=====+$` for records separated by
+/// lines of `=` of any length. `^` and `$` match at line boundaries. Like the other tokenizers, the
+/// documents are trimmed and empty ones are dropped.
+#[cfg(feature = "regex")]
+pub struct RegexTokenizer {
+    pub pattern: regex::Regex,
+}
+
+#[cfg(feature = "regex")]
+impl RegexTokenizer {
+    /// Compiles `pattern` in multi-line mode. Patterns that match the empty string are rejected,
+    /// since they would split the content between every character.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        let pattern = regex::RegexBuilder::new(pattern).multi_line(true).build()?;
+        if pattern.is_match("") {
+            return Err(regex::Error::Syntax(format!(
+                "separator pattern [{pattern}] matches the empty string"
+            )));
+        }
+        Ok(Self { pattern })
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Tokenizer for RegexTokenizer {
+    fn tokenize<'a>(&self, content: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        Box::new(
+            self.pattern
+                .split(content)
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )
+    }
+}
+
+/// Canonical form of a YAML document for use with
+/// [`ConfigItemWatcherOptions::canonicalize`](crate::ConfigItemWatcherOptions::canonicalize).
+///
+/// Aliases and merge keys (`<<: *base`) are expanded and mapping keys are sorted, so documents
+/// that only differ in these respects (or in formatting and comments) map to the same text.
+/// Returns `None` if the document is not valid YAML.
+#[cfg(feature = "yaml")]
+pub fn canonicalize_yaml(document: &str) -> Option<String> {
+    fn normalize(value: &mut serde_yaml::Value) {
+        match value {
+            serde_yaml::Value::Mapping(mapping) => {
+                let mut entries: Vec<_> = std::mem::take(mapping).into_iter().collect();
+                for (_, entry) in entries.iter_mut() {
+                    normalize(entry);
+                }
+                entries
+                    .sort_by_cached_key(|(key, _)| serde_yaml::to_string(key).unwrap_or_default());
+                mapping.extend(entries);
+            }
+            serde_yaml::Value::Sequence(sequence) => sequence.iter_mut().for_each(normalize),
+            serde_yaml::Value::Tagged(tagged) => normalize(&mut tagged.value),
+            _ => {}
+        }
+    }
+
+    let mut value: serde_yaml::Value = serde_yaml::from_str(document).ok()?;
+    value.apply_merge().ok()?;
+    normalize(&mut value);
+    serde_yaml::to_string(&value).ok()
+}
+
+/// Deep-merges YAML documents like Helm values files, for use as the
+/// [`LayerMergeFn`](crate::backend::LayerMergeFn) of a layered watcher.
+///
+/// Mappings are merged key by key, recursively; any other value, including sequences, is
+/// replaced by the value of the later layer. As in Helm, a key set to `null` removes the key.
+/// Empty layers are skipped. Returns `None` if a layer is not valid YAML.
+#[cfg(feature = "yaml")]
+pub fn merge_yaml_layers(layers: &[&str]) -> Option<String> {
+    fn merge(base: &mut serde_yaml::Value, layer: serde_yaml::Value) {
+        match (base, layer) {
+            (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(layer)) => {
+                for (key, value) in layer {
+                    if value.is_null() {
+                        base.remove(&key);
+                        continue;
+                    }
+                    match base.get_mut(&key) {
+                        Some(existing) => merge(existing, value),
+                        None => {
+                            base.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (_, serde_yaml::Value::Null) => {}
+            (base, layer) => *base = layer,
+        }
+    }
+
+    let mut merged = serde_yaml::Value::Null;
+    for layer in layers {
+        let mut value: serde_yaml::Value = serde_yaml::from_str(layer).ok()?;
+        value.apply_merge().ok()?;
+        merge(&mut merged, value);
+    }
+    serde_yaml::to_string(&merged).ok()
+}
@@ -0,0 +1,35 @@
+#![cfg(feature = "postgres")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::clock::TestClock;
+use std::{sync::Arc, time::Duration};
+
+#[tokio::test]
+async fn postgres_watcher_retries_on_its_clock() {
+    let clock = TestClock::new();
+    let (mut handle, _rx) = run_postgres_watcher_with_options(
+        "host=127.0.0.1 port=1 user=config connect_timeout=1".to_string(),
+        "config".to_string(),
+        "id".to_string(),
+        "content".to_string(),
+        "config".to_string(),
+        PostgresWatcherOptions {
+            retry_delay: RetryDelay::new(Duration::from_secs(60), 0.0),
+            clock: Arc::new(clock.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+
+    // The failed connection waits for the retry delay on the test clock, and the next attempt
+    // only starts once the clock has been advanced
+    common::wait_for_sleeps(&clock, 1).await;
+    clock.advance(Duration::from_secs(59));
+    assert_eq!(clock.pending_sleeps(), 1);
+    clock.advance(Duration::from_secs(1));
+    common::wait_for_sleeps(&clock, 1).await;
+    handle.stop().await.unwrap();
+}
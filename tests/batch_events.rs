@@ -0,0 +1,75 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn events_of_one_document_change_arrive_in_one_batch() {
+    let dir = common::tmpdir("batch-events");
+    std::fs::write(dir.join("a.yaml"), "kept\n---\nreplaced").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            batch_events: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let [ConfigItemEvent::Batch(batch)] = events.as_slice() else {
+        panic!("expected a single batch: {events:?}");
+    };
+    let (document, kept, replaced) = match batch.as_slice() {
+        [ConfigItemEvent::NewDocument(document, _), ConfigItemEvent::New(kept, kept_item), ConfigItemEvent::New(replaced, replaced_item)]
+            if kept_item == "kept" && replaced_item == "replaced" =>
+        {
+            (*document, *kept, *replaced)
+        }
+        _ => panic!("{batch:?}"),
+    };
+
+    // One item kept, one replaced and one added
+    std::fs::write(dir.join("a.yaml"), "kept\n---\nreplacement\n---\nadded").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let [ConfigItemEvent::Batch(batch)] = events.as_slice() else {
+        panic!("expected a single batch: {events:?}");
+    };
+    assert!(
+        matches!(
+            batch.as_slice(),
+            [
+                ConfigItemEvent::DocumentChanged(changed),
+                ConfigItemEvent::Removed(removed),
+                ConfigItemEvent::New(_, replacement),
+                ConfigItemEvent::New(_, added),
+            ] if *changed == document && *removed == replaced
+                && replacement == "replacement" && added == "added"
+        ),
+        "{batch:?}"
+    );
+
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let [ConfigItemEvent::Batch(batch)] = events.as_slice() else {
+        panic!("expected a single batch: {events:?}");
+    };
+    assert!(
+        matches!(
+            batch.as_slice(),
+            [
+                ConfigItemEvent::Removed(first),
+                ConfigItemEvent::Removed(second),
+                ConfigItemEvent::Removed(third),
+                ConfigItemEvent::RemoveDocument(removed),
+            ] if *removed == document && [first, second, third].contains(&&kept)
+        ),
+        "{batch:?}"
+    );
+    handle.stop().await.unwrap();
+}
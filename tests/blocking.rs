@@ -0,0 +1,60 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::blocking::BlockingConfigItemWatcher;
+use config_watcher::*;
+use std::time::Duration;
+
+#[test]
+fn blocking_watcher_delivers_events_without_a_runtime() {
+    let dir = common::tmpdir("blocking");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let mut watcher = BlockingConfigItemWatcher::new(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    assert!(watcher.wait_ready());
+
+    let mut items = Vec::new();
+    while let Ok(event) = watcher.try_recv() {
+        if let ConfigItemEvent::New(_, item) = event {
+            items.push(item);
+        }
+    }
+    items.sort();
+    assert_eq!(items, ["one", "two"]);
+
+    std::fs::write(dir.join("b.yaml"), "three").unwrap();
+    let event = std::iter::from_fn(|| watcher.recv_timeout(Duration::from_secs(5)))
+        .find(|event| matches!(event, ConfigItemEvent::New(..)));
+    assert!(
+        matches!(&event, Some(ConfigItemEvent::New(_, item)) if item == "three"),
+        "{event:?}"
+    );
+    assert!(watcher.recv_timeout(Duration::from_millis(300)).is_none());
+    watcher.stop().unwrap();
+}
+
+#[test]
+fn iteration_ends_when_the_watcher_terminates() {
+    let dir = common::tmpdir("blocking-iteration");
+    std::fs::write(dir.join("a.yaml"), "1\n---\nnot a number").unwrap();
+    let watch_dir = dir.clone();
+    let watcher = BlockingConfigItemWatcher::with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            on_deserialize_error: ErrorMode::Fail,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // The failing document ends the watcher, which closes the iterator
+    assert!(watcher.count() <= 2);
+}
@@ -0,0 +1,128 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+#[tokio::test]
+async fn items_are_deserialized_off_the_watcher_task() {
+    let dir = common::tmpdir("blocking-pool");
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2\n---\nnot a number").unwrap();
+    let watch_dir = dir.clone();
+    let threads = Arc::new(Mutex::new(Vec::new()));
+    let seen = threads.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        move |item: &str| {
+            seen.lock().unwrap().push(std::thread::current().id());
+            item.parse::<u32>()
+        },
+        ConfigItemWatcherOptions {
+            parse_on_blocking_pool: true,
+            include_raw: true,
+            on_deserialize_error: ErrorMode::Report,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+
+    let mut items: Vec<u32> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::NewWithRaw(_, item, raw) => {
+                assert_eq!(raw, &item.to_string());
+                Some(*item)
+            }
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, [1, 2]);
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            ConfigItemEvent::Error(WatcherError::Deserialize { document_id, .. }) if document_id.ends_with("a.yaml")
+        )),
+        "{events:?}"
+    );
+    // The test runs on a current-thread runtime, so the watcher task shares its thread
+    let threads = threads.lock().unwrap().clone();
+    assert_eq!(threads.len(), 3);
+    assert!(threads
+        .iter()
+        .all(|thread| *thread != std::thread::current().id()));
+    handle.stop().await.unwrap();
+}
+
+/// Parses a document of three items, each taking 100ms, while a spawned task ticks every 10ms.
+/// Returns the number of ticks that happened during the parse of each item.
+async fn ticks_while_parsing(name: &str, parse_on_blocking_pool: bool) -> Vec<usize> {
+    let dir = common::tmpdir(name);
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2\n---\n3").unwrap();
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = tokio::spawn({
+        let ticks = ticks.clone();
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                ticks.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let seen = progress.clone();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        move |item: &str| {
+            let before = ticks.load(Ordering::Relaxed);
+            std::thread::sleep(Duration::from_millis(100));
+            seen.lock()
+                .unwrap()
+                .push(ticks.load(Ordering::Relaxed) - before);
+            item.parse::<u32>()
+        },
+        ConfigItemWatcherOptions {
+            parse_on_blocking_pool,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ConfigItemEvent::New(..)))
+            .count(),
+        3,
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+    ticker.abort();
+    let progress = progress.lock().unwrap().clone();
+    progress
+}
+
+#[tokio::test]
+async fn runtime_keeps_running_while_items_are_parsed_on_the_blocking_pool() {
+    // The test runs on a current-thread runtime, so a parse on the watcher task stops the ticker
+    let ticks = ticks_while_parsing("blocking-pool-ticker", true).await;
+    assert_eq!(ticks.len(), 3);
+    assert!(ticks.iter().all(|ticks| *ticks >= 5), "{ticks:?}");
+
+    let ticks = ticks_while_parsing("blocking-pool-ticker-inline", false).await;
+    assert_eq!(ticks, [0, 0, 0]);
+}
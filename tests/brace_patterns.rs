@@ -0,0 +1,53 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+
+fn new_documents(events: &[DocumentEvent]) -> Vec<String> {
+    let mut names: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) => Some(
+                std::path::Path::new(id)
+                    .file_name()?
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn brace_groups_match_every_alternative() {
+    let dir = common::tmpdir("brace-patterns");
+    for name in ["a.yaml", "b.yml", "c.json", "d.toml", "e.txt"] {
+        std::fs::write(dir.join(name), "v: 0").unwrap();
+    }
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.{yaml,yml,json}").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_documents(&events), ["a.yaml", "b.yml", "c.json"]);
+
+    std::fs::write(dir.join("f.yml"), "v: 0").unwrap();
+    std::fs::write(dir.join("g.toml"), "v: 0").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(new_documents(&events), ["f.yml"]);
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn nested_brace_groups_are_expanded() {
+    let dir = common::tmpdir("brace-patterns-nested");
+    for name in ["app.yaml", "app.yml", "db.json", "db.yaml", "web.yaml"] {
+        std::fs::write(dir.join(name), "v: 0").unwrap();
+    }
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "{app.{yaml,yml},db.json}").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_documents(&events), ["app.yaml", "app.yml", "db.json"]);
+    handle.stop().await.unwrap();
+}
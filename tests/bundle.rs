@@ -0,0 +1,79 @@
+#![cfg(feature = "bundle")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::path::Path;
+use std::time::Duration;
+
+/// Writes a tar archive with the given files, atomically replacing `path`.
+fn write_tar(path: &Path, files: &[(&str, &str)]) {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, content.as_bytes())
+            .unwrap();
+    }
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, builder.into_inner().unwrap()).unwrap();
+    std::fs::rename(temporary, path).unwrap();
+}
+
+#[tokio::test]
+async fn archive_members_are_documents() {
+    let dir = common::tmpdir("bundle");
+    let archive = dir.join("config.tar");
+    write_tar(
+        &archive,
+        &[
+            ("a.yaml", "v: 0"),
+            ("sub/b.yaml", "v: 0"),
+            ("c.txt", "v: 0"),
+        ],
+    );
+    let (mut handle, mut rx) =
+        run_bundle_watcher(&archive, "**/*.yaml", Duration::from_millis(100)).unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let mut ids: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) => Some(id.as_str()),
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, ["a.yaml", "sub/b.yaml"]);
+
+    // Only the differences are emitted
+    write_tar(&archive, &[("a.yaml", "v: 1"), ("c.txt", "v: 1")]);
+    let events = common::collect(&mut rx, 800).await;
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert!(events.iter().any(
+        |event| matches!(event, DocumentEvent::ContentChanged(id, content) if id == "a.yaml" && content == "v: 1")
+    ));
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, DocumentEvent::DocumentRemoved(id) if id == "sub/b.yaml")));
+
+    std::fs::remove_file(&archive).unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id == "a.yaml"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[test]
+fn unsupported_archives_are_rejected() {
+    assert!(matches!(
+        run_bundle_watcher("/config.rar", "*", Duration::from_millis(100)),
+        Err(WatcherError::UnsupportedBundle(_))
+    ));
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[tokio::test]
+async fn callback_receives_new_and_removed_items() {
+    let dir = common::tmpdir("callback");
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2").unwrap();
+    let watch_dir = dir.clone();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    let mut handle = run_config_item_watcher_with_callback(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions::default(),
+        Arc::new(move |event| sink.lock().unwrap().push(event)),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(handle.wait_ready().await);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let events = std::mem::take(&mut *received.lock().unwrap());
+    let mut items: Vec<u32> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(*item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, [1, 2]);
+
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let events = std::mem::take(&mut *received.lock().unwrap());
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                ConfigItemEvent::DocumentChanged(_),
+                ConfigItemEvent::Removed(_)
+            ]
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::Arc;
+
+#[cfg(feature = "yaml")]
+#[test]
+fn canonical_yaml_expands_merge_keys_and_sorts_keys() {
+    let merged = "base: &base\n  a: 1\nitem:\n  <<: *base\n  b: 2";
+    let expanded = "item: {b: 2, a: 1}  # comment\nbase: {a: 1}";
+    assert_eq!(canonicalize_yaml(merged), canonicalize_yaml(expanded));
+    assert!(canonicalize_yaml(merged).is_some());
+    assert_ne!(canonicalize_yaml("a: 1"), canonicalize_yaml("a: 2"));
+    assert_eq!(canonicalize_yaml("a: [unclosed"), None);
+}
+
+#[tokio::test]
+async fn equal_canonical_forms_keep_the_item() {
+    let dir = common::tmpdir("canonicalize");
+    std::fs::write(dir.join("a.yaml"), "B").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            canonicalize: Some(Arc::new(|item: &str| Some(item.to_lowercase()))),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "b").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [ConfigItemEvent::DocumentChanged(_)]),
+        "{events:?}"
+    );
+
+    std::fs::write(dir.join("a.yaml"), "c").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, item) if item == "c")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,30 @@
+use config_watcher::*;
+
+fn chain() -> ChainTokenizer {
+    ChainTokenizer::new(vec![Box::new(YamlTokenizer), Box::new(JsonArrayTokenizer)])
+}
+
+#[test]
+fn every_document_of_a_stage_is_fed_into_the_next() {
+    let content = "[1, {\"a\": [2, 3]}]\n---\n[\"x\"]\n---\nplain";
+    assert_eq!(
+        chain().tokenize(content).collect::<Vec<_>>(),
+        ["1", "{\"a\": [2, 3]}", "\"x\"", "plain"]
+    );
+}
+
+#[test]
+fn failures_of_any_stage_are_reported() {
+    assert!(chain().try_tokenize("[1]\n---\n[2, 3]").is_ok());
+    assert!(chain().try_tokenize("[1]\n---\n[2, [3]").is_err());
+}
+
+#[test]
+fn an_empty_chain_keeps_the_content() {
+    assert_eq!(
+        ChainTokenizer::new(Vec::new())
+            .tokenize("a\n---\nb")
+            .collect::<Vec<_>>(),
+        ["a\n---\nb"]
+    );
+}
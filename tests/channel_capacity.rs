@@ -0,0 +1,51 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+/// Writes 50 files while the events are not received, then returns the number of events
+/// buffered in the channel and the ids of all documents received afterwards.
+async fn flood(name: &str, event_channel_capacity: usize) -> (usize, Vec<String>) {
+    let dir = common::tmpdir(name);
+    let options = ConfigFileWatcherOptions {
+        internal_channel_capacity: 1,
+        event_channel_capacity,
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // Nothing is received while the burst is written
+    for i in 0..50 {
+        std::fs::write(dir.join(format!("{i}.yaml")), format!("v: {i}")).unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let buffered = rx.len();
+    let mut ids: Vec<String> = common::collect(&mut rx, 500)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) | DocumentEvent::ContentChanged(id, _) => Some(id),
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    handle.stop().await.unwrap();
+    (buffered, ids)
+}
+
+#[tokio::test]
+async fn full_channels_apply_back_pressure_instead_of_dropping_events() {
+    let (buffered, ids) = flood("channel-capacity-small", 2).await;
+    assert!(buffered <= 2, "{buffered} events buffered");
+    assert_eq!(ids.len(), 50, "{ids:?}");
+
+    let (buffered, ids) = flood("channel-capacity-large", 100).await;
+    assert!(buffered > 2, "{buffered} events buffered");
+    assert_eq!(ids.len(), 50, "{ids:?}");
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn checkpoint_follows_held_back_changes_and_waits_for_the_ack() {
+    let dir = common::tmpdir("checkpoint");
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            // Longer than the test, so the edits are only emitted by the checkpoint
+            settle: Some(Duration::from_secs(60)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(handle.wait_ready().await);
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "2").unwrap();
+    std::fs::write(dir.join("b.yaml"), "3").unwrap();
+    // Past the debounce of the file watcher
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(rx.try_recv().is_err());
+
+    let controller = handle.controller();
+    let mut checkpoint = tokio::spawn(async move { controller.checkpoint().await });
+    let mut items = Vec::new();
+    let ack = loop {
+        match tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+        {
+            ConfigItemEvent::New(_, item) => items.push(item),
+            ConfigItemEvent::Checkpoint(ack) => break ack,
+            _ => {}
+        }
+    };
+    items.sort();
+    assert_eq!(items, [2, 3]);
+
+    // The caller is released by the ack
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), &mut checkpoint)
+            .await
+            .is_err()
+    );
+    ack.ack();
+    tokio::time::timeout(Duration::from_secs(5), checkpoint)
+        .await
+        .unwrap()
+        .unwrap();
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,117 @@
+use http::{Request, Response, StatusCode};
+use kube::{client::Body, Client};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Kubernetes API serving the objects of one resource type in a namespace.
+///
+/// Requests of the collection return all objects as a list, requests of a single object return
+/// it by name, or 404. Every watch request is answered with the next queued batch of watch
+/// events, after which the response ends and the watcher watches again. Without a queued batch
+/// the watch stays open without events, like a watch that missed changes.
+#[derive(Clone)]
+pub struct MockApi {
+    plural: String,
+    objects: Arc<Mutex<Vec<Value>>>,
+    watch_events: Arc<Mutex<VecDeque<Vec<Value>>>>,
+}
+
+impl MockApi {
+    /// Creates an API for the resource type with this plural name, e.g. `configmaps`.
+    pub fn new(plural: &str) -> Self {
+        Self {
+            plural: plural.to_string(),
+            objects: Arc::default(),
+            watch_events: Arc::default(),
+        }
+    }
+
+    /// Replaces the objects returned by lists and gets; watches are not notified.
+    pub fn set_objects(&self, objects: Vec<Value>) {
+        *self.objects.lock().unwrap() = objects;
+    }
+
+    /// Queues a batch of watch events, e.g. `json!({"type": "MODIFIED", "object": object})`.
+    pub fn queue_watch_events(&self, events: Vec<Value>) {
+        self.watch_events.lock().unwrap().push_back(events);
+    }
+
+    /// Returns a client whose requests are answered by this API.
+    pub fn client(&self) -> Client {
+        let (service, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let api = self.clone();
+        tokio::spawn(async move {
+            // Watches without queued events, kept open until the client is dropped
+            let mut open_watches = Vec::new();
+            while let Some((request, send)) = handle.next_request().await {
+                let is_watch = request
+                    .uri()
+                    .query()
+                    .is_some_and(|query| query.contains("watch=true"));
+                if is_watch {
+                    match api.watch_events.lock().unwrap().pop_front() {
+                        Some(events) => send.send_response(watch_response(&events)),
+                        None => open_watches.push(send),
+                    }
+                } else {
+                    send.send_response(api.respond(request.uri().path()));
+                }
+            }
+        });
+        Client::new(service, "default")
+    }
+
+    fn respond(&self, path: &str) -> Response<Body> {
+        let objects = self.objects.lock().unwrap();
+        let name = path.rsplit('/').next().unwrap_or_default();
+        if name == self.plural {
+            return json_response(
+                StatusCode::OK,
+                &json!({
+                    "apiVersion": "v1",
+                    "kind": "List",
+                    "metadata": {"resourceVersion": "1"},
+                    "items": *objects,
+                }),
+            );
+        }
+        match objects
+            .iter()
+            .find(|object| object["metadata"]["name"] == name)
+        {
+            Some(object) => json_response(StatusCode::OK, object),
+            None => json_response(
+                StatusCode::NOT_FOUND,
+                &json!({
+                    "apiVersion": "v1",
+                    "kind": "Status",
+                    "metadata": {},
+                    "status": "Failure",
+                    "message": format!("{} \"{}\" not found", self.plural, name),
+                    "reason": "NotFound",
+                    "code": 404,
+                }),
+            ),
+        }
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(body).unwrap()))
+        .unwrap()
+}
+
+/// Watch events are sent as one JSON object per line.
+fn watch_response(events: &[Value]) -> Response<Body> {
+    let lines: Vec<String> = events.iter().map(Value::to_string).collect();
+    Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from(lines.join("\n").into_bytes()))
+        .unwrap()
+}
@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+#[cfg(feature = "configmap")]
+pub mod mock_api;
+
+use std::path::PathBuf;
+pub fn tmpdir(name: &str) -> PathBuf {
+    let p = std::env::temp_dir().join(format!("cw-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&p);
+    std::fs::create_dir_all(&p).unwrap();
+    p
+}
+pub async fn collect<T>(rx: &mut tokio::sync::mpsc::Receiver<T>, ms: u64) -> Vec<T> {
+    let mut v = vec![];
+    while let Ok(Some(e)) =
+        tokio::time::timeout(std::time::Duration::from_millis(ms), rx.recv()).await
+    {
+        v.push(e);
+    }
+    v
+}
+/// Waits until `count` timers are waiting on `clock`.
+pub async fn wait_for_sleeps(clock: &config_watcher::clock::TestClock, count: usize) {
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while clock.pending_sleeps() != count {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the watcher did not set its timer");
+}
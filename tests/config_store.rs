@@ -0,0 +1,60 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::store::ConfigStore;
+use config_watcher::*;
+use std::time::Duration;
+
+fn items(store: &ConfigStore<String>) -> Vec<String> {
+    let mut items: Vec<String> = store.iter().map(|(_, item)| item).collect();
+    items.sort();
+    items
+}
+
+async fn wait_for(store: &mut ConfigStore<String>, expected: &[&str]) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while items(store) != expected {
+            store.changed().await.unwrap();
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("store has {:?}, expected {expected:?}", items(store)));
+}
+
+#[tokio::test]
+async fn store_follows_the_items_of_the_watcher() {
+    let dir = common::tmpdir("config-store");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    let mut store = ConfigStore::new(rx);
+    assert!(store.is_empty());
+    handle.start().await.unwrap();
+    wait_for(&mut store, &["one", "two"]).await;
+    let (hash, _) = store.iter().find(|(_, item)| item == "one").unwrap();
+    assert!(store.contains(&hash));
+    assert_eq!(store.get(&hash).as_deref(), Some("one"));
+
+    std::fs::write(dir.join("a.yaml"), "two\n---\nthree").unwrap();
+    wait_for(&mut store, &["three", "two"]).await;
+    assert!(!store.contains(&hash));
+
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    wait_for(&mut store, &[]).await;
+    assert_eq!(store.len(), 0);
+
+    // The version ends with the event stream
+    handle.stop().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while store.changed().await.is_ok() {}
+    })
+    .await
+    .unwrap();
+}
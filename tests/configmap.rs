@@ -0,0 +1,453 @@
+#![cfg(feature = "configmap")]
+
+mod common;
+
+use common::mock_api::MockApi;
+use config_watcher::backend::*;
+use futures::channel::mpsc::unbounded;
+use k8s_openapi::{
+    api::core::v1::ConfigMap, apimachinery::pkg::apis::meta::v1::ObjectMeta, ByteString,
+};
+use kube::runtime::watcher::Event;
+use std::{collections::BTreeMap, time::Duration};
+
+fn configmap(name: &str, data: &[(&str, &str)], binary_data: &[(&str, &[u8])]) -> ConfigMap {
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            ..Default::default()
+        },
+        data: Some(
+            data.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+        binary_data: Some(
+            binary_data
+                .iter()
+                .map(|(key, value)| (key.to_string(), ByteString(value.to_vec())))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn relisting_after_a_restart_reports_removed_keys() {
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_configmap_watcher_from_stream(
+        "config".to_string(),
+        stream,
+        ConfigMapWatcherOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::ConfigMap);
+    handle.start().await.unwrap();
+
+    events.unbounded_send(Event::Init).unwrap();
+    events
+        .unbounded_send(Event::InitApply(configmap(
+            "config",
+            &[("a", "v: 1"), ("b", "v: 1")],
+            &[],
+        )))
+        .unwrap();
+    events.unbounded_send(Event::InitDone).unwrap();
+    let events_before = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events_before.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, _),
+                DocumentEvent::NewDocument(b, _),
+                DocumentEvent::InitialScanComplete,
+            ] if a == "a" && b == "b"
+        ),
+        "{events_before:?}"
+    );
+
+    // The watch restarts and "b" was removed while it was down
+    events.unbounded_send(Event::Init).unwrap();
+    events
+        .unbounded_send(Event::InitApply(configmap("config", &[("a", "v: 1")], &[])))
+        .unwrap();
+    // Nothing is emitted before the listing is complete
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    events.unbounded_send(Event::InitDone).unwrap();
+    let events_after = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events_after.as_slice(), [DocumentEvent::DocumentRemoved(b)] if b == "b"),
+        "{events_after:?}"
+    );
+
+    // A restart that does not list the ConfigMap at all removes every key
+    events.unbounded_send(Event::Init).unwrap();
+    events.unbounded_send(Event::InitDone).unwrap();
+    let events_after = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events_after.as_slice(), [DocumentEvent::DocumentRemoved(a)] if a == "a"),
+        "{events_after:?}"
+    );
+
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn other_configmaps_are_ignored() {
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_configmap_watcher_from_stream(
+        "config".to_string(),
+        stream,
+        ConfigMapWatcherOptions::default(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+
+    events
+        .unbounded_send(Event::Apply(configmap("other", &[("a", "v: 1")], &[])))
+        .unwrap();
+    events
+        .unbounded_send(Event::Delete(configmap("other", &[], &[])))
+        .unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+
+    // The watcher ends with the stream
+    drop(events);
+    assert!(rx.recv().await.is_none());
+    handle.stop().await.unwrap();
+}
+
+async fn start_watcher(
+    options: ConfigMapWatcherOptions,
+) -> (
+    futures::channel::mpsc::UnboundedSender<Event<ConfigMap>>,
+    WatcherHandle,
+    tokio::sync::mpsc::Receiver<DocumentEvent>,
+) {
+    let (events, stream) = unbounded();
+    let (mut handle, rx) =
+        run_configmap_watcher_from_stream("config".to_string(), stream, options).unwrap();
+    handle.start().await.unwrap();
+    (events, handle, rx)
+}
+
+#[tokio::test]
+async fn key_pattern_filters_keys() {
+    let (events, mut handle, mut rx) = start_watcher(ConfigMapWatcherOptions {
+        key_pattern: Some("*.yaml".to_string()),
+        ..Default::default()
+    })
+    .await;
+
+    events
+        .unbounded_send(Event::Apply(configmap(
+            "config",
+            &[
+                ("a.yaml", "v: 1"),
+                ("b.yaml", "v: 1"),
+                ("notes.txt", "hello"),
+            ],
+            &[("c.yaml", b"v: 1"), ("image.png", b"png")],
+        )))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    let new: Vec<&str> = received
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(key, _) => Some(key.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(new, ["a.yaml", "b.yaml", "c.yaml"], "{received:?}");
+
+    // A key renamed so that it no longer matches is reported as removed
+    events
+        .unbounded_send(Event::Apply(configmap(
+            "config",
+            &[
+                ("a.yaml", "v: 1"),
+                ("b.txt", "v: 1"),
+                ("notes.txt", "hello"),
+            ],
+            &[("c.yaml", b"v: 1"), ("image.png", b"png")],
+        )))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::DocumentRemoved(b)] if b == "b.yaml"),
+        "{received:?}"
+    );
+
+    handle.stop().await.unwrap();
+}
+
+#[test]
+fn invalid_key_pattern_is_rejected() {
+    let (_events, stream) = unbounded::<Event<ConfigMap>>();
+    assert!(run_configmap_watcher_from_stream(
+        "config".to_string(),
+        stream,
+        ConfigMapWatcherOptions {
+            key_pattern: Some("[".to_string()),
+            ..Default::default()
+        },
+    )
+    .is_err());
+}
+
+async fn initial_documents(
+    options: ConfigMapWatcherOptions,
+    cm: ConfigMap,
+) -> (
+    futures::channel::mpsc::UnboundedSender<Event<ConfigMap>>,
+    WatcherHandle,
+    tokio::sync::mpsc::Receiver<DocumentEvent>,
+    Vec<DocumentEvent>,
+) {
+    let (events, handle, mut rx) = start_watcher(options).await;
+    events.unbounded_send(Event::Apply(cm)).unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    (events, handle, rx, received)
+}
+
+#[tokio::test]
+async fn key_collisions_follow_the_policy() {
+    let colliding = || {
+        configmap(
+            "config",
+            &[("app", "from: data"), ("other", "v: 1")],
+            &[("app", b"from: binary")],
+        )
+    };
+
+    for (policy, expected) in [
+        (KeyCollisionPolicy::PreferData, "from: data"),
+        (KeyCollisionPolicy::PreferBinaryData, "from: binary"),
+    ] {
+        let (_events, mut handle, _rx, received) = initial_documents(
+            ConfigMapWatcherOptions {
+                key_collisions: policy,
+                ..Default::default()
+            },
+            colliding(),
+        )
+        .await;
+        assert!(
+            matches!(
+                received.as_slice(),
+                [DocumentEvent::NewDocument(app, content), DocumentEvent::NewDocument(other, _)]
+                    if app == "app" && content == expected && other == "other"
+            ),
+            "{policy:?}: {received:?}"
+        );
+        handle.stop().await.unwrap();
+    }
+
+    // Skip emits neither value until the collision is resolved
+    let (events, mut handle, mut rx, received) = initial_documents(
+        ConfigMapWatcherOptions {
+            key_collisions: KeyCollisionPolicy::Skip,
+            ..Default::default()
+        },
+        colliding(),
+    )
+    .await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::NewDocument(other, _)] if other == "other"),
+        "{received:?}"
+    );
+    events
+        .unbounded_send(Event::Apply(configmap(
+            "config",
+            &[("app", "from: data"), ("other", "v: 1")],
+            &[],
+        )))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::NewDocument(app, content)] if app == "app" && content == "from: data"
+        ),
+        "{received:?}"
+    );
+    // A collision of a tracked key removes it
+    events.unbounded_send(Event::Apply(colliding())).unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::DocumentRemoved(app)] if app == "app"),
+        "{received:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn invalid_binary_data_follows_the_policy() {
+    let invalid = || configmap("config", &[], &[("bin", &[0xff, 0xfe])]);
+
+    // Skip treats the key as absent
+    let (_events, mut handle, _rx, received) = initial_documents(
+        ConfigMapWatcherOptions {
+            invalid_binary_data: BinaryDataPolicy::Skip,
+            ..Default::default()
+        },
+        invalid(),
+    )
+    .await;
+    assert!(received.is_empty(), "{received:?}");
+    handle.stop().await.unwrap();
+
+    // Base64 emits the encoded value
+    let (_events, mut handle, _rx, received) = initial_documents(
+        ConfigMapWatcherOptions {
+            invalid_binary_data: BinaryDataPolicy::Base64,
+            ..Default::default()
+        },
+        invalid(),
+    )
+    .await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::NewDocument(bin, content)] if bin == "bin" && content == "//4="
+        ),
+        "{received:?}"
+    );
+    handle.stop().await.unwrap();
+
+    // Error reports the value once
+    let (events, mut handle, mut rx, received) = initial_documents(
+        ConfigMapWatcherOptions {
+            invalid_binary_data: BinaryDataPolicy::Error,
+            ..Default::default()
+        },
+        invalid(),
+    )
+    .await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::ReadFailed(
+                bin,
+                config_watcher::WatcherError::InvalidBinaryData { configmap, key, .. }
+            )] if bin == "bin" && configmap == "config" && key == "bin"
+        ),
+        "{received:?}"
+    );
+    events.unbounded_send(Event::Apply(invalid())).unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(received.is_empty(), "{received:?}");
+    handle.stop().await.unwrap();
+}
+
+#[test]
+fn namespace_is_detected_from_the_service_account_or_the_environment() {
+    let dir = common::tmpdir("configmap-namespace");
+    let namespace_file = dir.join("namespace");
+
+    // Without the file, POD_NAMESPACE is used
+    assert_eq!(
+        detect_namespace(&namespace_file, Some("from-env\n".to_string())).unwrap(),
+        "from-env"
+    );
+
+    // Neither is available
+    assert!(matches!(
+        detect_namespace(&namespace_file, None),
+        Err(config_watcher::WatcherError::NamespaceUnavailable(path)) if path == namespace_file
+    ));
+    assert!(matches!(
+        detect_namespace(&namespace_file, Some(" ".to_string())),
+        Err(config_watcher::WatcherError::NamespaceUnavailable(_))
+    ));
+
+    // The service account file takes precedence, unless it is empty
+    std::fs::write(&namespace_file, "from-file\n").unwrap();
+    assert_eq!(
+        detect_namespace(&namespace_file, Some("from-env".to_string())).unwrap(),
+        "from-file"
+    );
+    assert_eq!(
+        detect_namespace(&namespace_file, None).unwrap(),
+        "from-file"
+    );
+    std::fs::write(&namespace_file, "").unwrap();
+    assert_eq!(
+        detect_namespace(&namespace_file, Some("from-env".to_string())).unwrap(),
+        "from-env"
+    );
+}
+
+#[tokio::test]
+async fn fetch_once_returns_the_initial_documents() {
+    let api = MockApi::new("configmaps");
+    let cm = configmap(
+        "config",
+        &[("a.yaml", "v: 1")],
+        &[("b.yaml", b"v: 2"), ("bin", &[0xff, 0xfe])],
+    );
+    api.set_objects(vec![serde_json::to_value(cm).unwrap()]);
+
+    // Binary data that is not valid UTF-8 is skipped
+    let documents = fetch_configmap_once_with_client(api.client(), "config", "default")
+        .await
+        .unwrap();
+    assert_eq!(
+        documents,
+        [
+            ("a.yaml".to_string(), "v: 1".to_string()),
+            ("b.yaml".to_string(), "v: 2".to_string()),
+        ]
+    );
+
+    let missing = fetch_configmap_once_with_client(api.client(), "missing", "default").await;
+    assert!(
+        matches!(missing, Err(config_watcher::WatcherError::KubeError(_))),
+        "{missing:?}"
+    );
+}
+
+#[tokio::test]
+async fn resync_catches_changes_the_watch_missed() {
+    let api = MockApi::new("configmaps");
+    let cm = configmap("config", &[("a", "v: 1"), ("b", "v: 1")], &[]);
+    api.set_objects(vec![serde_json::to_value(cm).unwrap()]);
+    let (mut handle, mut rx) = run_configmap_watcher_with_client(
+        api.client(),
+        "config".to_string(),
+        "default".to_string(),
+        ConfigMapWatcherOptions {
+            resync_interval: Some(Duration::from_millis(200)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, _),
+                DocumentEvent::NewDocument(b, _),
+                DocumentEvent::InitialScanComplete,
+            ] if a == "a" && b == "b"
+        ),
+        "{received:?}"
+    );
+
+    // The watch stays open without reporting the change
+    let cm = configmap("config", &[("a", "v: 2")], &[]);
+    api.set_objects(vec![serde_json::to_value(cm).unwrap()]);
+    let received = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::ContentChanged(a, content), DocumentEvent::DocumentRemoved(b)]
+                if a == "a" && content == "v: 2" && b == "b"
+        ),
+        "{received:?}"
+    );
+
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn filtered_documents_produce_no_items_and_lose_their_items() {
+    let dir = common::tmpdir("content-filter");
+    std::fs::write(dir.join("a.yaml"), "kind: A\n---\nkind: B").unwrap();
+    std::fs::write(dir.join("b.yaml"), "kind: Other").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            content_filter: Some(Arc::new(|_id, content| !content.contains("Other"))),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let mut items: Vec<String> = common::collect(&mut rx, 300)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, ["kind: A", "kind: B"]);
+
+    // A document that stops matching reports its items as removed
+    std::fs::write(dir.join("a.yaml"), "kind: A\n---\nkind: Other").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let removed = events
+        .iter()
+        .filter(|event| matches!(event, ConfigItemEvent::Removed(_)))
+        .count();
+    assert_eq!(removed, 2, "{events:?}");
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(..))),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
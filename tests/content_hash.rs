@@ -0,0 +1,35 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[test]
+fn content_hashes_are_the_hash_of_the_content() {
+    let new = DocumentEvent::NewDocument("a".to_string(), "v: 1".to_string());
+    let changed = DocumentEvent::ContentChanged("b".to_string(), "v: 1".to_string());
+    assert_eq!(new.content_hash(), Some(hash_str("v: 1")));
+    assert_eq!(new.content_hash(), changed.content_hash());
+    assert_eq!(
+        DocumentEvent::DocumentRemoved("a".to_string()).content_hash(),
+        None
+    );
+    assert_eq!(DocumentEvent::InitialScanComplete.content_hash(), None);
+}
+
+#[tokio::test]
+async fn equal_content_of_different_backends_has_equal_hashes() {
+    let dir = common::tmpdir("content-hash");
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let (mut file, mut file_rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    let (mut reader, mut reader_rx) = run_reader_watcher("doc", &b"v: 1"[..], false).unwrap();
+    file.start().await.unwrap();
+    reader.start().await.unwrap();
+    let from_file = common::collect(&mut file_rx, 300).await;
+    let from_reader = common::collect(&mut reader_rx, 300).await;
+    assert_eq!(from_file[0].content_hash(), Some(hash_str("v: 1")));
+    assert_eq!(from_file[0].content_hash(), from_reader[0].content_hash());
+    file.stop().await.unwrap();
+    reader.stop().await.unwrap();
+}
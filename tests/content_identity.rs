@@ -0,0 +1,55 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn item_events(events: &[ConfigItemEvent<String>]) -> Vec<String> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(hash, item) => {
+                assert_eq!(hash.filename_hash(), 0);
+                Some(format!("new {item}"))
+            }
+            ConfigItemEvent::Removed(hash) => {
+                assert_eq!(hash.filename_hash(), 0);
+                Some("removed".to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn identical_items_are_reported_once_until_the_last_document_drops_them() {
+    let dir = common::tmpdir("content-identity");
+    std::fs::write(dir.join("a.yaml"), "shared\n---\nonly-a").unwrap();
+    std::fs::write(dir.join("b.yaml"), "shared").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            identity: ConfigIdentity::Content,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let mut initial = item_events(&common::collect(&mut rx, 300).await);
+    initial.sort();
+    assert_eq!(initial, ["new only-a", "new shared"]);
+
+    // Still contained in a.yaml
+    std::fs::remove_file(dir.join("b.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(item_events(&events).is_empty(), "{events:?}");
+
+    std::fs::write(dir.join("a.yaml"), "only-a").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(item_events(&events), ["removed"]);
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+
+#[test]
+fn content_is_classified_heuristically() {
+    assert_eq!(ContentType::detect("  {\"a\": 1}"), Some(ContentType::Json));
+    assert_eq!(
+        ContentType::detect("# comment\n\nkey: value"),
+        Some(ContentType::Yaml)
+    );
+    assert_eq!(ContentType::detect("- item"), Some(ContentType::Yaml));
+    assert_eq!(ContentType::detect("---\na: 1"), Some(ContentType::Yaml));
+    assert_eq!(ContentType::detect("just some text"), None);
+    assert_eq!(ContentType::detect("# only a comment"), None);
+}
+
+#[tokio::test]
+async fn files_are_selected_by_their_content() {
+    let dir = common::tmpdir("content-type-filter");
+    std::fs::write(dir.join("config"), "key: value").unwrap();
+    std::fs::write(dir.join("notes"), "just some text").unwrap();
+    std::fs::write(dir.join("image"), [0x89, b'P', b'N', b'G', 0, 0xff, 0xfe]).unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher_with_options(
+        &dir,
+        "*",
+        ConfigFileWatcherOptions {
+            content_type_filter: Some(ContentType::Yaml),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    // The binary file is skipped without an error
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::NewDocument(id, _), DocumentEvent::InitialScanComplete]
+                if id.ends_with("/config")
+        ),
+        "{events:?}"
+    );
+
+    // A tracked file that is no longer YAML is removed, a file that becomes YAML is new
+    std::fs::write(dir.join("config"), "no longer yaml").unwrap();
+    std::fs::write(dir.join("notes"), "[1, 2]").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert!(events.iter().any(
+        |event| matches!(event, DocumentEvent::DocumentRemoved(id) if id.ends_with("/config"))
+    ));
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, DocumentEvent::NewDocument(id, _) if id.ends_with("/notes"))));
+    assert!(!handle.is_finished());
+    handle.stop().await.unwrap();
+}
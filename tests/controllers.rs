@@ -0,0 +1,90 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+fn document_id(dir: &std::path::Path) -> String {
+    dir.canonicalize()
+        .unwrap()
+        .join("a.yaml")
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[tokio::test]
+async fn cloned_controller_stops_the_backend() {
+    let dir = common::tmpdir("controllers-backend");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    let (controller, mut join) = handle.into_parts();
+    let clone = controller.clone();
+    controller.start().await.unwrap();
+    assert_eq!(
+        clone.load_document(&document_id(&dir)).await.unwrap(),
+        "v: 0"
+    );
+    common::collect(&mut rx, 300).await;
+
+    tokio::spawn(async move { clone.stop().await })
+        .await
+        .unwrap();
+    assert!(controller.is_finished());
+    assert!(join.is_finished());
+    join.join().await.unwrap();
+    // Stopping a stopped watcher succeeds
+    controller.stop().await;
+    join.join().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_the_first_join_returns_the_result() {
+    let dir = common::tmpdir("controllers-join");
+    let (handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    let (controller, mut join) = handle.into_parts();
+    controller.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // A read error ends the watcher
+    std::fs::write(dir.join("a.yaml"), "v: \0").unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    controller.stop().await;
+    assert!(matches!(
+        join.join().await,
+        Err(WatcherError::BinaryFile(_))
+    ));
+    join.join().await.unwrap();
+}
+
+#[tokio::test]
+async fn cloned_item_controller_stops_the_item_watcher() {
+    let dir = common::tmpdir("controllers-items");
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    let controller = handle.controller();
+    controller.clone().start().await.unwrap();
+    assert!(controller.wait_ready().await);
+
+    tokio::spawn(async move { controller.stop().await })
+        .await
+        .unwrap();
+    assert!(handle.is_finished());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::ConfigFileWatcherOptions;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn file_constructor_starts_and_stops_backend_and_item_watcher_together() {
+    let dir = common::tmpdir("convenience-file");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    std::fs::write(dir.join("a.json"), "{}").unwrap();
+    let (mut handle, mut rx) = ConfigItemWatcher::file(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions::default(),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let items: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(items, ["one"]);
+
+    handle.stop().await.unwrap();
+    // The event channel is closed once both tasks have ended
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn file_constructor_passes_the_backend_options_on() {
+    let dir = common::tmpdir("convenience-file-options");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    let (mut handle, mut rx) = ConfigItemWatcher::file(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions {
+            debounce: Some(Duration::from_millis(1000)),
+            ..Default::default()
+        },
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // The change is held back for the configured debounce
+    std::fs::write(dir.join("a.yaml"), "two").unwrap();
+    assert!(common::collect(&mut rx, 500).await.is_empty());
+    let events = common::collect(&mut rx, 1500).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, item) if item == "two")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
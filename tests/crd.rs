@@ -0,0 +1,73 @@
+#![cfg(feature = "configmap")]
+
+mod common;
+
+use common::mock_api::MockApi;
+use config_watcher::backend::*;
+use kube::CustomResource;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize)]
+#[kube(
+    group = "example.com",
+    version = "v1",
+    kind = "AppConfig",
+    namespaced,
+    schema = "disabled"
+)]
+struct AppConfigSpec {
+    value: u32,
+}
+
+fn app_config(name: &str, resource_version: &str, value: u32) -> Value {
+    let mut resource = AppConfig::new(name, AppConfigSpec { value });
+    resource.metadata.resource_version = Some(resource_version.to_string());
+    serde_json::to_value(resource).unwrap()
+}
+
+#[tokio::test]
+async fn resources_are_emitted_with_their_spec() {
+    let api = MockApi::new("appconfigs");
+    api.set_objects(vec![app_config("a", "1", 1), app_config("b", "1", 1)]);
+    let mut relabeled = app_config("b", "2", 1);
+    relabeled["metadata"]["labels"] = json!({"team": "ops"});
+    api.queue_watch_events(vec![
+        // Skipped by its known resourceVersion, despite the different spec
+        json!({"type": "MODIFIED", "object": app_config("a", "1", 5)}),
+        // A new resourceVersion with an unchanged spec
+        json!({"type": "MODIFIED", "object": relabeled}),
+        json!({"type": "MODIFIED", "object": app_config("a", "3", 2)}),
+        json!({"type": "DELETED", "object": app_config("b", "4", 1)}),
+    ]);
+
+    let (mut handle, mut rx) = run_crd_watcher_with_client::<AppConfig>(
+        api.client(),
+        "default".to_string(),
+        ResourceSelector::Labels("app=demo".to_string()),
+        CrdWatcherOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::CustomResource);
+    handle.start().await.unwrap();
+
+    let received = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, a_content),
+                DocumentEvent::NewDocument(b, b_content),
+                DocumentEvent::InitialScanComplete,
+                DocumentEvent::ContentChanged(changed, content),
+                DocumentEvent::DocumentRemoved(removed),
+            ] if a == "a" && a_content == r#"{"value":1}"#
+                && b == "b" && b_content == r#"{"value":1}"#
+                && changed == "a" && content == r#"{"value":2}"#
+                && removed == "b"
+        ),
+        "{received:?}"
+    );
+
+    handle.stop().await.unwrap();
+}
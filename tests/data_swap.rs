@@ -0,0 +1,46 @@
+#![cfg(all(feature = "file", unix))]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+/// Publishes `content` as `a.yaml` the way the kubelet updates a ConfigMap volume: in a new
+/// timestamped directory that the `..data` link is atomically switched to.
+fn publish(dir: &Path, version: &str, content: &str) {
+    let timestamped = format!("..{version}");
+    std::fs::create_dir(dir.join(&timestamped)).unwrap();
+    std::fs::write(dir.join(&timestamped).join("a.yaml"), content).unwrap();
+    symlink(&timestamped, dir.join("..data_tmp")).unwrap();
+    std::fs::rename(dir.join("..data_tmp"), dir.join("..data")).unwrap();
+    if !dir.join("a.yaml").exists() {
+        symlink("..data/a.yaml", dir.join("a.yaml")).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn swapped_data_directories_update_their_keys() {
+    let dir = common::tmpdir("data-swap");
+    publish(&dir, "2024_01", "v: 1");
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    // The timestamped copy is not a document of its own
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::NewDocument(id, content), DocumentEvent::InitialScanComplete]
+                if id.ends_with("/a.yaml") && !id.contains("..") && content == "v: 1"
+        ),
+        "{events:?}"
+    );
+
+    publish(&dir, "2024_02", "v: 2");
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(id, content)] if !id.contains("..") && content == "v: 2"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn continuous_writes_are_processed_after_max_wait() {
+    let dir = common::tmpdir("debounce-max-wait");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "v: 0").unwrap();
+    let options = ConfigFileWatcherOptions {
+        debounce: Some(Duration::from_millis(300)),
+        debounce_max_wait: Some(Duration::from_millis(600)),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    assert!(matches!(
+        initial.last(),
+        Some(DocumentEvent::InitialScanComplete)
+    ));
+
+    // Writes every 100 ms never pause for the debounce, but must be processed within max_wait
+    let started = Instant::now();
+    let mut changed_after = None;
+    for i in 1..=20 {
+        std::fs::write(&file, format!("v: {i}")).unwrap();
+        if let Ok(Some(DocumentEvent::ContentChanged(..))) =
+            tokio::time::timeout(Duration::from_millis(100), rx.recv()).await
+        {
+            changed_after = Some(started.elapsed());
+            break;
+        }
+    }
+    let changed_after = changed_after.expect("no change emitted while writes continued");
+    assert!(
+        changed_after < Duration::from_millis(1500),
+        "{changed_after:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn full_buffer_is_processed_immediately() {
+    let dir = common::tmpdir("debounce-max-events");
+    let options = ConfigFileWatcherOptions {
+        debounce: Some(Duration::from_secs(60)),
+        debounce_max_events: 1,
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, DocumentEvent::NewDocument(..))),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
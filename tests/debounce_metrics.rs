@@ -0,0 +1,56 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn debounce_coalesces_flooded_events() {
+    let dir = common::tmpdir("debounce-metrics");
+    let (mut handle, mut rx) = run_config_file_watcher_with_options(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions {
+            debounce: Some(Duration::from_millis(200)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    let initial = handle.debounce_metrics().await.unwrap();
+
+    for i in 0..50 {
+        std::fs::write(dir.join(format!("{i}.yaml")), format!("v: {i}")).unwrap();
+    }
+    let events = common::collect(&mut rx, 1000).await;
+    assert_eq!(events.len(), 50);
+
+    let metrics = handle.debounce_metrics().await.unwrap();
+    let raw_events = metrics.raw_events_total - initial.raw_events_total;
+    let batches = metrics.debounced_batches_total - initial.debounced_batches_total;
+    assert!(raw_events >= 50, "{metrics:?}");
+    assert!(batches >= 1 && batches < raw_events, "{metrics:?}");
+    assert_eq!(metrics.internal_channel_depth, 0);
+
+    handle.stop().await.unwrap();
+    assert!(handle.debounce_metrics().await.is_none());
+}
+
+#[tokio::test]
+async fn without_a_debounce_every_event_is_a_batch() {
+    let dir = common::tmpdir("debounce-metrics-none");
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    for i in 0..10 {
+        std::fs::write(dir.join(format!("{i}.yaml")), format!("v: {i}")).unwrap();
+    }
+    common::collect(&mut rx, 500).await;
+    let metrics = handle.debounce_metrics().await.unwrap();
+    assert!(metrics.raw_events_total >= 10, "{metrics:?}");
+    assert_eq!(metrics.debounced_batches_total, metrics.raw_events_total);
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+async fn debounced(
+    name: &str,
+) -> (
+    WatcherHandle,
+    tokio::sync::mpsc::Receiver<DocumentEvent>,
+    std::path::PathBuf,
+) {
+    let dir = common::tmpdir(name);
+    let options = ConfigFileWatcherOptions {
+        debounce: Some(Duration::from_millis(300)),
+        ..Default::default()
+    };
+    let (handle, mut rx) = run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    (handle, rx, dir)
+}
+
+#[tokio::test]
+async fn a_file_moved_in_and_modified_is_read_once() {
+    let (mut handle, mut rx, dir) = debounced("debounced-batch-move").await;
+    let outside = common::tmpdir("debounced-batch-outside");
+    std::fs::write(outside.join("a.yaml"), "v: 0").unwrap();
+    std::fs::rename(outside.join("a.yaml"), dir.join("a.yaml")).unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(_, content)] if content == "v: 1"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_file_created_and_removed_within_the_window_is_not_reported() {
+    let (mut handle, mut rx, dir) = debounced("debounced-batch-transient").await;
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(events.is_empty(), "{events:?}");
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::Mutex;
+
+/// Collects the diff summary lines logged by the item watcher.
+struct SummaryLogger(Mutex<Vec<String>>);
+
+impl log::Log for SummaryLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        let message = record.args().to_string();
+        if message.starts_with("Items of document") {
+            self.0.lock().unwrap().push(message);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: SummaryLogger = SummaryLogger(Mutex::new(Vec::new()));
+
+fn take_summaries() -> Vec<String> {
+    std::mem::take(&mut *LOGGER.0.lock().unwrap())
+}
+
+#[tokio::test]
+async fn diff_summary_counts_added_removed_and_unchanged_items() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+
+    let dir = common::tmpdir("diff-summary");
+    std::fs::write(dir.join("a.yaml"), "a: 1\n---\nb: 1").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            log_diff_summary: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    let summaries = take_summaries();
+    assert_eq!(summaries.len(), 1, "{summaries:?}");
+    assert!(
+        summaries[0].ends_with("{added: 2, removed: 0, unchanged: 0}"),
+        "{summaries:?}"
+    );
+
+    // One item is kept, one replaced and one added in the same update
+    std::fs::write(dir.join("a.yaml"), "a: 1\n---\nc: 1\n---\nd: 1").unwrap();
+    common::collect(&mut rx, 500).await;
+    let summaries = take_summaries();
+    assert_eq!(summaries.len(), 1, "{summaries:?}");
+    assert!(
+        summaries[0].ends_with("{added: 2, removed: 1, unchanged: 1}"),
+        "{summaries:?}"
+    );
+    handle.stop().await.unwrap();
+}
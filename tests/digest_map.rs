@@ -0,0 +1,43 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::collections::HashMap;
+
+#[tokio::test]
+async fn digest_map_follows_edits_and_removals() {
+    let dir = common::tmpdir("digest-map");
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    std::fs::write(dir.join("b.yaml"), "2\n---\n3").unwrap();
+    let root = dir.canonicalize().unwrap();
+    let a = root.join("a.yaml").to_string_lossy().into_owned();
+    let b = root.join("b.yaml").to_string_lossy().into_owned();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(handle.wait_ready().await);
+    common::collect(&mut rx, 300).await;
+    assert_eq!(
+        handle.digest_map(),
+        HashMap::from([
+            (a.clone(), hash_str("1")),
+            (b.clone(), hash_str("2\n---\n3"))
+        ])
+    );
+
+    std::fs::write(dir.join("a.yaml"), "4").unwrap();
+    common::collect(&mut rx, 500).await;
+    assert_eq!(handle.digest_map()[&a], hash_str("4"));
+
+    std::fs::remove_file(dir.join("b.yaml")).unwrap();
+    common::collect(&mut rx, 500).await;
+    assert_eq!(handle.digest_map(), HashMap::from([(a, hash_str("4"))]));
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::path::Path;
+
+/// Returns the ids of new documents relative to `dir`, sorted.
+fn new_documents(dir: &Path, events: &[DocumentEvent]) -> Vec<String> {
+    let root = dir.canonicalize().unwrap();
+    let mut paths: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) => Some(
+                Path::new(id)
+                    .strip_prefix(&root)
+                    .ok()?
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            _ => None,
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn write(dir: &Path, path: &str) {
+    let path = dir.join(path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, "v: 0").unwrap();
+}
+
+#[tokio::test]
+async fn double_star_matches_any_number_of_directories() {
+    let dir = common::tmpdir("directory-patterns");
+    for path in [
+        "secrets/a.yaml",
+        "app/secrets/b.yaml",
+        "app/prod/secrets/c.yaml",
+        "secrets/nested/d.yaml",
+        "mysecrets/e.yaml",
+        "app/secrets/f.json",
+        "g.yaml",
+    ] {
+        write(&dir, path);
+    }
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "**/secrets/*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(
+        new_documents(&dir, &events),
+        [
+            "app/prod/secrets/c.yaml",
+            "app/secrets/b.yaml",
+            "secrets/a.yaml"
+        ]
+    );
+
+    // Events are matched the same way
+    write(&dir, "x/y/z/secrets/h.yaml");
+    write(&dir, "x/secrets/y/i.yaml");
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(new_documents(&dir, &events), ["x/y/z/secrets/h.yaml"]);
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn wildcards_in_patterns_with_a_slash_match_one_segment() {
+    let dir = common::tmpdir("directory-patterns-segment");
+    for path in ["sub/a.yaml", "sub/deep/b.yaml", "other/c.yaml", "d.yaml"] {
+        write(&dir, path);
+    }
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*/*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_documents(&dir, &events), ["other/c.yaml", "sub/a.yaml"]);
+    handle.stop().await.unwrap();
+
+    // Without a slash, the pattern matches files at any depth
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(
+        new_documents(&dir, &events),
+        ["d.yaml", "other/c.yaml", "sub/a.yaml", "sub/deep/b.yaml"]
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+
+#[tokio::test]
+async fn renamed_directories_move_their_files() {
+    let dir = common::tmpdir("directory-rename");
+    std::fs::create_dir(dir.join("old")).unwrap();
+    std::fs::write(dir.join("old/a.yaml"), "v: 0").unwrap();
+    let root = dir.canonicalize().unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    let old_id = root.join("old/a.yaml").to_string_lossy().into_owned();
+    assert!(
+        initial
+            .iter()
+            .any(|event| matches!(event, DocumentEvent::NewDocument(id, _) if *id == old_id)),
+        "{initial:?}"
+    );
+
+    std::fs::rename(dir.join("old"), dir.join("new")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let new_id = root.join("new/a.yaml").to_string_lossy().into_owned();
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, DocumentEvent::DocumentRemoved(id) if *id == old_id)),
+        "{events:?}"
+    );
+    assert!(
+        events.iter().any(
+            |event| matches!(event, DocumentEvent::NewDocument(id, content) if *id == new_id && content == "v: 0")
+        ),
+        "{events:?}"
+    );
+
+    // Files below the renamed directory are watched at their new location
+    std::fs::write(dir.join("new/a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        events.iter().any(
+            |event| matches!(event, DocumentEvent::ContentChanged(id, content) if *id == new_id && content == "v: 1")
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn directories_renamed_away_remove_their_files() {
+    let dir = common::tmpdir("directory-rename-away");
+    let outside = common::tmpdir("directory-rename-outside");
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub/a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::rename(dir.join("sub"), outside.join("sub")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id.ends_with("sub/a.yaml")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn content_changes_open_with_document_changed() {
+    let dir = common::tmpdir("document-changed");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    let ConfigItemEvent::NewDocument(document, _) = initial[0] else {
+        panic!("{initial:?}");
+    };
+
+    std::fs::write(dir.join("a.yaml"), "one\n---\nthree").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [ConfigItemEvent::DocumentChanged(changed), ConfigItemEvent::Removed(_), ConfigItemEvent::New(_, item)]
+                | [ConfigItemEvent::DocumentChanged(changed), ConfigItemEvent::New(_, item), ConfigItemEvent::Removed(_)]
+                if *changed == document && item == "three"
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn changes_without_item_changes_are_reported() {
+    let dir = common::tmpdir("document-changed-items-kept");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // Items are trimmed, so the trailing newline changes the document but not its item
+    std::fs::write(dir.join("a.yaml"), "one\n").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [ConfigItemEvent::DocumentChanged(_)]),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn unreadable_document_keeps_its_items_until_it_is_read_again() {
+    let dir = common::tmpdir("document-error");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "1\n---\n2").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || {
+            run_config_file_watcher_with_options(
+                &watch_dir,
+                "*.yaml",
+                ConfigFileWatcherOptions {
+                    read_failure_limit: Some(3),
+                    ..Default::default()
+                },
+            )
+        },
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let filename_hash = match events.first() {
+        Some(ConfigItemEvent::NewDocument(filename_hash, _)) => *filename_hash,
+        _ => panic!("{events:?}"),
+    };
+
+    std::fs::write(&file, "1\n---\n\0").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    // Reported once per read of the file, without removing its items
+    assert!(!events.is_empty());
+    assert!(
+        events.iter().all(|event| matches!(
+            event,
+            ConfigItemEvent::DocumentError { filename_hash: hash, error: WatcherError::BinaryFile(_) }
+                if *hash == filename_hash
+        )),
+        "{events:?}"
+    );
+
+    // The successful read is diffed against the items reported before the error
+    std::fs::write(&file, "1\n---\n3").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, 3))),
+        "{events:?}"
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ConfigItemEvent::Removed(..)))
+            .count(),
+        1,
+        "{events:?}"
+    );
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::New(_, 1))));
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,73 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn item_events_are_framed_by_their_document_events() {
+    let dir = common::tmpdir("document-framing");
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            emit_emptied: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(handle.wait_ready().await);
+
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let filename_hash = match events.as_slice() {
+        [ConfigItemEvent::NewDocument(filename_hash, _), ConfigItemEvent::New(first, _), ConfigItemEvent::New(second, _)] =>
+        {
+            assert_eq!(first.filename_hash(), *filename_hash);
+            assert_eq!(second.filename_hash(), *filename_hash);
+            *filename_hash
+        }
+        events => panic!("{events:?}"),
+    };
+
+    std::fs::write(dir.join("a.yaml"), "2\n---\n3").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [ConfigItemEvent::DocumentChanged(hash), ConfigItemEvent::Removed(_), ConfigItemEvent::New(_, 3)]
+                | [ConfigItemEvent::DocumentChanged(hash), ConfigItemEvent::New(_, 3), ConfigItemEvent::Removed(_)]
+                if *hash == filename_hash
+        ),
+        "{events:?}"
+    );
+
+    std::fs::write(dir.join("a.yaml"), "").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [ConfigItemEvent::DocumentChanged(_), ConfigItemEvent::Removed(_), ConfigItemEvent::Removed(_), ConfigItemEvent::DocumentEmptied(hash)]
+                if *hash == filename_hash
+        ),
+        "{events:?}"
+    );
+
+    std::fs::write(dir.join("a.yaml"), "4").unwrap();
+    common::collect(&mut rx, 500).await;
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [ConfigItemEvent::Removed(item), ConfigItemEvent::RemoveDocument(hash)]
+                if item.filename_hash() == filename_hash && *hash == filename_hash
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
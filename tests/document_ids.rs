@@ -0,0 +1,35 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn document_ids_lists_the_tracked_documents_sorted() {
+    let dir = common::tmpdir("document-ids");
+    for name in ["c.yaml", "a.yaml", "b.yaml"] {
+        std::fs::write(dir.join(name), "item").unwrap();
+    }
+    let root = dir.canonicalize().unwrap();
+    let id = |name: &str| root.join(name).to_string_lossy().into_owned();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    assert!(handle.document_ids().is_empty());
+    handle.start().await.unwrap();
+    handle.wait_ready().await;
+    assert_eq!(
+        handle.document_ids(),
+        [id("a.yaml"), id("b.yaml"), id("c.yaml")]
+    );
+
+    std::fs::remove_file(dir.join("b.yaml")).unwrap();
+    common::collect(&mut rx, 500).await;
+    assert_eq!(handle.document_ids(), [id("a.yaml"), id("c.yaml")]);
+    handle.stop().await.unwrap();
+}
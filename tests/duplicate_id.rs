@@ -0,0 +1,75 @@
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn reader(content: &'static str) -> (WatcherHandle, tokio::sync::mpsc::Receiver<DocumentEvent>) {
+    run_reader_watcher("doc", content.as_bytes(), false).unwrap()
+}
+
+#[tokio::test]
+async fn ids_reported_by_several_sources_keep_the_first_owner() {
+    let (mut handle, mut rx) = run_merged_watcher(vec![
+        ("first".to_string(), reader("v: 1")),
+        ("second".to_string(), reader("v: 2")),
+    ])
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let documents: Vec<&DocumentEvent> = events
+        .iter()
+        .filter(|event| {
+            matches!(
+                event,
+                DocumentEvent::NewDocument(..) | DocumentEvent::ContentChanged(..)
+            )
+        })
+        .collect();
+    assert_eq!(documents.len(), 1, "{events:?}");
+    let DocumentEvent::NewDocument(id, content) = documents[0] else {
+        panic!("{events:?}");
+    };
+    assert_eq!(id, "doc");
+    let owner = if content == "v: 1" { "first" } else { "second" };
+    let other = if owner == "first" { "second" } else { "first" };
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            DocumentEvent::DuplicateId { id, sources } if id == "doc" && *sources == [owner, other]
+        )),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn item_watchers_report_duplicate_ids_as_errors() {
+    let (mut handle, mut rx) = run_config_item_watcher(
+        || {
+            run_merged_watcher(vec![
+                ("first".to_string(), reader("one")),
+                ("second".to_string(), reader("two")),
+            ])
+        },
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            ConfigItemEvent::Error(WatcherError::DuplicateId { id, .. }) if id == "doc"
+        )),
+        "{events:?}"
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ConfigItemEvent::New(..)))
+            .count(),
+        1
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,51 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn emptied_documents_are_reported_after_their_removals() {
+    let dir = common::tmpdir("emit-emptied");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            emit_emptied: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // Replaced items do not empty the document
+    std::fs::write(dir.join("a.yaml"), "three").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::DocumentEmptied(_))));
+
+    std::fs::write(dir.join("a.yaml"), "\n---\n  \n").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [ConfigItemEvent::DocumentChanged(changed), ConfigItemEvent::Removed(_), ConfigItemEvent::DocumentEmptied(emptied)]
+                if changed == emptied
+        ),
+        "{events:?}"
+    );
+
+    // Already empty
+    std::fs::write(dir.join("a.yaml"), "").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::DocumentEmptied(_))));
+    handle.stop().await.unwrap();
+}
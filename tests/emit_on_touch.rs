@@ -0,0 +1,80 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn options(emit_on_touch: bool) -> ConfigFileWatcherOptions {
+    ConfigFileWatcherOptions {
+        emit_on_touch,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn unchanged_writes_are_reported_as_touched() {
+    let dir = common::tmpdir("emit-on-touch");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options(true)).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::Touched(id)] if id.ends_with("a.yaml")),
+        "{events:?}"
+    );
+
+    // A change is still reported as such
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 1"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn unchanged_writes_are_ignored_by_default() {
+    let dir = common::tmpdir("emit-on-touch-default");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options(false)).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    assert!(common::collect(&mut rx, 500).await.is_empty());
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn item_watcher_reports_touched_documents() {
+    let dir = common::tmpdir("emit-on-touch-items");
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher_with_options(&watch_dir, "*.yaml", options(true)),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let filename_hash = match events.first() {
+        Some(ConfigItemEvent::NewDocument(filename_hash, _)) => *filename_hash,
+        _ => panic!("{events:?}"),
+    };
+
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [ConfigItemEvent::DocumentTouched(hash)] if *hash == filename_hash),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
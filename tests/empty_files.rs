@@ -0,0 +1,63 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+
+fn options() -> ConfigFileWatcherOptions {
+    ConfigFileWatcherOptions {
+        ignore_empty_files: true,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn empty_files_are_reported_once_they_have_content() {
+    let dir = common::tmpdir("empty-files");
+    std::fs::write(dir.join("a.yaml"), "").unwrap();
+    std::fs::write(dir.join("b.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options()).unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::NewDocument(id, _), DocumentEvent::InitialScanComplete]
+                if id.ends_with("b.yaml")
+        ),
+        "{events:?}"
+    );
+
+    std::fs::write(dir.join("c.yaml"), "").unwrap();
+    assert!(common::collect(&mut rx, 500).await.is_empty());
+    std::fs::write(dir.join("c.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(id, content)] if id.ends_with("c.yaml") && content == "v: 1"),
+        "{events:?}"
+    );
+
+    // Truncation removes a tracked file
+    std::fs::write(dir.join("b.yaml"), "").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id.ends_with("b.yaml")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn empty_files_are_documents_by_default() {
+    let dir = common::tmpdir("empty-files-default");
+    std::fs::write(dir.join("a.yaml"), "").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.first(), Some(DocumentEvent::NewDocument(_, content)) if content.is_empty()),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+fn new_items(events: &[ConfigItemEvent<u32>]) -> Vec<u32> {
+    let mut items: Vec<u32> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(*item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    items
+}
+
+#[tokio::test]
+async fn disabled_documents_are_removed_and_restored_on_reevaluate() {
+    let dir = common::tmpdir("enabled");
+    std::fs::write(dir.join("tenant-a.yaml"), "1\n---\n2").unwrap();
+    std::fs::write(dir.join("tenant-b.yaml"), "3").unwrap();
+    let watch_dir = dir.clone();
+    let tenant_a = Arc::new(AtomicBool::new(true));
+    let enabled = tenant_a.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            enabled: Some(Arc::new(move |id: &str| {
+                !id.ends_with("tenant-a.yaml") || enabled.load(Ordering::SeqCst)
+            })),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_items(&events), [1, 2, 3]);
+
+    // Nothing happens until the predicate is evaluated again
+    tenant_a.store(false, Ordering::SeqCst);
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    assert!(handle.reevaluate().await);
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ConfigItemEvent::Removed(_)))
+            .count(),
+        2,
+        "{events:?}"
+    );
+    assert!(
+        matches!(events.last(), Some(ConfigItemEvent::RemoveDocument(_))),
+        "{events:?}"
+    );
+
+    // Changes of the disabled document are recorded but not emitted
+    std::fs::write(dir.join("tenant-a.yaml"), "1\n---\n4").unwrap();
+    assert!(common::collect(&mut rx, 500).await.is_empty());
+
+    tenant_a.store(true, Ordering::SeqCst);
+    assert!(handle.reevaluate().await);
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.first(), Some(ConfigItemEvent::NewDocument(_, id)) if id.ends_with("tenant-a.yaml")),
+        "{events:?}"
+    );
+    assert_eq!(new_items(&events), [1, 4]);
+
+    handle.stop().await.unwrap();
+    assert!(!handle.reevaluate().await);
+}
@@ -0,0 +1,26 @@
+#![cfg(feature = "file")]
+
+use config_watcher::*;
+use std::path::PathBuf;
+
+#[test]
+fn summaries_name_the_variant_and_the_file() {
+    let error = WatcherError::BinaryFile(PathBuf::from("/config/a.yaml"));
+    let summary = error.summary();
+    assert_eq!(summary.kind, "BinaryFile");
+    assert_eq!(summary.message, error.to_string());
+    assert_eq!(summary.path, Some(PathBuf::from("/config/a.yaml")));
+    assert_eq!(summary.clone(), summary);
+}
+
+#[test]
+fn summaries_of_errors_without_a_file_have_no_path() {
+    let error = WatcherError::Deserialize {
+        document_id: "/config/a.yaml".to_string(),
+        detail: "invalid digit".to_string(),
+    };
+    let summary = error.summary();
+    assert_eq!(summary.kind, "Deserialize");
+    assert!(summary.message.contains("invalid digit"), "{summary:?}");
+    assert_eq!(summary.path, None);
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[test]
+fn mask_combines_categories() {
+    let mask = EventMask::ALL - EventMask::CHANGED;
+    assert!(mask.contains(EventMask::NEW | EventMask::REMOVED));
+    assert!(!mask.contains(EventMask::CHANGED));
+    assert!(mask.allows(&DocumentEvent::InitialScanComplete));
+    assert!(!EventMask::NONE.allows(&DocumentEvent::DocumentRemoved("a".into())));
+}
+
+#[tokio::test]
+async fn file_watcher_suppresses_masked_changes() {
+    let dir = common::tmpdir("event-mask-file");
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher_with_options(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions {
+            event_mask: EventMask::ALL - EventMask::CHANGED,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                DocumentEvent::NewDocument(..),
+                DocumentEvent::InitialScanComplete
+            ]
+        ),
+        "{events:?}"
+    );
+
+    std::fs::write(dir.join("a.yaml"), "v: 2").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id.ends_with("a.yaml")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn item_watcher_removes_the_reported_items_after_a_masked_change() {
+    let dir = common::tmpdir("event-mask-items");
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            event_mask: EventMask::ALL - EventMask::CHANGED,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let mut reported: Vec<ConfigItemHash> = common::collect(&mut rx, 300)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(hash, _) => Some(hash),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(reported.len(), 2);
+
+    std::fs::write(dir.join("a.yaml"), "3").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let mut removed: Vec<ConfigItemHash> = common::collect(&mut rx, 500)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::Removed(hash) => Some(hash),
+            _ => None,
+        })
+        .collect();
+    reported.sort();
+    removed.sort();
+    assert_eq!(removed, reported);
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,70 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use notify::EventKind;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn custom_policies_select_the_events_that_cause_reads() {
+    let dir = common::tmpdir("event-policy");
+    let options = ConfigFileWatcherOptions {
+        event_policy: FileEventPolicy::Custom(Arc::new(|kind| {
+            matches!(kind, EventKind::Create(_))
+        })),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // Created empty, the content is written by a modification
+    std::fs::write(dir.join("a.yaml"), "").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(_, content)] if content.is_empty()),
+        "{events:?}"
+    );
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    // Removals are always handled
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(_)]),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn close_write_reads_files_once_they_are_closed() {
+    let dir = common::tmpdir("event-policy-close-write");
+    let options = ConfigFileWatcherOptions {
+        event_policy: FileEventPolicy::CloseWrite,
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    let mut file = std::fs::File::create(dir.join("a.yaml")).unwrap();
+    std::io::Write::write_all(&mut file, b"v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    drop(file);
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(_, content)] if content == "v: 1"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use futures::channel::mpsc::unbounded;
+use notify::event::{CreateKind, DataChange, ModifyKind};
+use notify::{Event, EventKind};
+use std::time::Duration;
+
+#[tokio::test]
+async fn batches_of_the_stream_drive_the_watcher() {
+    let dir = common::tmpdir("event-stream").canonicalize().unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_config_file_watcher_from_stream(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions::default(),
+        stream,
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            initial.as_slice(),
+            [
+                DocumentEvent::NewDocument(..),
+                DocumentEvent::InitialScanComplete
+            ]
+        ),
+        "{initial:?}"
+    );
+
+    // Without an event, changes on disk are not noticed
+    std::fs::write(dir.join("b.yaml"), "v: 0").unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+
+    // The events of a batch read each file once
+    let b = dir.join("b.yaml");
+    events
+        .unbounded_send(Ok(vec![
+            Event::new(EventKind::Create(CreateKind::File)).add_path(b.clone()),
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path(b),
+        ]))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::NewDocument(id, _)] if id.ends_with("b.yaml")),
+        "{received:?}"
+    );
+
+    // Errors are logged
+    events
+        .unbounded_send(Err(vec![notify::Error::generic("synthetic")]))
+        .unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+
+    // The watcher ends with the stream
+    drop(events);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    handle.stop().await.unwrap();
+}
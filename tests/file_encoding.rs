@@ -0,0 +1,93 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+/// Starts a lazy file watcher on `dir`, so files are only read by `load_document`.
+async fn lazy_watcher(dir: &std::path::Path) -> WatcherHandle {
+    let (handle, mut rx) = run_config_file_watcher_with_options(
+        dir,
+        "*.yaml",
+        ConfigFileWatcherOptions {
+            lazy: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    // Keep the watcher from blocking on a full channel
+    tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    handle
+}
+
+fn id(dir: &std::path::Path, name: &str) -> String {
+    dir.canonicalize()
+        .unwrap()
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[tokio::test]
+async fn byte_order_mark_is_removed() {
+    let dir = common::tmpdir("encoding-bom");
+    std::fs::write(dir.join("a.yaml"), b"\xEF\xBB\xBFkey: value").unwrap();
+    let mut handle = lazy_watcher(&dir).await;
+    assert_eq!(
+        handle.load_document(&id(&dir, "a.yaml")).await.unwrap(),
+        "key: value"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn invalid_utf8_and_nul_bytes_fail_as_binary_files() {
+    let dir = common::tmpdir("encoding-binary");
+    std::fs::write(dir.join("invalid.yaml"), b"key: \xFF\xFE\xFD").unwrap();
+    std::fs::write(dir.join("nul.yaml"), b"key: \0value").unwrap();
+    let mut handle = lazy_watcher(&dir).await;
+    for name in ["invalid.yaml", "nul.yaml"] {
+        assert!(
+            matches!(
+                handle.load_document(&id(&dir, name)).await,
+                Err(WatcherError::BinaryFile(_))
+            ),
+            "{name}"
+        );
+    }
+    handle.stop().await.unwrap();
+}
+
+#[cfg(feature = "encoding")]
+#[tokio::test]
+async fn utf16_files_are_transcoded() {
+    let dir = common::tmpdir("encoding-utf16");
+    let bytes: Vec<u8> = "key: value"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    std::fs::write(dir.join("a.yaml"), bytes).unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher_with_options(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions {
+            encoding: Some(encoding_rs::UTF_16LE),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::NewDocument(_, content), DocumentEvent::InitialScanComplete]
+                if content == "key: value"
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::Arc;
+
+async fn item_hashes(dir: &std::path::Path) -> Vec<ConfigItemHash> {
+    let root = dir.canonicalize().unwrap().to_string_lossy().into_owned();
+    let watch_dir = dir.to_path_buf();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            filename_id_fn: Some(Arc::new(move |id: &str| {
+                hash_str(id.strip_prefix(&root).unwrap_or(id))
+            })),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let mut hashes: Vec<ConfigItemHash> = common::collect(&mut rx, 300)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(hash, _) => Some(hash),
+            _ => None,
+        })
+        .collect();
+    hashes.sort();
+    handle.stop().await.unwrap();
+    hashes
+}
+
+#[tokio::test]
+async fn item_hashes_stay_valid_when_the_watch_root_moves() {
+    let first = common::tmpdir("filename-id-first");
+    let second = common::tmpdir("filename-id-second");
+    for dir in [&first, &second] {
+        std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    }
+    let hashes = item_hashes(&first).await;
+    assert_eq!(hashes.len(), 2);
+    assert_eq!(hashes[0].filename_hash(), hash_str("/a.yaml"));
+    assert_eq!(item_hashes(&second).await, hashes);
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "git")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?}");
+}
+
+#[test]
+fn branches_that_look_like_options_are_rejected() {
+    for branch in ["", "--upload-pack=touch pwned", "-b"] {
+        assert!(
+            matches!(
+                run_git_watcher("repo", branch, "", "*", Duration::from_secs(60)),
+                Err(WatcherError::Git(_))
+            ),
+            "{branch}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn repository_is_not_taken_for_an_option() {
+    let dir = common::tmpdir("git-option");
+    let marker = dir.join("pwned");
+    let repository = format!("--upload-pack=touch {}", marker.display());
+    let (mut handle, mut rx) =
+        run_git_watcher(repository, "main", "", "*", Duration::from_secs(60)).unwrap();
+    handle.start().await.unwrap();
+    // The clone fails, so the initial scan does not complete
+    assert!(common::collect(&mut rx, 500).await.is_empty());
+    assert!(!marker.exists());
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn files_of_a_local_repository_are_emitted() {
+    let dir = common::tmpdir("git-local");
+    git(&dir, &["init", "--quiet", "--initial-branch=main"]);
+    std::fs::write(dir.join("a.yaml"), "key: value").unwrap();
+    git(&dir, &["add", "a.yaml"]);
+    git(
+        &dir,
+        &[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--quiet",
+            "-m",
+            "add a.yaml",
+        ],
+    );
+
+    let (mut handle, mut rx) = run_git_watcher(
+        dir.to_string_lossy(),
+        "main",
+        "",
+        "*.yaml",
+        Duration::from_secs(60),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 1000).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::NewDocument(id, content), DocumentEvent::InitialScanComplete]
+                if id == "a.yaml" && content == "key: value"
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
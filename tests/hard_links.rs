@@ -0,0 +1,38 @@
+#![cfg(all(feature = "file", unix))]
+
+mod common;
+
+use config_watcher::backend::*;
+
+#[tokio::test]
+async fn writes_through_one_link_update_the_others() {
+    let dir = common::tmpdir("hard-links");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    std::fs::hard_link(dir.join("a.yaml"), dir.join("b.yaml")).unwrap();
+    let options = ConfigFileWatcherOptions {
+        track_hard_links: true,
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let mut changed: Vec<String> = common::collect(&mut rx, 500)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            DocumentEvent::ContentChanged(id, content) if content == "v: 1" => Some(
+                std::path::Path::new(&id)
+                    .file_name()?
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            _ => None,
+        })
+        .collect();
+    changed.sort();
+    assert_eq!(changed, ["a.yaml", "b.yaml"]);
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,130 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::health::{HealthSource, WatcherStatus};
+use config_watcher::*;
+
+#[tokio::test]
+async fn item_watchers_report_starting_ready_and_finished() {
+    let dir = common::tmpdir("health");
+    let watch_dir = dir.clone();
+    let (mut handle, _rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    assert_eq!(handle.status(), WatcherStatus::Starting);
+    assert!(!handle.status().is_healthy());
+    handle.start().await.unwrap();
+    handle.wait_ready().await;
+    assert_eq!(handle.status(), WatcherStatus::Ready);
+    assert!(handle.status().is_healthy());
+    handle.stop().await.unwrap();
+    assert_eq!(handle.status(), WatcherStatus::Finished);
+}
+
+#[tokio::test]
+async fn backends_report_running_and_finished() {
+    let dir = common::tmpdir("health-backend");
+    let (mut handle, _rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    assert_eq!(handle.status(), WatcherStatus::Running);
+    handle.stop().await.unwrap();
+    assert_eq!(handle.status(), WatcherStatus::Finished);
+}
+
+#[cfg(feature = "http-health")]
+async fn connect(addr: std::net::SocketAddr) -> tokio::net::TcpStream {
+    // The server may still be binding
+    loop {
+        match tokio::net::TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+        }
+    }
+}
+
+#[cfg(feature = "http-health")]
+async fn get(addr: std::net::SocketAddr) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = connect(addr).await;
+    stream
+        .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[cfg(feature = "http-health")]
+#[tokio::test]
+async fn health_endpoint_answers_with_the_status_of_all_sources() {
+    let dir = common::tmpdir("health-http");
+    let (mut backend, _rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    backend.start().await.unwrap();
+    let (mut stopped, _stopped_rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    stopped.stop().await.unwrap();
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let healthy = tokio::select! {
+        result = health::serve_health(vec![&backend], addr) => panic!("{result:?}"),
+        response = get(addr) => response,
+    };
+    assert!(healthy.starts_with("HTTP/1.1 200 OK"), "{healthy}");
+    assert!(
+        healthy.ends_with(r#"{"healthy":true,"watchers":["Running"]}"#),
+        "{healthy}"
+    );
+
+    let unhealthy = tokio::select! {
+        result = health::serve_health(vec![&backend, &stopped], addr) => panic!("{result:?}"),
+        response = get(addr) => response,
+    };
+    assert!(
+        unhealthy.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "{unhealthy}"
+    );
+    assert!(
+        unhealthy.ends_with(r#"{"healthy":false,"watchers":["Running","Finished"]}"#),
+        "{unhealthy}"
+    );
+    backend.stop().await.unwrap();
+}
+
+#[cfg(feature = "http-health")]
+#[tokio::test]
+async fn stalled_client_does_not_block_other_requests() {
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    let dir = common::tmpdir("health-stalled");
+    let (mut backend, _rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    backend.start().await.unwrap();
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let response = tokio::select! {
+        result = health::serve_health(vec![&backend], addr) => panic!("{result:?}"),
+        response = async {
+            // Sends part of a request and then nothing, keeping the connection open
+            let mut stalled = connect(addr).await;
+            stalled.write_all(b"GET /hea").await.unwrap();
+            let response = tokio::time::timeout(Duration::from_secs(1), get(addr)).await;
+            drop(stalled);
+            response
+        } => response,
+    };
+    let response = response.expect("the stalled client blocked the request");
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    backend.stop().await.unwrap();
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::clock::TestClock;
+use config_watcher::*;
+use std::{sync::Arc, time::Duration};
+
+fn name(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("name: "))
+        .map(str::to_string)
+}
+
+#[tokio::test]
+async fn documents_are_identified_by_their_content() {
+    let dir = common::tmpdir("id-from-content");
+    std::fs::write(dir.join("a.yaml"), "name: app\nv: 1").unwrap();
+    std::fs::write(dir.join("b.yaml"), "v: 2").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            id_from_content: Some(Arc::new(name)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    let ids = handle.document_ids();
+    assert!(ids.contains(&"app".to_string()), "{ids:?}");
+    // Without a derived id, the backend id is kept
+    assert!(ids.iter().any(|id| id.ends_with("b.yaml")), "{ids:?}");
+
+    // Renaming the file keeps the document
+    std::fs::rename(dir.join("a.yaml"), dir.join("c.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+    assert!(handle.document_ids().contains(&"app".to_string()));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn rename_window_follows_the_clock_and_yields_to_stop() {
+    let dir = common::tmpdir("id-from-content-clock");
+    std::fs::write(dir.join("a.yaml"), "name: app\nv: 1").unwrap();
+    std::fs::write(dir.join("b.yaml"), "name: other\nv: 1").unwrap();
+    let clock = TestClock::new();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            id_from_content: Some(Arc::new(name)),
+            clock: Arc::new(clock.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // The removal is held back until the rename window has passed on the clock
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    common::wait_for_sleeps(&clock, 1).await;
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    clock.advance(Duration::from_millis(100));
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::Removed(_))),
+        "{events:?}"
+    );
+
+    // A stop during the rename window is handled without waiting for it
+    std::fs::remove_file(dir.join("b.yaml")).unwrap();
+    common::wait_for_sleeps(&clock, 1).await;
+    tokio::time::timeout(Duration::from_secs(2), handle.stop())
+        .await
+        .expect("stop waited for the rename window")
+        .unwrap();
+}
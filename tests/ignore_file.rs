@@ -0,0 +1,67 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+
+fn names(events: &[DocumentEvent], removed: bool) -> Vec<String> {
+    let mut names: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) if !removed => Some(id),
+            DocumentEvent::DocumentRemoved(id) if removed => Some(id),
+            _ => None,
+        })
+        .filter_map(|id| {
+            Some(
+                std::path::Path::new(id)
+                    .file_name()?
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn ignored_files_are_not_tracked() {
+    let dir = common::tmpdir("ignore-file");
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(
+        dir.join(".configwatcherignore"),
+        "# comment\n\nsecret.yaml\nsub/*.yaml\n",
+    )
+    .unwrap();
+    for name in ["a.yaml", "secret.yaml", "sub/b.yaml"] {
+        std::fs::write(dir.join(name), "v: 0").unwrap();
+    }
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(names(&events, false), ["a.yaml"]);
+
+    std::fs::write(dir.join("secret.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn changes_of_the_ignore_file_are_applied() {
+    let dir = common::tmpdir("ignore-file-reload");
+    std::fs::write(dir.join(".configwatcherignore"), "b.yaml\n").unwrap();
+    for name in ["a.yaml", "b.yaml"] {
+        std::fs::write(dir.join(name), "v: 0").unwrap();
+    }
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join(".configwatcherignore"), "a.yaml\n").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(names(&events, true), ["a.yaml"], "{events:?}");
+    assert_eq!(names(&events, false), ["b.yaml"], "{events:?}");
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn new_items_carry_their_trimmed_source_text() {
+    let dir = common::tmpdir("include-raw");
+    std::fs::write(dir.join("a.yaml"), "\n7\n---\n  8  \n").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            include_raw: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let mut items: Vec<(u32, String)> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::NewWithRaw(_, item, raw) => Some((*item, raw.clone())),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, [(7, "7".to_string()), (8, "8".to_string())]);
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::New(..))));
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::path::PathBuf;
+
+fn includes(content: &str, _path: &std::path::Path) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("include: "))
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[tokio::test]
+async fn included_files_are_expanded_and_watched() {
+    let dir = common::tmpdir("include-resolver");
+    let shared = common::tmpdir("include-resolver-shared");
+    std::fs::write(shared.join("base.inc"), "base: 1").unwrap();
+    std::fs::write(
+        dir.join("app.yaml"),
+        format!("include: {}\napp: 1", shared.join("base.inc").display()),
+    )
+    .unwrap();
+    let options = ConfigFileWatcherOptions {
+        include_resolver: Some(IncludeResolver::new(includes)),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(&events[0], DocumentEvent::NewDocument(_, content) if content.ends_with("app: 1\nbase: 1")),
+        "{events:?}"
+    );
+
+    // A change of the included file outside the watch path re-emits the including document
+    std::fs::write(shared.join("base.inc"), "base: 2").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(id, content)] if id.ends_with("app.yaml") && content.ends_with("app: 1\nbase: 2")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn relative_and_cyclic_includes_are_resolved_once() {
+    let dir = common::tmpdir("include-resolver-cycle");
+    std::fs::write(dir.join("a.yaml"), "include: b.inc\na: 1").unwrap();
+    std::fs::write(dir.join("b.inc"), "include: a.yaml\nb: 1").unwrap();
+    let options = ConfigFileWatcherOptions {
+        include_resolver: Some(IncludeResolver::new(includes)),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(&events[0], DocumentEvent::NewDocument(_, content) if content == "include: b.inc\na: 1\ninclude: a.yaml\nb: 1"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::{Duration, SystemTime};
+
+async fn initial_names(dir: &std::path::Path, options: ConfigFileWatcherOptions) -> Vec<String> {
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    let names = common::collect(&mut rx, 300)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) => Some(
+                std::path::Path::new(&id)
+                    .file_name()?
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            _ => None,
+        })
+        .collect();
+    handle.stop().await.unwrap();
+    names
+}
+
+#[tokio::test]
+async fn initial_documents_are_emitted_in_the_configured_order() {
+    let dir = common::tmpdir("initial-order");
+    let now = SystemTime::now();
+    // Modification times in the reverse order of the names
+    for (i, name) in ["a.yaml", "b.yaml", "c.yaml", "d.yaml"].iter().enumerate() {
+        let file = std::fs::File::create(dir.join(name)).unwrap();
+        file.set_modified(now - Duration::from_secs(100 * (i as u64 + 1)))
+            .unwrap();
+    }
+
+    for (order, expected) in [
+        (
+            InitialOrder::PathAsc,
+            ["a.yaml", "b.yaml", "c.yaml", "d.yaml"],
+        ),
+        (
+            InitialOrder::PathDesc,
+            ["d.yaml", "c.yaml", "b.yaml", "a.yaml"],
+        ),
+        (
+            InitialOrder::Mtime,
+            ["d.yaml", "c.yaml", "b.yaml", "a.yaml"],
+        ),
+    ] {
+        for initial_read_concurrency in [1, 3] {
+            let options = ConfigFileWatcherOptions {
+                initial_order: order,
+                initial_read_concurrency,
+                ..Default::default()
+            };
+            assert_eq!(
+                initial_names(&dir, options).await,
+                expected,
+                "{order:?} with {initial_read_concurrency} reads"
+            );
+        }
+    }
+}
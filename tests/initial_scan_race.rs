@@ -0,0 +1,52 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+#[tokio::test]
+async fn files_modified_during_the_initial_scan_are_reported_as_changed() {
+    let dir = common::tmpdir("initial-scan-race");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "v: 1").unwrap();
+
+    // The resolver runs after the file was read, so the write lands between the read and the end
+    // of the scan
+    let modified = Arc::new(AtomicBool::new(false));
+    let resolver = {
+        let modified = modified.clone();
+        let file = file.clone();
+        IncludeResolver::new(move |_, _| {
+            if !modified.swap(true, Ordering::SeqCst) {
+                std::fs::write(&file, "v: 2").unwrap();
+            }
+            Vec::new()
+        })
+    };
+    let options = ConfigFileWatcherOptions {
+        include_resolver: Some(resolver),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+
+    let events = common::collect(&mut rx, 500).await;
+    assert!(modified.load(Ordering::SeqCst));
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                DocumentEvent::NewDocument(_, first),
+                DocumentEvent::InitialScanComplete,
+                DocumentEvent::ContentChanged(_, changed),
+            ] if first == "v: 1" && changed == "v: 2"
+        ),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
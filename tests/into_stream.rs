@@ -0,0 +1,71 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use futures::StreamExt;
+use std::time::Duration;
+
+#[tokio::test]
+async fn receivers_become_streams_that_end_with_the_watcher() {
+    let dir = common::tmpdir("into-stream");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    handle.wait_ready().await;
+    handle.stop().await.unwrap();
+
+    let mut items: Vec<String> = tokio::time::timeout(
+        Duration::from_secs(5),
+        into_stream(rx)
+            .filter_map(|event| async move {
+                match event {
+                    ConfigItemEvent::New(_, item) => Some(item),
+                    _ => None,
+                }
+            })
+            .collect(),
+    )
+    .await
+    .unwrap();
+    items.sort();
+    assert_eq!(items, ["one", "two"]);
+}
+
+#[tokio::test]
+async fn streams_of_several_watchers_can_be_merged() {
+    let first = common::tmpdir("into-stream-first");
+    let second = common::tmpdir("into-stream-second");
+    std::fs::write(first.join("a.yaml"), "v: 0").unwrap();
+    std::fs::write(second.join("b.yaml"), "v: 0").unwrap();
+    let (mut first_handle, first_rx) = run_config_file_watcher(&first, "*.yaml").unwrap();
+    let (mut second_handle, second_rx) = run_config_file_watcher(&second, "*.yaml").unwrap();
+    first_handle.start().await.unwrap();
+    second_handle.start().await.unwrap();
+
+    let merged = futures::stream::select(into_stream(first_rx), into_stream(second_rx));
+    let mut ids: Vec<String> = merged
+        .filter_map(|event| async move {
+            match event {
+                DocumentEvent::NewDocument(id, _) => Some(id),
+                _ => None,
+            }
+        })
+        .take(2)
+        .collect()
+        .await;
+    ids.sort();
+    assert!(
+        ids[0].ends_with("a.yaml") && ids[1].ends_with("b.yaml"),
+        "{ids:?}"
+    );
+    first_handle.stop().await.unwrap();
+    second_handle.stop().await.unwrap();
+}
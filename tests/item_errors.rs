@@ -0,0 +1,170 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn report_options() -> ConfigItemWatcherOptions<u32> {
+    ConfigItemWatcherOptions {
+        on_deserialize_error: ErrorMode::Report,
+        ..Default::default()
+    }
+}
+
+fn errors(events: Vec<ConfigItemEvent<u32>>) -> Vec<WatcherError> {
+    events
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::Error(error) => Some(error),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn bad_item_is_reported_as_deserialize_error() {
+    let dir = common::tmpdir("item-errors-deserialize");
+    std::fs::write(dir.join("a.yaml"), "1\n---\nnot a number").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        report_options(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let errors = errors(common::collect(&mut rx, 300).await);
+    match errors.as_slice() {
+        [WatcherError::Deserialize {
+            document_id,
+            detail,
+        }] => {
+            assert!(document_id.ends_with("a.yaml"), "{document_id}");
+            // The `Debug` output of the deserializer's error
+            assert!(detail.contains("InvalidDigit"), "{detail}");
+        }
+        errors => panic!("{errors:?}"),
+    }
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn unsplittable_document_is_reported_as_tokenize_error() {
+    let dir = common::tmpdir("item-errors-tokenize");
+    std::fs::write(dir.join("a.json"), "[1, [2, 3]").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.json"),
+        &JsonArrayTokenizer,
+        |item: &str| item.parse::<u32>(),
+        report_options(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::New(..))));
+    match errors(events).as_slice() {
+        [WatcherError::Tokenize { document_id, .. }] => {
+            assert!(document_id.ends_with("a.json"), "{document_id}")
+        }
+        errors => panic!("{errors:?}"),
+    }
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn skip_mode_only_drops_the_bad_item() {
+    let dir = common::tmpdir("item-errors-skip");
+    std::fs::write(dir.join("a.yaml"), "1\n---\nnot a number\n---\n3").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let mut items: Vec<u32> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(*item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, [1, 3]);
+    assert!(errors(events).is_empty());
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn fail_mode_ends_the_watcher_with_deserialize_error() {
+    let dir = common::tmpdir("item-errors-fail-deserialize");
+    std::fs::write(dir.join("a.yaml"), "1\n---\nnot a number").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            on_deserialize_error: ErrorMode::Fail,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    // The receiver is closed
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    let result = handle.stop().await;
+    assert!(
+        matches!(&result, Err(WatcherError::Deserialize { document_id, .. }) if document_id.ends_with("a.yaml")),
+        "{result:?}"
+    );
+}
+
+#[tokio::test]
+async fn fail_mode_ends_the_watcher_with_tokenize_error() {
+    let dir = common::tmpdir("item-errors-fail");
+    std::fs::write(dir.join("a.json"), "[\"open]").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.json"),
+        &JsonArrayTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            on_deserialize_error: ErrorMode::Fail,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    assert!(matches!(
+        handle.stop().await,
+        Err(WatcherError::Tokenize { .. })
+    ));
+}
+
+#[test]
+fn json_array_tokenizer_rejects_unbalanced_arrays() {
+    assert!(JsonArrayTokenizer.try_tokenize("[1, {\"a\": 2}]").is_ok());
+    assert!(JsonArrayTokenizer.try_tokenize("[1, {\"a\": 2]").is_err());
+    assert!(JsonArrayTokenizer.try_tokenize("[\"a]").is_err());
+    assert!(JsonArrayTokenizer.try_tokenize("not an array").is_ok());
+    // `tokenize` still splits what it can
+    assert_eq!(
+        JsonArrayTokenizer
+            .tokenize("[1, [2, 3]")
+            .collect::<Vec<_>>(),
+        ["1", "[2, 3"]
+    );
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn hashes(events: &[ConfigItemEvent<String>], new: bool) -> Vec<ConfigItemHash> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(hash, _) if new => Some(*hash),
+            ConfigItemEvent::Removed(hash) if !new => Some(*hash),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_sorted(hashes: &[ConfigItemHash]) -> bool {
+    hashes.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+#[tokio::test]
+async fn item_events_are_ordered_by_hash() {
+    let dir = common::tmpdir("item-order");
+    let items: Vec<String> = (0..20).map(|i| format!("item-{i}")).collect();
+    std::fs::write(dir.join("a.yaml"), items.join("\n---\n")).unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let new = hashes(&events, true);
+    assert_eq!(new.len(), 20);
+    assert!(is_sorted(&new), "{new:?}");
+
+    let replaced: Vec<String> = (10..30).map(|i| format!("item-{i}")).collect();
+    std::fs::write(dir.join("a.yaml"), replaced.join("\n---\n")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let (new, removed) = (hashes(&events, true), hashes(&events, false));
+    assert_eq!((new.len(), removed.len()), (10, 10));
+    assert!(is_sorted(&new) && is_sorted(&removed), "{events:?}");
+    handle.stop().await.unwrap();
+}
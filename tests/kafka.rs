@@ -0,0 +1,174 @@
+#![cfg(feature = "kafka")]
+
+mod common;
+
+use config_watcher::{backend::*, clock::TestClock, WatcherError};
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use rskafka::record::{Record, RecordAndOffset};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+type PartitionRecord = (i32, Result<RecordAndOffset, WatcherError>);
+
+fn record(key: Option<&str>, value: Option<&[u8]>) -> KafkaStreamEvent {
+    KafkaStreamEvent::Record(Record {
+        key: key.map(|key| key.as_bytes().to_vec()),
+        value: value.map(<[u8]>::to_vec),
+        headers: Default::default(),
+        timestamp: Default::default(),
+    })
+}
+
+#[tokio::test]
+async fn latest_record_per_key_is_the_document() {
+    let (records, stream) = unbounded();
+    let (mut handle, mut rx) = run_kafka_watcher_from_stream(stream).unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::Kafka);
+    handle.start().await.unwrap();
+
+    // The compacted log is replayed before the watcher is ready
+    records
+        .unbounded_send(record(Some("a"), Some(b"v: 1")))
+        .unwrap();
+    records
+        .unbounded_send(record(Some("b"), Some(b"v: 1")))
+        .unwrap();
+    records
+        .unbounded_send(record(Some("a"), Some(b"v: 2")))
+        .unwrap();
+    records.unbounded_send(KafkaStreamEvent::CaughtUp).unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, _),
+                DocumentEvent::NewDocument(b, _),
+                DocumentEvent::ContentChanged(changed, content),
+                DocumentEvent::InitialScanComplete,
+            ] if a == "a" && b == "b" && changed == "a" && content == "v: 2"
+        ),
+        "{events:?}"
+    );
+
+    // A tombstone removes the document of its key
+    records.unbounded_send(record(Some("b"), None)).unwrap();
+    records
+        .unbounded_send(record(Some("unknown"), None))
+        .unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id == "b"),
+        "{events:?}"
+    );
+
+    // Records without a key or with invalid UTF-8 are skipped, and the scan completes once
+    records.unbounded_send(record(None, Some(b"v: 3"))).unwrap();
+    records
+        .unbounded_send(record(Some("a"), Some(&[0xff, 0xfe])))
+        .unwrap();
+    records.unbounded_send(KafkaStreamEvent::CaughtUp).unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+
+    drop(records);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    handle.stop().await.unwrap();
+}
+
+/// Topic whose records are sent by the test. A partition counts as caught up from its offset in
+/// `ends` on, as if only transaction markers followed.
+struct MemoryTopic {
+    high_watermarks: HashMap<i32, (i64, i64)>,
+    ends: HashMap<i32, i64>,
+    records: Option<UnboundedReceiver<PartitionRecord>>,
+}
+
+impl KafkaSource for MemoryTopic {
+    fn open<'a>(&'a mut self, _: &'a HashMap<i32, i64>) -> KafkaFuture<'a, KafkaPartitions> {
+        let records = self.records.take().expect("the partitions are opened once");
+        Box::pin(async move {
+            Ok(KafkaPartitions {
+                records: records.boxed(),
+                high_watermarks: self.high_watermarks.clone(),
+            })
+        })
+    }
+
+    fn is_caught_up(&self, partition: i32, offset: i64) -> KafkaFuture<'_, bool> {
+        let caught_up = self.ends.get(&partition).is_some_and(|end| offset >= *end);
+        Box::pin(async move { Ok(caught_up) })
+    }
+}
+
+fn send(records: &UnboundedSender<PartitionRecord>, partition: i32, offset: i64, key: &str) {
+    let record = Record {
+        key: Some(key.as_bytes().to_vec()),
+        value: Some(format!("offset: {offset}").into_bytes()),
+        headers: Default::default(),
+        timestamp: Default::default(),
+    };
+    records
+        .unbounded_send((partition, Ok(RecordAndOffset { record, offset })))
+        .unwrap();
+}
+
+#[tokio::test]
+async fn partition_ending_in_a_marker_completes_while_another_keeps_producing() {
+    let clock = TestClock::new();
+    let (records, stream) = unbounded();
+    let topic = MemoryTopic {
+        // Offset 2 of partition 0 is a transaction marker
+        high_watermarks: HashMap::from([(0, (0, 3)), (1, (0, 1))]),
+        ends: HashMap::from([(0, 2)]),
+        records: Some(stream),
+    };
+    let (mut handle, mut rx) = run_kafka_watcher_from_source(
+        topic,
+        KafkaWatcherOptions {
+            clock: Arc::new(clock.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+
+    send(&records, 0, 0, "a");
+    send(&records, 0, 1, "b");
+    send(&records, 1, 0, "c");
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, _),
+                DocumentEvent::NewDocument(b, _),
+                DocumentEvent::NewDocument(c, _),
+            ] if a == "a" && b == "b" && c == "c"
+        ),
+        "{events:?}"
+    );
+
+    // Partition 1 receives a record more often than the probe interval, which must not
+    // postpone the probe of partition 0
+    common::wait_for_sleeps(&clock, 1).await;
+    let mut events = vec![];
+    for offset in 1..=5 {
+        send(&records, 1, offset, "c");
+        events.extend(common::collect(&mut rx, 100).await);
+        clock.advance(Duration::from_millis(300));
+    }
+    events.extend(common::collect(&mut rx, 300).await);
+    let ready = events
+        .iter()
+        .filter(|event| matches!(event, DocumentEvent::InitialScanComplete))
+        .count();
+    assert_eq!(ready, 1, "{events:?}");
+
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,88 @@
+#![cfg(all(feature = "file", feature = "yaml"))]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn layers_are_merged_into_one_document() {
+    let dir = common::tmpdir("layered");
+    std::fs::write(dir.join("base.yaml"), "a: 1\nb:\n  c: 2").unwrap();
+    std::fs::write(dir.join("prod.yaml"), "b:\n  d: 3").unwrap();
+    std::fs::write(dir.join("other.yaml"), "x: 1").unwrap();
+    let root = dir.canonicalize().unwrap();
+    let layer = |name: &str| root.join(name).to_string_lossy().into_owned();
+    let (mut handle, mut rx) = run_layered_watcher(
+        run_config_file_watcher(&dir, "*.yaml").unwrap(),
+        vec![LayeredDocument::new(
+            "values",
+            vec![layer("base.yaml"), layer("prod.yaml")],
+        )],
+        Arc::new(merge_yaml_layers),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let merged = merge_yaml_layers(&["a: 1\nb:\n  c: 2", "b:\n  d: 3"]).unwrap();
+    assert_eq!(events.len(), 3, "{events:?}");
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            DocumentEvent::NewDocument(id, content) if id == "values" && *content == merged
+        )),
+        "{events:?}"
+    );
+    // Documents that are no layer pass through
+    let other = layer("other.yaml");
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            DocumentEvent::NewDocument(id, content) if *id == other && content == "x: 1"
+        )),
+        "{events:?}"
+    );
+    assert!(matches!(
+        events.last(),
+        Some(DocumentEvent::InitialScanComplete)
+    ));
+
+    std::fs::write(dir.join("prod.yaml"), "a: 5").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let merged = merge_yaml_layers(&["a: 1\nb:\n  c: 2", "a: 5"]).unwrap();
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::ContentChanged(id, content)] if id == "values" && *content == merged
+        ),
+        "{events:?}"
+    );
+
+    // The remaining layer is still applied
+    std::fs::remove_file(dir.join("base.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let merged = merge_yaml_layers(&["a: 5"]).unwrap();
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::ContentChanged(id, content)] if id == "values" && *content == merged
+        ),
+        "{events:?}"
+    );
+
+    std::fs::remove_file(dir.join("prod.yaml")).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id == "values"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[test]
+fn yaml_layers_are_deep_merged() {
+    let merged = merge_yaml_layers(&["a: 1\nb:\n  c: 2\n  d: 3", "", "b:\n  c: 4\n  d: null"]);
+    assert_eq!(merged.as_deref(), Some("a: 1\nb:\n  c: 4\n"));
+    assert_eq!(merge_yaml_layers(&["a: [1", "b: 2"]), None);
+}
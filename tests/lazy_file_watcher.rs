@@ -0,0 +1,82 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn load_document_emits_the_content_of_a_lazy_file() {
+    let dir = common::tmpdir("lazy-load");
+    std::fs::write(dir.join("a.yaml"), "first").unwrap();
+    let (handle, mut rx) = run_config_file_watcher_with_options(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions {
+            lazy: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let id = match events.as_slice() {
+        [DocumentEvent::NewDocument(id, content), DocumentEvent::InitialScanComplete] => {
+            assert_eq!(content, "");
+            id.clone()
+        }
+        events => panic!("{events:?}"),
+    };
+
+    assert_eq!(handle.load_document(&id).await.unwrap(), "first");
+    let events = common::collect(&mut rx, 300).await;
+    assert!(matches!(
+        events.as_slice(),
+        [DocumentEvent::ContentChanged(changed, content)] if *changed == id && content == "first"
+    ));
+
+    // Loading unchanged content again emits nothing
+    assert_eq!(handle.load_document(&id).await.unwrap(), "first");
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+}
+
+#[tokio::test]
+async fn item_watcher_receives_items_of_loaded_lazy_files() {
+    let dir = common::tmpdir("lazy-items");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || {
+            run_config_file_watcher_with_options(
+                &watch_dir,
+                "*.yaml",
+                ConfigFileWatcherOptions {
+                    lazy: true,
+                    ..Default::default()
+                },
+            )
+        },
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::New(..))));
+
+    let id = dir.canonicalize().unwrap().join("a.yaml");
+    handle.load_document(&id.to_string_lossy()).await.unwrap();
+    let mut items: Vec<String> = common::collect(&mut rx, 300)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, ["one", "two"]);
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn limit_errors(events: &[ConfigItemEvent<String>]) -> Vec<&'static str> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::Error(WatcherError::LimitExceeded { kind, .. }) => Some(*kind),
+            _ => None,
+        })
+        .collect()
+}
+
+fn new_items(events: &[ConfigItemEvent<String>]) -> usize {
+    events
+        .iter()
+        .filter(|event| matches!(event, ConfigItemEvent::New(..)))
+        .count()
+}
+
+#[tokio::test]
+async fn documents_beyond_the_limit_are_rejected_until_budget_is_freed() {
+    let dir = common::tmpdir("limits-documents");
+    std::fs::write(dir.join("a.yaml"), "a").unwrap();
+    std::fs::write(dir.join("b.yaml"), "b").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            max_documents: Some(1),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_items(&events), 1);
+    assert_eq!(limit_errors(&events), ["documents"]);
+    let tracked = handle.document_ids();
+    assert_eq!(tracked.len(), 1);
+
+    // Removing the tracked document frees budget for the next change of the other one
+    std::fs::remove_file(&tracked[0]).unwrap();
+    common::collect(&mut rx, 500).await;
+    let other = if tracked[0].ends_with("a.yaml") {
+        "b.yaml"
+    } else {
+        "a.yaml"
+    };
+    std::fs::write(dir.join(other), "changed").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, item) if item == "changed")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn items_beyond_the_limit_are_skipped_and_reported_each_time() {
+    let dir = common::tmpdir("limits-items");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo\n---\nthree").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            max_items: Some(2),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_items(&events), 2);
+    assert_eq!(limit_errors(&events), ["items"]);
+
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo\n---\nthree\n").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert_eq!(limit_errors(&events), ["items"], "{events:?}");
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn changes_within_the_interval_are_held_back_and_the_latest_is_emitted() {
+    let dir = common::tmpdir("min-interval");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "v0").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            min_interval: Some(Duration::from_millis(800)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    // Documents of the initial scan are not delayed
+    let events = common::collect(&mut rx, 300).await;
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::New(_, item) if item == "v0")));
+
+    // A change after the interval is emitted right away, the following ones at most once per
+    // interval
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    std::fs::write(&file, "v1").unwrap();
+    let events = common::collect(&mut rx, 200).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, item) if item == "v1")),
+        "{events:?}"
+    );
+    std::fs::write(&file, "v2").unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    std::fs::write(&file, "v3").unwrap();
+    let events = common::collect(&mut rx, 200).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    let events = common::collect(&mut rx, 1000).await;
+    let items: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(items, ["v3"], "{events:?}");
+    handle.stop().await.unwrap();
+}
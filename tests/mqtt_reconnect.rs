@@ -0,0 +1,33 @@
+#![cfg(feature = "mqtt")]
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn watcher_gives_up_on_an_unreachable_broker() {
+    let options = MqttWatcherOptions {
+        retry_delay: RetryDelay::new(Duration::from_millis(10), 0.0),
+        max_reconnect_attempts: Some(3),
+        ..Default::default()
+    };
+    // Nothing listens on port 1, so every connection attempt is refused
+    let (mut handle, mut rx) = run_mqtt_watcher_with_options(
+        rumqttc::MqttOptions::new("config-watcher-reconnect-test", "127.0.0.1", 1),
+        "config",
+        10,
+        options,
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    // The receiver is closed when the watcher gives up
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    assert!(matches!(
+        handle.stop().await,
+        Err(WatcherError::MqttReconnectExhausted(3))
+    ));
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "mqtt")]
+
+mod common;
+
+use config_watcher::backend::*;
+use futures::channel::mpsc::unbounded;
+use std::time::Duration;
+
+fn publish(topic: &str, payload: &str, retain: bool) -> MqttClientEvent {
+    MqttClientEvent::PublishMessage(MqttPublishEvent {
+        topic: topic.to_string(),
+        payload: payload.to_string(),
+        duplicate: false,
+        retain,
+        qos: rumqttc::QoS::AtLeastOnce,
+    })
+}
+
+#[tokio::test]
+async fn retained_burst_ends_before_the_first_live_message() {
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_mqtt_watcher_from_stream(
+        stream,
+        MqttWatcherOptions {
+            // Long enough that only the live message can end the burst
+            retained_settle: Duration::from_secs(10),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::Mqtt);
+    handle.start().await.unwrap();
+
+    events.unbounded_send(MqttClientEvent::Connect).unwrap();
+    events
+        .unbounded_send(publish("config/a", "v: 1", true))
+        .unwrap();
+    events
+        .unbounded_send(publish("config/b", "v: 1", true))
+        .unwrap();
+    events
+        .unbounded_send(publish("config/a", "v: 2", false))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, _),
+                DocumentEvent::NewDocument(b, _),
+                DocumentEvent::InitialScanComplete,
+                DocumentEvent::ContentChanged(changed, content),
+            ] if a == "config/a" && b == "config/b" && changed == "config/a" && content == "v: 2"
+        ),
+        "{received:?}"
+    );
+
+    // After a reconnect the broker delivers the retained messages again; identical content
+    // produces no events, and the initial scan is only completed once
+    events.unbounded_send(MqttClientEvent::Disconnect).unwrap();
+    events.unbounded_send(MqttClientEvent::Connect).unwrap();
+    events
+        .unbounded_send(publish("config/a", "v: 2", true))
+        .unwrap();
+    events
+        .unbounded_send(publish("config/b", "v: 1", true))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(received.is_empty(), "{received:?}");
+
+    // An empty payload removes the document
+    events
+        .unbounded_send(publish("config/b", "", false))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::DocumentRemoved(b)] if b == "config/b"),
+        "{received:?}"
+    );
+
+    events.unbounded_send(MqttClientEvent::Stop).unwrap();
+    assert!(rx.recv().await.is_none());
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn retained_burst_ends_after_a_quiet_period() {
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_mqtt_watcher_from_stream(
+        stream,
+        MqttWatcherOptions {
+            retained_settle: Duration::from_millis(100),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+
+    events.unbounded_send(MqttClientEvent::Connect).unwrap();
+    events
+        .unbounded_send(publish("config/a", "v: 1", true))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::NewDocument(a, _), DocumentEvent::InitialScanComplete] if a == "config/a"
+        ),
+        "{received:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "mqtt")]
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn watch(topic: &str, wildcard: Wildcard) -> Result<WatcherHandle, WatcherError> {
+    let options = MqttWatcherOptions {
+        wildcard,
+        ..Default::default()
+    };
+    run_mqtt_watcher_with_options(
+        rumqttc::MqttOptions::new("config-watcher-test", "127.0.0.1", 1883),
+        topic,
+        10,
+        options,
+    )
+    .map(|(handle, _)| handle)
+}
+
+#[tokio::test]
+async fn wildcards_are_appended_to_the_config_topic() {
+    for (topic, wildcard) in [
+        ("config", Wildcard::MultiLevel),
+        ("config/", Wildcard::MultiLevel),
+        ("config", Wildcard::SingleLevel),
+        ("config/+/app", Wildcard::None),
+    ] {
+        let mut handle = watch(topic, wildcard).unwrap();
+        handle.stop().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn invalid_topic_filters_are_rejected() {
+    for (topic, wildcard, filter) in [
+        ("config/#", Wildcard::MultiLevel, "config/#/#"),
+        ("config/#", Wildcard::SingleLevel, "config/#/+"),
+        ("config/#/app", Wildcard::None, "config/#/app"),
+    ] {
+        assert!(
+            matches!(watch(topic, wildcard), Err(WatcherError::InvalidTopic(invalid)) if invalid == filter),
+            "{topic} {wildcard:?}"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn unmute_emits_the_net_change_of_muted_documents() {
+    let dir = common::tmpdir("mute");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    std::fs::write(dir.join("b.yaml"), "kept").unwrap();
+    let root = dir.canonicalize().unwrap();
+    let ids: Vec<String> = ["a.yaml", "b.yaml"]
+        .iter()
+        .map(|name| root.join(name).to_string_lossy().into_owned())
+        .collect();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    assert!(handle.mute(ids.clone()).await);
+    std::fs::write(dir.join("a.yaml"), "two").unwrap();
+    common::collect(&mut rx, 300).await;
+    std::fs::write(dir.join("a.yaml"), "three").unwrap();
+    // Changed and changed back
+    std::fs::write(dir.join("b.yaml"), "changed").unwrap();
+    common::collect(&mut rx, 300).await;
+    std::fs::write(dir.join("b.yaml"), "kept").unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    assert!(handle.unmute(ids).await);
+    let events = common::collect(&mut rx, 300).await;
+    let items: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(items, ["three"], "{events:?}");
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ConfigItemEvent::Removed(_)))
+            .count(),
+        1,
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn other_documents_are_not_held_back() {
+    let dir = common::tmpdir("mute-others");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    let id = dir
+        .canonicalize()
+        .unwrap()
+        .join("a.yaml")
+        .to_string_lossy()
+        .into_owned();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    assert!(handle.mute(vec![id]).await);
+    std::fs::write(dir.join("a.yaml"), "two").unwrap();
+    std::fs::write(dir.join("b.yaml"), "other").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    let items: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(items, ["other"], "{events:?}");
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn document_muted_while_settling_is_emitted_on_unmute() {
+    let dir = common::tmpdir("mute-settle");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "one").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            settle: Some(Duration::from_millis(300)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // Held back by `settle`, then muted before the settle window ends
+    std::fs::write(&file, "two").unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    let id = dir.canonicalize().unwrap().join("a.yaml");
+    assert!(handle.mute(vec![id.to_string_lossy().into_owned()]).await);
+    let events = common::collect(&mut rx, 600).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    assert!(handle.unmute(vec![id.to_string_lossy().into_owned()]).await);
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, item) if item == "two")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
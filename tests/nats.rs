@@ -0,0 +1,77 @@
+#![cfg(feature = "nats")]
+
+mod common;
+
+use async_nats::Message;
+use config_watcher::backend::*;
+use futures::channel::mpsc::unbounded;
+use std::time::Duration;
+
+fn message(subject: &str, payload: &'static str) -> Message {
+    Message {
+        subject: subject.into(),
+        reply: None,
+        payload: payload.into(),
+        headers: None,
+        status: None,
+        description: None,
+        length: payload.len(),
+    }
+}
+
+#[tokio::test]
+async fn messages_are_emitted_as_documents_of_their_subject() {
+    let (messages, stream) = unbounded();
+    let (mut handle, mut rx) = run_nats_watcher_from_stream(stream).unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::Nats);
+    handle.start().await.unwrap();
+
+    messages
+        .unbounded_send(message("config.a", "v: 1"))
+        .unwrap();
+    messages
+        .unbounded_send(message("config.b", "v: 2"))
+        .unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::NewDocument(a, a_content), DocumentEvent::NewDocument(b, _)]
+                if a == "config.a" && a_content == "v: 1" && b == "config.b"
+        ),
+        "{events:?}"
+    );
+
+    // Repeated content is no change
+    messages
+        .unbounded_send(message("config.a", "v: 1"))
+        .unwrap();
+    messages
+        .unbounded_send(message("config.a", "v: 3"))
+        .unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(id, content)] if id == "config.a" && content == "v: 3"),
+        "{events:?}"
+    );
+
+    // An empty payload removes the document
+    messages.unbounded_send(message("config.b", "")).unwrap();
+    messages
+        .unbounded_send(message("config.unknown", ""))
+        .unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id == "config.b"),
+        "{events:?}"
+    );
+
+    // The watcher ends with the stream
+    drop(messages);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use futures::channel::mpsc::unbounded;
+use notify::event::{DataChange, ModifyKind, RemoveKind};
+use notify::{Event, EventKind};
+
+#[tokio::test]
+async fn differently_spelled_paths_map_to_one_document() {
+    let dir = common::tmpdir("path-spellings").canonicalize().unwrap();
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let id = dir.join("a.yaml").to_string_lossy().into_owned();
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_config_file_watcher_from_stream(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions::default(),
+        stream,
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    let modified =
+        |path| Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path(path);
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    events
+        .unbounded_send(Ok(vec![modified(dir.join(".").join("a.yaml"))]))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::ContentChanged(changed, _)] if *changed == id),
+        "{received:?}"
+    );
+
+    // Another spelling of the same content is no change
+    events
+        .unbounded_send(Ok(vec![modified(dir.join("sub/../a.yaml"))]))
+        .unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    assert_eq!(
+        handle
+            .load_document(&format!("{}/./a.yaml", dir.display()))
+            .await
+            .unwrap(),
+        "v: 1"
+    );
+
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    events
+        .unbounded_send(Ok(vec![
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(dir.join("sub/./../a.yaml"))
+        ]))
+        .unwrap();
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::DocumentRemoved(removed)] if *removed == id),
+        "{received:?}"
+    );
+    handle.stop().await.unwrap();
+}
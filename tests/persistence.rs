@@ -0,0 +1,146 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::persistence::*;
+use config_watcher::*;
+use std::path::Path;
+use std::sync::Arc;
+
+fn start(
+    dir: &Path,
+    store: &MemoryEventStore,
+) -> (
+    ConfigItemWatcherHandle<u32>,
+    tokio::sync::mpsc::Receiver<ConfigItemEvent<u32>>,
+) {
+    let watch_dir = dir.to_path_buf();
+    run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            persistence: Some(Arc::new(store.clone())),
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn new_items(events: &[ConfigItemEvent<u32>]) -> Vec<u32> {
+    let mut items: Vec<u32> = events
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(*item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    items
+}
+
+#[tokio::test]
+async fn restart_only_reports_what_changed_while_stopped() {
+    let dir = common::tmpdir("persistence-restart");
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2").unwrap();
+    std::fs::write(dir.join("b.yaml"), "5").unwrap();
+    std::fs::write(dir.join("c.yaml"), "7").unwrap();
+    let store = MemoryEventStore::default();
+
+    let (mut handle, mut rx) = start(&dir, &store);
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_items(&events), [1, 2, 5, 7]);
+    handle.stop().await.unwrap();
+    assert!(!store.records().is_empty());
+
+    std::fs::write(dir.join("a.yaml"), "2\n---\n3").unwrap();
+    std::fs::remove_file(dir.join("c.yaml")).unwrap();
+
+    let (mut handle, mut rx) = start(&dir, &store);
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    // Unchanged items and documents are not reported again
+    assert_eq!(new_items(&events), [3]);
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::NewDocument(..))),
+        "{events:?}"
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, ConfigItemEvent::Removed(..)))
+            .count(),
+        2,
+        "{events:?}"
+    );
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::DocumentChanged(..))),
+        "{events:?}"
+    );
+    // c.yaml is gone since the last run
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::RemoveDocument(..))),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+
+    let (mut handle, mut rx) = start(&dir, &store);
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(events.is_empty(), "{events:?}");
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn restored_documents_count_towards_the_document_limit() {
+    let dir = common::tmpdir("persistence-limit");
+    std::fs::write(dir.join("a.yaml"), "1").unwrap();
+    let store = MemoryEventStore::default();
+    let start_limited = || {
+        let watch_dir = dir.clone();
+        run_config_item_watcher_with_options(
+            move || run_config_file_watcher(&watch_dir, "*.yaml"),
+            &YamlTokenizer,
+            |item: &str| item.parse::<u32>(),
+            ConfigItemWatcherOptions {
+                persistence: Some(Arc::new(store.clone())),
+                max_documents: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+
+    let (mut handle, mut rx) = start_limited();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(new_items(&events), [1]);
+    handle.stop().await.unwrap();
+
+    // The restored document uses up the limit, so the new one is rejected
+    std::fs::write(dir.join("b.yaml"), "2").unwrap();
+    let (mut handle, mut rx) = start_limited();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(new_items(&events).is_empty(), "{events:?}");
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            ConfigItemEvent::Error(WatcherError::LimitExceeded {
+                kind: "documents",
+                ..
+            })
+        )),
+        "{events:?}"
+    );
+    assert_eq!(handle.document_ids().len(), 1);
+    handle.stop().await.unwrap();
+}
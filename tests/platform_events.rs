@@ -0,0 +1,109 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{Event, EventKind};
+use std::path::Path;
+
+fn send(events: &UnboundedSender<FileEventBatch>, kind: EventKind, path: &Path) {
+    events
+        .unbounded_send(Ok(vec![Event::new(kind).add_path(path.to_path_buf())]))
+        .unwrap();
+}
+
+#[tokio::test]
+async fn windows_event_kinds_are_handled() {
+    let dir = common::tmpdir("platform-events-windows")
+        .canonicalize()
+        .unwrap();
+    let file = dir.join("a.yaml");
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_config_file_watcher_from_stream(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions::default(),
+        stream,
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(&file, "v: 1").unwrap();
+    send(&events, EventKind::Create(CreateKind::Any), &file);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::NewDocument(..)]),
+        "{received:?}"
+    );
+
+    std::fs::write(&file, "v: 2").unwrap();
+    send(&events, EventKind::Modify(ModifyKind::Any), &file);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 2"),
+        "{received:?}"
+    );
+
+    std::fs::remove_file(&file).unwrap();
+    send(&events, EventKind::Remove(RemoveKind::Any), &file);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::DocumentRemoved(_)]),
+        "{received:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn fsevents_renames_are_resolved_from_the_file_system() {
+    let dir = common::tmpdir("platform-events-fsevents")
+        .canonicalize()
+        .unwrap();
+    let (from, to) = (dir.join("a.yaml"), dir.join("b.yaml"));
+    std::fs::write(&from, "v: 1").unwrap();
+    let (events, stream) = unbounded();
+    let (mut handle, mut rx) = run_config_file_watcher_from_stream(
+        &dir,
+        "*.yaml",
+        ConfigFileWatcherOptions::default(),
+        stream,
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // FSEvents does not tell whether a path was renamed from or to
+    std::fs::rename(&from, &to).unwrap();
+    send(
+        &events,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Any)),
+        &from,
+    );
+    send(
+        &events,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Any)),
+        &to,
+    );
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::DocumentRemoved(removed), DocumentEvent::NewDocument(added, _)]
+                if removed.ends_with("a.yaml") && added.ends_with("b.yaml")
+        ),
+        "{received:?}"
+    );
+
+    // Coalesced removal of a file that exists again
+    std::fs::write(&to, "v: 2").unwrap();
+    send(&events, EventKind::Remove(RemoveKind::File), &to);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 2"),
+        "{received:?}"
+    );
+    handle.stop().await.unwrap();
+}
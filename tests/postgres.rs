@@ -0,0 +1,198 @@
+#![cfg(feature = "postgres")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::clock::TestClock;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// In-memory table whose notifications are sent by the test.
+#[derive(Clone, Default)]
+struct MemoryTable {
+    rows: Arc<Mutex<HashMap<String, String>>>,
+    // Sender of the notifications of the current connection
+    notifications: Arc<Mutex<Option<mpsc::UnboundedSender<String>>>>,
+    connects: Arc<Mutex<usize>>,
+}
+
+impl MemoryTable {
+    fn set(&self, id: &str, content: &str) {
+        self.rows
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), content.to_string());
+    }
+
+    fn remove(&self, id: &str) {
+        self.rows.lock().unwrap().remove(id);
+    }
+
+    fn notify(&self, payload: &str) {
+        let notifications = self.notifications.lock().unwrap();
+        notifications
+            .as_ref()
+            .unwrap()
+            .send(payload.to_string())
+            .unwrap();
+    }
+
+    /// Closes the notification channel like a lost connection.
+    fn disconnect(&self) {
+        self.notifications.lock().unwrap().take();
+    }
+
+    fn connects(&self) -> usize {
+        *self.connects.lock().unwrap()
+    }
+}
+
+impl PostgresSource for MemoryTable {
+    fn connect(&mut self) -> PostgresFuture<'_, mpsc::UnboundedReceiver<String>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.notifications.lock().unwrap() = Some(sender);
+        *self.connects.lock().unwrap() += 1;
+        Box::pin(async move { Ok(receiver) })
+    }
+
+    fn query_all(&mut self) -> PostgresFuture<'_, HashMap<String, String>> {
+        let rows = self.rows.lock().unwrap().clone();
+        Box::pin(async move { Ok(rows) })
+    }
+
+    fn query_one<'a>(&'a mut self, id: &'a str) -> PostgresFuture<'a, Option<String>> {
+        let row = self.rows.lock().unwrap().get(id).cloned();
+        Box::pin(async move { Ok(row) })
+    }
+}
+
+#[tokio::test]
+async fn rows_are_seeded_refreshed_and_reconciled() {
+    let table = MemoryTable::default();
+    table.set("a", "v: 1");
+    table.set("b", "v: 1");
+    let clock = TestClock::new();
+    let (mut handle, mut rx) = run_postgres_watcher_from_source(
+        table.clone(),
+        PostgresWatcherOptions {
+            retry_delay: RetryDelay::new(Duration::from_secs(5), 0.0),
+            reconcile_interval: Duration::from_secs(60),
+            clock: Arc::new(clock.clone()),
+        },
+    )
+    .unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::Postgres);
+    handle.start().await.unwrap();
+
+    // The table is seeded before the initial scan completes
+    let events = common::collect(&mut rx, 300).await;
+    let mut seeded: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, _) => Some(id.as_str()),
+            _ => None,
+        })
+        .collect();
+    seeded.sort();
+    assert_eq!(seeded, ["a", "b"], "{events:?}");
+    assert!(matches!(
+        events.last(),
+        Some(DocumentEvent::InitialScanComplete)
+    ));
+
+    // A notification re-queries its row only
+    table.set("a", "v: 2");
+    table.set("b", "v: 2");
+    table.notify("a");
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::ContentChanged(id, content)] if id == "a" && content == "v: 2"
+        ),
+        "{events:?}"
+    );
+
+    // The periodic reconcile catches the change of "b" whose notification was missed
+    common::wait_for_sleeps(&clock, 1).await;
+    clock.advance(Duration::from_secs(60));
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::ContentChanged(id, content)] if id == "b" && content == "v: 2"
+        ),
+        "{events:?}"
+    );
+
+    // An empty payload re-queries the whole table
+    table.set("c", "v: 1");
+    table.notify("");
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(c, _)] if c == "c"),
+        "{events:?}"
+    );
+
+    // A notification of a missing row removes it
+    table.remove("a");
+    table.notify("a");
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(a)] if a == "a"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn lost_connections_are_reestablished_and_reconciled() {
+    let table = MemoryTable::default();
+    table.set("a", "v: 1");
+    let clock = TestClock::new();
+    let (mut handle, mut rx) = run_postgres_watcher_from_source(
+        table.clone(),
+        PostgresWatcherOptions {
+            retry_delay: RetryDelay::new(Duration::from_secs(5), 0.0),
+            reconcile_interval: Duration::from_secs(60),
+            clock: Arc::new(clock.clone()),
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    assert_eq!(table.connects(), 1);
+
+    // Changes while disconnected are only picked up after reconnecting
+    table.disconnect();
+    table.remove("a");
+    table.set("b", "v: 1");
+    // The reconcile timer was dropped, the retry delay is pending
+    common::wait_for_sleeps(&clock, 1).await;
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    clock.advance(Duration::from_secs(5));
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [DocumentEvent::DocumentRemoved(a), DocumentEvent::NewDocument(b, _)]
+                if a == "a" && b == "b"
+        ),
+        "{events:?}"
+    );
+    assert_eq!(table.connects(), 2);
+
+    // Notifications of the new connection are handled
+    table.set("b", "v: 2");
+    table.notify("b");
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(b, _)] if b == "b"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
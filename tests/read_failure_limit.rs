@@ -0,0 +1,56 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn unreadable_files_are_reported_and_read_again_once_fixed() {
+    let dir = common::tmpdir("read-failure-limit");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "v: 0").unwrap();
+    let options = ConfigFileWatcherOptions {
+        read_failure_limit: Some(3),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(&file, "v: \0").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(
+            events.first(),
+            Some(DocumentEvent::ReadFailed(_, WatcherError::BinaryFile(_)))
+        ),
+        "{events:?}"
+    );
+    assert!(!handle.is_finished());
+
+    // Unchanged content is emitted again after a failure
+    std::fs::write(&file, "v: 0").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 0"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn without_a_limit_a_read_error_ends_the_watcher() {
+    let dir = common::tmpdir("read-failure-fatal");
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: \0").unwrap();
+    common::collect(&mut rx, 500).await;
+    assert!(matches!(
+        handle.stop().await,
+        Err(WatcherError::BinaryFile(_))
+    ));
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+
+#[tokio::test]
+async fn only_the_head_of_files_is_read() {
+    let dir = common::tmpdir("read-limit");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "head: 1\ntail: 1").unwrap();
+    let options = ConfigFileWatcherOptions {
+        read_limit: Some(8),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(&initial[0], DocumentEvent::NewDocument(_, content) if content == "head: 1\n"),
+        "{initial:?}"
+    );
+
+    // Changes after the limit are not seen
+    std::fs::write(&file, "head: 1\ntail: 2").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    std::fs::write(&file, "head: 2\ntail: 2").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "head: 2\n"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn characters_cut_off_at_the_limit_are_dropped() {
+    let dir = common::tmpdir("read-limit-utf8");
+    // "ä" is two bytes, the limit ends after its first byte
+    std::fs::write(dir.join("a.yaml"), "v: ä").unwrap();
+    let options = ConfigFileWatcherOptions {
+        read_limit: Some(4),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(&initial[0], DocumentEvent::NewDocument(_, content) if content == "v: "),
+        "{initial:?}"
+    );
+    handle.stop().await.unwrap();
+}
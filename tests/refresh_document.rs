@@ -0,0 +1,163 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn refreshed_documents_are_emitted_even_if_unchanged() {
+    let dir = common::tmpdir("refresh-document");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let root = dir.canonicalize().unwrap();
+    let id = |name: &str| root.join(name).to_string_lossy().into_owned();
+    let options = ConfigFileWatcherOptions {
+        removal_grace: Some(std::time::Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    handle.refresh_document(&id("a.yaml")).await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(refreshed, content)] if *refreshed == id("a.yaml") && content == "v: 0"),
+        "{events:?}"
+    );
+
+    // Removed right away, regardless of the grace period
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    handle.refresh_document(&id("a.yaml")).await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(_)]),
+        "{events:?}"
+    );
+
+    assert!(matches!(
+        handle.refresh_document(&id("missing.yaml")).await,
+        Err(WatcherError::DocumentUnavailable(_))
+    ));
+    assert!(matches!(
+        handle.refresh_document(&id("a.json")).await,
+        Err(WatcherError::DocumentUnavailable(_))
+    ));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn refreshing_an_untracked_matching_file_reports_it_as_new() {
+    let dir = common::tmpdir("refresh-document-new");
+    let id = dir
+        .canonicalize()
+        .unwrap()
+        .join("a.yaml")
+        .to_string_lossy()
+        .into_owned();
+    let options = ConfigFileWatcherOptions {
+        event_policy: FileEventPolicy::Custom(std::sync::Arc::new(|_| false)),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    // Not read because of the event policy
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    handle.refresh_document(&id).await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(..)]),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn other_backends_have_no_documents_to_refresh() {
+    let (mut handle, _rx) = run_reader_watcher("doc", &b"v: 0"[..], false).unwrap();
+    handle.start().await.unwrap();
+    assert!(matches!(
+        handle.refresh_document("doc").await,
+        Err(WatcherError::DocumentUnavailable(_))
+    ));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn layered_documents_are_refreshed_with_the_merged_content() {
+    let dir = common::tmpdir("refresh-document-layered");
+    std::fs::write(dir.join("base.yaml"), "a").unwrap();
+    std::fs::write(dir.join("override.yaml"), "b").unwrap();
+    std::fs::write(dir.join("other.yaml"), "c").unwrap();
+    let root = dir.canonicalize().unwrap();
+    let id = |name: &str| root.join(name).to_string_lossy().into_owned();
+    let (mut handle, mut rx) = run_layered_watcher(
+        run_config_file_watcher(&dir, "*.yaml").unwrap(),
+        vec![LayeredDocument::new(
+            "values",
+            vec![id("base.yaml"), id("override.yaml")],
+        )],
+        std::sync::Arc::new(|layers: &[&str]| Some(layers.join("+"))),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    handle.refresh_document("values").await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(refreshed, content)] if refreshed == "values" && content == "a+b"),
+        "{events:?}"
+    );
+
+    // Other documents are refreshed by the backend
+    handle.refresh_document(&id("other.yaml")).await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(refreshed, content)] if *refreshed == id("other.yaml") && content == "c"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn merged_watchers_refresh_through_the_owning_source() {
+    let first = common::tmpdir("refresh-document-merged-first");
+    let second = common::tmpdir("refresh-document-merged-second");
+    std::fs::write(first.join("a.yaml"), "a").unwrap();
+    std::fs::write(second.join("b.yaml"), "b").unwrap();
+    let b = second
+        .canonicalize()
+        .unwrap()
+        .join("b.yaml")
+        .to_string_lossy()
+        .into_owned();
+    let (mut handle, mut rx) = run_merged_watcher(vec![
+        (
+            "first".to_string(),
+            run_config_file_watcher(&first, "*.yaml").unwrap(),
+        ),
+        (
+            "second".to_string(),
+            run_config_file_watcher(&second, "*.yaml").unwrap(),
+        ),
+    ])
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    handle.refresh_document(&b).await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(refreshed, content)] if *refreshed == b && content == "b"),
+        "{events:?}"
+    );
+    assert!(handle.refresh_document("unknown").await.is_err());
+    handle.stop().await.unwrap();
+}
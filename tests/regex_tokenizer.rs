@@ -0,0 +1,54 @@
+#![cfg(all(feature = "file", feature = "regex"))]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[test]
+fn splits_on_separators_of_any_length() {
+    let tokenizer = RegexTokenizer::new("^=====+$").unwrap();
+    let content = "a: 1\n=====\nb: 2\n==========\n\n=======\nc: 3\n===";
+    assert_eq!(
+        tokenizer.tokenize(content).collect::<Vec<_>>(),
+        ["a: 1", "b: 2", "c: 3\n==="]
+    );
+    // Separators only match whole lines
+    assert_eq!(
+        tokenizer.tokenize("a: ====== 1").collect::<Vec<_>>(),
+        ["a: ====== 1"]
+    );
+}
+
+#[test]
+fn rejects_invalid_and_empty_matching_patterns() {
+    assert!(RegexTokenizer::new("^(=+$").is_err());
+    assert!(RegexTokenizer::new("^=*$").is_err());
+}
+
+#[tokio::test]
+async fn item_watcher_splits_documents_with_the_pattern() {
+    let dir = common::tmpdir("regex-tokenizer");
+    std::fs::write(dir.join("a.txt"), "1\n=====\n2\n========\n3\n").unwrap();
+    let tokenizer: &'static RegexTokenizer =
+        Box::leak(Box::new(RegexTokenizer::new("^=====+$").unwrap()));
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.txt"),
+        tokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let mut items: Vec<u32> = common::collect(&mut rx, 300)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, [1, 2, 3]);
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::path::Path;
+
+#[tokio::test]
+async fn relative_watch_paths_report_absolute_ids() {
+    // Integration tests run in the package root
+    let dir = format!("./target/cw-relative-watch-path-{}", std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let relative = format!("{dir}/.");
+    std::fs::write(Path::new(&relative).join("a.yaml"), "v: 0").unwrap();
+    let expected = Path::new(&relative)
+        .canonicalize()
+        .unwrap()
+        .join("a.yaml")
+        .to_string_lossy()
+        .into_owned();
+
+    let (mut handle, mut rx) = run_config_file_watcher(&relative, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(&events[0], DocumentEvent::NewDocument(id, _) if *id == expected),
+        "{events:?}"
+    );
+
+    // Live events match the watch path as well
+    std::fs::write(Path::new(&relative).join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(id, _)] if *id == expected),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
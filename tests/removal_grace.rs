@@ -0,0 +1,59 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+async fn watch(
+    name: &str,
+) -> (
+    WatcherHandle,
+    tokio::sync::mpsc::Receiver<DocumentEvent>,
+    std::path::PathBuf,
+) {
+    let dir = common::tmpdir(name);
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let options = ConfigFileWatcherOptions {
+        removal_grace: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let (handle, mut rx) = run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+    (handle, rx, dir)
+}
+
+#[tokio::test]
+async fn files_recreated_within_the_grace_period_are_not_removed() {
+    let (mut handle, mut rx, dir) = watch("removal-grace-unchanged").await;
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(events.is_empty(), "{events:?}");
+
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 1"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn removals_are_reported_when_the_grace_period_ends() {
+    let (mut handle, mut rx, dir) = watch("removal-grace-removed").await;
+    std::fs::remove_file(dir.join("a.yaml")).unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(events.is_empty(), "{events:?}");
+    let events = common::collect(&mut rx, 800).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(_)]),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+fn items(events: Vec<ConfigItemEvent<String>>) -> Vec<String> {
+    let mut items: Vec<String> = events
+        .into_iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    items
+}
+
+async fn run(
+    name: &str,
+    replay_on_resubscribe: bool,
+) -> (
+    ConfigItemWatcherHandle<String>,
+    tokio::sync::mpsc::Receiver<ConfigItemEvent<String>>,
+    std::path::PathBuf,
+) {
+    let dir = common::tmpdir(name);
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (handle, rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            replay_on_resubscribe,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    (handle, rx, dir)
+}
+
+#[tokio::test]
+async fn resubscribe_replays_the_current_items() {
+    let (mut handle, mut rx, _dir) = run("resubscribe-replay", true).await;
+    assert_eq!(items(common::collect(&mut rx, 300).await), ["one", "two"]);
+
+    let mut rx = handle.resubscribe().await.unwrap();
+    assert_eq!(items(common::collect(&mut rx, 300).await), ["one", "two"]);
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn resubscribe_without_replay_only_delivers_live_events() {
+    let (mut handle, mut rx, dir) = run("resubscribe-live", false).await;
+    assert_eq!(items(common::collect(&mut rx, 300).await), ["one", "two"]);
+
+    let mut rx = handle.resubscribe().await.unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    std::fs::write(dir.join("a.yaml"), "one\n---\nthree").unwrap();
+    assert_eq!(items(common::collect(&mut rx, 500).await), ["three"]);
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn reevaluated_documents_are_emitted_again_without_replay() {
+    let dir = common::tmpdir("resubscribe-enabled");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    let enabled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let watch_dir = dir.clone();
+    let predicate = enabled.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            enabled: Some(std::sync::Arc::new(move |_: &str| {
+                predicate.load(std::sync::atomic::Ordering::Relaxed)
+            })),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert_eq!(items(common::collect(&mut rx, 300).await), ["one"]);
+
+    enabled.store(false, std::sync::atomic::Ordering::Relaxed);
+    handle.reevaluate().await;
+    common::collect(&mut rx, 300).await;
+    enabled.store(true, std::sync::atomic::Ordering::Relaxed);
+    handle.reevaluate().await;
+    assert_eq!(items(common::collect(&mut rx, 300).await), ["one"]);
+    handle.stop().await.unwrap();
+}
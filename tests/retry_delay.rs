@@ -0,0 +1,54 @@
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+#[test]
+fn jitter_is_clamped_and_nan_disables_it() {
+    let delay = RetryDelay::new(Duration::from_secs(1), f64::NAN);
+    assert_eq!(delay.jitter(), 0.0);
+    assert_eq!(delay.sample(), Duration::from_secs(1));
+
+    assert_eq!(RetryDelay::new(Duration::from_secs(1), 5.0).jitter(), 1.0);
+    assert_eq!(RetryDelay::new(Duration::from_secs(1), -1.0).jitter(), 0.0);
+}
+
+#[test]
+fn samples_stay_within_the_jitter() {
+    let delay = RetryDelay::new(Duration::from_secs(10), 0.5);
+    for _ in 0..100 {
+        let sample = delay.sample();
+        assert!(sample >= Duration::from_secs(5) && sample <= Duration::from_secs(15));
+    }
+}
+
+#[test]
+fn huge_delays_saturate() {
+    let delay = RetryDelay::new(Duration::MAX, 1.0);
+    for _ in 0..100 {
+        delay.sample();
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn postgres_watcher_stops_during_the_retry_delay() {
+    let (mut handle, mut rx) = run_postgres_watcher_with_options(
+        "host=127.0.0.1 port=1 user=config connect_timeout=1".to_string(),
+        "config".to_string(),
+        "id".to_string(),
+        "content".to_string(),
+        "config".to_string(),
+        PostgresWatcherOptions {
+            retry_delay: RetryDelay::new(Duration::from_secs(3600), 0.0),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(common::collect(&mut rx, 300).await.is_empty());
+    tokio::time::timeout(Duration::from_secs(2), handle.stop())
+        .await
+        .expect("stop waited for the retry delay")
+        .unwrap();
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn debounce_can_be_changed_while_running() {
+    let dir = common::tmpdir("runtime-debounce");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    handle
+        .set_debounce(Duration::from_millis(600))
+        .await
+        .unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    // Still within the debounce window
+    assert!(common::collect(&mut rx, 200).await.is_empty());
+    std::fs::write(dir.join("a.yaml"), "v: 2").unwrap();
+    let events = common::collect(&mut rx, 1000).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 2"),
+        "{events:?}"
+    );
+
+    // A zero debounce processes events immediately again
+    handle.set_debounce(Duration::ZERO).await.unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 3").unwrap();
+    let events = common::collect(&mut rx, 200).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 3"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "s3")]
+
+mod common;
+
+use config_watcher::backend::*;
+use object_store::{memory::InMemory, path::Path, ObjectStore};
+use std::{sync::Arc, time::Duration};
+
+async fn put(store: &Arc<dyn ObjectStore>, key: &str, content: &'static [u8]) {
+    store.put(&Path::from(key), content.into()).await.unwrap();
+}
+
+#[tokio::test]
+async fn polling_emits_the_differences_of_the_listing() {
+    let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+    put(&store, "config/prod/a.yaml", b"v: 1").await;
+    put(&store, "config/prod/b.yaml", b"v: 1").await;
+    put(&store, "config/prod/binary", &[0xff, 0xfe]).await;
+    put(&store, "config/test/c.yaml", b"v: 1").await;
+
+    let (mut handle, mut rx) =
+        run_s3_watcher_from_store(store.clone(), "config/prod", Duration::from_millis(100))
+            .unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::S3);
+    handle.start().await.unwrap();
+
+    // Objects outside the prefix and objects that are not valid UTF-8 are skipped
+    let received = common::collect(&mut rx, 300).await;
+    let mut ids: Vec<&str> = received
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(id, content) if content == "v: 1" => Some(id.as_str()),
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, ["a.yaml", "b.yaml"], "{received:?}");
+    assert!(
+        matches!(
+            received.as_slice(),
+            [_, _, DocumentEvent::InitialScanComplete]
+        ),
+        "{received:?}"
+    );
+
+    // A new ETag with identical content produces no event
+    put(&store, "config/prod/a.yaml", b"v: 2").await;
+    put(&store, "config/prod/b.yaml", b"v: 1").await;
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::ContentChanged(id, content)] if id == "a.yaml" && content == "v: 2"
+        ),
+        "{received:?}"
+    );
+
+    // Objects that are no longer listed are removed
+    store
+        .delete(&Path::from("config/prod/b.yaml"))
+        .await
+        .unwrap();
+    put(&store, "config/prod/c.yaml", b"v: 1").await;
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::DocumentRemoved(removed), DocumentEvent::NewDocument(added, _)]
+                if removed == "b.yaml" && added == "c.yaml"
+        ),
+        "{received:?}"
+    );
+
+    handle.stop().await.unwrap();
+}
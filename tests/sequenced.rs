@@ -0,0 +1,86 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn messages_are_numbered_without_gaps() {
+    let dir = common::tmpdir("sequenced");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_sequenced(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions::default(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let mut events = common::collect(&mut rx, 300).await;
+    std::fs::write(dir.join("a.yaml"), "three").unwrap();
+    events.extend(common::collect(&mut rx, 500).await);
+
+    assert!(events.len() >= 6, "{events:?}");
+    for (expected, event) in events.iter().enumerate() {
+        assert_eq!(event.seq, expected as u64);
+    }
+    assert!(matches!(events[0].event, ConfigItemEvent::NewDocument(..)));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_batch_counts_as_one_message() {
+    let dir = common::tmpdir("sequenced-batch");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    std::fs::write(dir.join("b.yaml"), "three").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_sequenced(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            batch_events: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(events.len(), 2, "{events:?}");
+    assert!(events
+        .iter()
+        .all(|event| matches!(event.event, ConfigItemEvent::Batch(_))));
+    assert_eq!((events[0].seq, events[1].seq), (0, 1));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn messages_dropped_without_a_receiver_leave_a_gap() {
+    let dir = common::tmpdir("sequenced-gap");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_sequenced(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions::default(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let last = common::collect(&mut rx, 300).await.last().unwrap().seq;
+    drop(rx);
+
+    std::fs::write(dir.join("b.yaml"), "two").unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let mut rx = handle.resubscribe().await.unwrap();
+    std::fs::write(dir.join("c.yaml"), "three").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(!events.is_empty());
+    assert!(events[0].seq > last + 1, "{last} {events:?}");
+    for pair in events.windows(2) {
+        assert_eq!(pair[1].seq, pair[0].seq + 1);
+    }
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::store::*;
+use config_watcher::*;
+use std::time::Duration;
+
+fn items(snapshot: &ConfigSnapshot<u32>) -> Vec<u32> {
+    let mut items: Vec<u32> = snapshot.values().map(|item| **item).collect();
+    items.sort();
+    items
+}
+
+#[tokio::test]
+async fn snapshot_reflects_the_current_items() {
+    let dir = common::tmpdir("snapshot-watcher");
+    std::fs::write(dir.join("a.yaml"), "1\n---\n2").unwrap();
+    std::fs::write(dir.join("b.yaml"), "3").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+    )
+    .unwrap();
+    let snapshots = ConfigSnapshotWatcher::new(rx);
+    let mut updates = snapshots.subscribe();
+    assert!(snapshots.snapshot().is_empty());
+    handle.start().await.unwrap();
+    assert!(handle.wait_ready().await);
+    // Checkpoints are acknowledged once the snapshot including the preceding events is published
+    handle.checkpoint().await;
+    assert!(updates.has_changed().unwrap());
+    assert_eq!(items(&updates.borrow_and_update()), [1, 2, 3]);
+
+    std::fs::write(dir.join("a.yaml"), "2\n---\n4").unwrap();
+    tokio::time::timeout(Duration::from_secs(5), updates.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    handle.checkpoint().await;
+    assert_eq!(items(&snapshots.snapshot()), [2, 3, 4]);
+
+    std::fs::remove_file(dir.join("b.yaml")).unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    handle.checkpoint().await;
+    assert_eq!(items(&snapshots.snapshot()), [2, 4]);
+
+    // Receivers are told when the stream has ended
+    handle.stop().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while updates.changed().await.is_ok() {}
+    })
+    .await
+    .unwrap();
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn handles_report_the_kind_of_their_source() {
+    let dir = common::tmpdir("source-kind");
+    let (mut file, _file_rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    assert_eq!(file.source_kind(), SourceKind::File);
+    assert_eq!(file.controller().source_kind(), SourceKind::File);
+    file.stop().await.unwrap();
+
+    let (mut reader, _reader_rx) = run_reader_watcher("doc", &b"v: 0"[..], true).unwrap();
+    assert_eq!(reader.source_kind(), SourceKind::Reader);
+    reader.stop().await.unwrap();
+
+    let source = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    let (mut merged, _merged_rx) = run_merged_watcher(vec![("files".to_string(), source)]).unwrap();
+    assert_eq!(merged.source_kind(), SourceKind::Merged);
+    merged.stop().await.unwrap();
+
+    let watch_dir = dir.clone();
+    let (mut items, _items_rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    assert_eq!(items.source_kind(), SourceKind::File);
+    items.stop().await.unwrap();
+}
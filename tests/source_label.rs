@@ -0,0 +1,56 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+
+#[tokio::test]
+async fn document_ids_are_prefixed_with_the_label() {
+    let dir = common::tmpdir("source-label");
+    std::fs::write(dir.join("a.yaml"), "one").unwrap();
+    let id = dir
+        .canonicalize()
+        .unwrap()
+        .join("a.yaml")
+        .to_string_lossy()
+        .into_owned();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions {
+            source_label: Some("file".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    let labeled = format!("file:{id}");
+    assert!(
+        matches!(&events[0], ConfigItemEvent::NewDocument(hash, name) if *name == labeled && *hash == hash_str(&labeled)),
+        "{events:?}"
+    );
+    assert_eq!(handle.document_ids(), [labeled.as_str()]);
+
+    // Muted ids include the label
+    assert!(handle.mute(vec![labeled]).await);
+    std::fs::write(dir.join("a.yaml"), "two").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(events.is_empty(), "{events:?}");
+    handle.stop().await.unwrap();
+}
+
+#[test]
+fn backend_events_can_be_labeled() {
+    assert!(matches!(
+        DocumentEvent::ContentChanged("a".to_string(), "v: 1".to_string()).with_source_label("mqtt"),
+        DocumentEvent::ContentChanged(id, content) if id == "mqtt:a" && content == "v: 1"
+    ));
+    assert!(matches!(
+        DocumentEvent::InitialScanComplete.with_source_label("mqtt"),
+        DocumentEvent::InitialScanComplete
+    ));
+}
@@ -0,0 +1,132 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::clock::TestClock;
+use std::{sync::Arc, time::Duration};
+
+const DELAY: Duration = Duration::from_secs(1);
+
+fn options(clock: &TestClock) -> ConfigFileWatcherOptions {
+    ConfigFileWatcherOptions {
+        stable_read: Some(DELAY),
+        clock: Arc::new(clock.clone()),
+        ..Default::default()
+    }
+}
+
+/// Waits until the watcher has set its timer on `clock`.
+async fn wait_for_timer(clock: &TestClock) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while clock.pending_sleeps() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the watcher did not set its timer");
+}
+
+fn contents(events: &[DocumentEvent]) -> Vec<&str> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            DocumentEvent::NewDocument(_, content) | DocumentEvent::ContentChanged(_, content) => {
+                Some(content.as_str())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn settled_content_is_emitted_after_the_delay() {
+    let dir = common::tmpdir("stable-read-settled");
+    let clock = TestClock::new();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options(&clock)).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    wait_for_timer(&clock).await;
+    assert!(common::collect(&mut rx, 200).await.is_empty());
+
+    clock.advance(DELAY);
+    assert_eq!(contents(&common::collect(&mut rx, 300).await), ["v: 1"]);
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn writes_during_the_delay_start_over() {
+    let dir = common::tmpdir("stable-read-rewrite");
+    let clock = TestClock::new();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options(&clock)).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    wait_for_timer(&clock).await;
+    std::fs::write(dir.join("a.yaml"), "v: 2").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    clock.advance(DELAY);
+    assert_eq!(contents(&common::collect(&mut rx, 300).await), ["v: 2"]);
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_is_handled_while_a_file_settles() {
+    let dir = common::tmpdir("stable-read-stop");
+    let clock = TestClock::new();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options(&clock)).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    wait_for_timer(&clock).await;
+    tokio::time::timeout(Duration::from_secs(1), handle.stop())
+        .await
+        .expect("stop waited for the stable read")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn unsettled_files_of_the_initial_scan_are_emitted_once_settled() {
+    let dir = common::tmpdir("stable-read-scan");
+    let file = dir.join("a.yaml");
+    std::fs::write(&file, "v: 0").unwrap();
+    let clock = TestClock::new();
+    // Without file system events, only the stable read timers read the file again
+    let (mut handle, mut rx) = run_config_file_watcher_from_stream(
+        &dir,
+        "*.yaml",
+        options(&clock),
+        futures::stream::pending::<FileEventBatch>(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+
+    // The content changes before every read of the scan
+    for i in 1..STABLE_READ_ATTEMPTS {
+        wait_for_timer(&clock).await;
+        std::fs::write(&file, format!("v: {i}")).unwrap();
+        clock.advance(DELAY);
+    }
+    let events = common::collect(&mut rx, 300).await;
+    assert!(matches!(
+        events.as_slice(),
+        [DocumentEvent::InitialScanComplete]
+    ));
+
+    wait_for_timer(&clock).await;
+    clock.advance(DELAY);
+    let events = common::collect(&mut rx, 300).await;
+    assert_eq!(
+        contents(&events),
+        [format!("v: {}", STABLE_READ_ATTEMPTS - 1)]
+    );
+    handle.stop().await.unwrap();
+}
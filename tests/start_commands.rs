@@ -0,0 +1,59 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn repeated_starts_are_ignored() {
+    let dir = common::tmpdir("start-repeated");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            events.as_slice(),
+            [
+                DocumentEvent::NewDocument(..),
+                DocumentEvent::InitialScanComplete
+            ]
+        ),
+        "{events:?}"
+    );
+    assert!(!handle.is_finished());
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn stop_before_start_ends_the_watcher() {
+    let dir = common::tmpdir("start-stopped");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.stop().await.unwrap();
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn commands_sent_before_start_are_applied_once_running() {
+    let dir = common::tmpdir("start-deferred");
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle
+        .set_debounce(Duration::from_millis(800))
+        .await
+        .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 400).await;
+    assert!(events.is_empty(), "{events:?}");
+    let events = common::collect(&mut rx, 1000).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(..)]),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn events_not_yet_received_are_returned() {
+    let dir = common::tmpdir("stop-draining");
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_secs(5), handle.wait_ready())
+            .await
+            .unwrap()
+    );
+
+    let remaining = handle.stop_draining(&mut rx).await.unwrap();
+    let mut items: Vec<&str> = remaining
+        .iter()
+        .filter_map(|event| match event {
+            ConfigItemEvent::New(_, item) => Some(item.as_str()),
+            _ => None,
+        })
+        .collect();
+    items.sort();
+    assert_eq!(items, ["one", "two"]);
+    assert!(matches!(remaining[0], ConfigItemEvent::NewDocument(..)));
+    // The channel is closed and empty
+    assert!(rx.recv().await.is_none());
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn reader_watcher_exits_at_eof_when_terminating() {
+    let (handle, mut rx) = run_reader_watcher("input", &b"key: value"[..], true).unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(matches!(
+        events.as_slice(),
+        [
+            DocumentEvent::NewDocument(id, content),
+            DocumentEvent::InitialScanComplete
+        ] if id == "input" && content == "key: value"
+    ));
+    // The channel closes once the task has exited
+    assert!(rx.recv().await.is_none());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(handle.is_finished());
+}
+
+#[tokio::test]
+async fn reader_watcher_keeps_running_at_eof_by_default() {
+    let (mut handle, mut rx) = run_reader_watcher("input", &b"key: value"[..], false).unwrap();
+    handle.start().await.unwrap();
+    assert_eq!(common::collect(&mut rx, 300).await.len(), 2);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!handle.is_finished());
+    handle.stop().await.unwrap();
+}
+
+#[cfg(feature = "configmap")]
+#[test]
+fn configmap_watcher_ends_with_its_stream_by_default() {
+    assert!(!ConfigMapWatcherOptions::default().restart_on_source_end);
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::{Duration, SystemTime};
+
+#[tokio::test]
+async fn events_are_unstamped_by_default() {
+    let dir = common::tmpdir("timestamps-default");
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) = run_config_file_watcher(&dir, "*.yaml").unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(!events.is_empty());
+    assert!(events.iter().all(|event| event.observed_at().is_none()));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn file_events_carry_the_time_of_the_debounced_change() {
+    let dir = common::tmpdir("timestamps-debounce");
+    let options = ConfigFileWatcherOptions {
+        debounce: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    handle.emit_timestamps().await.unwrap();
+    handle.start().await.unwrap();
+    let initial = common::collect(&mut rx, 300).await;
+    assert!(matches!(
+        initial.as_slice(),
+        [DocumentEvent::Observed { event, .. }] if matches!(**event, DocumentEvent::InitialScanComplete)
+    ));
+
+    let written_at = SystemTime::now();
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let event = rx.recv().await.unwrap();
+    let received_at = SystemTime::now();
+    let (observed_at, event) = event.into_observed();
+    let observed_at = observed_at.expect("event is not stamped");
+    assert!(matches!(event, DocumentEvent::NewDocument(..)), "{event:?}");
+    // Stamped when the change was detected, not when the debounce window ended
+    assert!(observed_at >= written_at);
+    assert!(
+        received_at.duration_since(observed_at).unwrap() >= Duration::from_millis(400),
+        "{observed_at:?} {received_at:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn merged_watcher_keeps_the_times_of_its_sources() {
+    let dir = common::tmpdir("timestamps-merged");
+    let options = ConfigFileWatcherOptions {
+        debounce: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let source = run_config_file_watcher_with_options(&dir, "*.yaml", options).unwrap();
+    let (mut handle, mut rx) = run_merged_watcher(vec![("files".to_string(), source)]).unwrap();
+    handle.emit_timestamps().await.unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::write(dir.join("a.yaml"), "v: 1").unwrap();
+    let (observed_at, event) = rx.recv().await.unwrap().into_observed();
+    let received_at = SystemTime::now();
+    assert!(matches!(event, DocumentEvent::NewDocument(..)), "{event:?}");
+    // Not wrapped twice
+    let observed_at = observed_at.expect("event is not stamped");
+    assert!(received_at.duration_since(observed_at).unwrap() >= Duration::from_millis(400));
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn timestamped_item_events_carry_the_detection_time_and_sequence_numbers() {
+    let dir = common::tmpdir("timestamps-items");
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_timestamped(
+        move || {
+            run_config_file_watcher_with_options(
+                &watch_dir,
+                "*.yaml",
+                ConfigFileWatcherOptions {
+                    debounce: Some(Duration::from_millis(500)),
+                    ..Default::default()
+                },
+            )
+        },
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+        ConfigItemWatcherOptions::default(),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    let written_at = SystemTime::now();
+    std::fs::write(dir.join("a.yaml"), "one\n---\ntwo").unwrap();
+    let first = rx.recv().await.unwrap();
+    let received_at = SystemTime::now();
+    let mut events = vec![first];
+    events.extend(common::collect(&mut rx, 300).await);
+    assert!(events
+        .iter()
+        .any(|event| matches!(&event.event, ConfigItemEvent::New(_, item) if item == "one")));
+    for event in &events {
+        assert!(event.observed_at >= written_at);
+        assert!(
+            received_at.duration_since(event.observed_at).unwrap() >= Duration::from_millis(400)
+        );
+    }
+    // Messages of one backend event share its time and are numbered without gaps
+    assert!(events
+        .iter()
+        .all(|event| event.observed_at == events[0].observed_at));
+    for pair in events.windows(2) {
+        assert_eq!(pair[1].seq, pair[0].seq + 1);
+    }
+    handle.stop().await.unwrap();
+}
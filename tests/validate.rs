@@ -0,0 +1,65 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn rejected_items_are_reported_instead_of_tracked() {
+    let dir = common::tmpdir("validate");
+    std::fs::write(dir.join("a.yaml"), "1\n---\n200").unwrap();
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher_with_options(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| item.parse::<u32>(),
+        ConfigItemWatcherOptions {
+            validate: Some(Arc::new(|item: &u32| {
+                if *item < 100 {
+                    Ok(())
+                } else {
+                    Err(format!("{item} is too large").into())
+                }
+            })),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::New(_, 1))),
+        "{events:?}"
+    );
+    assert!(
+        events.iter().any(|event| matches!(
+            event,
+            ConfigItemEvent::ValidationError { error, .. } if error.0 == "200 is too large"
+        )),
+        "{events:?}"
+    );
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ConfigItemEvent::New(_, 200))));
+
+    // An item that changes to something invalid is removed
+    std::fs::write(dir.join("a.yaml"), "300\n---\n200").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, ConfigItemEvent::Removed(_))),
+        "{events:?}"
+    );
+    // Reported again each time the document is processed
+    let rejected = events
+        .iter()
+        .filter(|event| matches!(event, ConfigItemEvent::ValidationError { .. }))
+        .count();
+    assert_eq!(rejected, 2, "{events:?}");
+    handle.stop().await.unwrap();
+}
@@ -0,0 +1,212 @@
+#![cfg(feature = "vault")]
+
+mod common;
+
+use config_watcher::{backend::*, WatcherError};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Vault HTTP API answering each path with a fixed status and JSON body, 404 by default.
+#[derive(Clone, Default)]
+struct MockVault {
+    responses: Arc<Mutex<HashMap<String, (u16, String)>>>,
+    // "METHOD path" of the received requests
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockVault {
+    /// Serves the API on a local port and returns its address.
+    fn serve(&self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let vault = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                vault.respond(stream.unwrap());
+            }
+        });
+        addr
+    }
+
+    fn respond(&self, stream: std::net::TcpStream) {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        reader
+            .by_ref()
+            .take(content_length)
+            .read_to_end(&mut vec![])
+            .unwrap();
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        self.requests
+            .lock()
+            .unwrap()
+            .push(format!("{method} {path}"));
+        let (status, body) = self
+            .responses
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .unwrap_or((404, r#"{"errors":[]}"#.to_string()));
+        write!(
+            &stream,
+            "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    }
+
+    fn set(&self, path: &str, status: u16, body: &str) {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), (status, body.to_string()));
+    }
+
+    fn set_secret(&self, version: u64, data: &str) {
+        self.set(
+            "/v1/secret/data/app",
+            200,
+            &format!(r#"{{"data":{{"data":{data},"metadata":{{"version":{version}}}}}}}"#),
+        );
+    }
+
+    fn count(&self, request: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|received| *received == request)
+            .count()
+    }
+}
+
+#[tokio::test]
+async fn new_secret_versions_are_diffed_by_field() {
+    let vault = MockVault::default();
+    vault.set_secret(1, r#"{"a":"x","b":{"k":1}}"#);
+    let (mut handle, mut rx) = run_vault_watcher_with_options(
+        vault.serve(),
+        "token",
+        "secret",
+        "app",
+        Duration::from_millis(100),
+        VaultWatcherOptions {
+            renew_token: false,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(handle.source_kind(), SourceKind::Vault);
+    handle.start().await.unwrap();
+
+    // String fields are emitted as they are, other values as JSON
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [
+                DocumentEvent::NewDocument(a, a_content),
+                DocumentEvent::NewDocument(b, b_content),
+                DocumentEvent::InitialScanComplete,
+            ] if a == "a" && a_content == "x" && b == "b" && b_content == r#"{"k":1}"#
+        ),
+        "{received:?}"
+    );
+
+    // Reads of a known version are skipped
+    vault.set_secret(1, r#"{"a":"changed"}"#);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(received.is_empty(), "{received:?}");
+
+    vault.set_secret(2, r#"{"a":"y"}"#);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(
+            received.as_slice(),
+            [DocumentEvent::ContentChanged(a, content), DocumentEvent::DocumentRemoved(b)]
+                if a == "a" && content == "y" && b == "b"
+        ),
+        "{received:?}"
+    );
+
+    // A deleted secret has no fields
+    vault.set("/v1/secret/data/app", 404, r#"{"errors":[]}"#);
+    let received = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(received.as_slice(), [DocumentEvent::DocumentRemoved(a)] if a == "a"),
+        "{received:?}"
+    );
+    assert_eq!(vault.count("GET /v1/auth/token/lookup-self"), 0);
+
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn token_is_renewed_until_vault_rejects_it() {
+    let vault = MockVault::default();
+    vault.set_secret(1, r#"{"a":"x"}"#);
+    vault.set(
+        "/v1/auth/token/lookup-self",
+        200,
+        r#"{"data":{"ttl":1,"renewable":true}}"#,
+    );
+    vault.set(
+        "/v1/auth/token/renew-self",
+        200,
+        r#"{"auth":{"lease_duration":1,"renewable":true}}"#,
+    );
+    let (handle, mut rx) = run_vault_watcher(
+        vault.serve(),
+        "token",
+        "secret",
+        "app",
+        Duration::from_millis(100),
+    )
+    .unwrap();
+    let (controller, mut join) = handle.into_parts();
+    controller.start().await.unwrap();
+
+    // Renewed when half of the one second TTL has elapsed
+    let received = common::collect(&mut rx, 1200).await;
+    assert_eq!(received.len(), 2, "{received:?}");
+    assert!(vault.count("POST /v1/auth/token/renew-self") >= 1);
+
+    // An expired token ends the watcher
+    vault.set(
+        "/v1/secret/data/app",
+        403,
+        r#"{"errors":["permission denied"]}"#,
+    );
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while rx.recv().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    assert!(matches!(
+        join.join().await,
+        Err(WatcherError::VaultAccessDenied)
+    ));
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use config_watcher::*;
+use std::time::Duration;
+
+#[tokio::test]
+async fn wait_ready_resolves_after_the_initial_documents() {
+    let dir = common::tmpdir("wait-ready");
+    for i in 0..20 {
+        std::fs::write(dir.join(format!("{i}.yaml")), format!("item-{i}")).unwrap();
+    }
+    let watch_dir = dir.clone();
+    let (mut handle, mut rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.start().await.unwrap();
+    assert!(!handle.is_ready());
+    assert!(
+        tokio::time::timeout(Duration::from_secs(5), handle.wait_ready())
+            .await
+            .unwrap()
+    );
+    assert!(handle.is_ready());
+
+    // All items of the initial scan were sent before
+    let mut items = 0;
+    while let Ok(event) = rx.try_recv() {
+        if matches!(event, ConfigItemEvent::New(..)) {
+            items += 1;
+        }
+    }
+    assert_eq!(items, 20);
+
+    // Resolves right away once ready
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), handle.wait_ready())
+            .await
+            .unwrap()
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn wait_ready_waits_for_the_start() {
+    let dir = common::tmpdir("wait-ready-start");
+    let watch_dir = dir.clone();
+    let (mut handle, _rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), handle.wait_ready())
+            .await
+            .is_err()
+    );
+    handle.start().await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_secs(5), handle.wait_ready())
+            .await
+            .unwrap()
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn wait_ready_fails_if_the_watcher_stops_first() {
+    let dir = common::tmpdir("wait-ready-stopped");
+    let watch_dir = dir.clone();
+    let (mut handle, _rx) = run_config_item_watcher(
+        move || run_config_file_watcher(&watch_dir, "*.yaml"),
+        &YamlTokenizer,
+        |item: &str| Ok::<_, ()>(item.to_string()),
+    )
+    .unwrap();
+    handle.stop().await.unwrap();
+    assert!(
+        !tokio::time::timeout(Duration::from_secs(5), handle.wait_ready())
+            .await
+            .unwrap()
+    );
+}
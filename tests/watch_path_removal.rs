@@ -0,0 +1,103 @@
+#![cfg(feature = "file")]
+
+mod common;
+
+use config_watcher::backend::*;
+use std::time::Duration;
+
+fn options() -> ConfigFileWatcherOptions {
+    ConfigFileWatcherOptions {
+        retry_delay: RetryDelay::new(Duration::from_millis(100), 0.0),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn removed_watch_paths_remove_their_documents_until_recreated() {
+    let parent = common::tmpdir("watch-path-removal");
+    let dir = parent.join("config");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options()).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::DocumentRemoved(id)] if id.ends_with("a.yaml")),
+        "{events:?}"
+    );
+    assert!(!handle.is_finished());
+
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("b.yaml"), "v: 1").unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(id, _)] if id.ends_with("b.yaml")),
+        "{events:?}"
+    );
+
+    // Watched again
+    std::fs::write(dir.join("b.yaml"), "v: 2").unwrap();
+    let events = common::collect(&mut rx, 500).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::ContentChanged(_, content)] if content == "v: 2"),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn missing_watch_paths_are_waited_for() {
+    let parent = common::tmpdir("watch-path-missing");
+    let dir = parent.join("config");
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options()).unwrap();
+    handle.start().await.unwrap();
+    let events = common::collect(&mut rx, 300).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::InitialScanComplete]),
+        "{events:?}"
+    );
+
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    let events = common::collect(&mut rx, 800).await;
+    assert!(
+        matches!(events.as_slice(), [DocumentEvent::NewDocument(id, _)] if id.ends_with("a.yaml")),
+        "{events:?}"
+    );
+    handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn steady_commands_do_not_postpone_the_watch_path_check() {
+    let parent = common::tmpdir("watch-path-steady");
+    let dir = parent.join("config");
+    let (mut handle, mut rx) =
+        run_config_file_watcher_with_options(&dir, "*.yaml", options()).unwrap();
+    handle.start().await.unwrap();
+    common::collect(&mut rx, 300).await;
+
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("a.yaml"), "v: 0").unwrap();
+    // Commands arrive more often than the retry delay
+    let found = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            handle.debounce_metrics().await;
+            if let Ok(event) = rx.try_recv() {
+                break event;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("the watch path check was postponed by the commands");
+    assert!(
+        matches!(&found, DocumentEvent::NewDocument(id, _) if id.ends_with("a.yaml")),
+        "{found:?}"
+    );
+    handle.stop().await.unwrap();
+}